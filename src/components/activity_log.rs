@@ -0,0 +1,175 @@
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+use gtk::glib;
+use relm4::gtk::prelude::*;
+use relm4::prelude::*;
+
+use crate::state::{ActivityEntry, SonusmixMsg, SonusmixReducer, SonusmixState};
+
+/// Lists the changes made by other clients that Sonusmix reverted because they were locked.
+pub struct ActivityLog {
+    sonusmix_state: Arc<SonusmixState>,
+    list: gtk::ListBox,
+    /// The id of the newest entry shown, so the list is only rebuilt when it changes
+    newest_shown: Option<u64>,
+}
+
+#[derive(Debug)]
+pub enum ActivityLogMsg {
+    UpdateState(Arc<SonusmixState>),
+}
+
+#[relm4::component(pub)]
+impl SimpleComponent for ActivityLog {
+    type Init = ();
+    type Input = ActivityLogMsg;
+    type Output = Infallible;
+
+    view! {
+        gtk::Box {
+            set_orientation: gtk::Orientation::Vertical,
+            set_margin_all: 16,
+            set_spacing: 8,
+
+            gtk::Box {
+                set_orientation: gtk::Orientation::Horizontal,
+
+                gtk::Label {
+                    set_hexpand: true,
+                    set_halign: gtk::Align::Start,
+                    set_wrap: true,
+                    set_label: "Changes other programs made to locked volumes and connections, \
+                        which Sonusmix reverted.",
+                },
+                gtk::Button {
+                    set_label: "Clear",
+                    #[watch]
+                    set_sensitive: !model.sonusmix_state.activity_log.is_empty(),
+                    connect_clicked => move |_| {
+                        SonusmixReducer::emit(SonusmixMsg::ClearActivityLog);
+                    },
+                },
+            },
+
+            if model.sonusmix_state.activity_log.is_empty() {
+                gtk::Label {
+                    set_vexpand: true,
+                    set_valign: gtk::Align::Center,
+                    set_label: "Nothing has been reverted yet.",
+                }
+            } else {
+                gtk::ScrolledWindow {
+                    set_vexpand: true,
+                    set_policy: (gtk::PolicyType::Never, gtk::PolicyType::Automatic),
+
+                    #[local_ref]
+                    list -> gtk::ListBox {
+                        set_selection_mode: gtk::SelectionMode::None,
+                        add_css_class: "boxed-list",
+                    },
+                }
+            },
+        }
+    }
+
+    fn init(_init: (), root: Self::Root, sender: ComponentSender<Self>) -> ComponentParts<Self> {
+        let sonusmix_state =
+            SonusmixReducer::subscribe(sender.input_sender(), ActivityLogMsg::UpdateState);
+
+        let mut model = ActivityLog {
+            sonusmix_state,
+            list: gtk::ListBox::default(),
+            newest_shown: None,
+        };
+        model.update_list();
+
+        let list = &model.list;
+        let widgets = view_output!();
+
+        ComponentParts { model, widgets }
+    }
+
+    fn update(&mut self, msg: ActivityLogMsg, _sender: ComponentSender<Self>) {
+        match msg {
+            ActivityLogMsg::UpdateState(state) => {
+                self.sonusmix_state = state;
+                self.update_list();
+            }
+        }
+    }
+}
+
+impl ActivityLog {
+    fn update_list(&mut self) {
+        let newest = self
+            .sonusmix_state
+            .activity_log
+            .back()
+            .map(|entry| entry.id);
+        if newest == self.newest_shown {
+            return;
+        }
+        self.newest_shown = newest;
+
+        while let Some(child) = self.list.first_child() {
+            self.list.remove(&child);
+        }
+        // Show the newest entries first
+        for entry in self.sonusmix_state.activity_log.iter().rev() {
+            self.list.append(&entry_row(entry));
+        }
+    }
+}
+
+fn entry_row(entry: &ActivityEntry) -> gtk::Box {
+    let row = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(12)
+        .margin_top(4)
+        .margin_bottom(4)
+        .margin_start(8)
+        .margin_end(8)
+        .build();
+
+    let time = entry
+        .time
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .and_then(|since_epoch| glib::DateTime::from_unix_local(since_epoch.as_secs() as i64).ok())
+        .and_then(|time| time.format("%H:%M:%S").ok())
+        .map(String::from)
+        .unwrap_or_default();
+    row.append(
+        &gtk::Label::builder()
+            .label(time)
+            .css_classes(["dim-label", "numeric"])
+            .build(),
+    );
+
+    let description = match &entry.client {
+        Some(client) => format!("{} (by {client})", entry.description),
+        None => entry.description.clone(),
+    };
+    row.append(
+        &gtk::Label::builder()
+            .label(description)
+            .hexpand(true)
+            .xalign(0.0)
+            .wrap(true)
+            .selectable(true)
+            .build(),
+    );
+
+    if entry.is_lock_fight() {
+        let warning = gtk::Image::from_icon_name("dialog-warning-symbolic");
+        warning.set_tooltip_text(Some(&format!(
+            "Reverted {} times in the last minute",
+            entry.repeat_count
+        )));
+        row.append(&warning);
+    }
+
+    row
+}
@@ -1,4 +1,5 @@
 use std::convert::Infallible;
+use std::path::PathBuf;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
 
@@ -12,17 +13,23 @@ use tempfile::TempPath;
 use crate::pipewire_api::PortKind;
 use crate::state::settings::SonusmixSettings;
 use crate::state::{
-    EndpointDescriptor, GroupNodeKind, SonusmixMsg, SonusmixOutputMsg, SonusmixReducer,
-    SonusmixState, SONUSMIX_SETTINGS,
+    EndpointDescriptor, GroupNodeKind, ImportMode, LoopbackConfig, MuteGroupTarget, PortableConfig,
+    SonusmixMsg, SonusmixOutputMsg, SonusmixReducer, SonusmixState, SONUSMIX_SETTINGS,
 };
 use crate::{MainMsg, APP_WINDOW_ID, MAIN_BROKER};
 
 use super::about::{open_third_party_licenses, AboutComponent};
-use super::choose_endpoint_dialog::{ChooseEndpointDialog, ChooseEndpointDialogMsg};
+use super::activity_log::ActivityLog;
+use super::choose_endpoint_dialog::{
+    ChooseEndpointDialog, ChooseEndpointDialogMsg, CHOOSE_ENDPOINT_BROKER,
+};
+use super::color_menu::COLOR_CSS;
 use super::debug_view::{DebugView, DebugViewMsg};
-use super::endpoint_list::EndpointList;
-use super::group::{Group, GroupChangeWarning, GroupChangeWarningMsg, GroupOutput};
+use super::endpoint_list::{EndpointList, EndpointListMsg};
+use super::group::{Group, GroupChangeWarning, GroupChangeWarningMsg, GroupMsg, GroupOutput};
+use super::mute_groups::MuteGroups;
 use super::settings_page::SettingsPage;
+use super::vca::VcaStrip;
 
 pub struct App {
     sonusmix_state: Arc<SonusmixState>,
@@ -33,9 +40,13 @@ pub struct App {
     sources: Controller<EndpointList>,
     sinks: Controller<EndpointList>,
     groups: FactoryVecDeque<Group>,
+    vcas: FactoryVecDeque<VcaStrip>,
+    filter: String,
     choose_endpoint_dialog: Controller<ChooseEndpointDialog>,
     debug_view: Controller<DebugView>,
     settings_page: Controller<SettingsPage>,
+    activity_log: Controller<ActivityLog>,
+    mute_groups: Controller<MuteGroups>,
     _group_change_warning: Controller<GroupChangeWarning>,
 }
 
@@ -46,9 +57,17 @@ pub enum Msg {
     BringToTop,
     Close,
     AddGroupNode,
+    AddLoopback,
+    AddVca,
+    ToggleMuteAllSources,
     OpenAbout,
     OpenThirdPartyLicenses,
     ChangePage(Page),
+    Filter(String),
+    ExportConfig,
+    ImportConfig,
+    /// Show what importing the configuration file would change, and ask how to import it
+    PreviewImport(PathBuf),
 }
 
 #[derive(Debug)]
@@ -60,9 +79,12 @@ pub enum CommandMsg {
 pub enum Page {
     Main,
     Settings,
+    Activity,
 }
 
 relm4::new_action_group!(MainMenuActionGroup, "main-menu");
+relm4::new_stateless_action!(AddLoopbackAction, MainMenuActionGroup, "add-loopback");
+relm4::new_stateless_action!(AddVcaAction, MainMenuActionGroup, "add-vca");
 relm4::new_stateless_action!(AboutAction, MainMenuActionGroup, "about");
 relm4::new_stateless_action!(
     ThirdPartyLicensesAction,
@@ -70,6 +92,8 @@ relm4::new_stateless_action!(
     "third-party-licenses"
 );
 relm4::new_stateless_action!(ShowDebugViewAction, MainMenuActionGroup, "show-debug-view");
+relm4::new_stateless_action!(ExportConfigAction, MainMenuActionGroup, "export-config");
+relm4::new_stateless_action!(ImportConfigAction, MainMenuActionGroup, "import-config");
 
 #[relm4::component(pub)]
 impl Component for App {
@@ -84,6 +108,7 @@ impl Component for App {
             set_title: Some(match model.page {
                 Page::Main => "Sonusmix",
                 Page::Settings => "Settings",
+                Page::Activity => "Activity",
             }),
             set_default_size: (1100, 800),
 
@@ -104,6 +129,30 @@ impl Component for App {
                     set_icon_name: "go-previous-symbolic",
                     connect_clicked => Msg::ChangePage(Page::Main),
                 },
+                pack_start = &gtk::ToggleButton {
+                    #[watch]
+                    set_visible: model.page == Page::Main,
+                    #[watch]
+                    set_icon_name: if model.all_sources_muted()
+                        { "microphone-sensitivity-muted-symbolic" } else { "microphone-sensitivity-high-symbolic" },
+                    #[watch]
+                    set_tooltip: if model.all_sources_muted()
+                        { "Unmute all sources" } else { "Mute all sources" },
+                    #[watch]
+                    set_css_classes: if model.all_sources_muted()
+                        { &["destructive-action", "image-button"] } else { &["flat", "image-button"] },
+
+                    #[watch]
+                    set_active: model.all_sources_muted(),
+                    connect_clicked => Msg::ToggleMuteAllSources,
+                },
+                pack_start = &gtk::MenuButton {
+                    #[watch]
+                    set_visible: model.page == Page::Main,
+                    set_icon_name: "audio-volume-muted-symbolic",
+                    set_tooltip: "Mute groups",
+                    set_popover: Some(model.mute_groups.widget()),
+                },
                 pack_end = &gtk::MenuButton {
                     set_icon_name: "view-more-symbolic",
                     set_menu_model: Some(&main_menu),
@@ -114,85 +163,133 @@ impl Component for App {
                     set_icon_name: "preferences-system-symbolic",
                     connect_clicked => Msg::ChangePage(Page::Settings),
                 },
+                pack_end = &gtk::Button {
+                    #[watch]
+                    set_visible: model.page != Page::Activity,
+                    set_icon_name: "document-open-recent-symbolic",
+                    set_tooltip: "Activity",
+                    connect_clicked => Msg::ChangePage(Page::Activity),
+                },
+                pack_end = &gtk::Button {
+                    #[watch]
+                    set_visible: model.sonusmix_state.midi_learn.is_some(),
+                    add_css_class: "suggested-action",
+                    set_label: "Waiting for MIDI input…",
+                    set_tooltip: "Use a control on your MIDI controller to map it, or click to cancel",
+                    connect_clicked => move |_| {
+                        SonusmixReducer::emit(SonusmixMsg::CancelMidiLearn);
+                    },
+                },
             },
 
             #[transition(SlideLeftRight)]
             match model.page {
-                Page::Main => gtk::Paned {
+                Page::Main => gtk::Box {
                     set_orientation: gtk::Orientation::Vertical,
                     set_margin_all: 8,
-                    set_wide_handle: true,
-                    set_shrink_start_child: false,
-                    set_shrink_end_child: false,
+                    set_spacing: 8,
 
-                    #[wrap(Some)]
-                    set_start_child = &gtk::Paned {
-                        set_orientation: gtk::Orientation::Horizontal,
+                    gtk::SearchEntry {
+                        set_halign: gtk::Align::Center,
+                        set_width_chars: 30,
+                        set_placeholder_text: Some("Filter endpoints…"),
+
+                        connect_search_changed[sender] => move |search| {
+                            sender.input(Msg::Filter(String::from(search.text())));
+                        }
+                    },
+                    gtk::Paned {
+                        set_orientation: gtk::Orientation::Vertical,
+                        set_vexpand: true,
                         set_wide_handle: true,
                         set_shrink_start_child: false,
                         set_shrink_end_child: false,
-                        set_margin_bottom: 4,
 
                         #[wrap(Some)]
-                        set_start_child = &gtk::Box {
-                            set_margin_end: 4,
+                        set_start_child = &gtk::Paned {
+                            set_orientation: gtk::Orientation::Horizontal,
+                            set_wide_handle: true,
+                            set_shrink_start_child: false,
+                            set_shrink_end_child: false,
+                            set_margin_bottom: 4,
+
+                            #[wrap(Some)]
+                            set_start_child = &gtk::Box {
+                                set_margin_end: 4,
+
+                                append: model.sources.widget(),
+                            },
+                            #[wrap(Some)]
+                            set_end_child = &gtk::Box {
+                                set_margin_start: 4,
 
-                            append: model.sources.widget(),
+                                append: model.sinks.widget(),
+                            },
                         },
-                        #[wrap(Some)]
-                        set_end_child = &gtk::Box {
-                            set_margin_start: 4,
 
-                            append: model.sinks.widget(),
-                        },
-                    },
-
-                    #[wrap(Some)]
-                    set_end_child = &gtk::Frame {
-                        set_margin_top: 4,
-
-                        gtk::Box {
-                            set_orientation: gtk::Orientation::Vertical,
-                            set_hexpand: true,
-
-                            gtk::CenterBox {
-                                set_orientation: gtk::Orientation::Horizontal,
-                                set_margin_top: 4,
-                                set_margin_start: 4,
-
-                                #[wrap(Some)]
-                                set_start_widget = &gtk::Button {
-                                    set_icon_name: "list-add-symbolic",
-                                    set_has_frame: true,
+                        #[wrap(Some)]
+                        set_end_child = &gtk::Frame {
+                            set_margin_top: 4,
 
-                                    connect_clicked => Msg::AddGroupNode,
+                            gtk::Box {
+                                set_orientation: gtk::Orientation::Vertical,
+                                set_hexpand: true,
+
+                                gtk::CenterBox {
+                                    set_orientation: gtk::Orientation::Horizontal,
+                                    set_margin_top: 4,
+                                    set_margin_start: 4,
+
+                                    #[wrap(Some)]
+                                    set_start_widget = &gtk::Button {
+                                        set_icon_name: "list-add-symbolic",
+                                        set_has_frame: true,
+
+                                        connect_clicked => Msg::AddGroupNode,
+                                    },
+                                    #[wrap(Some)]
+                                    set_center_widget = &gtk::Label {
+                                        set_markup: "<big>Groups/Virtual Devices</big>",
+                                    },
                                 },
-                                #[wrap(Some)]
-                                set_center_widget = &gtk::Label {
-                                    set_markup: "<big>Groups/Virtual Devices</big>",
+                                gtk::Separator {
+                                    set_orientation: gtk::Orientation::Vertical,
+                                    set_margin_vertical: 4,
                                 },
-                            },
-                            gtk::Separator {
-                                set_orientation: gtk::Orientation::Vertical,
-                                set_margin_vertical: 4,
-                            },
-                            if model.groups.is_empty() {
-                                gtk::Label {
-                                    set_vexpand: true,
-                                    set_valign: gtk::Align::Center,
-                                    set_halign: gtk::Align::Center,
-                                    set_label: "Add some groups to control them here.",
-                                }
-                            } else {
-                                gtk::ScrolledWindow {
-                                    set_hexpand: true,
-                                    set_policy: (gtk::PolicyType::Automatic, gtk::PolicyType::Never),
-
-                                    #[local_ref]
-                                    groups_list -> gtk::Box {
-                                        set_orientation: gtk::Orientation::Horizontal,
-                                        set_margin_all: 4,
-                                        set_spacing: 8,
+                                if model.groups.is_empty() && model.vcas.is_empty() {
+                                    gtk::Label {
+                                        set_vexpand: true,
+                                        set_valign: gtk::Align::Center,
+                                        set_halign: gtk::Align::Center,
+                                        set_label: "Add some groups or VCAs to control them here.",
+                                    }
+                                } else {
+                                    gtk::ScrolledWindow {
+                                        set_hexpand: true,
+                                        set_policy: (gtk::PolicyType::Automatic, gtk::PolicyType::Never),
+
+                                        gtk::Box {
+                                            set_orientation: gtk::Orientation::Horizontal,
+
+                                            #[local_ref]
+                                            vcas_list -> gtk::Box {
+                                                set_orientation: gtk::Orientation::Horizontal,
+                                                set_margin_all: 4,
+                                                set_spacing: 8,
+                                            },
+                                            gtk::Separator {
+                                                set_orientation: gtk::Orientation::Vertical,
+                                                #[watch]
+                                                set_visible: !model.vcas.is_empty()
+                                                    && !model.groups.is_empty(),
+                                            },
+                                            #[local_ref]
+                                            groups_list -> gtk::Box {
+                                                set_orientation: gtk::Orientation::Horizontal,
+                                                set_margin_all: 4,
+                                                set_spacing: 8,
+                                            }
+                                        }
                                     }
                                 }
                             }
@@ -200,12 +297,17 @@ impl Component for App {
                     }
                 },
                 Page::Settings => model.settings_page.widget().clone(),
+                Page::Activity => model.activity_log.widget().clone(),
             }
         }
     }
 
     menu! {
         main_menu: {
+            "Add Loopback" => AddLoopbackAction,
+            "Add VCA" => AddVcaAction,
+            "Export Configuration…" => ExportConfigAction,
+            "Import Configuration…" => ImportConfigAction,
             "About" => AboutAction,
             "View Third-Party Licenses" => ThirdPartyLicensesAction,
             "Show Debug View" => ShowDebugViewAction,
@@ -219,10 +321,11 @@ impl Component for App {
             Msg::UpdateSettings(settings.clone())
         });
         let settings = { SONUSMIX_SETTINGS.read().clone() };
+        relm4::set_global_css(COLOR_CSS);
 
         let choose_endpoint_dialog = ChooseEndpointDialog::builder()
             .transient_for(&root)
-            .launch(())
+            .launch_with_broker((), &CHOOSE_ENDPOINT_BROKER)
             .detach();
         let sources = EndpointList::builder()
             .launch(PortKind::Source)
@@ -236,6 +339,8 @@ impl Component for App {
             });
         let debug_view = DebugView::builder().launch(()).detach();
         let settings_page = SettingsPage::builder().launch(()).detach();
+        let activity_log = ActivityLog::builder().launch(()).detach();
+        let mute_groups = MuteGroups::builder().launch(()).detach();
         let group_change_warning = GroupChangeWarning::builder()
             .transient_for(&root)
             .launch(())
@@ -252,6 +357,15 @@ impl Component for App {
                 groups.push_back(*group);
             }
         }
+        let mut vcas = FactoryVecDeque::builder()
+            .launch(gtk::Box::default())
+            .detach();
+        {
+            let mut vcas = vcas.guard();
+            for vca in sonusmix_state.vcas.keys() {
+                vcas.push_back(*vca);
+            }
+        }
 
         let model = App {
             sonusmix_state,
@@ -262,13 +376,18 @@ impl Component for App {
             sources,
             sinks,
             groups,
+            vcas,
+            filter: String::new(),
             choose_endpoint_dialog,
             debug_view,
             settings_page,
+            activity_log,
+            mute_groups,
             _group_change_warning: group_change_warning,
         };
 
         let groups_list = model.groups.widget();
+        let vcas_list = model.vcas.widget();
         let widgets = view_output!();
 
         // Set up actions
@@ -280,6 +399,20 @@ impl Component for App {
             }
         });
         group.add_action(about_action);
+        let add_loopback_action: RelmAction<AddLoopbackAction> = RelmAction::new_stateless({
+            let sender = sender.clone();
+            move |_| {
+                sender.input(Msg::AddLoopback);
+            }
+        });
+        group.add_action(add_loopback_action);
+        let add_vca_action: RelmAction<AddVcaAction> = RelmAction::new_stateless({
+            let sender = sender.clone();
+            move |_| {
+                sender.input(Msg::AddVca);
+            }
+        });
+        group.add_action(add_vca_action);
         let third_party_licenses_action: RelmAction<ThirdPartyLicensesAction> =
             RelmAction::new_stateless({
                 let sender = sender.clone();
@@ -295,6 +428,20 @@ impl Component for App {
             }
         });
         group.add_action(show_debug_view_action);
+        let export_config_action: RelmAction<ExportConfigAction> = RelmAction::new_stateless({
+            let sender = sender.clone();
+            move |_| {
+                sender.input(Msg::ExportConfig);
+            }
+        });
+        group.add_action(export_config_action);
+        let import_config_action: RelmAction<ImportConfigAction> = RelmAction::new_stateless({
+            let sender = sender.clone();
+            move |_| {
+                sender.input(Msg::ImportConfig);
+            }
+        });
+        group.add_action(import_config_action);
         group.register_for_widget(&widgets.main_window);
 
         widgets.main_window.set_visible(true);
@@ -309,7 +456,7 @@ impl Component for App {
         root: &gtk::ApplicationWindow,
     ) {
         match msg {
-            Msg::UpdateState(state, msg) => {
+            Msg::UpdateState(state, _msg) => {
                 self.sonusmix_state = state;
                 // Update the choose endpoint dialog if it's open
                 if let Some(list) = self.choose_endpoint_dialog.model().active_list() {
@@ -319,23 +466,8 @@ impl Component for App {
                         .send(ChooseEndpointDialogMsg::Show(list));
                 }
 
-                match msg {
-                    Some(SonusmixOutputMsg::EndpointAdded(EndpointDescriptor::GroupNode(id))) => {
-                        self.groups.guard().push_back(id);
-                    }
-                    Some(SonusmixOutputMsg::EndpointRemoved(
-                        endpoint_desc @ EndpointDescriptor::GroupNode(_),
-                    )) => {
-                        let index = self
-                            .groups
-                            .iter()
-                            .position(|group| group.endpoint.descriptor == endpoint_desc);
-                        if let Some(index) = index {
-                            self.groups.guard().remove(index);
-                        }
-                    }
-                    _ => {}
-                }
+                self.update_groups();
+                self.update_vcas();
             }
             Msg::UpdateSettings(settings) => {
                 self.settings = settings;
@@ -366,6 +498,43 @@ impl Component for App {
                     }
                 }
             }
+            Msg::AddLoopback => {
+                for num in 1.. {
+                    let name = format!("Loopback {num}");
+                    if self
+                        .sonusmix_state
+                        .loopbacks
+                        .values()
+                        .all(|loopback| loopback.name != name)
+                    {
+                        SonusmixReducer::emit(SonusmixMsg::AddLoopback(
+                            name,
+                            LoopbackConfig::default(),
+                        ));
+                        break;
+                    }
+                }
+            }
+            Msg::AddVca => {
+                for num in 1.. {
+                    let name = format!("VCA {num}");
+                    if self
+                        .sonusmix_state
+                        .vcas
+                        .values()
+                        .all(|vca| vca.name != name)
+                    {
+                        SonusmixReducer::emit(SonusmixMsg::AddVca(name));
+                        break;
+                    }
+                }
+            }
+            Msg::ToggleMuteAllSources => {
+                SonusmixReducer::emit(SonusmixMsg::SetMuteGroupEngaged(
+                    MuteGroupTarget::AllSources,
+                    !self.all_sources_muted(),
+                ));
+            }
             Msg::OpenAbout => {
                 self.about_component = Some(AboutComponent::builder().launch(()).detach());
             }
@@ -378,6 +547,82 @@ impl Component for App {
             Msg::ChangePage(page) => {
                 self.page = page;
             }
+            Msg::Filter(filter) => {
+                self.sources.emit(EndpointListMsg::Filter(filter.clone()));
+                self.sinks.emit(EndpointListMsg::Filter(filter.clone()));
+                self.groups.broadcast(GroupMsg::Filter(filter.clone()));
+                self.filter = filter;
+            }
+            Msg::ExportConfig => {
+                let config = self
+                    .sonusmix_state
+                    .export_config(&SonusmixReducer::graph(), &self.settings);
+                let dialog = gtk::FileDialog::builder()
+                    .title("Export Configuration")
+                    .initial_name("sonusmix-config.ron")
+                    .build();
+                dialog.save(Some(root), None::<&gtk::gio::Cancellable>, move |result| {
+                    let Some(path) = result.ok().and_then(|file| file.path()) else {
+                        return;
+                    };
+                    if let Err(err) = config.save(&path) {
+                        error!("Failed to export configuration: {err:#}");
+                    }
+                });
+            }
+            Msg::ImportConfig => {
+                let dialog = gtk::FileDialog::builder()
+                    .title("Import Configuration")
+                    .build();
+                dialog.open(Some(root), None::<&gtk::gio::Cancellable>, move |result| {
+                    if let Some(path) = result.ok().and_then(|file| file.path()) {
+                        sender.input(Msg::PreviewImport(path));
+                    }
+                });
+            }
+            Msg::PreviewImport(path) => {
+                let config = match PortableConfig::load(&path) {
+                    Ok(config) => config,
+                    Err(err) => {
+                        gtk::AlertDialog::builder()
+                            .modal(true)
+                            .message("Could not import the configuration")
+                            .detail(format!("{err:#}"))
+                            .build()
+                            .show(Some(root));
+                        return;
+                    }
+                };
+                let preview = self.sonusmix_state.preview_import(
+                    &config,
+                    &SonusmixReducer::graph(),
+                    &SONUSMIX_SETTINGS.read(),
+                );
+                let dialog = gtk::AlertDialog::builder()
+                    .modal(true)
+                    .message("Import Configuration")
+                    .detail(format!(
+                        "Merging adds the configuration to the current setup, keeping the current \
+                         settings. Replacing removes the current endpoints first, except for group \
+                         nodes in the configuration, and also imports the settings.\n\n{}",
+                        preview.summary()
+                    ))
+                    .buttons(["Cancel", "Merge", "Replace"])
+                    .cancel_button(0)
+                    .default_button(1)
+                    .build();
+                dialog.choose(Some(root), None::<&gtk::gio::Cancellable>, move |result| {
+                    let mode = match result {
+                        Ok(1) => ImportMode::Merge,
+                        Ok(2) => {
+                            *SONUSMIX_SETTINGS.write() = config.settings.clone();
+                            ImportMode::Replace
+                        }
+                        _ => return,
+                    };
+                    SonusmixReducer::emit(SonusmixMsg::ImportConfig(Box::new(config), mode));
+                });
+            }
         };
     }
 
@@ -395,3 +640,45 @@ impl Component for App {
         }
     }
 }
+
+impl App {
+    /// Add, remove, and reorder the groups to match the state.
+    fn update_groups(&mut self) {
+        let group_ids = self.sonusmix_state.group_nodes.keys().copied();
+        if self
+            .groups
+            .iter()
+            .map(|group| group.group_node.id)
+            .eq(group_ids.clone())
+        {
+            return;
+        }
+        {
+            let mut groups = self.groups.guard();
+            groups.clear();
+            for id in group_ids {
+                groups.push_back(id);
+            }
+        }
+        self.groups.broadcast(GroupMsg::Filter(self.filter.clone()));
+    }
+
+    /// Whether the built-in mute group that mutes every source is engaged.
+    fn all_sources_muted(&self) -> bool {
+        self.sonusmix_state
+            .is_mute_group_engaged(MuteGroupTarget::AllSources)
+    }
+
+    /// Add, remove, and reorder the VCAs to match the state.
+    fn update_vcas(&mut self) {
+        let vca_ids = self.sonusmix_state.vcas.keys().copied();
+        if self.vcas.iter().map(|vca| vca.vca.id).eq(vca_ids.clone()) {
+            return;
+        }
+        let mut vcas = self.vcas.guard();
+        vcas.clear();
+        for id in vca_ids {
+            vcas.push_back(id);
+        }
+    }
+}
@@ -0,0 +1,230 @@
+use relm4::gtk::prelude::*;
+use relm4::prelude::*;
+
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use crate::pipewire_api::Graph;
+use crate::state::{
+    ApplicationId, SonusmixMsg, SonusmixReducer, SonusmixState, StreamMatcher, StreamStatus,
+    SONUSMIX_SETTINGS,
+};
+
+/// Lists the streams an application endpoint controls, and lets the user exclude streams from it
+/// or include them again.
+pub struct ApplicationStreams {
+    id: ApplicationId,
+    state: Arc<SonusmixState>,
+    graph: Arc<Graph>,
+    rows: Vec<StreamRow>,
+    list: gtk::Box,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct StreamRow {
+    /// The node ID of a stream that currently exists, or None for an excluded stream that doesn't
+    node_id: Option<u32>,
+    description: String,
+    /// The matcher to exclude the stream with, if it has a media name or title
+    matcher: Option<StreamMatcher>,
+    status: StreamStatus,
+}
+
+#[derive(Debug)]
+pub enum ApplicationStreamsMsg {
+    UpdateState(Arc<SonusmixState>),
+    UpdateGraph(Arc<Graph>),
+}
+
+#[relm4::component(pub)]
+impl SimpleComponent for ApplicationStreams {
+    type Init = ApplicationId;
+    type Input = ApplicationStreamsMsg;
+    type Output = Infallible;
+
+    view! {
+        #[root]
+        gtk::Popover {
+            set_autohide: true,
+
+            #[local_ref]
+            list -> gtk::Box {
+                set_orientation: gtk::Orientation::Vertical,
+                set_spacing: 8,
+            }
+        }
+    }
+
+    fn init(
+        id: ApplicationId,
+        root: Self::Root,
+        sender: ComponentSender<Self>,
+    ) -> ComponentParts<Self> {
+        let state =
+            SonusmixReducer::subscribe(sender.input_sender(), ApplicationStreamsMsg::UpdateState);
+        let graph = SonusmixReducer::subscribe_graph(
+            sender.input_sender(),
+            ApplicationStreamsMsg::UpdateGraph,
+        );
+
+        let mut model = ApplicationStreams {
+            id,
+            state,
+            graph,
+            rows: Vec::new(),
+            list: gtk::Box::default(),
+        };
+        model.update_rows();
+
+        let list = &model.list;
+        let widgets = view_output!();
+
+        ComponentParts { model, widgets }
+    }
+
+    fn update(&mut self, msg: ApplicationStreamsMsg, _sender: ComponentSender<Self>) {
+        match msg {
+            ApplicationStreamsMsg::UpdateState(state) => self.state = state,
+            ApplicationStreamsMsg::UpdateGraph(graph) => self.graph = graph,
+        }
+        self.update_rows();
+    }
+}
+
+impl ApplicationStreams {
+    fn update_rows(&mut self) {
+        let settings = { SONUSMIX_SETTINGS.read().clone() };
+        let streams = self
+            .state
+            .application_streams(&self.graph, self.id, &settings);
+
+        let mut rows: Vec<StreamRow> = streams
+            .iter()
+            .map(|stream| {
+                let matcher = StreamMatcher::for_stream(&stream.identifier);
+                StreamRow {
+                    node_id: Some(stream.node_id),
+                    description: matcher
+                        .as_ref()
+                        .map(StreamMatcher::description)
+                        .unwrap_or_else(|| format!("Node {} (untitled)", stream.node_id)),
+                    matcher,
+                    status: stream.status.clone(),
+                }
+            })
+            .collect();
+        // Also list excluded streams that aren't playing right now, so they can be included again
+        if let Some(application) = self.state.applications.get(&self.id) {
+            for matcher in &application.excluded_streams {
+                if !streams
+                    .iter()
+                    .any(|stream| stream.status == StreamStatus::Excluded(matcher.clone()))
+                {
+                    rows.push(StreamRow {
+                        node_id: None,
+                        description: matcher.description(),
+                        matcher: Some(matcher.clone()),
+                        status: StreamStatus::Excluded(matcher.clone()),
+                    });
+                }
+            }
+        }
+        if rows == self.rows {
+            return;
+        }
+        self.rows = rows;
+
+        while let Some(child) = self.list.first_child() {
+            self.list.remove(&child);
+        }
+        if self.rows.is_empty() {
+            self.list.append(
+                &gtk::Label::builder()
+                    .label("The application has no streams right now")
+                    .css_classes(["dim-label"])
+                    .build(),
+            );
+        }
+        for row in &self.rows {
+            self.list.append(&stream_row(self.id, row));
+        }
+    }
+}
+
+fn stream_row(id: ApplicationId, row: &StreamRow) -> gtk::Box {
+    let container = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(8)
+        .build();
+    let labels = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .hexpand(true)
+        .build();
+    labels.append(
+        &gtk::Label::builder()
+            .label(&row.description)
+            .max_width_chars(40)
+            .ellipsize(gtk::pango::EllipsizeMode::End)
+            .tooltip_text(&row.description)
+            .xalign(0.0)
+            .build(),
+    );
+    let status = match (&row.status, row.node_id) {
+        (StreamStatus::Included, _) => "Controlled by this endpoint",
+        (StreamStatus::Excluded(_), Some(_)) => "Excluded",
+        (StreamStatus::Excluded(_), None) => "Excluded, not playing right now",
+        (StreamStatus::SeparateEndpoint(_), _) => "Has its own endpoint",
+    };
+    labels.append(
+        &gtk::Label::builder()
+            .label(status)
+            .css_classes(["caption", "dim-label"])
+            .xalign(0.0)
+            .build(),
+    );
+    container.append(&labels);
+
+    match &row.status {
+        StreamStatus::Included => {
+            let button = gtk::Button::with_label("Exclude");
+            button.set_valign(gtk::Align::Center);
+            match row.matcher.clone() {
+                Some(matcher) => {
+                    button.set_tooltip_text(Some(
+                        "Leave streams with this name out of the endpoint, even when they are \
+                         recreated",
+                    ));
+                    button.connect_clicked(move |_| {
+                        SonusmixReducer::emit(SonusmixMsg::SetStreamExcluded(
+                            id,
+                            matcher.clone(),
+                            true,
+                        ))
+                    });
+                }
+                None => {
+                    button.set_sensitive(false);
+                    button.set_tooltip_text(Some(
+                        "This stream has no media name or title to recognize it by",
+                    ));
+                }
+            }
+            container.append(&button);
+        }
+        StreamStatus::Excluded(matcher) => {
+            let button = gtk::Button::with_label("Include");
+            button.set_valign(gtk::Align::Center);
+            button.set_tooltip_text(Some(
+                "Control streams with this name with the endpoint again",
+            ));
+            let matcher = matcher.clone();
+            button.connect_clicked(move |_| {
+                SonusmixReducer::emit(SonusmixMsg::SetStreamExcluded(id, matcher.clone(), false))
+            });
+            container.append(&button);
+        }
+        StreamStatus::SeparateEndpoint(_) => {}
+    }
+
+    container
+}
@@ -9,12 +9,24 @@ use itertools::Itertools;
 use relm4::factory::FactoryVecDeque;
 use relm4::gtk::prelude::*;
 use relm4::prelude::*;
+use relm4::MessageBroker;
 
 use crate::{
-    pipewire_api::{NodeIdentifier, PortKind},
-    state::{Application, EndpointDescriptor, SonusmixMsg, SonusmixReducer, SonusmixState},
+    pipewire_api::{MediaRole, NodeIdentifier, PortKind},
+    state::{
+        media_role_name_with_tag, Application, EndpointDescriptor, SonusmixMsg, SonusmixReducer,
+        SonusmixState,
+    },
 };
 
+/// Lets endpoints open the dialog to choose what to replace them with.
+pub static CHOOSE_ENDPOINT_BROKER: MessageBroker<ChooseEndpointDialogMsg> = MessageBroker::new();
+
+/// The matcher used to search for endpoints by name.
+pub fn fuzzy_matcher() -> SkimMatcherV2 {
+    SkimMatcherV2::default().smart_case()
+}
+
 pub struct ChooseEndpointDialog {
     sonusmix_state: Arc<SonusmixState>,
     list: PortKind,
@@ -22,12 +34,16 @@ pub struct ChooseEndpointDialog {
     applications: FactoryVecDeque<ChooseEndpointItem>,
     visible: bool,
     search_text: String,
+    /// The placeholder endpoint that the chosen endpoint will replace, if any
+    replacing: Option<EndpointDescriptor>,
 }
 
 #[derive(Debug)]
 pub enum ChooseEndpointDialogMsg {
     SonusmixState(Arc<SonusmixState>),
     Show(PortKind),
+    /// Choose a node or application to rebind a placeholder endpoint to
+    ShowReplace(EndpointDescriptor, PortKind),
     #[doc(hidden)]
     ListChanged(PortKind),
     #[doc(hidden)]
@@ -97,6 +113,9 @@ impl SimpleComponent for ChooseEndpointDialog {
                 #[wrap(Some)]
                 set_title_widget = &gtk::StackSwitcher {
                     set_visible: true,
+                    // A placeholder can only be replaced by an endpoint from the same list
+                    #[watch]
+                    set_sensitive: model.replacing.is_none(),
                     set_stack: Some(&list_switcher_dummy),
                 }
             },
@@ -236,6 +255,7 @@ impl SimpleComponent for ChooseEndpointDialog {
             applications,
             visible: false,
             search_text: String::new(),
+            replacing: None,
         };
 
         let nodes_list_box = model.nodes.widget();
@@ -258,12 +278,25 @@ impl SimpleComponent for ChooseEndpointDialog {
                 self.update_inactive_endpoints();
                 self.visible = true;
             }
+            ChooseEndpointDialogMsg::ShowReplace(placeholder, list) => {
+                self.replacing = Some(placeholder);
+                self.list = list;
+                self.update_inactive_endpoints();
+                self.visible = true;
+            }
             ChooseEndpointDialogMsg::ListChanged(list) => {
                 self.list = list;
                 self.update_inactive_endpoints();
             }
             ChooseEndpointDialogMsg::Close => {
                 self.visible = false;
+                self.replacing = None;
+            }
+            ChooseEndpointDialogMsg::EndpointChosen(endpoint) if self.replacing.is_some() => {
+                if let Some(placeholder) = self.replacing.take() {
+                    SonusmixReducer::emit(SonusmixMsg::RebindEndpoint(placeholder, endpoint));
+                }
+                self.visible = false;
             }
             ChooseEndpointDialogMsg::EndpointChosen(endpoint) => match endpoint {
                 EndpointDescriptor::EphemeralNode(id, kind) => {
@@ -272,6 +305,12 @@ impl SimpleComponent for ChooseEndpointDialog {
                 EndpointDescriptor::Application(id, kind) => {
                     SonusmixReducer::emit(SonusmixMsg::AddApplication(id, kind))
                 }
+                EndpointDescriptor::DefaultNode(kind) => {
+                    SonusmixReducer::emit(SonusmixMsg::AddDefaultNode(kind))
+                }
+                EndpointDescriptor::MediaRole(role, kind) => {
+                    SonusmixReducer::emit(SonusmixMsg::AddMediaRole(role, kind))
+                }
                 _ => todo!(),
             },
             ChooseEndpointDialogMsg::SearchUpdated(search_text) => {
@@ -315,7 +354,7 @@ impl ChooseEndpointDialog {
 
         // Sort and filter the nodes
         if !self.search_text.is_empty() {
-            let fuzzy_matcher = SkimMatcherV2::default().smart_case();
+            let fuzzy_matcher = fuzzy_matcher();
             // Computing the match twice is simpler by far, and probably isn't a performance
             // issue. If it is, we can come back to this later.
             nodes.retain(|node| {
@@ -351,7 +390,7 @@ impl ChooseEndpointDialog {
 
         // Sort and filter the applications
         if !self.search_text.is_empty() {
-            let fuzzy_matcher = SkimMatcherV2::default().smart_case();
+            let fuzzy_matcher = fuzzy_matcher();
 
             applications.retain(|(application, nodes)| {
                 // Keep an application if it matches, or if it has any matching nodes
@@ -372,6 +411,26 @@ impl ChooseEndpointDialog {
             });
         }
 
+        // Offer the endpoint that follows the default node first, unless it was already added
+        let default_desc = EndpointDescriptor::DefaultNode(self.list);
+        let default_name = match self.list {
+            PortKind::Source => "Default Input",
+            PortKind::Sink => "Default Output",
+        };
+        if !self.sonusmix_state.endpoints.contains_key(&default_desc)
+            && (self.search_text.is_empty()
+                || fuzzy_matcher()
+                    .fuzzy_match(default_name, &self.search_text)
+                    .is_some())
+        {
+            node_factory.push_back((
+                default_desc,
+                default_name.to_owned(),
+                Some("Follows whichever device is currently the default".to_owned()),
+                ChooseEndpointItemMode::Normal,
+            ));
+        }
+
         for (id, kind, identifier) in nodes {
             node_factory.push_back((
                 EndpointDescriptor::EphemeralNode(*id, *kind),
@@ -381,6 +440,37 @@ impl ChooseEndpointDialog {
             ));
         }
 
+        // Offer the media roles before the applications, since they gather streams from all of them
+        for role in MediaRole::ALL {
+            let role_desc = EndpointDescriptor::MediaRole(role, self.list);
+            let role_name = media_role_name_with_tag(role);
+            if self.sonusmix_state.endpoints.contains_key(&role_desc)
+                || !self.search_text.is_empty()
+                    && fuzzy_matcher()
+                        .fuzzy_match(&role_name, &self.search_text)
+                        .is_none()
+            {
+                continue;
+            }
+            let stream_count = self
+                .sonusmix_state
+                .candidates
+                .iter()
+                .filter(|(_, kind, identifier)| {
+                    *kind == self.list && identifier.media_role() == Some(role)
+                })
+                .count();
+            application_factory.push_back((
+                role_desc,
+                role_name,
+                Some(format!(
+                    "All {} streams from any application, {stream_count} right now",
+                    role.name().to_lowercase()
+                )),
+                ChooseEndpointItemMode::Normal,
+            ));
+        }
+
         for (application, nodes) in applications {
             if application.is_active && nodes.is_empty() {
                 continue;
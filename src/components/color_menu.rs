@@ -0,0 +1,61 @@
+use relm4::actions::{RelmAction, RelmActionGroup};
+use relm4::gtk;
+
+use crate::state::{EndpointColor, EndpointDescriptor, SonusmixMsg, SonusmixReducer};
+
+relm4::new_action_group!(pub ColorMenuActionGroup, "color");
+relm4::new_stateless_action!(pub NoColorAction, ColorMenuActionGroup, "none");
+relm4::new_stateless_action!(pub BlueAction, ColorMenuActionGroup, "blue");
+relm4::new_stateless_action!(pub GreenAction, ColorMenuActionGroup, "green");
+relm4::new_stateless_action!(pub YellowAction, ColorMenuActionGroup, "yellow");
+relm4::new_stateless_action!(pub OrangeAction, ColorMenuActionGroup, "orange");
+relm4::new_stateless_action!(pub RedAction, ColorMenuActionGroup, "red");
+relm4::new_stateless_action!(pub PurpleAction, ColorMenuActionGroup, "purple");
+relm4::new_stateless_action!(pub BrownAction, ColorMenuActionGroup, "brown");
+
+/// CSS for the colors endpoints can be tagged with. This should be loaded once for the whole app.
+pub const COLOR_CSS: &str = "
+.endpoint-color-blue { box-shadow: inset 4px 0 #3584e4; }
+.endpoint-color-green { box-shadow: inset 4px 0 #33d17a; }
+.endpoint-color-yellow { box-shadow: inset 4px 0 #f6d32d; }
+.endpoint-color-orange { box-shadow: inset 4px 0 #ff7800; }
+.endpoint-color-red { box-shadow: inset 4px 0 #e01b24; }
+.endpoint-color-purple { box-shadow: inset 4px 0 #9141ac; }
+.endpoint-color-brown { box-shadow: inset 4px 0 #986a44; }
+";
+
+/// Register the actions used by the color submenu of an endpoint's menu on its menu button.
+pub fn register_color_menu_actions(
+    endpoint_desc: EndpointDescriptor,
+    widget: &impl AsRef<gtk::Widget>,
+) {
+    let mut group = RelmActionGroup::<ColorMenuActionGroup>::new();
+    let set_color = |color: Option<EndpointColor>| {
+        move |_: &gtk::gio::SimpleAction| {
+            SonusmixReducer::emit(SonusmixMsg::SetEndpointColor(endpoint_desc, color));
+        }
+    };
+    group.add_action(RelmAction::<NoColorAction>::new_stateless(set_color(None)));
+    group.add_action(RelmAction::<BlueAction>::new_stateless(set_color(Some(
+        EndpointColor::Blue,
+    ))));
+    group.add_action(RelmAction::<GreenAction>::new_stateless(set_color(Some(
+        EndpointColor::Green,
+    ))));
+    group.add_action(RelmAction::<YellowAction>::new_stateless(set_color(Some(
+        EndpointColor::Yellow,
+    ))));
+    group.add_action(RelmAction::<OrangeAction>::new_stateless(set_color(Some(
+        EndpointColor::Orange,
+    ))));
+    group.add_action(RelmAction::<RedAction>::new_stateless(set_color(Some(
+        EndpointColor::Red,
+    ))));
+    group.add_action(RelmAction::<PurpleAction>::new_stateless(set_color(Some(
+        EndpointColor::Purple,
+    ))));
+    group.add_action(RelmAction::<BrownAction>::new_stateless(set_color(Some(
+        EndpointColor::Brown,
+    ))));
+    group.register_for_widget(widget);
+}
@@ -9,8 +9,8 @@ use std::sync::Arc;
 use crate::pipewire_api::PortKind;
 use crate::state::settings::SonusmixSettings;
 use crate::state::{
-    Endpoint, EndpointDescriptor, LinkState, SonusmixMsg, SonusmixReducer, SonusmixState,
-    SONUSMIX_SETTINGS,
+    Endpoint, EndpointDescriptor, LinkSend, LinkState, MidiAction, SonusmixMsg, SonusmixReducer,
+    SonusmixState, SONUSMIX_SETTINGS,
 };
 
 pub struct ConnectEndpoints {
@@ -20,6 +20,10 @@ pub struct ConnectEndpoints {
     base_kind: PortKind,
     items: FactoryVecDeque<ConnectEndpointItem>,
     header_indices: Rc<Cell<[Option<i32>; 3]>>,
+    /// The endpoints shown and their names, so the items are only re-created when they change.
+    /// Otherwise they are updated in place, so that dragging a send level slider isn't
+    /// interrupted.
+    shown: Vec<(EndpointDescriptor, String)>,
 }
 
 #[derive(Debug)]
@@ -153,6 +157,7 @@ impl SimpleComponent for ConnectEndpoints {
             base_kind,
             items,
             header_indices: Rc::new(Cell::new([None; 3])),
+            shown: Vec::new(),
         };
         model.update_items();
 
@@ -170,19 +175,16 @@ impl SimpleComponent for ConnectEndpoints {
             }
             ConnectEndpointsMsg::UpdateSettings(settings) => {
                 self.settings = settings;
+                // Re-create the items so their sliders use the new settings
+                self.shown.clear();
+                self.update_items();
             }
             ConnectEndpointsMsg::ConnectionChanged((other_descriptor, msg)) => {
                 let (source, sink) = match self.base_kind {
                     PortKind::Source => (self.base_endpoint.descriptor, other_descriptor),
                     PortKind::Sink => (other_descriptor, self.base_endpoint.descriptor),
                 };
-                let connects_group = matches!(source, EndpointDescriptor::GroupNode(_))
-                    || matches!(sink, EndpointDescriptor::GroupNode(_));
-                let auto_lock = if connects_group {
-                    self.settings.lock_group_node_connections
-                } else {
-                    self.settings.lock_endpoint_connections
-                };
+                let auto_lock = self.settings.lock_new_link(source, sink);
                 let (msg, auto_lock_msg) = match msg {
                     ConnectEndpointAction::ConnectEndpoint => {
                         (SonusmixMsg::Link(source, sink), auto_lock.then_some(true))
@@ -194,6 +196,24 @@ impl SimpleComponent for ConnectEndpoints {
                     ConnectEndpointAction::SetEndpointLocked(locked) => {
                         (SonusmixMsg::SetLinkLocked(source, sink, locked), None)
                     }
+                    ConnectEndpointAction::LearnMidi => (
+                        SonusmixMsg::StartMidiLearn(MidiAction::Link(source, sink)),
+                        None,
+                    ),
+                    ConnectEndpointAction::SetSendVolume(volume) => (
+                        SonusmixMsg::SetLinkVolume(
+                            source,
+                            sink,
+                            self.settings.fader_curve.slider_to_volume(volume),
+                        ),
+                        None,
+                    ),
+                    ConnectEndpointAction::SetSendMute(muted) => {
+                        (SonusmixMsg::SetLinkMute(source, sink, muted), None)
+                    }
+                    ConnectEndpointAction::RemoveSend => {
+                        (SonusmixMsg::RemoveLinkSend(source, sink), None)
+                    }
                 };
                 SonusmixReducer::emit(msg);
                 if let Some(lock) = auto_lock_msg {
@@ -206,79 +226,108 @@ impl SimpleComponent for ConnectEndpoints {
 
 impl ConnectEndpoints {
     fn update_items(&mut self) {
-        let mut factory = self.items.guard();
-        factory.clear();
+        let mut candidates: Vec<&Endpoint> = Vec::new();
         let mut header_indices = [None; 3];
 
         if self.base_kind == PortKind::Sink {
             if !self.sonusmix_state.active_sources.is_empty() {
                 header_indices[0] = Some(0);
             }
-            for candidate in self
-                .sonusmix_state
-                .active_sources
-                .iter()
-                .filter_map(|id| self.sonusmix_state.endpoints.get(id))
-                .cloned()
-            {
-                factory.push_back((
-                    self.base_endpoint.descriptor,
-                    self.base_kind,
-                    candidate,
-                    self.sonusmix_state.clone(),
-                ));
-            }
+            candidates.extend(
+                self.sonusmix_state
+                    .active_sources
+                    .iter()
+                    .filter_map(|id| self.sonusmix_state.endpoints.get(id)),
+            );
         }
 
         if self.base_kind == PortKind::Source {
             if !self.sonusmix_state.active_sinks.is_empty() {
-                header_indices[1] = Some(factory.len() as i32);
-            }
-            for candidate in self
-                .sonusmix_state
-                .active_sinks
-                .iter()
-                .filter_map(|id| self.sonusmix_state.endpoints.get(id))
-                .cloned()
-            {
-                factory.push_back((
-                    self.base_endpoint.descriptor,
-                    self.base_kind,
-                    candidate,
-                    self.sonusmix_state.clone(),
-                ));
+                header_indices[1] = Some(candidates.len() as i32);
             }
+            candidates.extend(
+                self.sonusmix_state
+                    .active_sinks
+                    .iter()
+                    .filter_map(|id| self.sonusmix_state.endpoints.get(id)),
+            );
         }
 
         if !self.sonusmix_state.group_nodes.is_empty() {
-            header_indices[2] = Some(factory.len() as i32);
+            header_indices[2] = Some(candidates.len() as i32);
         }
-        for candidate in self
-            .sonusmix_state
-            .group_nodes
-            .keys()
-            .filter_map(|id| {
-                let descriptor = EndpointDescriptor::GroupNode(*id);
-                (descriptor != self.base_endpoint.descriptor)
-                    .then(|| self.sonusmix_state.endpoints.get(&descriptor))
-                    .flatten()
+        candidates.extend(self.sonusmix_state.group_nodes.keys().filter_map(|id| {
+            let descriptor = EndpointDescriptor::GroupNode(*id);
+            (descriptor != self.base_endpoint.descriptor)
+                .then(|| self.sonusmix_state.endpoints.get(&descriptor))
+                .flatten()
+        }));
+
+        let shown: Vec<(EndpointDescriptor, String)> = candidates
+            .iter()
+            .map(|endpoint| {
+                (
+                    endpoint.descriptor,
+                    endpoint.custom_or_display_name().to_owned(),
+                )
             })
-            .cloned()
-        {
+            .collect();
+        if shown == self.shown {
+            for index in 0..self.items.len() {
+                self.items.send(
+                    index,
+                    ConnectEndpointItemMsg::UpdateState(self.sonusmix_state.clone()),
+                );
+            }
+            return;
+        }
+
+        let mut factory = self.items.guard();
+        factory.clear();
+        for candidate in candidates {
             factory.push_back((
                 self.base_endpoint.descriptor,
                 self.base_kind,
-                candidate,
+                candidate.clone(),
                 self.sonusmix_state.clone(),
+                self.settings.clone(),
             ));
         }
+        drop(factory);
+        self.shown = shown;
         self.header_indices.set(header_indices);
     }
 }
 
 struct ConnectEndpointItem {
     candidate_endpoint: Endpoint,
+    source: EndpointDescriptor,
+    sink: EndpointDescriptor,
     link_state: Option<LinkState>,
+    send: Option<LinkSend>,
+    settings: SonusmixSettings,
+}
+
+impl ConnectEndpointItem {
+    fn update_link(&mut self, sonusmix_state: &SonusmixState) {
+        let link = sonusmix_state
+            .links
+            .iter()
+            .find(|link| link.start == self.source && link.end == self.sink);
+        self.link_state = link.map(|link| link.state);
+        self.send = link.and_then(|link| link.send);
+    }
+
+    /// Whether the link is connected enough to have a send level.
+    fn can_send(&self) -> bool {
+        self.link_state
+            .is_some_and(|state| state.is_connected() != Some(false))
+    }
+}
+
+#[derive(Debug)]
+enum ConnectEndpointItemMsg {
+    UpdateState(Arc<SonusmixState>),
 }
 
 #[derive(Debug)]
@@ -286,12 +335,24 @@ pub enum ConnectEndpointAction {
     ConnectEndpoint,
     DisconnectEndpoint,
     SetEndpointLocked(bool),
+    /// Map the next MIDI control that is used to toggling this link
+    LearnMidi,
+    /// The position of the send level slider
+    SetSendVolume(f64),
+    SetSendMute(bool),
+    RemoveSend,
 }
 
 #[relm4::factory]
 impl FactoryComponent for ConnectEndpointItem {
-    type Init = (EndpointDescriptor, PortKind, Endpoint, Arc<SonusmixState>);
-    type Input = Infallible;
+    type Init = (
+        EndpointDescriptor,
+        PortKind,
+        Endpoint,
+        Arc<SonusmixState>,
+        SonusmixSettings,
+    );
+    type Input = ConnectEndpointItemMsg;
     type Output = (EndpointDescriptor, ConnectEndpointAction);
     type CommandOutput = ();
     type ParentWidget = gtk::ListBox;
@@ -305,10 +366,14 @@ impl FactoryComponent for ConnectEndpointItem {
             gtk::ToggleButton {
                 add_css_class: "flat",
 
+                #[watch]
                 set_sensitive: self.link_state != Some(LinkState::PartiallyConnected),
+                #[watch]
                 set_active: self.link_state.map(|link| link.is_locked()).unwrap_or(false),
+                #[watch]
                 set_icon_name: if link_lock_button.is_active()
                     { "changes-prevent-symbolic" } else { "changes-allow-symbolic" },
+                #[watch]
                 set_tooltip: if link_lock_button.is_active()
                 {
                     "Allow this link to be changed outside of Sonusmix"
@@ -326,7 +391,10 @@ impl FactoryComponent for ConnectEndpointItem {
 
             gtk::CheckButton {
                 set_label: Some(self.candidate_endpoint.custom_or_display_name()),
+                #[watch]
+                #[block_signal(endpoint_toggled_handler)]
                 set_active: self.link_state.and_then(|link| link.is_connected()).unwrap_or(false),
+                #[watch]
                 set_inconsistent: self.link_state.map(|link| link.is_connected().is_none()).unwrap_or(false),
 
                 connect_toggled[sender, descriptor = self.candidate_endpoint.descriptor] => move |check| {
@@ -336,16 +404,87 @@ impl FactoryComponent for ConnectEndpointItem {
                         let _ = sender.output((descriptor, ConnectEndpointAction::DisconnectEndpoint));
                     }
                 } @endpoint_toggled_handler
-            }
+            },
+
+            gtk::Box {
+                set_orientation: gtk::Orientation::Horizontal,
+                set_hexpand: true,
+                set_halign: gtk::Align::End,
+
+                gtk::Scale {
+                    set_width_request: 100,
+                    set_range: (0.0, self.settings.slider_limit()),
+                    set_increments: (1.0, 5.0),
+                    add_mark: (
+                        self.settings.fader_curve.volume_to_slider(1.0),
+                        gtk::PositionType::Bottom,
+                        None,
+                    ),
+                    set_tooltip: "Send level of this link",
+                    #[watch]
+                    set_sensitive: self.can_send(),
+                    #[watch]
+                    #[block_signal(send_volume_handler)]
+                    set_value: self.settings.fader_curve.volume_to_slider(
+                        self.send.map_or(1.0, |send| send.volume),
+                    ),
+
+                    connect_value_changed[sender, descriptor = self.candidate_endpoint.descriptor] => move |scale| {
+                        let _ = sender.output((descriptor, ConnectEndpointAction::SetSendVolume(scale.value())));
+                    } @send_volume_handler
+                },
+                #[name(send_mute_button)]
+                gtk::ToggleButton {
+                    add_css_class: "flat",
+                    #[watch]
+                    set_sensitive: self.can_send(),
+                    #[watch]
+                    #[block_signal(send_mute_handler)]
+                    set_active: self.send.is_some_and(|send| send.muted),
+                    #[watch]
+                    set_icon_name: if send_mute_button.is_active() {
+                        "audio-volume-muted-symbolic"
+                    } else {
+                        "audio-volume-high-symbolic"
+                    },
+                    set_tooltip: "Mute this link",
+
+                    connect_toggled[sender, descriptor = self.candidate_endpoint.descriptor] => move |button| {
+                        let _ = sender.output((descriptor, ConnectEndpointAction::SetSendMute(button.is_active())));
+                    } @send_mute_handler
+                },
+                gtk::Button {
+                    add_css_class: "flat",
+                    set_icon_name: "edit-undo-symbolic",
+                    set_tooltip: "Connect directly at full level",
+                    #[watch]
+                    set_visible: self.send.is_some(),
+
+                    connect_clicked[sender, descriptor = self.candidate_endpoint.descriptor] => move |_| {
+                        let _ = sender.output((descriptor, ConnectEndpointAction::RemoveSend));
+                    },
+                },
+            },
+
+            gtk::Button {
+                add_css_class: "flat",
+                set_icon_name: "input-dialpad-symbolic",
+                set_tooltip: "Map a MIDI control to toggle this link",
+
+                connect_clicked[sender, descriptor = self.candidate_endpoint.descriptor] => move |_| {
+                    let _ = sender.output((descriptor, ConnectEndpointAction::LearnMidi));
+                },
+            },
         }
     }
 
     fn init_model(
-        (base_endpoint, base_kind, candidate_endpoint, sonusmix_state): (
+        (base_endpoint, base_kind, candidate_endpoint, sonusmix_state, settings): (
             EndpointDescriptor,
             PortKind,
             Endpoint,
             Arc<SonusmixState>,
+            SonusmixSettings,
         ),
         _index: &DynamicIndex,
         _sender: FactorySender<Self>,
@@ -356,15 +495,23 @@ impl FactoryComponent for ConnectEndpointItem {
             (candidate_endpoint.descriptor, base_endpoint)
         };
 
-        let link_state = sonusmix_state
-            .links
-            .iter()
-            .find(|link| link.start == source && link.end == sink)
-            .map(|link| link.state);
-
-        Self {
+        let mut item = Self {
             candidate_endpoint,
-            link_state,
+            source,
+            sink,
+            link_state: None,
+            send: None,
+            settings,
+        };
+        item.update_link(&sonusmix_state);
+        item
+    }
+
+    fn update(&mut self, msg: ConnectEndpointItemMsg, _sender: FactorySender<Self>) {
+        match msg {
+            ConnectEndpointItemMsg::UpdateState(sonusmix_state) => {
+                self.update_link(&sonusmix_state)
+            }
         }
     }
 }
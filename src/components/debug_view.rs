@@ -1,22 +1,42 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
 use std::convert::Infallible;
+use std::rc::Rc;
 use std::sync::Arc;
 
+use gtk::gio;
 use gtk::glib::Propagation;
+use log::error;
 use relm4::gtk::prelude::*;
 use relm4::prelude::*;
+use serde_json::{json, Value};
 
-use crate::state::{SonusmixReducer, SonusmixState};
+use crate::pipewire_api::Graph;
+use crate::rpc::StateView;
+use crate::state::{SonusmixReducer, SonusmixState, SONUSMIX_SETTINGS};
 
+/// Shows the Pipewire graph and the Sonusmix state as a searchable tree.
 pub struct DebugView {
+    window: gtk::Window,
     sonusmix_state: Arc<SonusmixState>,
+    graph: Arc<Graph>,
     visible: bool,
-    state_text_buffer: gtk::TextBuffer,
+    search: String,
+    /// The graph and state as JSON, which the tree is built from
+    document: Value,
+    tree: gtk::Box,
+    /// Paths of the expanded rows, so they stay expanded when the tree is rebuilt
+    expanded: Rc<RefCell<HashSet<String>>>,
 }
 
 #[derive(Debug, Clone)]
 pub enum DebugViewMsg {
     UpdateState(Arc<SonusmixState>),
+    UpdateGraph(Arc<Graph>),
     SetVisible(bool),
+    Search(String),
+    CopyJson,
+    ExportJson,
 }
 
 #[relm4::component(pub)]
@@ -27,6 +47,7 @@ impl SimpleComponent for DebugView {
 
     view! {
         gtk::Window {
+            set_title: Some("Debug View"),
             #[watch]
             set_visible: model.visible,
             set_default_size: (800, 600),
@@ -36,30 +57,65 @@ impl SimpleComponent for DebugView {
                 Propagation::Stop
             },
 
-            gtk::ScrolledWindow {
+            gtk::Box {
+                set_orientation: gtk::Orientation::Vertical,
                 set_margin_all: 8,
-                set_policy: (gtk::PolicyType::Automatic, gtk::PolicyType::Automatic),
+                set_spacing: 8,
 
-                gtk::TextView::with_buffer(&model.state_text_buffer) {
-                    set_editable: false,
-                    set_monospace: true,
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Horizontal,
+                    set_spacing: 8,
+
+                    gtk::SearchEntry {
+                        set_hexpand: true,
+                        set_placeholder_text: Some("Search the graph and state…"),
+                        connect_search_changed[sender] => move |entry| {
+                            sender.input(DebugViewMsg::Search(entry.text().to_string()));
+                        },
+                    },
+                    gtk::Button {
+                        set_label: "Copy JSON",
+                        set_tooltip: "Copy the graph and state as JSON, for bug reports",
+                        connect_clicked => DebugViewMsg::CopyJson,
+                    },
+                    gtk::Button {
+                        set_label: "Export JSON…",
+                        set_tooltip: "Save the graph and state as JSON, for bug reports",
+                        connect_clicked => DebugViewMsg::ExportJson,
+                    },
                 },
-            }
+
+                gtk::ScrolledWindow {
+                    set_vexpand: true,
+                    set_policy: (gtk::PolicyType::Automatic, gtk::PolicyType::Automatic),
+
+                    #[local_ref]
+                    tree -> gtk::Box {
+                        set_orientation: gtk::Orientation::Vertical,
+                    },
+                },
+            },
         }
     }
 
     fn init(_init: (), root: Self::Root, sender: ComponentSender<Self>) -> ComponentParts<Self> {
         let sonusmix_state =
             SonusmixReducer::subscribe(sender.input_sender(), DebugViewMsg::UpdateState);
-
-        let state_text_buffer = gtk::TextBuffer::new(None);
+        let graph =
+            SonusmixReducer::subscribe_graph(sender.input_sender(), DebugViewMsg::UpdateGraph);
 
         let model = DebugView {
+            window: root.clone(),
             sonusmix_state,
+            graph,
             visible: false,
-            state_text_buffer,
+            search: String::new(),
+            document: Value::Null,
+            tree: gtk::Box::default(),
+            expanded: Rc::default(),
         };
 
+        let tree = &model.tree;
         let widgets = view_output!();
 
         ComponentParts { model, widgets }
@@ -70,22 +126,226 @@ impl SimpleComponent for DebugView {
             DebugViewMsg::UpdateState(state) => {
                 self.sonusmix_state = state;
                 if self.visible {
-                    self.update_text();
+                    self.update_document();
+                }
+            }
+            DebugViewMsg::UpdateGraph(graph) => {
+                self.graph = graph;
+                if self.visible {
+                    self.update_document();
                 }
             }
             DebugViewMsg::SetVisible(visible) => {
                 self.visible = visible;
                 if self.visible {
-                    self.update_text();
+                    self.update_document();
                 }
             }
+            DebugViewMsg::Search(search) => {
+                self.search = search;
+                self.rebuild_tree();
+            }
+            DebugViewMsg::CopyJson => {
+                self.update_document();
+                if let Some(display) = gtk::gdk::Display::default() {
+                    display.clipboard().set_text(&self.json());
+                }
+            }
+            DebugViewMsg::ExportJson => {
+                self.update_document();
+                let json = self.json();
+                let dialog = gtk::FileDialog::builder()
+                    .title("Export Debug Info")
+                    .initial_name("sonusmix-debug.json")
+                    .build();
+                dialog.save(
+                    Some(&self.window),
+                    None::<&gio::Cancellable>,
+                    move |result| {
+                        let Some(path) = result.ok().and_then(|file| file.path()) else {
+                            return;
+                        };
+                        if let Err(err) = std::fs::write(&path, json) {
+                            error!("Failed to export debug info to {}: {err}", path.display());
+                        }
+                    },
+                );
+            }
         }
     }
 }
 
 impl DebugView {
-    fn update_text(&mut self) {
-        self.state_text_buffer
-            .set_text(&format!("{:#?}", self.sonusmix_state));
+    /// Rebuild the JSON document, and the tree if it changed.
+    fn update_document(&mut self) {
+        let settings = { SONUSMIX_SETTINGS.read().clone() };
+        let resolutions = self.sonusmix_state.node_resolutions(&self.graph, &settings);
+
+        let mut graph = serde_json::to_value(self.graph.as_ref())
+            .unwrap_or_else(|err| json!({ "error": err.to_string() }));
+        // Explain which endpoints each node resolves to, or why it doesn't
+        if let Some(nodes) = graph.get_mut("nodes").and_then(Value::as_object_mut) {
+            for (id, node) in nodes {
+                let descriptions: Vec<String> = id
+                    .parse()
+                    .ok()
+                    .and_then(|id: u32| resolutions.get(&id))
+                    .into_iter()
+                    .flatten()
+                    .map(|resolution| self.sonusmix_state.describe_resolution(resolution))
+                    .collect();
+                if let Some(node) = node.as_object_mut() {
+                    node.insert("resolution".to_owned(), json!(descriptions));
+                }
+            }
+        }
+        let state = serde_json::to_value(StateView::new(&self.sonusmix_state))
+            .unwrap_or_else(|err| json!({ "error": err.to_string() }));
+
+        let document = json!({ "graph": graph, "state": state });
+        if document != self.document {
+            self.document = document;
+            self.rebuild_tree();
+        }
+    }
+
+    fn json(&self) -> String {
+        serde_json::to_string_pretty(&self.document).unwrap_or_default()
+    }
+
+    fn rebuild_tree(&self) {
+        while let Some(child) = self.tree.first_child() {
+            self.tree.remove(&child);
+        }
+        let search = self.search.to_lowercase();
+        append_children(&self.tree, "", &self.document, &search, &self.expanded);
+    }
+}
+
+/// Add a row for each child of an object or array that matches the search.
+fn append_children(
+    parent: &gtk::Box,
+    path: &str,
+    value: &Value,
+    search: &str,
+    expanded: &Rc<RefCell<HashSet<String>>>,
+) {
+    let children: Vec<(String, &Value)> = match value {
+        Value::Object(map) => map
+            .iter()
+            .map(|(key, value)| (key.clone(), value))
+            .collect(),
+        Value::Array(values) => values
+            .iter()
+            .enumerate()
+            .map(|(index, value)| (index.to_string(), value))
+            .collect(),
+        _ => Vec::new(),
+    };
+    for (key, child) in children {
+        if !matches_search(&key, child, search) {
+            continue;
+        }
+        // Show everything inside of a row whose key matches
+        let search = if key.to_lowercase().contains(search) {
+            ""
+        } else {
+            search
+        };
+        parent.append(&tree_row(
+            &format!("{path}/{key}"),
+            &key,
+            child,
+            search,
+            expanded,
+        ));
     }
 }
+
+fn tree_row(
+    path: &str,
+    key: &str,
+    value: &Value,
+    search: &str,
+    expanded: &Rc<RefCell<HashSet<String>>>,
+) -> gtk::Widget {
+    let (Value::Object(_) | Value::Array(_)) = value else {
+        let value = match value {
+            Value::String(string) => string.clone(),
+            value => value.to_string(),
+        };
+        return gtk::Label::builder()
+            .label(format!("{key}: {value}"))
+            .xalign(0.0)
+            .wrap(true)
+            .selectable(true)
+            .build()
+            .upcast();
+    };
+
+    let label = match summary(value) {
+        Some(summary) => format!("{key}: {summary}"),
+        None => key.to_owned(),
+    };
+    let children = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .margin_start(16)
+        .build();
+    let expander = gtk::Expander::builder()
+        .label(label)
+        .child(&children)
+        .build();
+
+    // Children are only built once the row is expanded, since the graph can be very large. Rows
+    // are always expanded while searching, so that the matches can be seen.
+    let built = Rc::new(RefCell::new(false));
+    if !search.is_empty() || expanded.borrow().contains(path) {
+        append_children(&children, path, value, search, expanded);
+        *built.borrow_mut() = true;
+        expander.set_expanded(true);
+    }
+    let path = path.to_owned();
+    let value = value.clone();
+    let search = search.to_owned();
+    let expanded = expanded.clone();
+    expander.connect_expanded_notify(move |expander| {
+        if search.is_empty() {
+            if expander.is_expanded() {
+                expanded.borrow_mut().insert(path.clone());
+            } else {
+                expanded.borrow_mut().remove(&path);
+            }
+        }
+        if expander.is_expanded() && !*built.borrow() {
+            append_children(&children, &path, &value, &search, &expanded);
+            *built.borrow_mut() = true;
+        }
+    });
+
+    expander.upcast()
+}
+
+/// Whether a row or any of its children contain the search text.
+fn matches_search(key: &str, value: &Value, search: &str) -> bool {
+    if search.is_empty() || key.to_lowercase().contains(search) {
+        return true;
+    }
+    match value {
+        Value::Object(map) => map
+            .iter()
+            .any(|(key, value)| matches_search(key, value, search)),
+        Value::Array(values) => values.iter().any(|value| matches_search("", value, search)),
+        Value::String(string) => string.to_lowercase().contains(search),
+        value => value.to_string().contains(search),
+    }
+}
+
+/// A short name for an object, shown next to its key so objects can be told apart without
+/// expanding them.
+fn summary(value: &Value) -> Option<&str> {
+    value
+        .get("name")
+        .or_else(|| value.pointer("/props/node.name"))
+        .or_else(|| value.get("display_name"))
+        .and_then(Value::as_str)
+}
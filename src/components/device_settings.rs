@@ -0,0 +1,246 @@
+use relm4::prelude::*;
+use relm4::{factory::FactoryVecDeque, gtk::prelude::*};
+
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use crate::state::{
+    DeviceOption, EndpointDescriptor, EndpointDevice, SonusmixMsg, SonusmixReducer, SonusmixState,
+};
+
+pub struct DeviceSettings {
+    endpoint_desc: EndpointDescriptor,
+    device: Option<EndpointDevice>,
+    profiles: FactoryVecDeque<DeviceOptionItem>,
+    routes: FactoryVecDeque<DeviceOptionItem>,
+}
+
+#[derive(Debug)]
+pub enum DeviceSettingsMsg {
+    UpdateState(Arc<SonusmixState>),
+    ProfileActivated(i32),
+    RouteActivated(i32),
+    SetProfileLocked(bool),
+}
+
+#[relm4::component(pub)]
+impl SimpleComponent for DeviceSettings {
+    type Init = EndpointDescriptor;
+    type Input = DeviceSettingsMsg;
+    type Output = Infallible;
+
+    view! {
+        #[root]
+        gtk::Popover {
+            set_autohide: true,
+
+            gtk::Box {
+                set_orientation: gtk::Orientation::Vertical,
+                set_spacing: 4,
+
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Horizontal,
+                    set_spacing: 8,
+
+                    gtk::Label {
+                        set_hexpand: true,
+                        set_halign: gtk::Align::Start,
+                        add_css_class: "heading",
+                        set_label: "Profile",
+                    },
+                    #[name(profile_lock_button)]
+                    gtk::ToggleButton {
+                        add_css_class: "flat",
+
+                        #[watch]
+                        #[block_signal(profile_lock_handler)]
+                        set_active: model.device.as_ref().map(|device| device.profile_locked).unwrap_or(false),
+                        #[watch]
+                        set_icon_name: if profile_lock_button.is_active()
+                            { "changes-prevent-symbolic" } else { "changes-allow-symbolic" },
+                        #[watch]
+                        set_tooltip: if profile_lock_button.is_active()
+                        {
+                            "Allow the profile to be changed outside of Sonusmix"
+                        } else {
+                            "Restore this profile if it is changed outside of Sonusmix"
+                        },
+
+                        connect_clicked[sender] => move |button| {
+                            sender.input(DeviceSettingsMsg::SetProfileLocked(button.is_active()));
+                        } @profile_lock_handler
+                    },
+                },
+                gtk::ScrolledWindow {
+                    set_policy: (gtk::PolicyType::Never, gtk::PolicyType::Automatic),
+                    set_propagate_natural_height: true,
+                    set_max_content_height: 300,
+
+                    #[local_ref]
+                    profile_list -> gtk::ListBox {
+                        set_selection_mode: gtk::SelectionMode::None,
+                        set_show_separators: true,
+
+                        connect_row_activated[sender] => move |_, row| {
+                            sender.input(DeviceSettingsMsg::ProfileActivated(row.index()));
+                        },
+                    },
+                },
+                gtk::Label {
+                    set_halign: gtk::Align::Start,
+                    set_margin_top: 4,
+                    add_css_class: "heading",
+                    set_label: "Port",
+                    #[watch]
+                    set_visible: !model.routes.is_empty(),
+                },
+                #[local_ref]
+                route_list -> gtk::ListBox {
+                    set_selection_mode: gtk::SelectionMode::None,
+                    set_show_separators: true,
+                    #[watch]
+                    set_visible: !model.routes.is_empty(),
+
+                    connect_row_activated[sender] => move |_, row| {
+                        sender.input(DeviceSettingsMsg::RouteActivated(row.index()));
+                    },
+                },
+            }
+        }
+    }
+
+    fn init(
+        endpoint_desc: EndpointDescriptor,
+        root: Self::Root,
+        sender: ComponentSender<Self>,
+    ) -> ComponentParts<Self> {
+        let sonusmix_state =
+            SonusmixReducer::subscribe(sender.input_sender(), DeviceSettingsMsg::UpdateState);
+
+        let profiles = FactoryVecDeque::builder()
+            .launch(gtk::ListBox::default())
+            .forward(sender.input_sender(), |msg| match msg {});
+        let routes = FactoryVecDeque::builder()
+            .launch(gtk::ListBox::default())
+            .forward(sender.input_sender(), |msg| match msg {});
+
+        let mut model = Self {
+            endpoint_desc,
+            device: None,
+            profiles,
+            routes,
+        };
+        model.update_device(&sonusmix_state);
+
+        let profile_list = model.profiles.widget();
+        let route_list = model.routes.widget();
+        let widgets = view_output!();
+
+        ComponentParts { model, widgets }
+    }
+
+    fn update(&mut self, msg: DeviceSettingsMsg, _sender: ComponentSender<Self>) {
+        match msg {
+            DeviceSettingsMsg::UpdateState(sonusmix_state) => {
+                self.update_device(&sonusmix_state);
+            }
+            DeviceSettingsMsg::ProfileActivated(row) => {
+                if let Some(profile) = self.profiles.get(row as usize) {
+                    if profile.option.available && !profile.active {
+                        SonusmixReducer::emit(SonusmixMsg::SetDeviceProfile(
+                            self.endpoint_desc,
+                            profile.option.index,
+                        ));
+                    }
+                }
+            }
+            DeviceSettingsMsg::RouteActivated(row) => {
+                if let Some(route) = self.routes.get(row as usize) {
+                    if route.option.available && !route.active {
+                        SonusmixReducer::emit(SonusmixMsg::SetDeviceRoute(
+                            self.endpoint_desc,
+                            route.option.index,
+                        ));
+                    }
+                }
+            }
+            DeviceSettingsMsg::SetProfileLocked(locked) => {
+                SonusmixReducer::emit(SonusmixMsg::SetDeviceProfileLocked(
+                    self.endpoint_desc,
+                    locked,
+                ));
+            }
+        }
+    }
+}
+
+impl DeviceSettings {
+    fn update_device(&mut self, sonusmix_state: &SonusmixState) {
+        let device = sonusmix_state
+            .endpoints
+            .get(&self.endpoint_desc)
+            .and_then(|endpoint| endpoint.device.clone());
+        if device == self.device {
+            return;
+        }
+        self.device = device;
+
+        let mut profiles = self.profiles.guard();
+        let mut routes = self.routes.guard();
+        profiles.clear();
+        routes.clear();
+        let Some(device) = &self.device else {
+            return;
+        };
+        for profile in &device.profiles {
+            profiles.push_back((
+                profile.clone(),
+                device.active_profile == Some(profile.index),
+            ));
+        }
+        for route in &device.routes {
+            routes.push_back((route.clone(), device.active_route == Some(route.index)));
+        }
+    }
+}
+
+struct DeviceOptionItem {
+    option: DeviceOption,
+    active: bool,
+}
+
+#[relm4::factory]
+impl FactoryComponent for DeviceOptionItem {
+    type Init = (DeviceOption, bool);
+    type Input = Infallible;
+    type Output = Infallible;
+    type CommandOutput = ();
+    type ParentWidget = gtk::ListBox;
+
+    view! {
+        gtk::Box {
+            set_orientation: gtk::Orientation::Horizontal,
+            set_spacing: 8,
+            set_margin_all: 4,
+            set_sensitive: self.option.available,
+
+            gtk::Image {
+                set_icon_name: Some("object-select-symbolic"),
+                set_opacity: if self.active { 1.0 } else { 0.0 },
+            },
+            gtk::Label {
+                set_halign: gtk::Align::Start,
+                set_label: &self.option.description,
+                set_tooltip_text: (!self.option.available).then_some("Unavailable"),
+            },
+        }
+    }
+
+    fn init_model(
+        (option, active): (DeviceOption, bool),
+        _index: &DynamicIndex,
+        _sender: FactorySender<Self>,
+    ) -> Self {
+        Self { option, active }
+    }
+}
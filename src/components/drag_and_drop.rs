@@ -0,0 +1,31 @@
+use relm4::gtk::{self, gdk, glib, prelude::*};
+
+use crate::state::EndpointDescriptor;
+
+/// A controller that lets an endpoint be dragged from the widget it is added to.
+pub fn endpoint_drag_source(endpoint_desc: EndpointDescriptor) -> gtk::DragSource {
+    let drag_source = gtk::DragSource::new();
+    drag_source.set_actions(gdk::DragAction::MOVE);
+    drag_source.connect_prepare(move |_, _, _| {
+        let data = serde_json::to_string(&endpoint_desc).expect("descriptors can be serialized");
+        Some(gdk::ContentProvider::for_value(&data.to_value()))
+    });
+    drag_source
+}
+
+/// A controller that calls `dropped` with any endpoint dropped on the widget it is added to.
+pub fn endpoint_drop_target(dropped: impl Fn(EndpointDescriptor) + 'static) -> gtk::DropTarget {
+    let drop_target = gtk::DropTarget::new(glib::Type::STRING, gdk::DragAction::MOVE);
+    drop_target.connect_drop(move |_, value, _, _| {
+        let Some(endpoint_desc) = value
+            .get::<String>()
+            .ok()
+            .and_then(|data| serde_json::from_str(&data).ok())
+        else {
+            return false;
+        };
+        dropped(endpoint_desc);
+        true
+    });
+    drop_target
+}
@@ -1,5 +1,6 @@
 use std::sync::Arc;
 
+use fuzzy_matcher::FuzzyMatcher;
 use gtk::glib::Propagation;
 use relm4::actions::RelmAction;
 use relm4::factory::FactoryView;
@@ -9,18 +10,58 @@ use relm4::{actions::RelmActionGroup, gtk::prelude::*};
 use crate::pipewire_api::PortKind;
 use crate::state::settings::SonusmixSettings;
 use crate::state::{
-    Endpoint as PwEndpoint, EndpointDescriptor, SonusmixMsg, SonusmixReducer, SonusmixState,
-    SONUSMIX_SETTINGS,
+    Endpoint as PwEndpoint, EndpointColor, EndpointDescriptor, PushToTalkMode, SonusmixMsg,
+    SonusmixReducer, SonusmixState, SONUSMIX_SETTINGS,
 };
 
+use super::application_streams::ApplicationStreams;
+use super::choose_endpoint_dialog::{
+    fuzzy_matcher, ChooseEndpointDialogMsg, CHOOSE_ENDPOINT_BROKER,
+};
+use super::color_menu::{
+    register_color_menu_actions, BlueAction, BrownAction, GreenAction, NoColorAction, OrangeAction,
+    PurpleAction, RedAction, YellowAction,
+};
 use super::connect_endpoints::ConnectEndpoints;
+use super::device_settings::DeviceSettings;
+use super::drag_and_drop::{endpoint_drag_source, endpoint_drop_target};
+use super::lock_fights::LockFights;
+use super::loopback_settings::LoopbackSettings;
+use super::midi_menu::{
+    register_midi_menu_actions, ForgetMappingsAction, LearnMuteAction, LearnPushToTalkAction,
+    LearnVolumeAction, LearnVolumeLockAction,
+};
+use super::push_to_talk_menu::{
+    register_push_to_talk_menu_actions, DisablePushToTalkAction, PushToMuteAction, PushToTalkAction,
+};
+use super::recording_button::RecordingButton;
+use super::volume_limits::VolumeLimits;
+use super::volume_spin_button::attach_volume_spin_button;
 
 pub struct Endpoint {
     endpoint: PwEndpoint,
+    list: PortKind,
+    /// Whether the endpoint is soloed to the cue endpoint
+    soloed: bool,
+    /// Whether the endpoint is the cue endpoint
+    is_cue: bool,
+    /// Whether a cue endpoint has been chosen, which sources can only be soloed to if it has
+    has_cue: bool,
     settings: SonusmixSettings,
     renaming: bool,
     custom_name_buffer: gtk::EntryBuffer,
+    choosing_icon: bool,
+    custom_icon_buffer: gtk::EntryBuffer,
+    filter: String,
     connect_endpoints: Controller<ConnectEndpoints>,
+    loopback_settings: Option<Controller<LoopbackSettings>>,
+    application_streams: Option<Controller<ApplicationStreams>>,
+    device_settings: Controller<DeviceSettings>,
+    volume_limits: Controller<VolumeLimits>,
+    recording_button: Option<Controller<RecordingButton>>,
+    lock_fights: Controller<LockFights>,
+    /// Explains which programs are fighting over the endpoint's locks, if any are
+    lock_fight_tooltip: Option<String>,
     details_short: String,
     details_long: String,
 }
@@ -31,6 +72,31 @@ impl Endpoint {
     }
 }
 
+/// Names the programs fighting over an endpoint's locks, if any are.
+fn lock_fight_tooltip(state: &SonusmixState, endpoint_desc: EndpointDescriptor) -> Option<String> {
+    let mut clients: Vec<&str> = state
+        .lock_fights(endpoint_desc)
+        .map(|(_, backoff)| backoff.client.as_deref().unwrap_or("another program"))
+        .collect();
+    if clients.is_empty() {
+        return None;
+    }
+    clients.sort_unstable();
+    clients.dedup();
+    Some(format!(
+        "Fighting with {} over this endpoint's locks",
+        clients.join(", ")
+    ))
+}
+
+/// Whether an endpoint should be shown while the main page is filtered.
+pub fn matches_filter(endpoint: &PwEndpoint, filter: &str) -> bool {
+    filter.is_empty()
+        || fuzzy_matcher()
+            .fuzzy_match(endpoint.custom_or_display_name(), filter)
+            .is_some()
+}
+
 #[derive(Debug, Clone)]
 pub enum EndpointMsg {
     UpdateState(Arc<SonusmixState>),
@@ -38,11 +104,21 @@ pub enum EndpointMsg {
     Volume(f64),
     ToggleMute,
     ToggleLocked,
+    /// Solo a source, or use a sink as the cue endpoint
+    ToggleSolo,
     Remove,
+    /// Choose a node or application to move a placeholder endpoint to
+    StartReplace,
     StartRename,
     /// true if confirmed, false if cancelled
     FinishRename(bool),
     ResetName,
+    StartChooseIcon,
+    /// true if confirmed, false if cancelled
+    FinishChooseIcon(bool),
+    ResetIcon,
+    /// Only show the endpoint if it matches the filter
+    Filter(String),
 }
 
 #[derive(Debug, Clone)]
@@ -52,6 +128,8 @@ relm4::new_action_group!(EndpointMenuActionGroup, "endpoint-menu");
 relm4::new_stateless_action!(RemoveAction, EndpointMenuActionGroup, "remove");
 relm4::new_stateless_action!(RenameAction, EndpointMenuActionGroup, "rename");
 relm4::new_stateless_action!(ResetNameAction, EndpointMenuActionGroup, "reset-name");
+relm4::new_stateless_action!(ChangeIconAction, EndpointMenuActionGroup, "change-icon");
+relm4::new_stateless_action!(ResetIconAction, EndpointMenuActionGroup, "reset-icon");
 
 #[relm4::factory(pub)]
 impl FactoryComponent for Endpoint {
@@ -67,12 +145,18 @@ impl FactoryComponent for Endpoint {
             set_orientation: gtk::Orientation::Horizontal,
             set_spacing: 8,
             set_margin_all: 4,
+            #[watch]
+            set_visible: matches_filter(&self.endpoint, &self.filter),
+            #[watch]
+            set_css_classes: self.endpoint.color.map(EndpointColor::css_class).as_slice(),
 
             gtk::Box {
                 set_hexpand: true,
                 set_orientation: gtk::Orientation::Vertical,
                 set_spacing: 10,
 
+                // The endpoint can be dragged by its header to reorder it
+                #[name(header)]
                 gtk::Box {
                     set_orientation: gtk::Orientation::Horizontal,
 
@@ -96,7 +180,7 @@ impl FactoryComponent for Endpoint {
                             #[watch]
                             set_pixel_size: icon_view.pixel_size().max(24),
                             #[watch]
-                            set_icon_name: Some(&self.endpoint.icon_name),
+                            set_icon_name: Some(self.endpoint.custom_or_default_icon_name()),
                         }
                     },
 
@@ -120,6 +204,27 @@ impl FactoryComponent for Endpoint {
                                 connect_leave => EndpointMsg::FinishRename(false),
                             }
                         }
+                    } else if self.choosing_icon {
+                        gtk::Entry::with_buffer(&self.custom_icon_buffer) {
+                            set_placeholder_text: Some("Icon name"),
+                            connect_map => |entry| { entry.grab_focus(); },
+                            connect_activate => EndpointMsg::FinishChooseIcon(true),
+
+                            // Add an event controller to cancel choosing the icon on Esc
+                            add_controller = gtk::EventControllerKey {
+                                connect_key_pressed[sender] => move |_, key, _, _| {
+                                    if key == gtk::gdk::Key::Escape {
+                                        sender.input(EndpointMsg::FinishChooseIcon(false));
+                                        Propagation::Stop
+                                    } else {
+                                        Propagation::Proceed
+                                    }
+                                }
+                            },
+                            add_controller = gtk::EventControllerFocus {
+                                connect_leave => EndpointMsg::FinishChooseIcon(false),
+                            }
+                        }
                     } else {
                         gtk::Label {
                             set_hexpand: true,
@@ -145,6 +250,25 @@ impl FactoryComponent for Endpoint {
                         }
                     },
 
+                    gtk::MenuButton {
+                        set_margin_end: 4,
+                        add_css_class: "flat",
+                        set_icon_name: "dialog-warning-symbolic",
+                        #[watch]
+                        set_visible: self.lock_fight_tooltip.is_some(),
+                        #[watch]
+                        set_tooltip: self.lock_fight_tooltip.as_deref().unwrap_or_default(),
+                        set_popover: Some(self.lock_fights.widget()),
+                    },
+                    gtk::Button {
+                        set_margin_end: 4,
+                        add_css_class: "flat",
+                        set_icon_name: "edit-find-replace-symbolic",
+                        set_tooltip: "Replace with…",
+                        #[watch]
+                        set_visible: self.endpoint.is_placeholder,
+                        connect_clicked => EndpointMsg::StartReplace,
+                    },
                     gtk::Label {
                         #[watch]
                         set_label: &match self.endpoint.descriptor {
@@ -166,28 +290,42 @@ impl FactoryComponent for Endpoint {
                     },
                     #[watch]
                     set_tooltip: if self.endpoint.is_placeholder {
-                        "This endpoint is not active. You may reconnect or recreate this endpoint."
+                        "This endpoint is not active. You may reconnect, recreate or replace this endpoint."
                     } else {
                         &self.details_long
                     }
                 },
-                gtk::Scale {
-                    #[watch]
-                    set_range: (0.0, self.settings.volume_limit),
-                    set_increments: (1.0, 5.0),
-                    set_draw_value: true,
-                    #[watch]
-                    clear_marks: (),
-                    #[watch]
-                    add_mark: (100.0, gtk::PositionType::Bottom, None),
-                    set_format_value_func => move |_, value| format!("{value:.0}%"),
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Horizontal,
+                    set_spacing: 4,
 
-                    #[watch]
-                    #[block_signal(volume_handler)]
-                    set_value: volume_to_slider(self.endpoint.volume),
-                    connect_value_changed[sender] => move |scale| {
-                        sender.input(EndpointMsg::Volume(scale.value()));
-                    } @volume_handler
+                    #[name(volume_scale)]
+                    gtk::Scale {
+                        set_hexpand: true,
+                        #[watch]
+                        set_range: (0.0, self.endpoint.slider_limit(&self.settings)),
+                        set_increments: (1.0, 5.0),
+                        #[watch]
+                        clear_marks: (),
+                        #[watch]
+                        add_mark: (
+                            self.settings.fader_curve.volume_to_slider(1.0),
+                            gtk::PositionType::Bottom,
+                            None,
+                        ),
+
+                        #[watch]
+                        #[block_signal(volume_handler)]
+                        set_value: self.settings.fader_curve.volume_to_slider(self.endpoint.volume),
+                        connect_value_changed[sender] => move |scale| {
+                            sender.input(EndpointMsg::Volume(scale.value()));
+                        } @volume_handler
+                    },
+                    #[name(volume_spin_button)]
+                    gtk::SpinButton {
+                        set_valign: gtk::Align::Center,
+                        set_width_chars: 14,
+                    },
                 }
             },
 
@@ -203,6 +341,30 @@ impl FactoryComponent for Endpoint {
                         set_label: "Connections",
                         set_popover: Some(self.connect_endpoints.widget()),
                     },
+                    gtk::MenuButton {
+                        set_icon_name: "audio-card-symbolic",
+                        set_tooltip: "Device profile and port",
+                        #[watch]
+                        set_visible: self.endpoint.device.is_some(),
+                        set_popover: Some(self.device_settings.widget()),
+                    },
+                    gtk::MenuButton {
+                        set_icon_name: "audio-speakers-symbolic",
+                        set_tooltip: "Volume limits",
+                        set_popover: Some(self.volume_limits.widget()),
+                    },
+                    gtk::MenuButton {
+                        set_icon_name: "emblem-system-symbolic",
+                        set_tooltip: "Loopback settings",
+                        set_visible: self.loopback_settings.is_some(),
+                        set_popover: self.loopback_settings.as_ref().map(|settings| settings.widget()),
+                    },
+                    gtk::MenuButton {
+                        set_icon_name: "view-list-symbolic",
+                        set_tooltip: "Streams",
+                        set_visible: self.application_streams.is_some(),
+                        set_popover: self.application_streams.as_ref().map(|streams| streams.widget()),
+                    },
                     #[name(endpoint_menu_button)]
                     gtk::MenuButton {
                         set_icon_name: "view-more-symbolic",
@@ -213,6 +375,39 @@ impl FactoryComponent for Endpoint {
                     set_orientation: gtk::Orientation::Horizontal,
                     set_spacing: 4,
 
+                    #[name(recording_box)]
+                    gtk::Box {
+                        set_visible: self.recording_button.is_some(),
+                    },
+                    gtk::Image {
+                        set_icon_name: Some("input-keyboard-symbolic"),
+                        #[watch]
+                        set_visible: self.endpoint.push_to_talk.is_some(),
+                        #[watch]
+                        set_tooltip: match self.endpoint.push_to_talk {
+                            Some(PushToTalkMode::PushToMute) => "Push to mute",
+                            _ => "Push to talk",
+                        },
+                    },
+                    gtk::ToggleButton {
+                        set_icon_name: "audio-headphones-symbolic",
+                        #[watch]
+                        set_tooltip: match (self.list, self.soloed || self.is_cue) {
+                            (PortKind::Source, false) => "Solo to the cue output",
+                            (PortKind::Source, true) => "Release solo",
+                            (PortKind::Sink, false) => "Use as the cue output for soloed sources",
+                            (PortKind::Sink, true) => "Stop using as the cue output",
+                        },
+                        #[watch]
+                        set_css_classes: if self.soloed || self.is_cue
+                            { &["suggested-action", "image-button"] } else { &["flat", "image-button"] },
+
+                        #[watch]
+                        set_sensitive: self.list == PortKind::Sink || self.has_cue,
+                        #[watch]
+                        set_active: self.soloed || self.is_cue,
+                        connect_clicked => EndpointMsg::ToggleSolo,
+                    },
                     #[name(mute_button)]
                     gtk::ToggleButton {
                         #[watch]
@@ -262,6 +457,32 @@ impl FactoryComponent for Endpoint {
             "Remove" => RemoveAction,
             "Rename" => RenameAction,
             "Reset Name" => ResetNameAction,
+            section! {
+                "Change Icon" => ChangeIconAction,
+                "Reset Icon" => ResetIconAction,
+                "Color" {
+                    "None" => NoColorAction,
+                    "Blue" => BlueAction,
+                    "Green" => GreenAction,
+                    "Yellow" => YellowAction,
+                    "Orange" => OrangeAction,
+                    "Red" => RedAction,
+                    "Purple" => PurpleAction,
+                    "Brown" => BrownAction,
+                },
+            },
+            section! {
+                "Push to Talk" => PushToTalkAction,
+                "Push to Mute" => PushToMuteAction,
+                "Always On" => DisablePushToTalkAction,
+            },
+            section! {
+                "Map Volume to MIDI Control" => LearnVolumeAction,
+                "Map Mute to MIDI Control" => LearnMuteAction,
+                "Map Volume Lock to MIDI Control" => LearnVolumeLockAction,
+                "Map Push to Talk to MIDI Control" => LearnPushToTalkAction,
+                "Forget MIDI Mappings" => ForgetMappingsAction,
+            },
         }
     }
 
@@ -270,11 +491,13 @@ impl FactoryComponent for Endpoint {
         _index: &DynamicIndex,
         sender: FactorySender<Self>,
     ) -> Self {
-        let endpoint = SonusmixReducer::subscribe(sender.input_sender(), EndpointMsg::UpdateState)
+        let state = SonusmixReducer::subscribe(sender.input_sender(), EndpointMsg::UpdateState);
+        let endpoint = state
             .endpoints
             .get(&endpoint_desc)
             .expect("endpoint component failed to find matching endpoint on init")
             .clone();
+        let lock_fight_tooltip = lock_fight_tooltip(&state, endpoint_desc);
         SONUSMIX_SETTINGS.subscribe(sender.input_sender(), |settings| {
             EndpointMsg::UpdateSettings(settings.clone())
         });
@@ -286,14 +509,66 @@ impl FactoryComponent for Endpoint {
             .launch((endpoint.descriptor, list))
             .forward(sender.input_sender(), |msg| match msg {});
 
+        let loopback_settings = match endpoint.descriptor {
+            EndpointDescriptor::Loopback(id, _) => Some(
+                LoopbackSettings::builder()
+                    .launch(id)
+                    .forward(sender.input_sender(), |msg| match msg {}),
+            ),
+            _ => None,
+        };
+
+        let application_streams = match endpoint.descriptor {
+            EndpointDescriptor::Application(id, _) => Some(
+                ApplicationStreams::builder()
+                    .launch(id)
+                    .forward(sender.input_sender(), |msg| match msg {}),
+            ),
+            _ => None,
+        };
+
+        let device_settings = DeviceSettings::builder()
+            .launch(endpoint.descriptor)
+            .forward(sender.input_sender(), |msg| match msg {});
+
+        let volume_limits = VolumeLimits::builder()
+            .launch(endpoint.descriptor)
+            .forward(sender.input_sender(), |msg| match msg {});
+
+        // Only sources can be recorded
+        let recording_button = (list == PortKind::Source).then(|| {
+            RecordingButton::builder()
+                .launch(endpoint.descriptor)
+                .forward(sender.input_sender(), |msg| match msg {})
+        });
+
+        let lock_fights = LockFights::builder()
+            .launch(endpoint.descriptor)
+            .forward(sender.input_sender(), |msg| match msg {});
+
         let custom_name_buffer = gtk::EntryBuffer::new(None::<&str>);
+        let custom_icon_buffer = gtk::EntryBuffer::new(None::<&str>);
 
         Self {
+            soloed: state.soloed.contains(&endpoint.descriptor),
+            is_cue: state.cue_endpoint == Some(endpoint.descriptor),
+            has_cue: state.cue_endpoint.is_some(),
             endpoint,
+            list,
             settings,
             renaming: false,
             custom_name_buffer,
+            choosing_icon: false,
+            custom_icon_buffer,
+            filter: String::new(),
             connect_endpoints,
+            loopback_settings,
+            application_streams,
+            device_settings,
+            volume_limits,
+            recording_button,
+            lock_fights,
+            lock_fight_tooltip,
             details_short,
             details_long,
         }
@@ -306,7 +581,19 @@ impl FactoryComponent for Endpoint {
         _returned_widget: &<Self::ParentWidget as FactoryView>::ReturnedWidget,
         sender: FactorySender<Self>,
     ) -> Self::Widgets {
+        let endpoint_desc = self.endpoint.descriptor;
+        root.add_controller(endpoint_drop_target(move |dropped| {
+            SonusmixReducer::emit(SonusmixMsg::MoveEndpoint(dropped, endpoint_desc));
+        }));
+
         let widgets = view_output!();
+        attach_volume_spin_button(&widgets.volume_spin_button, &widgets.volume_scale);
+        widgets
+            .header
+            .add_controller(endpoint_drag_source(endpoint_desc));
+        if let Some(recording_button) = &self.recording_button {
+            widgets.recording_box.append(recording_button.widget());
+        }
 
         let mut group = RelmActionGroup::<EndpointMenuActionGroup>::new();
         let remove_action: RelmAction<RemoveAction> = RelmAction::new_stateless({
@@ -330,7 +617,29 @@ impl FactoryComponent for Endpoint {
             }
         });
         group.add_action(reset_name_action);
+        let change_icon_action: RelmAction<ChangeIconAction> = RelmAction::new_stateless({
+            let sender = sender.clone();
+            move |_| {
+                sender.input(EndpointMsg::StartChooseIcon);
+            }
+        });
+        group.add_action(change_icon_action);
+        let reset_icon_action: RelmAction<ResetIconAction> = RelmAction::new_stateless({
+            let sender = sender.clone();
+            move |_| {
+                sender.input(EndpointMsg::ResetIcon);
+            }
+        });
+        group.add_action(reset_icon_action);
         group.register_for_widget(&widgets.endpoint_menu_button);
+        register_color_menu_actions(self.endpoint.descriptor, &widgets.endpoint_menu_button);
+        register_midi_menu_actions(self.endpoint.descriptor, &widgets.endpoint_menu_button);
+        if self.endpoint.descriptor.is_kind(PortKind::Source) {
+            register_push_to_talk_menu_actions(
+                self.endpoint.descriptor,
+                &widgets.endpoint_menu_button,
+            );
+        }
 
         widgets
     }
@@ -343,13 +652,17 @@ impl FactoryComponent for Endpoint {
                     self.details_short = self.endpoint.details_short();
                     self.details_long = self.endpoint.details_long();
                 }
+                self.lock_fight_tooltip = lock_fight_tooltip(&state, self.endpoint.descriptor);
+                self.soloed = state.soloed.contains(&self.endpoint.descriptor);
+                self.is_cue = state.cue_endpoint == Some(self.endpoint.descriptor);
+                self.has_cue = state.cue_endpoint.is_some();
             }
             EndpointMsg::UpdateSettings(settings) => {
                 self.settings = settings;
             }
             EndpointMsg::Volume(volume) => SonusmixReducer::emit(SonusmixMsg::SetVolume(
                 self.endpoint.descriptor,
-                slider_to_volume(volume),
+                self.settings.fader_curve.slider_to_volume(volume),
             )),
             EndpointMsg::ToggleMute => {
                 let mute = self
@@ -366,9 +679,24 @@ impl FactoryComponent for Endpoint {
                     !self.endpoint.volume_locked_muted.is_locked(),
                 ));
             }
+            EndpointMsg::ToggleSolo => match self.list {
+                PortKind::Source => SonusmixReducer::emit(SonusmixMsg::SetSolo(
+                    self.endpoint.descriptor,
+                    !self.soloed,
+                )),
+                PortKind::Sink => SonusmixReducer::emit(SonusmixMsg::SetCueEndpoint(
+                    (!self.is_cue).then_some(self.endpoint.descriptor),
+                )),
+            },
             EndpointMsg::Remove => {
                 SonusmixReducer::emit(SonusmixMsg::RemoveEndpoint(self.endpoint.descriptor));
             }
+            EndpointMsg::StartReplace => {
+                CHOOSE_ENDPOINT_BROKER.send(ChooseEndpointDialogMsg::ShowReplace(
+                    self.endpoint.descriptor,
+                    self.list,
+                ));
+            }
             EndpointMsg::StartRename => {
                 self.renaming = true;
                 self.custom_name_buffer
@@ -387,14 +715,27 @@ impl FactoryComponent for Endpoint {
                 self.renaming = false;
                 SonusmixReducer::emit(SonusmixMsg::RenameEndpoint(self.endpoint.descriptor, None));
             }
+            EndpointMsg::StartChooseIcon => {
+                self.choosing_icon = true;
+                self.custom_icon_buffer
+                    .set_text(self.endpoint.custom_or_default_icon_name());
+            }
+            EndpointMsg::FinishChooseIcon(confirm) => {
+                self.choosing_icon = false;
+                if confirm {
+                    SonusmixReducer::emit(SonusmixMsg::SetEndpointIcon(
+                        self.endpoint.descriptor,
+                        Some(self.custom_icon_buffer.text().to_string()),
+                    ));
+                }
+            }
+            EndpointMsg::ResetIcon => {
+                self.choosing_icon = false;
+                SonusmixReducer::emit(SonusmixMsg::SetEndpointIcon(self.endpoint.descriptor, None));
+            }
+            EndpointMsg::Filter(filter) => {
+                self.filter = filter;
+            }
         }
     }
 }
-
-pub fn volume_to_slider(volume: f32) -> f64 {
-    (volume.powf(1.0 / 3.0) * 100.0) as f64
-}
-
-pub fn slider_to_volume(volume: f64) -> f32 {
-    (volume as f32 / 100.0).powf(3.0)
-}
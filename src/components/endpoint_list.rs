@@ -5,18 +5,29 @@ use relm4::gtk::prelude::*;
 use relm4::prelude::*;
 
 use crate::pipewire_api::PortKind;
-use crate::state::{SonusmixOutputMsg, SonusmixReducer, SonusmixState};
+use crate::state::{EndpointDescriptor, SectionId, SonusmixMsg, SonusmixReducer, SonusmixState};
 
-use super::endpoint::Endpoint;
+use super::drag_and_drop::endpoint_drop_target;
+use super::endpoint::{Endpoint, EndpointMsg};
+use super::endpoint_section::{EndpointSection, EndpointSectionMsg};
 
 pub struct EndpointList {
     list: PortKind,
+    sonusmix_state: Arc<SonusmixState>,
+    /// The endpoints shown in each section, used to check if the widgets need to be rebuilt
+    layout: Vec<(Option<SectionId>, Vec<EndpointDescriptor>)>,
+    /// Endpoints that are not in any section
     endpoints: FactoryVecDeque<Endpoint>,
+    sections: FactoryVecDeque<EndpointSection>,
+    filter: String,
 }
 
 #[derive(Debug)]
 pub enum EndpointListMsg {
-    UpdateState(Arc<SonusmixState>, Option<SonusmixOutputMsg>),
+    UpdateState(Arc<SonusmixState>),
+    /// Only show endpoints that match the filter
+    Filter(String),
+    AddSection,
 }
 
 #[derive(Debug)]
@@ -34,10 +45,13 @@ impl SimpleComponent for EndpointList {
                 set_orientation: gtk::Orientation::Vertical,
                 set_hexpand: true,
 
+                // Endpoints dropped on the header are taken out of their section
+                #[name(header)]
                 gtk::CenterBox {
                     set_orientation: gtk::Orientation::Horizontal,
                     set_margin_top: 4,
                     set_margin_start: 4,
+                    set_margin_end: 4,
 
                     #[wrap(Some)]
                     set_start_widget = &gtk::Button {
@@ -55,12 +69,18 @@ impl SimpleComponent for EndpointList {
                             PortKind::Sink => "<big>Sinks</big>",
                         }
                     },
+                    #[wrap(Some)]
+                    set_end_widget = &gtk::Button {
+                        set_icon_name: "folder-new-symbolic",
+                        set_tooltip: "Add section",
+                        connect_clicked => EndpointListMsg::AddSection,
+                    },
                 },
                 gtk::Separator {
                     set_orientation: gtk::Orientation::Vertical,
                     set_margin_vertical: 4,
                 },
-                if model.endpoints.is_empty() {
+                if model.endpoints.is_empty() && model.sections.is_empty() {
                     gtk::Label {
                         set_vexpand: true,
                         set_valign: gtk::Align::Center,
@@ -76,10 +96,18 @@ impl SimpleComponent for EndpointList {
                         set_vexpand: true,
                         set_policy: (gtk::PolicyType::Never, gtk::PolicyType::Automatic),
 
-                        #[local_ref]
-                        endpoints_list -> gtk::Box {
+                        gtk::Box {
                             set_orientation: gtk::Orientation::Vertical,
                             set_margin_all: 4,
+
+                            #[local_ref]
+                            endpoints_list -> gtk::Box {
+                                set_orientation: gtk::Orientation::Vertical,
+                            },
+                            #[local_ref]
+                            sections_list -> gtk::Box {
+                                set_orientation: gtk::Orientation::Vertical,
+                            },
                         }
                     }
                 }
@@ -93,51 +121,100 @@ impl SimpleComponent for EndpointList {
         sender: ComponentSender<Self>,
     ) -> ComponentParts<Self> {
         let sonusmix_state =
-            SonusmixReducer::subscribe_msg(sender.input_sender(), EndpointListMsg::UpdateState);
+            SonusmixReducer::subscribe(sender.input_sender(), EndpointListMsg::UpdateState);
 
-        let mut endpoints = FactoryVecDeque::builder()
+        let endpoints = FactoryVecDeque::builder()
             .launch(gtk::Box::default())
             .forward(sender.input_sender(), |output| match output {});
-        {
-            let mut endpoints = endpoints.guard();
-            let active_endpoints = match list {
-                PortKind::Source => &sonusmix_state.active_sources,
-                PortKind::Sink => &sonusmix_state.active_sinks,
-            };
-            for endpoint in active_endpoints {
-                endpoints.push_back((*endpoint, list));
-            }
-        }
-
-        let model = EndpointList { list, endpoints };
+        let sections = FactoryVecDeque::builder()
+            .launch(gtk::Box::default())
+            .detach();
+
+        let mut model = EndpointList {
+            list,
+            sonusmix_state: sonusmix_state.clone(),
+            layout: Vec::new(),
+            endpoints,
+            sections,
+            filter: String::new(),
+        };
+        model.update_layout(&sonusmix_state);
 
         let endpoints_list = model.endpoints.widget();
+        let sections_list = model.sections.widget();
         let widgets = view_output!();
 
+        widgets
+            .header
+            .add_controller(endpoint_drop_target(|dropped| {
+                SonusmixReducer::emit(SonusmixMsg::SetEndpointSection(dropped, None));
+            }));
+
         ComponentParts { model, widgets }
     }
 
     fn update(&mut self, msg: EndpointListMsg, _sender: ComponentSender<Self>) {
         match msg {
-            EndpointListMsg::UpdateState(_state, msg) => match msg {
-                Some(SonusmixOutputMsg::EndpointAdded(descriptor))
-                    if descriptor.is_list(self.list) =>
-                {
-                    self.endpoints.guard().push_back((descriptor, self.list));
+            EndpointListMsg::UpdateState(state) => {
+                self.update_layout(&state);
+                self.sonusmix_state = state;
+            }
+            EndpointListMsg::Filter(filter) => {
+                self.filter = filter;
+                self.send_filter();
+            }
+            EndpointListMsg::AddSection => {
+                for num in 1.. {
+                    let name = format!("Section {num}");
+                    if self
+                        .sonusmix_state
+                        .sections
+                        .values()
+                        .all(|section| section.list != self.list || section.name != name)
+                    {
+                        SonusmixReducer::emit(SonusmixMsg::AddSection(name, self.list));
+                        break;
+                    }
                 }
-                Some(SonusmixOutputMsg::EndpointRemoved(descriptor)) => {
-                    if descriptor.is_list(self.list) {
-                        let index = self
-                            .endpoints
-                            .iter()
-                            .position(|endpoint| endpoint.id() == descriptor);
-                        if let Some(index) = index {
-                            self.endpoints.guard().remove(index);
+            }
+        }
+    }
+}
+
+impl EndpointList {
+    /// Rebuild the endpoint widgets if endpoints were added, removed, or moved.
+    fn update_layout(&mut self, state: &SonusmixState) {
+        let layout = state.list_layout(self.list);
+        if layout == self.layout {
+            return;
+        }
+
+        {
+            let mut endpoints = self.endpoints.guard();
+            let mut sections = self.sections.guard();
+            endpoints.clear();
+            sections.clear();
+            for (section_id, endpoint_descs) in &layout {
+                match section_id.and_then(|id| state.sections.get(&id)) {
+                    Some(section) => {
+                        sections.push_back((section.clone(), endpoint_descs.clone(), self.list));
+                    }
+                    None => {
+                        for endpoint_desc in endpoint_descs {
+                            endpoints.push_back((*endpoint_desc, self.list));
                         }
                     }
                 }
-                _ => {}
-            },
+            }
         }
+        self.layout = layout;
+        self.send_filter();
+    }
+
+    fn send_filter(&self) {
+        self.endpoints
+            .broadcast(EndpointMsg::Filter(self.filter.clone()));
+        self.sections
+            .broadcast(EndpointSectionMsg::Filter(self.filter.clone()));
     }
 }
@@ -0,0 +1,242 @@
+use std::sync::Arc;
+
+use gtk::glib::Propagation;
+use relm4::actions::{RelmAction, RelmActionGroup};
+use relm4::factory::{FactoryVecDeque, FactoryView};
+use relm4::gtk::prelude::*;
+use relm4::prelude::*;
+
+use crate::pipewire_api::PortKind;
+use crate::state::{EndpointDescriptor, Section, SonusmixMsg, SonusmixReducer, SonusmixState};
+
+use super::drag_and_drop::endpoint_drop_target;
+use super::endpoint::{matches_filter, Endpoint, EndpointMsg};
+
+/// A collapsible section of the sources or sinks list.
+pub struct EndpointSection {
+    section: Section,
+    sonusmix_state: Arc<SonusmixState>,
+    endpoint_descs: Vec<EndpointDescriptor>,
+    endpoints: FactoryVecDeque<Endpoint>,
+    renaming: bool,
+    name_buffer: gtk::EntryBuffer,
+    filter: String,
+}
+
+#[derive(Debug, Clone)]
+pub enum EndpointSectionMsg {
+    UpdateState(Arc<SonusmixState>),
+    SetExpanded(bool),
+    Remove,
+    StartRename,
+    /// true if confirmed, false if cancelled
+    FinishRename(bool),
+    /// Only show the endpoints that match the filter, and hide the section if there are none
+    Filter(String),
+}
+
+relm4::new_action_group!(SectionMenuActionGroup, "section-menu");
+relm4::new_stateless_action!(RenameAction, SectionMenuActionGroup, "rename");
+relm4::new_stateless_action!(RemoveAction, SectionMenuActionGroup, "remove");
+
+#[relm4::factory(pub)]
+impl FactoryComponent for EndpointSection {
+    type Init = (Section, Vec<EndpointDescriptor>, PortKind);
+    type Input = EndpointSectionMsg;
+    type Output = ();
+    type CommandOutput = ();
+    type ParentWidget = gtk::Box;
+
+    view! {
+        #[root]
+        gtk::Expander {
+            set_margin_top: 4,
+            #[watch]
+            set_visible: self.is_visible(),
+
+            // Sections are always expanded while filtering, so that matching endpoints can be
+            // seen
+            #[watch]
+            #[block_signal(expanded_handler)]
+            set_expanded: !self.section.collapsed || !self.filter.is_empty(),
+            connect_expanded_notify[sender] => move |expander| {
+                sender.input(EndpointSectionMsg::SetExpanded(expander.is_expanded()));
+            } @expanded_handler,
+
+            #[wrap(Some)]
+            #[name(header)]
+            set_label_widget = &gtk::Box {
+                set_orientation: gtk::Orientation::Horizontal,
+                set_spacing: 8,
+
+                if self.renaming {
+                    gtk::Entry::with_buffer(&self.name_buffer) {
+                        connect_map => |entry| { entry.grab_focus(); },
+                        connect_activate => EndpointSectionMsg::FinishRename(true),
+
+                        // Add an event controller to cancel renaming on Esc
+                        add_controller = gtk::EventControllerKey {
+                            connect_key_pressed[sender] => move |_, key, _, _| {
+                                if key == gtk::gdk::Key::Escape {
+                                    sender.input(EndpointSectionMsg::FinishRename(false));
+                                    Propagation::Stop
+                                } else {
+                                    Propagation::Proceed
+                                }
+                            }
+                        },
+                        add_controller = gtk::EventControllerFocus {
+                            connect_leave => EndpointSectionMsg::FinishRename(false),
+                        }
+                    }
+                } else {
+                    gtk::Label {
+                        add_css_class: "heading",
+                        #[watch]
+                        set_label: &format!("{} ({})", self.section.name, self.endpoint_descs.len()),
+                    }
+                },
+
+                #[name(section_menu_button)]
+                gtk::MenuButton {
+                    add_css_class: "flat",
+                    set_icon_name: "view-more-symbolic",
+                    set_menu_model: Some(&section_menu),
+                },
+            },
+
+            set_child: Some(self.endpoints.widget()),
+        }
+    }
+
+    menu! {
+        section_menu: {
+            "Rename Section" => RenameAction,
+            "Remove Section" => RemoveAction,
+        }
+    }
+
+    fn init_model(
+        (section, endpoint_descs, list): Self::Init,
+        _index: &DynamicIndex,
+        sender: FactorySender<Self>,
+    ) -> Self {
+        let sonusmix_state =
+            SonusmixReducer::subscribe(sender.input_sender(), EndpointSectionMsg::UpdateState);
+
+        let mut endpoints = FactoryVecDeque::builder()
+            .launch(
+                gtk::Box::builder()
+                    .orientation(gtk::Orientation::Vertical)
+                    .build(),
+            )
+            .detach();
+        {
+            let mut endpoints = endpoints.guard();
+            for endpoint_desc in &endpoint_descs {
+                endpoints.push_back((*endpoint_desc, list));
+            }
+        }
+
+        Self {
+            section,
+            sonusmix_state,
+            endpoint_descs,
+            endpoints,
+            renaming: false,
+            name_buffer: gtk::EntryBuffer::new(None::<&str>),
+            filter: String::new(),
+        }
+    }
+
+    fn init_widgets(
+        &mut self,
+        _index: &Self::Index,
+        root: Self::Root,
+        _returned_widget: &<Self::ParentWidget as FactoryView>::ReturnedWidget,
+        sender: FactorySender<Self>,
+    ) -> Self::Widgets {
+        let widgets = view_output!();
+
+        // Endpoints dropped on the header are moved to the end of the section
+        let section_id = self.section.id;
+        widgets
+            .header
+            .add_controller(endpoint_drop_target(move |dropped| {
+                SonusmixReducer::emit(SonusmixMsg::SetEndpointSection(dropped, Some(section_id)));
+            }));
+
+        let mut group = RelmActionGroup::<SectionMenuActionGroup>::new();
+        let rename_action: RelmAction<RenameAction> = RelmAction::new_stateless({
+            let sender = sender.clone();
+            move |_| {
+                sender.input(EndpointSectionMsg::StartRename);
+            }
+        });
+        group.add_action(rename_action);
+        let remove_action: RelmAction<RemoveAction> = RelmAction::new_stateless({
+            let sender = sender.clone();
+            move |_| {
+                sender.input(EndpointSectionMsg::Remove);
+            }
+        });
+        group.add_action(remove_action);
+        group.register_for_widget(&widgets.section_menu_button);
+
+        widgets
+    }
+
+    fn update(&mut self, msg: EndpointSectionMsg, _sender: FactorySender<Self>) {
+        match msg {
+            EndpointSectionMsg::UpdateState(state) => {
+                if let Some(section) = state.sections.get(&self.section.id) {
+                    self.section = section.clone();
+                }
+                self.sonusmix_state = state;
+            }
+            EndpointSectionMsg::SetExpanded(expanded) => {
+                // Expanding a section while filtering doesn't change whether it's collapsed
+                if self.filter.is_empty() {
+                    SonusmixReducer::emit(SonusmixMsg::SetSectionCollapsed(
+                        self.section.id,
+                        !expanded,
+                    ));
+                }
+            }
+            EndpointSectionMsg::Remove => {
+                SonusmixReducer::emit(SonusmixMsg::RemoveSection(self.section.id));
+            }
+            EndpointSectionMsg::StartRename => {
+                self.renaming = true;
+                self.name_buffer.set_text(&self.section.name);
+            }
+            EndpointSectionMsg::FinishRename(confirm) => {
+                self.renaming = false;
+                if confirm {
+                    SonusmixReducer::emit(SonusmixMsg::RenameSection(
+                        self.section.id,
+                        self.name_buffer.text().to_string(),
+                    ));
+                }
+            }
+            EndpointSectionMsg::Filter(filter) => {
+                self.endpoints
+                    .broadcast(EndpointMsg::Filter(filter.clone()));
+                self.filter = filter;
+            }
+        }
+    }
+}
+
+impl EndpointSection {
+    /// Hide the section while filtering if none of its endpoints match.
+    fn is_visible(&self) -> bool {
+        self.filter.is_empty()
+            || self.endpoint_descs.iter().any(|desc| {
+                self.sonusmix_state
+                    .endpoints
+                    .get(desc)
+                    .is_some_and(|endpoint| matches_filter(endpoint, &self.filter))
+            })
+    }
+}
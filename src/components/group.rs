@@ -9,12 +9,26 @@ use relm4::{factory::FactoryView, gtk::prelude::*};
 use crate::pipewire_api::PortKind;
 use crate::state::settings::SonusmixSettings;
 use crate::state::{
-    Endpoint as PwEndpoint, EndpointDescriptor, GroupNode, GroupNodeId, GroupNodeKind, SonusmixMsg,
-    SonusmixReducer, SonusmixState, SONUSMIX_SETTINGS,
+    Endpoint as PwEndpoint, EndpointColor, EndpointDescriptor, GroupNode, GroupNodeId,
+    GroupNodeKind, PushToTalkMode, SonusmixMsg, SonusmixReducer, SonusmixState, SONUSMIX_SETTINGS,
 };
 
+use super::color_menu::{
+    register_color_menu_actions, BlueAction, BrownAction, GreenAction, NoColorAction, OrangeAction,
+    PurpleAction, RedAction, YellowAction,
+};
 use super::connect_endpoints::ConnectEndpoints;
-use super::endpoint::{slider_to_volume, volume_to_slider};
+use super::drag_and_drop::{endpoint_drag_source, endpoint_drop_target};
+use super::endpoint::matches_filter;
+use super::midi_menu::{
+    register_midi_menu_actions, ForgetMappingsAction, LearnMuteAction, LearnPushToTalkAction,
+    LearnVolumeAction, LearnVolumeLockAction,
+};
+use super::push_to_talk_menu::{
+    register_push_to_talk_menu_actions, DisablePushToTalkAction, PushToMuteAction, PushToTalkAction,
+};
+use super::recording_button::RecordingButton;
+use super::volume_spin_button::attach_volume_spin_button;
 
 pub struct Group {
     pub endpoint: PwEndpoint,
@@ -22,8 +36,10 @@ pub struct Group {
     settings: SonusmixSettings,
     renaming: bool,
     name_buffer: gtk::EntryBuffer,
+    filter: String,
     connect_sources: Controller<ConnectEndpoints>,
     connect_sinks: Controller<ConnectEndpoints>,
+    recording_button: Controller<RecordingButton>,
 }
 
 #[derive(Debug, Clone)]
@@ -37,6 +53,8 @@ pub enum GroupMsg {
     StartRename,
     FinishRename(bool),
     ChangeKind(GroupNodeKind),
+    /// Only show the group if it matches the filter
+    Filter(String),
 }
 
 #[derive(Debug, Clone)]
@@ -62,7 +80,13 @@ impl FactoryComponent for Group {
             set_hexpand: false,
             set_spacing: 8,
             set_margin_all: 4,
+            #[watch]
+            set_visible: matches_filter(&self.endpoint, &self.filter),
+            #[watch]
+            set_css_classes: self.endpoint.color.map(EndpointColor::css_class).as_slice(),
 
+            // The group can be dragged by its header to reorder it
+            #[name(header)]
             gtk::Box {
                 set_orientation: gtk::Orientation::Horizontal,
                 set_spacing: 8,
@@ -139,26 +163,39 @@ impl FactoryComponent for Group {
                 set_orientation: gtk::Orientation::Horizontal,
                 set_vexpand: true,
 
-                gtk::Scale {
+                gtk::Box {
                     set_orientation: gtk::Orientation::Vertical,
-                    set_inverted: true,
-                    #[watch]
-                    set_range: (0.0, self.settings.volume_limit),
-                    set_increments: (1.0, 5.0),
-                    set_draw_value: true,
-                    set_value_pos: gtk::PositionType::Bottom,
-                    #[watch]
-                    clear_marks: (),
-                    #[watch]
-                    add_mark: (100.0, gtk::PositionType::Right, None),
-                    set_format_value_func => move |_, value| format!("{value:.0}%"),
+                    set_spacing: 4,
 
-                    #[watch]
-                    #[block_signal(volume_handler)]
-                    set_value: volume_to_slider(self.endpoint.volume),
-                    connect_value_changed[sender] => move |scale| {
-                        sender.input(GroupMsg::Volume(scale.value()));
-                    } @ volume_handler
+                    #[name(volume_scale)]
+                    gtk::Scale {
+                        set_orientation: gtk::Orientation::Vertical,
+                        set_inverted: true,
+                        set_vexpand: true,
+                        #[watch]
+                        set_range: (0.0, self.settings.slider_limit()),
+                        set_increments: (1.0, 5.0),
+                        #[watch]
+                        clear_marks: (),
+                        #[watch]
+                        add_mark: (
+                            self.settings.fader_curve.volume_to_slider(1.0),
+                            gtk::PositionType::Right,
+                            None,
+                        ),
+
+                        #[watch]
+                        #[block_signal(volume_handler)]
+                        set_value: self.settings.fader_curve.volume_to_slider(self.endpoint.volume),
+                        connect_value_changed[sender] => move |scale| {
+                            sender.input(GroupMsg::Volume(scale.value()));
+                        } @ volume_handler
+                    },
+                    #[name(volume_spin_button)]
+                    gtk::SpinButton {
+                        set_orientation: gtk::Orientation::Vertical,
+                        set_width_chars: 14,
+                    },
                 },
                 gtk::CenterBox {
                     set_orientation: gtk::Orientation::Vertical,
@@ -219,6 +256,16 @@ impl FactoryComponent for Group {
                         set_halign: gtk::Align::Start,
                         set_spacing: 4,
 
+                        gtk::Image {
+                            set_icon_name: Some("input-keyboard-symbolic"),
+                            #[watch]
+                            set_visible: self.endpoint.push_to_talk.is_some(),
+                            #[watch]
+                            set_tooltip: match self.endpoint.push_to_talk {
+                                Some(PushToTalkMode::PushToMute) => "Push to mute",
+                                _ => "Push to talk",
+                            },
+                        },
                         #[name(mute_button)]
                         gtk::ToggleButton {
                             #[watch]
@@ -254,6 +301,7 @@ impl FactoryComponent for Group {
                             set_active: self.endpoint.volume_locked_muted.is_locked(),
                             connect_clicked => GroupMsg::ToggleLocked,
                         },
+                        append: self.recording_button.widget(),
                     }
                 }
             },
@@ -264,6 +312,30 @@ impl FactoryComponent for Group {
         group_menu: {
             "Remove" => RemoveAction,
             "Rename" => RenameAction,
+            section! {
+                "Color" {
+                    "None" => NoColorAction,
+                    "Blue" => BlueAction,
+                    "Green" => GreenAction,
+                    "Yellow" => YellowAction,
+                    "Orange" => OrangeAction,
+                    "Red" => RedAction,
+                    "Purple" => PurpleAction,
+                    "Brown" => BrownAction,
+                },
+            },
+            section! {
+                "Push to Talk" => PushToTalkAction,
+                "Push to Mute" => PushToMuteAction,
+                "Always On" => DisablePushToTalkAction,
+            },
+            section! {
+                "Map Volume to MIDI Control" => LearnVolumeAction,
+                "Map Mute to MIDI Control" => LearnMuteAction,
+                "Map Volume Lock to MIDI Control" => LearnVolumeLockAction,
+                "Map Push to Talk to MIDI Control" => LearnPushToTalkAction,
+                "Forget MIDI Mappings" => ForgetMappingsAction,
+            },
         }
     }
 
@@ -291,6 +363,9 @@ impl FactoryComponent for Group {
         let connect_sinks = ConnectEndpoints::builder()
             .launch((endpoint.descriptor, PortKind::Source))
             .forward(sender.input_sender(), |msg| match msg {});
+        let recording_button = RecordingButton::builder()
+            .launch(endpoint.descriptor)
+            .forward(sender.input_sender(), |msg| match msg {});
         let name_buffer = gtk::EntryBuffer::new(None::<&str>);
 
         Self {
@@ -299,19 +374,30 @@ impl FactoryComponent for Group {
             settings,
             renaming: false,
             name_buffer,
+            filter: String::new(),
             connect_sources,
             connect_sinks,
+            recording_button,
         }
     }
 
     fn init_widgets(
         &mut self,
         _index: &Self::Index,
-        _root: Self::Root,
+        root: Self::Root,
         _returned_widget: &<Self::ParentWidget as FactoryView>::ReturnedWidget,
         sender: FactorySender<Self>,
     ) -> Self::Widgets {
+        let endpoint_desc = self.endpoint.descriptor;
+        root.add_controller(endpoint_drop_target(move |dropped| {
+            SonusmixReducer::emit(SonusmixMsg::MoveEndpoint(dropped, endpoint_desc));
+        }));
+
         let widgets = view_output!();
+        attach_volume_spin_button(&widgets.volume_spin_button, &widgets.volume_scale);
+        widgets
+            .header
+            .add_controller(endpoint_drag_source(endpoint_desc));
 
         let mut group = RelmActionGroup::<GroupMenuActionGroup>::new();
         let remove_action: RelmAction<RemoveAction> = RelmAction::new_stateless({
@@ -329,6 +415,9 @@ impl FactoryComponent for Group {
         });
         group.add_action(rename_action);
         group.register_for_widget(&widgets.group_menu_button);
+        register_color_menu_actions(self.endpoint.descriptor, &widgets.group_menu_button);
+        register_midi_menu_actions(self.endpoint.descriptor, &widgets.group_menu_button);
+        register_push_to_talk_menu_actions(self.endpoint.descriptor, &widgets.group_menu_button);
 
         widgets
     }
@@ -349,7 +438,7 @@ impl FactoryComponent for Group {
             }
             GroupMsg::Volume(volume) => SonusmixReducer::emit(SonusmixMsg::SetVolume(
                 self.endpoint.descriptor,
-                slider_to_volume(volume),
+                self.settings.fader_curve.slider_to_volume(volume),
             )),
             GroupMsg::ToggleMute => {
                 let mute = self
@@ -375,16 +464,12 @@ impl FactoryComponent for Group {
             }
             GroupMsg::FinishRename(confirm) => {
                 self.renaming = false;
-                let message = SonusmixMsg::RenameEndpoint(
-                    self.endpoint.descriptor,
-                    Some(self.name_buffer.text().to_string()),
-                );
+                // Renaming changes the live node, so it doesn't need a warning
                 if confirm {
-                    if self.settings.show_group_node_change_warning {
-                        let _ = sender.output(GroupOutput::MessageWithWarning(message));
-                    } else {
-                        SonusmixReducer::emit(message);
-                    }
+                    SonusmixReducer::emit(SonusmixMsg::RenameEndpoint(
+                        self.endpoint.descriptor,
+                        Some(self.name_buffer.text().to_string()),
+                    ));
                 }
             }
             GroupMsg::ChangeKind(kind) => {
@@ -395,6 +480,9 @@ impl FactoryComponent for Group {
                     SonusmixReducer::emit(message);
                 }
             }
+            GroupMsg::Filter(filter) => {
+                self.filter = filter;
+            }
         }
     }
 }
@@ -465,9 +553,9 @@ impl SimpleComponent for GroupChangeWarning {
                     set_valign: gtk::Align::Start,
                     set_justify: gtk::Justification::Center,
                     set_wrap: true,
-                    set_label: "Changing some properties of group nodes deletes and re-creates \
-                        them in Pipewire, which may reset the group's connections and volume, if \
-                        they are unlocked.",
+                    set_label: "Changing the kind of a group node replaces it with a new node in \
+                        Pipewire. Its connections are moved to the new node, but connections that \
+                        don't fit the new kind and unlocked volumes may be reset.",
                 },
                 gtk::CheckButton {
                     set_align: gtk::Align::Center,
@@ -0,0 +1,132 @@
+use relm4::gtk::prelude::*;
+use relm4::prelude::*;
+
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use crate::state::{EndpointDescriptor, LockTarget, SonusmixMsg, SonusmixReducer, SonusmixState};
+
+/// Lists the locks of an endpoint that other clients keep changing, and lets the user unlock them
+/// or keep restoring them immediately.
+pub struct LockFights {
+    endpoint_desc: EndpointDescriptor,
+    /// Each fought over lock, with its description and the client fighting over it
+    fights: Vec<(LockTarget, String, Option<String>)>,
+    list: gtk::Box,
+}
+
+#[derive(Debug)]
+pub enum LockFightsMsg {
+    UpdateState(Arc<SonusmixState>),
+}
+
+#[relm4::component(pub)]
+impl SimpleComponent for LockFights {
+    type Init = EndpointDescriptor;
+    type Input = LockFightsMsg;
+    type Output = Infallible;
+
+    view! {
+        #[root]
+        gtk::Popover {
+            set_autohide: true,
+
+            #[local_ref]
+            list -> gtk::Box {
+                set_orientation: gtk::Orientation::Vertical,
+                set_spacing: 12,
+            }
+        }
+    }
+
+    fn init(
+        endpoint_desc: EndpointDescriptor,
+        root: Self::Root,
+        sender: ComponentSender<Self>,
+    ) -> ComponentParts<Self> {
+        let state = SonusmixReducer::subscribe(sender.input_sender(), LockFightsMsg::UpdateState);
+
+        let mut model = LockFights {
+            endpoint_desc,
+            fights: Vec::new(),
+            list: gtk::Box::default(),
+        };
+        model.update_fights(&state);
+
+        let list = &model.list;
+        let widgets = view_output!();
+
+        ComponentParts { model, widgets }
+    }
+
+    fn update(&mut self, msg: LockFightsMsg, _sender: ComponentSender<Self>) {
+        match msg {
+            LockFightsMsg::UpdateState(state) => self.update_fights(&state),
+        }
+    }
+}
+
+impl LockFights {
+    fn update_fights(&mut self, state: &SonusmixState) {
+        let mut fights: Vec<_> = state
+            .lock_fights(self.endpoint_desc)
+            .map(|(target, backoff)| {
+                (
+                    target,
+                    state.describe_lock_target(target),
+                    backoff.client.clone(),
+                )
+            })
+            .collect();
+        fights.sort_by(|a, b| a.1.cmp(&b.1));
+        if fights == self.fights {
+            return;
+        }
+        self.fights = fights;
+
+        while let Some(child) = self.list.first_child() {
+            self.list.remove(&child);
+        }
+        for (target, description, client) in &self.fights {
+            self.list
+                .append(&fight_row(*target, description, client.as_deref()));
+        }
+    }
+}
+
+fn fight_row(target: LockTarget, description: &str, client: Option<&str>) -> gtk::Box {
+    let row = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(4)
+        .build();
+    row.append(
+        &gtk::Label::builder()
+            .label(format!(
+                "{description} is locked, but {} keeps changing it. Sonusmix is waiting longer \
+                 and longer before restoring it.",
+                client.unwrap_or("another program")
+            ))
+            .max_width_chars(40)
+            .wrap(true)
+            .xalign(0.0)
+            .build(),
+    );
+
+    let buttons = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(4)
+        .halign(gtk::Align::End)
+        .build();
+    let unlock_button = gtk::Button::with_label("Unlock");
+    unlock_button.set_tooltip_text(Some("Let the other program have its way"));
+    unlock_button.connect_clicked(move |_| SonusmixReducer::emit(target.unlock_message()));
+    buttons.append(&unlock_button);
+    let keep_fighting_button = gtk::Button::with_label("Keep Fighting");
+    keep_fighting_button.set_tooltip_text(Some("Always restore the lock immediately"));
+    keep_fighting_button
+        .connect_clicked(move |_| SonusmixReducer::emit(SonusmixMsg::KeepFighting(target)));
+    buttons.append(&keep_fighting_button);
+    row.append(&buttons);
+
+    row
+}
@@ -0,0 +1,154 @@
+use relm4::gtk::prelude::*;
+use relm4::prelude::*;
+
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use crate::state::{LoopbackConfig, LoopbackId, SonusmixMsg, SonusmixReducer, SonusmixState};
+
+pub struct LoopbackSettings {
+    id: LoopbackId,
+    config: LoopbackConfig,
+    latency_adjustment: gtk::Adjustment,
+    capture_channels_buffer: gtk::EntryBuffer,
+    playback_channels_buffer: gtk::EntryBuffer,
+}
+
+#[derive(Debug)]
+pub enum LoopbackSettingsMsg {
+    UpdateState(Arc<SonusmixState>),
+    Apply,
+}
+
+#[relm4::component(pub)]
+impl SimpleComponent for LoopbackSettings {
+    type Init = LoopbackId;
+    type Input = LoopbackSettingsMsg;
+    type Output = Infallible;
+
+    view! {
+        #[root]
+        gtk::Popover {
+            set_autohide: true,
+
+            gtk::Grid {
+                set_row_spacing: 4,
+                set_column_spacing: 8,
+
+                attach[0, 0, 1, 1] = &gtk::Label {
+                    set_halign: gtk::Align::Start,
+                    set_label: "Latency (ms)",
+                },
+                attach[1, 0, 1, 1] = &gtk::SpinButton {
+                    set_adjustment: &model.latency_adjustment,
+                    set_digits: 0,
+                    connect_activate => LoopbackSettingsMsg::Apply,
+                },
+                attach[0, 1, 1, 1] = &gtk::Label {
+                    set_halign: gtk::Align::Start,
+                    set_label: "Input channels",
+                },
+                attach[1, 1, 1, 1] = &gtk::Entry::with_buffer(&model.capture_channels_buffer) {
+                    set_tooltip: "Space-separated channel positions, e.g. FL FR",
+                    connect_activate => LoopbackSettingsMsg::Apply,
+                },
+                attach[0, 2, 1, 1] = &gtk::Label {
+                    set_halign: gtk::Align::Start,
+                    set_label: "Output channels",
+                },
+                attach[1, 2, 1, 1] = &gtk::Entry::with_buffer(&model.playback_channels_buffer) {
+                    set_tooltip: "Space-separated channel positions, e.g. FL FR",
+                    connect_activate => LoopbackSettingsMsg::Apply,
+                },
+                attach[1, 3, 1, 1] = &gtk::Button {
+                    set_halign: gtk::Align::End,
+                    set_label: "Apply",
+                    add_css_class: "suggested-action",
+                    connect_clicked => LoopbackSettingsMsg::Apply,
+                },
+            }
+        }
+    }
+
+    fn init(
+        id: LoopbackId,
+        root: Self::Root,
+        sender: ComponentSender<Self>,
+    ) -> ComponentParts<Self> {
+        let sonusmix_state =
+            SonusmixReducer::subscribe(sender.input_sender(), LoopbackSettingsMsg::UpdateState);
+        let config = sonusmix_state
+            .loopbacks
+            .get(&id)
+            .map(|loopback| loopback.config.clone())
+            .unwrap_or_default();
+
+        let model = Self {
+            id,
+            latency_adjustment: gtk::Adjustment::new(
+                0.0,
+                *LoopbackConfig::LATENCY_RANGE_MS.start() as f64,
+                *LoopbackConfig::LATENCY_RANGE_MS.end() as f64,
+                1.0,
+                10.0,
+                0.0,
+            ),
+            capture_channels_buffer: gtk::EntryBuffer::new(None::<&str>),
+            playback_channels_buffer: gtk::EntryBuffer::new(None::<&str>),
+            config,
+        };
+        model.reset_fields();
+
+        let widgets = view_output!();
+
+        ComponentParts { model, widgets }
+    }
+
+    fn update(&mut self, msg: LoopbackSettingsMsg, _sender: ComponentSender<Self>) {
+        match msg {
+            LoopbackSettingsMsg::UpdateState(state) => {
+                if let Some(loopback) = state.loopbacks.get(&self.id) {
+                    if loopback.config != self.config {
+                        self.config = loopback.config.clone();
+                        self.reset_fields();
+                    }
+                }
+            }
+            LoopbackSettingsMsg::Apply => {
+                let capture_channels = parse_channels(&self.capture_channels_buffer.text());
+                let playback_channels = parse_channels(&self.playback_channels_buffer.text());
+                // An empty channel map isn't valid, so put back the current one instead
+                if capture_channels.is_empty() || playback_channels.is_empty() {
+                    self.reset_fields();
+                    return;
+                }
+                SonusmixReducer::emit(SonusmixMsg::ChangeLoopbackConfig(
+                    self.id,
+                    LoopbackConfig {
+                        latency_ms: self.latency_adjustment.value() as u32,
+                        capture_channels,
+                        playback_channels,
+                    },
+                ));
+            }
+        }
+    }
+}
+
+impl LoopbackSettings {
+    fn reset_fields(&self) {
+        self.latency_adjustment
+            .set_value(self.config.latency_ms as f64);
+        self.capture_channels_buffer
+            .set_text(self.config.capture_channels.join(" "));
+        self.playback_channels_buffer
+            .set_text(self.config.playback_channels.join(" "));
+    }
+}
+
+fn parse_channels(text: &str) -> Vec<String> {
+    text.split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|channel| !channel.is_empty())
+        .map(|channel| channel.to_uppercase())
+        .collect()
+}
@@ -0,0 +1,46 @@
+use relm4::actions::{RelmAction, RelmActionGroup};
+use relm4::gtk;
+
+use crate::state::{EndpointDescriptor, MidiAction, SonusmixMsg, SonusmixReducer};
+
+relm4::new_action_group!(pub MidiMenuActionGroup, "midi");
+relm4::new_stateless_action!(pub LearnVolumeAction, MidiMenuActionGroup, "learn-volume");
+relm4::new_stateless_action!(pub LearnMuteAction, MidiMenuActionGroup, "learn-mute");
+relm4::new_stateless_action!(pub LearnVolumeLockAction, MidiMenuActionGroup, "learn-volume-lock");
+relm4::new_stateless_action!(
+    pub LearnPushToTalkAction,
+    MidiMenuActionGroup,
+    "learn-push-to-talk"
+);
+relm4::new_stateless_action!(pub ForgetMappingsAction, MidiMenuActionGroup, "forget-mappings");
+
+/// Register the actions used by the MIDI section of an endpoint's menu on its menu button.
+pub fn register_midi_menu_actions(
+    endpoint_desc: EndpointDescriptor,
+    widget: &impl AsRef<gtk::Widget>,
+) {
+    let mut group = RelmActionGroup::<MidiMenuActionGroup>::new();
+    let learn = |action: MidiAction| {
+        move |_: &gtk::gio::SimpleAction| {
+            SonusmixReducer::emit(SonusmixMsg::StartMidiLearn(action));
+        }
+    };
+    group.add_action(RelmAction::<LearnVolumeAction>::new_stateless(learn(
+        MidiAction::Volume(endpoint_desc),
+    )));
+    group.add_action(RelmAction::<LearnMuteAction>::new_stateless(learn(
+        MidiAction::Mute(endpoint_desc),
+    )));
+    group.add_action(RelmAction::<LearnVolumeLockAction>::new_stateless(learn(
+        MidiAction::VolumeLock(endpoint_desc),
+    )));
+    group.add_action(RelmAction::<LearnPushToTalkAction>::new_stateless(learn(
+        MidiAction::PushToTalk(endpoint_desc),
+    )));
+    group.add_action(RelmAction::<ForgetMappingsAction>::new_stateless(
+        move |_| {
+            SonusmixReducer::emit(SonusmixMsg::RemoveMidiMappings(endpoint_desc));
+        },
+    ));
+    group.register_for_widget(widget);
+}
@@ -1,9 +1,24 @@
 mod about;
+mod activity_log;
 pub mod app;
+mod application_streams;
 mod choose_endpoint_dialog;
+mod color_menu;
 mod connect_endpoints;
 mod debug_view;
+mod device_settings;
+mod drag_and_drop;
 mod endpoint;
 mod endpoint_list;
+mod endpoint_section;
 mod group;
+mod lock_fights;
+mod loopback_settings;
+mod midi_menu;
+mod mute_groups;
+mod push_to_talk_menu;
+mod recording_button;
 mod settings_page;
+mod vca;
+mod volume_limits;
+mod volume_spin_button;
@@ -0,0 +1,199 @@
+use relm4::gtk::prelude::*;
+use relm4::prelude::*;
+
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use crate::state::{
+    EndpointDescriptor, MuteGroup, MuteGroupTarget, SonusmixMsg, SonusmixReducer, SonusmixState,
+};
+
+/// Lists the mute groups, with a switch to engage each one and check boxes for their members,
+/// and lets the user add new ones.
+pub struct MuteGroups {
+    groups: Vec<MuteGroup>,
+    /// Each endpoint that can be added to a group, with its name
+    candidates: Vec<(EndpointDescriptor, String)>,
+    name_buffer: gtk::EntryBuffer,
+    list: gtk::Box,
+}
+
+#[derive(Debug)]
+pub enum MuteGroupsMsg {
+    UpdateState(Arc<SonusmixState>),
+    Add,
+}
+
+#[relm4::component(pub)]
+impl SimpleComponent for MuteGroups {
+    type Init = ();
+    type Input = MuteGroupsMsg;
+    type Output = Infallible;
+
+    view! {
+        #[root]
+        gtk::Popover {
+            set_autohide: true,
+
+            gtk::Box {
+                set_orientation: gtk::Orientation::Vertical,
+                set_spacing: 8,
+
+                gtk::ScrolledWindow {
+                    set_propagate_natural_height: true,
+                    set_max_content_height: 400,
+                    set_policy: (gtk::PolicyType::Never, gtk::PolicyType::Automatic),
+
+                    #[local_ref]
+                    list -> gtk::Box {
+                        set_orientation: gtk::Orientation::Vertical,
+                        set_spacing: 12,
+                    }
+                },
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Horizontal,
+                    set_spacing: 4,
+
+                    gtk::Entry::with_buffer(&model.name_buffer) {
+                        set_hexpand: true,
+                        set_placeholder_text: Some("New mute group…"),
+                        connect_activate => MuteGroupsMsg::Add,
+                    },
+                    gtk::Button {
+                        set_icon_name: "list-add-symbolic",
+                        set_tooltip: "Add mute group",
+                        connect_clicked => MuteGroupsMsg::Add,
+                    },
+                },
+            }
+        }
+    }
+
+    fn init(_init: (), root: Self::Root, sender: ComponentSender<Self>) -> ComponentParts<Self> {
+        let state = SonusmixReducer::subscribe(sender.input_sender(), MuteGroupsMsg::UpdateState);
+
+        let mut model = MuteGroups {
+            groups: Vec::new(),
+            candidates: Vec::new(),
+            name_buffer: gtk::EntryBuffer::new(None::<&str>),
+            list: gtk::Box::default(),
+        };
+        model.update_groups(&state);
+
+        let list = &model.list;
+        let widgets = view_output!();
+
+        ComponentParts { model, widgets }
+    }
+
+    fn update(&mut self, msg: MuteGroupsMsg, _sender: ComponentSender<Self>) {
+        match msg {
+            MuteGroupsMsg::UpdateState(state) => self.update_groups(&state),
+            MuteGroupsMsg::Add => {
+                let name = self.name_buffer.text().to_string();
+                if !name.is_empty() {
+                    SonusmixReducer::emit(SonusmixMsg::AddMuteGroup(name));
+                    self.name_buffer.set_text("");
+                }
+            }
+        }
+    }
+}
+
+impl MuteGroups {
+    fn update_groups(&mut self, state: &SonusmixState) {
+        let groups: Vec<MuteGroup> = state.mute_groups.values().cloned().collect();
+        let candidates: Vec<_> = state
+            .active_sources
+            .iter()
+            .chain(&state.active_sinks)
+            .filter_map(|descriptor| {
+                let endpoint = state.endpoints.get(descriptor)?;
+                Some((*descriptor, endpoint.custom_or_display_name().to_owned()))
+            })
+            .collect();
+        if groups == self.groups && candidates == self.candidates {
+            return;
+        }
+        self.groups = groups;
+        self.candidates = candidates;
+
+        while let Some(child) = self.list.first_child() {
+            self.list.remove(&child);
+        }
+        for group in &self.groups {
+            self.list.append(&group_row(group, &self.candidates));
+        }
+    }
+}
+
+fn group_row(group: &MuteGroup, candidates: &[(EndpointDescriptor, String)]) -> gtk::Box {
+    let id = group.id;
+    let row = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(4)
+        .build();
+
+    let header = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(8)
+        .build();
+    let engaged = gtk::Switch::builder()
+        .active(group.held.is_some())
+        .valign(gtk::Align::Center)
+        .tooltip_text("Mute and lock every member")
+        .build();
+    engaged.connect_state_set(move |_, engaged| {
+        SonusmixReducer::emit(SonusmixMsg::SetMuteGroupEngaged(
+            MuteGroupTarget::Group(id),
+            engaged,
+        ));
+        gtk::glib::Propagation::Proceed
+    });
+    header.append(&engaged);
+    header.append(
+        &gtk::Label::builder()
+            .label(&group.name)
+            .hexpand(true)
+            .xalign(0.0)
+            .css_classes(["heading"])
+            .build(),
+    );
+    let remove_button = gtk::Button::builder()
+        .icon_name("user-trash-symbolic")
+        .tooltip_text("Remove mute group")
+        .css_classes(["flat"])
+        .build();
+    remove_button.connect_clicked(move |_| SonusmixReducer::emit(SonusmixMsg::RemoveMuteGroup(id)));
+    header.append(&remove_button);
+    row.append(&header);
+
+    let members = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(4)
+        .margin_start(12)
+        .build();
+    for (descriptor, name) in candidates {
+        let descriptor = *descriptor;
+        let check = gtk::CheckButton::builder()
+            .label(name)
+            .active(group.members.contains(&descriptor))
+            .build();
+        check.connect_toggled(move |check| {
+            SonusmixReducer::emit(SonusmixMsg::SetMuteGroupMember(
+                id,
+                descriptor,
+                check.is_active(),
+            ));
+        });
+        members.append(&check);
+    }
+    row.append(
+        &gtk::Expander::builder()
+            .label(format!("Members ({})", group.members.len()))
+            .child(&members)
+            .build(),
+    );
+
+    row
+}
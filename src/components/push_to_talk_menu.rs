@@ -0,0 +1,34 @@
+use relm4::actions::{RelmAction, RelmActionGroup};
+use relm4::gtk;
+
+use crate::state::{EndpointDescriptor, PushToTalkMode, SonusmixMsg, SonusmixReducer};
+
+relm4::new_action_group!(pub PushToTalkMenuActionGroup, "push-to-talk");
+relm4::new_stateless_action!(pub PushToTalkAction, PushToTalkMenuActionGroup, "push-to-talk");
+relm4::new_stateless_action!(pub PushToMuteAction, PushToTalkMenuActionGroup, "push-to-mute");
+relm4::new_stateless_action!(pub DisablePushToTalkAction, PushToTalkMenuActionGroup, "disable");
+
+/// Register the actions used by the push to talk section of an endpoint's menu on its menu
+/// button. Menu items without registered actions are disabled, so this should only be called for
+/// sources.
+pub fn register_push_to_talk_menu_actions(
+    endpoint_desc: EndpointDescriptor,
+    widget: &impl AsRef<gtk::Widget>,
+) {
+    let mut group = RelmActionGroup::<PushToTalkMenuActionGroup>::new();
+    let set_mode = |mode: Option<PushToTalkMode>| {
+        move |_: &gtk::gio::SimpleAction| {
+            SonusmixReducer::emit(SonusmixMsg::SetPushToTalk(endpoint_desc, mode));
+        }
+    };
+    group.add_action(RelmAction::<PushToTalkAction>::new_stateless(set_mode(
+        Some(PushToTalkMode::PushToTalk),
+    )));
+    group.add_action(RelmAction::<PushToMuteAction>::new_stateless(set_mode(
+        Some(PushToTalkMode::PushToMute),
+    )));
+    group.add_action(RelmAction::<DisablePushToTalkAction>::new_stateless(
+        set_mode(None),
+    ));
+    group.register_for_widget(widget);
+}
@@ -0,0 +1,200 @@
+use relm4::gtk::prelude::*;
+use relm4::prelude::*;
+
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::error;
+
+use crate::state::{
+    EndpointDescriptor, Recording, SonusmixMsg, SonusmixReducer, SonusmixState, SONUSMIX_SETTINGS,
+};
+
+/// A toggle button that records an endpoint, with the length and size of the recording next to it
+/// while recording.
+pub struct RecordingButton {
+    endpoint_desc: EndpointDescriptor,
+    endpoint_name: String,
+    recording: Option<Recording>,
+    file_size: u64,
+}
+
+#[derive(Debug)]
+pub enum RecordingButtonMsg {
+    UpdateState(Arc<SonusmixState>),
+    Toggle,
+}
+
+#[relm4::component(pub)]
+impl Component for RecordingButton {
+    type Init = EndpointDescriptor;
+    type Input = RecordingButtonMsg;
+    type Output = Infallible;
+    type CommandOutput = ();
+
+    view! {
+        #[root]
+        gtk::Box {
+            set_orientation: gtk::Orientation::Horizontal,
+            set_spacing: 4,
+
+            #[name(record_button)]
+            gtk::ToggleButton {
+                set_icon_name: "media-record-symbolic",
+
+                #[watch]
+                #[block_signal(toggle_handler)]
+                set_active: model.recording.is_some(),
+                #[watch]
+                set_tooltip: if record_button.is_active() { "Stop recording" } else { "Record to a WAV file" },
+                #[watch]
+                set_css_classes: if record_button.is_active()
+                    { &["destructive-action", "image-button"] } else { &["flat", "image-button"] },
+
+                connect_clicked[sender] => move |_| {
+                    sender.input(RecordingButtonMsg::Toggle);
+                } @toggle_handler
+            },
+            gtk::Label {
+                set_css_classes: &["caption", "numeric"],
+
+                #[watch]
+                set_visible: model.recording.is_some(),
+                #[watch]
+                set_label: &model.recording.as_ref().map(|recording| format!(
+                    "{} · {}",
+                    format_elapsed(recording.started_at.elapsed()),
+                    format_size(model.file_size),
+                )).unwrap_or_default(),
+                #[watch]
+                set_tooltip: &model.recording.as_ref()
+                    .map(|recording| recording.path.display().to_string())
+                    .unwrap_or_default(),
+            },
+        }
+    }
+
+    fn init(
+        endpoint_desc: EndpointDescriptor,
+        root: Self::Root,
+        sender: ComponentSender<Self>,
+    ) -> ComponentParts<Self> {
+        let sonusmix_state =
+            SonusmixReducer::subscribe(sender.input_sender(), RecordingButtonMsg::UpdateState);
+
+        // Refresh the length and size once a second
+        sender.command(|out, shutdown| {
+            shutdown
+                .register(async move {
+                    let mut interval = tokio::time::interval(Duration::from_secs(1));
+                    loop {
+                        interval.tick().await;
+                        if out.send(()).is_err() {
+                            break;
+                        }
+                    }
+                })
+                .drop_on_shutdown()
+        });
+
+        let mut model = Self {
+            endpoint_desc,
+            endpoint_name: String::new(),
+            recording: None,
+            file_size: 0,
+        };
+        model.update_recording(&sonusmix_state);
+
+        let widgets = view_output!();
+
+        ComponentParts { model, widgets }
+    }
+
+    fn update(
+        &mut self,
+        msg: RecordingButtonMsg,
+        _sender: ComponentSender<Self>,
+        _root: &Self::Root,
+    ) {
+        match msg {
+            RecordingButtonMsg::UpdateState(sonusmix_state) => {
+                self.update_recording(&sonusmix_state);
+            }
+            RecordingButtonMsg::Toggle => {
+                if self.recording.is_some() {
+                    SonusmixReducer::emit(SonusmixMsg::StopRecording(self.endpoint_desc));
+                    return;
+                }
+
+                let directory = { SONUSMIX_SETTINGS.read().recording_directory() };
+                if let Err(err) = std::fs::create_dir_all(&directory) {
+                    error!("Failed to create recording directory {directory:?}: {err:?}");
+                    return;
+                }
+                let timestamp = gtk::glib::DateTime::now_local()
+                    .and_then(|now| now.format("%Y-%m-%d %H-%M-%S"))
+                    .map(|timestamp| timestamp.to_string())
+                    .unwrap_or_default();
+                let file_name = format!("{} {timestamp}.wav", self.endpoint_name)
+                    .replace(std::path::MAIN_SEPARATOR, "-");
+                SonusmixReducer::emit(SonusmixMsg::StartRecording(
+                    self.endpoint_desc,
+                    directory.join(file_name.trim()),
+                ));
+            }
+        }
+    }
+
+    fn update_cmd(&mut self, _: (), _sender: ComponentSender<Self>, _root: &Self::Root) {
+        if let Some(recording) = &self.recording {
+            self.file_size = std::fs::metadata(&recording.path)
+                .map(|metadata| metadata.len())
+                .unwrap_or(0);
+        }
+    }
+}
+
+impl RecordingButton {
+    fn update_recording(&mut self, sonusmix_state: &SonusmixState) {
+        if let Some(endpoint) = sonusmix_state.endpoints.get(&self.endpoint_desc) {
+            self.endpoint_name = endpoint.custom_or_display_name().to_owned();
+        }
+        let recording = sonusmix_state.recordings.get(&self.endpoint_desc).cloned();
+        if recording.as_ref().map(|recording| recording.id)
+            != self.recording.as_ref().map(|recording| recording.id)
+        {
+            self.file_size = 0;
+        }
+        self.recording = recording;
+    }
+}
+
+fn format_elapsed(elapsed: Duration) -> String {
+    let seconds = elapsed.as_secs();
+    if seconds >= 3600 {
+        format!(
+            "{}:{:02}:{:02}",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        )
+    } else {
+        format!("{:02}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1000.0 && unit < UNITS.len() - 1 {
+        size /= 1000.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} {}", UNITS[0])
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
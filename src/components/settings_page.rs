@@ -2,11 +2,13 @@ use std::convert::Infallible;
 use std::fmt::Debug;
 use std::ops::Deref;
 
-use relm4::binding::{Binding, BoolBinding, ConnectBinding, F64Binding, StringBinding};
+use relm4::binding::{
+    Binding, BoolBinding, ConnectBinding, F64Binding, StringBinding, U32Binding,
+};
 use relm4::gtk::prelude::*;
 use relm4::{prelude::*, view};
 
-use crate::state::settings::{SonusmixSettings, DEFAULT_SETTINGS};
+use crate::state::settings::{FaderCurve, SonusmixSettings, DEFAULT_SETTINGS};
 use crate::state::{SonusmixReducer, SONUSMIX_SETTINGS};
 use crate::{MainMsg, MAIN_BROKER};
 
@@ -28,7 +30,22 @@ pub struct SettingsPage {
     lock_group_node_connections_binding: BoolBinding,
     show_group_node_change_warning_binding: BoolBinding,
     application_sources_include_monitors_binding: BoolBinding,
+    notify_lock_fights_binding: BoolBinding,
     volume_limit_binding: F64Binding,
+    volume_limit_db_binding: F64Binding,
+    fader_curve_binding: U32Binding,
+    recording_directory_binding: StringBinding,
+    osc_enabled_binding: BoolBinding,
+    osc_port_binding: F64Binding,
+    osc_bind_address_binding: StringBinding,
+    osc_feedback_port_binding: F64Binding,
+    push_to_talk_release_delay_ms_binding: F64Binding,
+    dim_cue_on_solo_binding: BoolBinding,
+    cue_dim_db_binding: F64Binding,
+    mute_fade_ms_binding: F64Binding,
+    unmute_fade_ms_binding: F64Binding,
+    remote_volume_fade_ms_binding: F64Binding,
+    profile_fade_ms_binding: F64Binding,
     confirm_clear_dialog: gtk::AlertDialog,
 }
 
@@ -88,8 +105,8 @@ impl Component for SettingsPage {
                         )),
                         #[template]
                         ConfigRow<gtk::Switch, BoolBinding> ((
-                            "Show the warning that connections will be broken when changing properties of a \
-                                group node",
+                            "Show the warning that some connections may be reset when changing the kind of \
+                                a group node",
                             model.show_group_node_change_warning_binding.clone(),
                             DEFAULT_SETTINGS.show_group_node_change_warning,
                         )),
@@ -100,6 +117,13 @@ impl Component for SettingsPage {
                             DEFAULT_SETTINGS.application_sources_include_monitors,
                         )),
                         #[template]
+                        ConfigRow<gtk::Switch, BoolBinding> ((
+                            "Show a notification when another program keeps changing something \
+                                that is locked",
+                            model.notify_lock_fights_binding.clone(),
+                            DEFAULT_SETTINGS.notify_lock_fights,
+                        )),
+                        #[template]
                         ConfigRow<gtk::SpinButton, F64Binding> ((
                             "Volume limit of the volume sliders (%)",
                             model.volume_limit_binding.clone(),
@@ -112,6 +136,186 @@ impl Component for SettingsPage {
                                 set_value: model.volume_limit_binding.get(),
                             }
                         },
+                        #[template]
+                        ConfigRow<gtk::SpinButton, F64Binding> ((
+                            "Volume limit of the volume sliders (dB)",
+                            model.volume_limit_db_binding.clone(),
+                            DEFAULT_SETTINGS.volume_limit_db(),
+                        )) {
+                            #[template_child]
+                            control {
+                                set_range: (-60.0, 20.0),
+                                set_increments: (0.5, 3.0),
+                                set_digits: 1,
+                                set_value: model.volume_limit_db_binding.get(),
+                            }
+                        },
+                        gtk::Box {
+                            set_orientation: gtk::Orientation::Horizontal,
+
+                            gtk::Label {
+                                set_hexpand: true,
+                                set_halign: gtk::Align::Start,
+                                set_margin_end: 48,
+                                set_label: "Curve of the volume sliders",
+                            },
+                            #[name(fader_curve_drop_down)]
+                            gtk::DropDown::from_strings(&FaderCurve::ALL.map(FaderCurve::name)) {
+                                set_margin_start: 8,
+                                set_valign: gtk::Align::Center,
+                            },
+                        },
+                        #[template]
+                        ConfigRow<gtk::Entry, StringBinding> ((
+                            "Directory to save recordings in",
+                            model.recording_directory_binding.clone(),
+                            DEFAULT_SETTINGS.recording_directory,
+                        )) {
+                            #[template_child]
+                            control {
+                                set_width_chars: 24,
+                                set_placeholder_text: DEFAULT_SETTINGS
+                                    .recording_directory()
+                                    .to_str(),
+                            }
+                        },
+                        #[template]
+                        ConfigRow<gtk::SpinButton, F64Binding> ((
+                            "Delay before releasing a push to talk key takes effect (ms)",
+                            model.push_to_talk_release_delay_ms_binding.clone(),
+                            DEFAULT_SETTINGS.push_to_talk_release_delay_ms as f64,
+                        )) {
+                            #[template_child]
+                            control {
+                                set_range: (0.0, 5000.0),
+                                set_increments: (50.0, 250.0),
+                                set_value: model.push_to_talk_release_delay_ms_binding.get(),
+                            }
+                        },
+                        #[template]
+                        ConfigRow<gtk::Switch, BoolBinding> ((
+                            "Dim the other sources connected to the cue output while soloing",
+                            model.dim_cue_on_solo_binding.clone(),
+                            DEFAULT_SETTINGS.dim_cue_on_solo,
+                        )),
+                        #[template]
+                        ConfigRow<gtk::SpinButton, F64Binding> ((
+                            "How much to dim the other cue sources by (dB)",
+                            model.cue_dim_db_binding.clone(),
+                            DEFAULT_SETTINGS.cue_dim_db,
+                        )) {
+                            #[template_child]
+                            control {
+                                set_range: (-60.0, 0.0),
+                                set_increments: (1.0, 6.0),
+                                set_value: model.cue_dim_db_binding.get(),
+                            }
+                        },
+                        #[template]
+                        ConfigRow<gtk::SpinButton, F64Binding> ((
+                            "Time to fade out before muting (ms)",
+                            model.mute_fade_ms_binding.clone(),
+                            DEFAULT_SETTINGS.mute_fade_ms as f64,
+                        )) {
+                            #[template_child]
+                            control {
+                                set_range: (0.0, 5000.0),
+                                set_increments: (10.0, 100.0),
+                                set_value: model.mute_fade_ms_binding.get(),
+                            }
+                        },
+                        #[template]
+                        ConfigRow<gtk::SpinButton, F64Binding> ((
+                            "Time to fade in after unmuting (ms)",
+                            model.unmute_fade_ms_binding.clone(),
+                            DEFAULT_SETTINGS.unmute_fade_ms as f64,
+                        )) {
+                            #[template_child]
+                            control {
+                                set_range: (0.0, 5000.0),
+                                set_increments: (10.0, 100.0),
+                                set_value: model.unmute_fade_ms_binding.get(),
+                            }
+                        },
+                        #[template]
+                        ConfigRow<gtk::SpinButton, F64Binding> ((
+                            "Time to fade to volumes set by OSC, MIDI or FadeVolume on the control socket (ms)",
+                            model.remote_volume_fade_ms_binding.clone(),
+                            DEFAULT_SETTINGS.remote_volume_fade_ms as f64,
+                        )) {
+                            #[template_child]
+                            control {
+                                set_range: (0.0, 5000.0),
+                                set_increments: (10.0, 100.0),
+                                set_value: model.remote_volume_fade_ms_binding.get(),
+                            }
+                        },
+                        #[template]
+                        ConfigRow<gtk::SpinButton, F64Binding> ((
+                            "Time to fade out and back in when switching device profiles (ms)",
+                            model.profile_fade_ms_binding.clone(),
+                            DEFAULT_SETTINGS.profile_fade_ms as f64,
+                        )) {
+                            #[template_child]
+                            control {
+                                set_range: (0.0, 5000.0),
+                                set_increments: (10.0, 100.0),
+                                set_value: model.profile_fade_ms_binding.get(),
+                            }
+                        },
+                    }
+                },
+
+                #[template]
+                ConfigSection("Remote Control") {
+                    #[template_child]
+                    contents {
+                        #[template]
+                        ConfigRow<gtk::Switch, BoolBinding> ((
+                            "Listen for OSC messages",
+                            model.osc_enabled_binding.clone(),
+                            DEFAULT_SETTINGS.osc_enabled,
+                        )),
+                        #[template]
+                        ConfigRow<gtk::SpinButton, F64Binding> ((
+                            "UDP port to listen for OSC messages on",
+                            model.osc_port_binding.clone(),
+                            DEFAULT_SETTINGS.osc_port as f64,
+                        )) {
+                            #[template_child]
+                            control {
+                                set_range: (1.0, u16::MAX as f64),
+                                set_increments: (1.0, 100.0),
+                                set_value: model.osc_port_binding.get(),
+                            }
+                        },
+                        #[template]
+                        ConfigRow<gtk::Entry, StringBinding> ((
+                            "Address to listen for OSC messages on (0.0.0.0 to allow other \
+                                computers)",
+                            model.osc_bind_address_binding.clone(),
+                            DEFAULT_SETTINGS.osc_bind_address,
+                        )) {
+                            #[template_child]
+                            control {
+                                set_width_chars: 16,
+                                set_placeholder_text: Some("127.0.0.1"),
+                            }
+                        },
+                        #[template]
+                        ConfigRow<gtk::SpinButton, F64Binding> ((
+                            "UDP port to send OSC feedback to (0 to reply to the port messages \
+                                were sent from)",
+                            model.osc_feedback_port_binding.clone(),
+                            DEFAULT_SETTINGS.osc_feedback_port as f64,
+                        )) {
+                            #[template_child]
+                            control {
+                                set_range: (0.0, u16::MAX as f64),
+                                set_increments: (1.0, 100.0),
+                                set_value: model.osc_feedback_port_binding.get(),
+                            }
+                        },
                     }
                 },
 
@@ -189,9 +393,69 @@ impl Component for SettingsPage {
                 .write()
                 .application_sources_include_monitors = b.get()
         });
+        let notify_lock_fights_binding = BoolBinding::new(settings.notify_lock_fights);
+        notify_lock_fights_binding
+            .connect_value_notify(|b| SONUSMIX_SETTINGS.write().notify_lock_fights = b.get());
         let volume_limit_binding = F64Binding::new(settings.volume_limit);
         volume_limit_binding
             .connect_value_notify(|v| SONUSMIX_SETTINGS.write().volume_limit = v.get());
+        let volume_limit_db_binding = F64Binding::new(settings.volume_limit_db());
+        volume_limit_db_binding.connect_value_notify(|v| {
+            // Converting between percent and decibels isn't exact, so only write changes that
+            // didn't come from the percent setting
+            if !approx_eq(SONUSMIX_SETTINGS.read().volume_limit_db(), v.get()) {
+                SONUSMIX_SETTINGS.write().set_volume_limit_db(v.get());
+            }
+        });
+        let fader_curve_binding = U32Binding::new(fader_curve_index(settings.fader_curve));
+        fader_curve_binding.connect_value_notify(|v| {
+            if let Some(curve) = FaderCurve::ALL.get(v.get() as usize) {
+                SONUSMIX_SETTINGS.write().fader_curve = *curve;
+            }
+        });
+        let recording_directory_binding = StringBinding::new(settings.recording_directory);
+        recording_directory_binding
+            .connect_value_notify(|s| SONUSMIX_SETTINGS.write().recording_directory = s.get());
+        let osc_enabled_binding = BoolBinding::new(settings.osc_enabled);
+        osc_enabled_binding
+            .connect_value_notify(|b| SONUSMIX_SETTINGS.write().osc_enabled = b.get());
+        let osc_port_binding = F64Binding::new(settings.osc_port as f64);
+        osc_port_binding
+            .connect_value_notify(|v| SONUSMIX_SETTINGS.write().osc_port = v.get() as u16);
+        let osc_bind_address_binding = StringBinding::new(settings.osc_bind_address);
+        osc_bind_address_binding
+            .connect_value_notify(|s| SONUSMIX_SETTINGS.write().osc_bind_address = s.get());
+        let osc_feedback_port_binding = F64Binding::new(settings.osc_feedback_port as f64);
+        osc_feedback_port_binding.connect_value_notify(|v| {
+            SONUSMIX_SETTINGS.write().osc_feedback_port = v.get() as u16
+        });
+        let push_to_talk_release_delay_ms_binding =
+            F64Binding::new(settings.push_to_talk_release_delay_ms as f64);
+        push_to_talk_release_delay_ms_binding.connect_value_notify(|v| {
+            SONUSMIX_SETTINGS.write().push_to_talk_release_delay_ms = v.get() as u32
+        });
+        let dim_cue_on_solo_binding = BoolBinding::new(settings.dim_cue_on_solo);
+        dim_cue_on_solo_binding
+            .connect_value_notify(|b| SONUSMIX_SETTINGS.write().dim_cue_on_solo = b.get());
+        let cue_dim_db_binding = F64Binding::new(settings.cue_dim_db);
+        cue_dim_db_binding
+            .connect_value_notify(|v| SONUSMIX_SETTINGS.write().cue_dim_db = v.get());
+        let mute_fade_ms_binding = F64Binding::new(settings.mute_fade_ms as f64);
+        mute_fade_ms_binding.connect_value_notify(|v| {
+            SONUSMIX_SETTINGS.write().mute_fade_ms = v.get() as u32
+        });
+        let unmute_fade_ms_binding = F64Binding::new(settings.unmute_fade_ms as f64);
+        unmute_fade_ms_binding.connect_value_notify(|v| {
+            SONUSMIX_SETTINGS.write().unmute_fade_ms = v.get() as u32
+        });
+        let remote_volume_fade_ms_binding = F64Binding::new(settings.remote_volume_fade_ms as f64);
+        remote_volume_fade_ms_binding.connect_value_notify(|v| {
+            SONUSMIX_SETTINGS.write().remote_volume_fade_ms = v.get() as u32
+        });
+        let profile_fade_ms_binding = F64Binding::new(settings.profile_fade_ms as f64);
+        profile_fade_ms_binding.connect_value_notify(|v| {
+            SONUSMIX_SETTINGS.write().profile_fade_ms = v.get() as u32
+        });
 
         let model = SettingsPage {
             collapse_to_tray_on_close_binding,
@@ -200,7 +464,22 @@ impl Component for SettingsPage {
             lock_group_node_connections_binding,
             show_group_node_change_warning_binding,
             application_sources_include_monitors_binding,
+            notify_lock_fights_binding,
             volume_limit_binding,
+            volume_limit_db_binding,
+            fader_curve_binding,
+            recording_directory_binding,
+            osc_enabled_binding,
+            osc_port_binding,
+            osc_bind_address_binding,
+            osc_feedback_port_binding,
+            push_to_talk_release_delay_ms_binding,
+            dim_cue_on_solo_binding,
+            cue_dim_db_binding,
+            mute_fade_ms_binding,
+            unmute_fade_ms_binding,
+            remote_volume_fade_ms_binding,
+            profile_fade_ms_binding,
             confirm_clear_dialog: gtk::AlertDialog::builder()
                 .message("Confirm clear")
                 .detail("Are you sure you want to clear state and/or settings?")
@@ -212,6 +491,12 @@ impl Component for SettingsPage {
         };
 
         let widgets = view_output!();
+        model
+            .fader_curve_binding
+            .bind_property("value", &widgets.fader_curve_drop_down, "selected")
+            .bidirectional()
+            .sync_create()
+            .build();
 
         ComponentParts { model, widgets }
     }
@@ -225,7 +510,46 @@ impl Component for SettingsPage {
                 update_property!(self, settings, lock_group_node_connections);
                 update_property!(self, settings, show_group_node_change_warning);
                 update_property!(self, settings, application_sources_include_monitors);
+                update_property!(self, settings, notify_lock_fights);
                 update_property!(self, settings, volume_limit);
+                if !approx_eq(self.volume_limit_db_binding.get(), settings.volume_limit_db()) {
+                    self.volume_limit_db_binding.set(settings.volume_limit_db());
+                }
+                let fader_curve = fader_curve_index(settings.fader_curve);
+                if self.fader_curve_binding.get() != fader_curve {
+                    self.fader_curve_binding.set(fader_curve);
+                }
+                update_property!(self, settings, recording_directory);
+                update_property!(self, settings, osc_enabled);
+                update_property!(self, settings, osc_bind_address);
+                // These settings are integers, but spin buttons use floats
+                if self.osc_port_binding.get() != settings.osc_port as f64 {
+                    self.osc_port_binding.set(settings.osc_port as f64);
+                }
+                if self.osc_feedback_port_binding.get() != settings.osc_feedback_port as f64 {
+                    self.osc_feedback_port_binding
+                        .set(settings.osc_feedback_port as f64);
+                }
+                if self.push_to_talk_release_delay_ms_binding.get()
+                    != settings.push_to_talk_release_delay_ms as f64
+                {
+                    self.push_to_talk_release_delay_ms_binding
+                        .set(settings.push_to_talk_release_delay_ms as f64);
+                }
+                update_property!(self, settings, dim_cue_on_solo);
+                update_property!(self, settings, cue_dim_db);
+                if self.mute_fade_ms_binding.get() != settings.mute_fade_ms as f64 {
+                    self.mute_fade_ms_binding.set(settings.mute_fade_ms as f64);
+                }
+                if self.unmute_fade_ms_binding.get() != settings.unmute_fade_ms as f64 {
+                    self.unmute_fade_ms_binding.set(settings.unmute_fade_ms as f64);
+                }
+                if self.remote_volume_fade_ms_binding.get() != settings.remote_volume_fade_ms as f64 {
+                    self.remote_volume_fade_ms_binding.set(settings.remote_volume_fade_ms as f64);
+                }
+                if self.profile_fade_ms_binding.get() != settings.profile_fade_ms as f64 {
+                    self.profile_fade_ms_binding.set(settings.profile_fade_ms as f64);
+                }
             }
             SettingsMsg::Save {
                 clear_state,
@@ -256,6 +580,18 @@ impl Component for SettingsPage {
     }
 }
 
+fn approx_eq(a: f64, b: f64) -> bool {
+    (a - b).abs() < 1e-6
+}
+
+/// The position of a fader curve in the drop down.
+fn fader_curve_index(curve: FaderCurve) -> u32 {
+    FaderCurve::ALL
+        .iter()
+        .position(|c| *c == curve)
+        .unwrap_or_default() as u32
+}
+
 #[relm4::widget_template(pub)]
 impl WidgetTemplate for ConfigSection {
     type Init = &'static str;
@@ -0,0 +1,348 @@
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use gtk::glib::Propagation;
+use relm4::actions::{RelmAction, RelmActionGroup};
+use relm4::prelude::*;
+use relm4::{factory::FactoryView, gtk::prelude::*};
+
+use crate::state::{
+    EndpointDescriptor, SonusmixMsg, SonusmixReducer, SonusmixState, Vca, VcaId, VCA_MAX_DB,
+    VCA_MIN_DB,
+};
+
+/// A fader strip for a VCA, which scales the volumes of its members together.
+pub struct VcaStrip {
+    pub vca: Vca,
+    renaming: bool,
+    name_buffer: gtk::EntryBuffer,
+    members: Controller<VcaMembers>,
+}
+
+#[derive(Debug, Clone)]
+pub enum VcaStripMsg {
+    UpdateState(Arc<SonusmixState>),
+    Gain(f64),
+    ToggleMute,
+    Remove,
+    StartRename,
+    FinishRename(bool),
+}
+
+relm4::new_action_group!(VcaMenuActionGroup, "vca-menu");
+relm4::new_stateless_action!(RemoveAction, VcaMenuActionGroup, "remove");
+relm4::new_stateless_action!(RenameAction, VcaMenuActionGroup, "rename");
+
+#[relm4::factory(pub)]
+impl FactoryComponent for VcaStrip {
+    type Init = VcaId;
+    type Input = VcaStripMsg;
+    type Output = Infallible;
+    type CommandOutput = Infallible;
+    type ParentWidget = gtk::Box;
+
+    view! {
+        gtk::Box {
+            set_orientation: gtk::Orientation::Vertical,
+            set_hexpand: false,
+            set_spacing: 8,
+            set_margin_all: 4,
+
+            gtk::Box {
+                set_orientation: gtk::Orientation::Horizontal,
+                set_spacing: 8,
+
+                if self.renaming {
+                    gtk::Entry::with_buffer(&self.name_buffer) {
+                        set_width_chars: 10,
+                        set_max_width_chars: 10,
+                        connect_map => |entry| { entry.grab_focus(); },
+                        connect_activate => VcaStripMsg::FinishRename(true),
+
+                        // Add an event controller to cancel renaming on Esc
+                        add_controller = gtk::EventControllerKey {
+                            connect_key_pressed[sender] => move |_, key, _, _| {
+                                if key == gtk::gdk::Key::Escape {
+                                    sender.input(VcaStripMsg::FinishRename(false));
+                                    Propagation::Stop
+                                } else {
+                                    Propagation::Proceed
+                                }
+                            }
+                        },
+                        add_controller = gtk::EventControllerFocus {
+                            connect_leave => VcaStripMsg::FinishRename(false),
+                        }
+                    }
+                } else {
+                    gtk::Label {
+                        set_justify: gtk::Justification::Center,
+                        set_width_chars: 10,
+                        set_max_width_chars: 10,
+                        set_ellipsize: gtk::pango::EllipsizeMode::End,
+                        set_css_classes: &["heading"],
+
+                        #[watch]
+                        set_label: &self.vca.name,
+                        #[watch]
+                        set_tooltip: &self.vca.name,
+
+                        // Start renaming when the user double-clicks the VCA name
+                        add_controller = gtk::GestureClick {
+                            connect_released[sender] => move |_, num_presses, _, _| {
+                                if num_presses >= 2 {
+                                    sender.input(VcaStripMsg::StartRename);
+                                }
+                            }
+                        }
+                    }
+                },
+                #[name(vca_menu_button)]
+                gtk::MenuButton {
+                    set_halign: gtk::Align::End,
+                    set_icon_name: "view-more-symbolic",
+                    set_menu_model: Some(&vca_menu)
+                },
+            },
+            gtk::Label {
+                add_css_class: "dim-label",
+                set_label: "VCA",
+            },
+            gtk::Scale {
+                set_orientation: gtk::Orientation::Vertical,
+                set_inverted: true,
+                set_vexpand: true,
+                set_range: (VCA_MIN_DB as f64, VCA_MAX_DB as f64),
+                set_increments: (1.0, 6.0),
+                add_mark: (0.0, gtk::PositionType::Right, None),
+
+                #[watch]
+                #[block_signal(gain_handler)]
+                set_value: self.vca.gain_db as f64,
+                connect_value_changed[sender] => move |scale| {
+                    sender.input(VcaStripMsg::Gain(scale.value()));
+                } @ gain_handler
+            },
+            gtk::Label {
+                #[watch]
+                set_label: &format!("{:+.1} dB", self.vca.gain_db),
+            },
+            gtk::Box {
+                set_orientation: gtk::Orientation::Horizontal,
+                set_halign: gtk::Align::Center,
+                set_spacing: 4,
+
+                gtk::ToggleButton {
+                    #[watch]
+                    set_icon_name: if self.vca.muted
+                        { "audio-volume-muted-symbolic" } else { "audio-volume-high-symbolic" },
+                    #[watch]
+                    set_tooltip: if self.vca.muted { "Unmute all members" } else { "Mute all members" },
+                    #[watch]
+                    set_css_classes: if self.vca.muted
+                        { &["destructive-action", "image-button"] } else { &["flat", "image-button"] },
+
+                    #[watch]
+                    set_active: self.vca.muted,
+                    connect_clicked => VcaStripMsg::ToggleMute,
+                },
+                gtk::MenuButton {
+                    set_icon_name: "view-list-symbolic",
+                    set_tooltip: "Members",
+                    set_popover: Some(self.members.widget()),
+                },
+            },
+        }
+    }
+
+    menu! {
+        vca_menu: {
+            "Remove" => RemoveAction,
+            "Rename" => RenameAction,
+        }
+    }
+
+    fn init_model(id: VcaId, _index: &DynamicIndex, sender: FactorySender<Self>) -> Self {
+        let sonusmix_state =
+            SonusmixReducer::subscribe(sender.input_sender(), VcaStripMsg::UpdateState);
+        let vca = sonusmix_state
+            .vcas
+            .get(&id)
+            .expect("VCA component failed to find matching VCA on init")
+            .clone();
+        let members = VcaMembers::builder().launch(id).detach();
+
+        Self {
+            vca,
+            renaming: false,
+            name_buffer: gtk::EntryBuffer::new(None::<&str>),
+            members,
+        }
+    }
+
+    fn init_widgets(
+        &mut self,
+        _index: &Self::Index,
+        root: Self::Root,
+        _returned_widget: &<Self::ParentWidget as FactoryView>::ReturnedWidget,
+        sender: FactorySender<Self>,
+    ) -> Self::Widgets {
+        let widgets = view_output!();
+
+        let mut group = RelmActionGroup::<VcaMenuActionGroup>::new();
+        let remove_action: RelmAction<RemoveAction> = RelmAction::new_stateless({
+            let sender = sender.clone();
+            move |_| {
+                sender.input(VcaStripMsg::Remove);
+            }
+        });
+        group.add_action(remove_action);
+        let rename_action: RelmAction<RenameAction> = RelmAction::new_stateless({
+            let sender = sender.clone();
+            move |_| {
+                sender.input(VcaStripMsg::StartRename);
+            }
+        });
+        group.add_action(rename_action);
+        group.register_for_widget(&widgets.vca_menu_button);
+
+        widgets
+    }
+
+    fn update(&mut self, msg: VcaStripMsg, _sender: FactorySender<Self>) {
+        match msg {
+            VcaStripMsg::UpdateState(state) => {
+                if let Some(vca) = state.vcas.get(&self.vca.id) {
+                    self.vca = vca.clone();
+                }
+            }
+            VcaStripMsg::Gain(gain_db) => {
+                SonusmixReducer::emit(SonusmixMsg::SetVcaGain(self.vca.id, gain_db as f32));
+            }
+            VcaStripMsg::ToggleMute => {
+                SonusmixReducer::emit(SonusmixMsg::SetVcaMute(self.vca.id, !self.vca.muted));
+            }
+            VcaStripMsg::Remove => SonusmixReducer::emit(SonusmixMsg::RemoveVca(self.vca.id)),
+            VcaStripMsg::StartRename => {
+                self.renaming = true;
+                self.name_buffer.set_text(&self.vca.name);
+            }
+            VcaStripMsg::FinishRename(confirm) => {
+                self.renaming = false;
+                let name = self.name_buffer.text().to_string();
+                if confirm && !name.is_empty() {
+                    SonusmixReducer::emit(SonusmixMsg::RenameVca(self.vca.id, name));
+                }
+            }
+        }
+    }
+}
+
+/// Lists every endpoint with a check box for whether it belongs to a VCA.
+pub struct VcaMembers {
+    id: VcaId,
+    /// Each endpoint that can be added, with its name and whether it's a member
+    shown: Vec<(EndpointDescriptor, String, bool)>,
+    list: gtk::Box,
+}
+
+#[derive(Debug)]
+pub enum VcaMembersMsg {
+    UpdateState(Arc<SonusmixState>),
+}
+
+#[relm4::component(pub)]
+impl SimpleComponent for VcaMembers {
+    type Init = VcaId;
+    type Input = VcaMembersMsg;
+    type Output = Infallible;
+
+    view! {
+        #[root]
+        gtk::Popover {
+            set_autohide: true,
+
+            gtk::ScrolledWindow {
+                set_propagate_natural_height: true,
+                set_max_content_height: 400,
+                set_policy: (gtk::PolicyType::Never, gtk::PolicyType::Automatic),
+
+                #[local_ref]
+                list -> gtk::Box {
+                    set_orientation: gtk::Orientation::Vertical,
+                    set_spacing: 4,
+                }
+            }
+        }
+    }
+
+    fn init(id: VcaId, root: Self::Root, sender: ComponentSender<Self>) -> ComponentParts<Self> {
+        let state = SonusmixReducer::subscribe(sender.input_sender(), VcaMembersMsg::UpdateState);
+
+        let mut model = VcaMembers {
+            id,
+            shown: Vec::new(),
+            list: gtk::Box::default(),
+        };
+        model.update_members(&state);
+
+        let list = &model.list;
+        let widgets = view_output!();
+
+        ComponentParts { model, widgets }
+    }
+
+    fn update(&mut self, msg: VcaMembersMsg, _sender: ComponentSender<Self>) {
+        match msg {
+            VcaMembersMsg::UpdateState(state) => self.update_members(&state),
+        }
+    }
+}
+
+impl VcaMembers {
+    fn update_members(&mut self, state: &SonusmixState) {
+        let Some(vca) = state.vcas.get(&self.id) else {
+            return;
+        };
+        let shown: Vec<_> = state
+            .active_sources
+            .iter()
+            .chain(&state.active_sinks)
+            .copied()
+            .chain(
+                state
+                    .group_nodes
+                    .keys()
+                    .map(|id| EndpointDescriptor::GroupNode(*id)),
+            )
+            .filter_map(|descriptor| {
+                let endpoint = state.endpoints.get(&descriptor)?;
+                Some((
+                    descriptor,
+                    endpoint.custom_or_display_name().to_owned(),
+                    vca.members.contains(&descriptor),
+                ))
+            })
+            .collect();
+        if shown == self.shown {
+            return;
+        }
+        self.shown = shown;
+
+        while let Some(child) = self.list.first_child() {
+            self.list.remove(&child);
+        }
+        let id = self.id;
+        for (descriptor, name, member) in &self.shown {
+            let descriptor = *descriptor;
+            let check = gtk::CheckButton::builder()
+                .label(name)
+                .active(*member)
+                .build();
+            check.connect_toggled(move |check| {
+                SonusmixReducer::emit(SonusmixMsg::SetVcaMember(id, descriptor, check.is_active()));
+            });
+            self.list.append(&check);
+        }
+    }
+}
@@ -0,0 +1,220 @@
+use relm4::gtk::prelude::*;
+use relm4::prelude::*;
+
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use crate::state::settings::SonusmixSettings;
+use crate::state::{
+    EndpointDescriptor, SonusmixMsg, SonusmixReducer, SonusmixState, SONUSMIX_SETTINGS,
+};
+
+use super::volume_spin_button::format_volume_spin_button;
+
+pub struct VolumeLimits {
+    endpoint_desc: EndpointDescriptor,
+    settings: SonusmixSettings,
+    max_volume: Option<f32>,
+    start_volume: Option<f32>,
+    /// Slider positions shown in the spin buttons, which are kept while a limit is turned off
+    max_volume_slider: f64,
+    start_volume_slider: f64,
+}
+
+#[derive(Debug)]
+pub enum VolumeLimitsMsg {
+    UpdateState(Arc<SonusmixState>),
+    UpdateSettings(SonusmixSettings),
+    MaxVolumeEnabled(bool),
+    MaxVolume(f64),
+    StartVolumeEnabled(bool),
+    StartVolume(f64),
+}
+
+#[relm4::component(pub)]
+impl SimpleComponent for VolumeLimits {
+    type Init = EndpointDescriptor;
+    type Input = VolumeLimitsMsg;
+    type Output = Infallible;
+
+    view! {
+        #[root]
+        gtk::Popover {
+            set_autohide: true,
+
+            gtk::Grid {
+                set_row_spacing: 4,
+                set_column_spacing: 8,
+
+                attach[0, 0, 1, 1] = &gtk::CheckButton {
+                    set_label: Some("Limit volume to"),
+                    set_tooltip: "Never set this endpoint above this volume, even if the volume slider limit is higher",
+
+                    #[watch]
+                    #[block_signal(max_volume_enabled_handler)]
+                    set_active: model.max_volume.is_some(),
+                    connect_toggled[sender] => move |check| {
+                        sender.input(VolumeLimitsMsg::MaxVolumeEnabled(check.is_active()));
+                    } @max_volume_enabled_handler
+                },
+                #[name(max_volume_spin_button)]
+                attach[1, 0, 1, 1] = &gtk::SpinButton {
+                    set_width_chars: 14,
+                    set_increments: (1.0, 5.0),
+                    #[watch]
+                    set_range: (0.0, model.settings.slider_limit()),
+                    #[watch]
+                    set_sensitive: model.max_volume.is_some(),
+
+                    #[watch]
+                    #[block_signal(max_volume_handler)]
+                    set_value: model.max_volume_slider,
+                    connect_value_changed[sender] => move |spin_button| {
+                        sender.input(VolumeLimitsMsg::MaxVolume(spin_button.value()));
+                    } @max_volume_handler
+                },
+                attach[0, 1, 1, 1] = &gtk::CheckButton {
+                    set_label: Some("Start at"),
+                    set_tooltip: "Set this endpoint to this volume whenever it reappears",
+                    set_visible: model.has_start_volume(),
+
+                    #[watch]
+                    #[block_signal(start_volume_enabled_handler)]
+                    set_active: model.start_volume.is_some(),
+                    connect_toggled[sender] => move |check| {
+                        sender.input(VolumeLimitsMsg::StartVolumeEnabled(check.is_active()));
+                    } @start_volume_enabled_handler
+                },
+                #[name(start_volume_spin_button)]
+                attach[1, 1, 1, 1] = &gtk::SpinButton {
+                    set_width_chars: 14,
+                    set_increments: (1.0, 5.0),
+                    set_visible: model.has_start_volume(),
+                    #[watch]
+                    set_range: (0.0, model.settings.slider_limit()),
+                    #[watch]
+                    set_sensitive: model.start_volume.is_some(),
+
+                    #[watch]
+                    #[block_signal(start_volume_handler)]
+                    set_value: model.start_volume_slider,
+                    connect_value_changed[sender] => move |spin_button| {
+                        sender.input(VolumeLimitsMsg::StartVolume(spin_button.value()));
+                    } @start_volume_handler
+                },
+            }
+        }
+    }
+
+    fn init(
+        endpoint_desc: EndpointDescriptor,
+        root: Self::Root,
+        sender: ComponentSender<Self>,
+    ) -> ComponentParts<Self> {
+        let sonusmix_state =
+            SonusmixReducer::subscribe(sender.input_sender(), VolumeLimitsMsg::UpdateState);
+        SONUSMIX_SETTINGS.subscribe(sender.input_sender(), |settings| {
+            VolumeLimitsMsg::UpdateSettings(settings.clone())
+        });
+        let settings = { SONUSMIX_SETTINGS.read().clone() };
+
+        let full_volume = settings.fader_curve.volume_to_slider(1.0);
+        let mut model = Self {
+            endpoint_desc,
+            settings,
+            max_volume: None,
+            start_volume: None,
+            max_volume_slider: full_volume,
+            start_volume_slider: full_volume,
+        };
+        model.update_limits(&sonusmix_state);
+
+        let widgets = view_output!();
+        format_volume_spin_button(&widgets.max_volume_spin_button);
+        format_volume_spin_button(&widgets.start_volume_spin_button);
+
+        ComponentParts { model, widgets }
+    }
+
+    fn update(&mut self, msg: VolumeLimitsMsg, _sender: ComponentSender<Self>) {
+        match msg {
+            VolumeLimitsMsg::UpdateState(sonusmix_state) => {
+                self.update_limits(&sonusmix_state);
+            }
+            VolumeLimitsMsg::UpdateSettings(settings) => {
+                self.settings = settings;
+                self.update_sliders();
+            }
+            VolumeLimitsMsg::MaxVolumeEnabled(enabled) => {
+                SonusmixReducer::emit(SonusmixMsg::SetMaxVolume(
+                    self.endpoint_desc,
+                    enabled.then(|| {
+                        self.settings
+                            .fader_curve
+                            .slider_to_volume(self.max_volume_slider)
+                    }),
+                ));
+            }
+            VolumeLimitsMsg::MaxVolume(slider) => {
+                self.max_volume_slider = slider;
+                if self.max_volume.is_some() {
+                    SonusmixReducer::emit(SonusmixMsg::SetMaxVolume(
+                        self.endpoint_desc,
+                        Some(self.settings.fader_curve.slider_to_volume(slider)),
+                    ));
+                }
+            }
+            VolumeLimitsMsg::StartVolumeEnabled(enabled) => {
+                SonusmixReducer::emit(SonusmixMsg::SetStartVolume(
+                    self.endpoint_desc,
+                    enabled.then(|| {
+                        self.settings
+                            .fader_curve
+                            .slider_to_volume(self.start_volume_slider)
+                    }),
+                ));
+            }
+            VolumeLimitsMsg::StartVolume(slider) => {
+                self.start_volume_slider = slider;
+                if self.start_volume.is_some() {
+                    SonusmixReducer::emit(SonusmixMsg::SetStartVolume(
+                        self.endpoint_desc,
+                        Some(self.settings.fader_curve.slider_to_volume(slider)),
+                    ));
+                }
+            }
+        }
+    }
+}
+
+impl VolumeLimits {
+    /// Only persistent, application and media role endpoints can disappear and come back.
+    fn has_start_volume(&self) -> bool {
+        matches!(
+            self.endpoint_desc,
+            EndpointDescriptor::PersistentNode(..)
+                | EndpointDescriptor::Application(..)
+                | EndpointDescriptor::MediaRole(..)
+        )
+    }
+
+    fn update_limits(&mut self, sonusmix_state: &SonusmixState) {
+        let Some(endpoint) = sonusmix_state.endpoints.get(&self.endpoint_desc) else {
+            return;
+        };
+        self.max_volume = endpoint.max_volume;
+        self.start_volume = endpoint.start_volume;
+        self.update_sliders();
+    }
+
+    /// Move the spin buttons to the current limits, which depend on the fader curve.
+    fn update_sliders(&mut self) {
+        let curve = self.settings.fader_curve;
+        if let Some(max_volume) = self.max_volume {
+            self.max_volume_slider = curve.volume_to_slider(max_volume);
+        }
+        if let Some(start_volume) = self.start_volume {
+            self.start_volume_slider = curve.volume_to_slider(start_volume);
+        }
+    }
+}
@@ -0,0 +1,30 @@
+use relm4::gtk::{self, glib::Propagation, prelude::*};
+
+use crate::state::SONUSMIX_SETTINGS;
+
+/// Make a spin button show the value of a volume slider as a percentage and in decibels, and
+/// allow typing an exact value in either.
+pub fn attach_volume_spin_button(spin_button: &gtk::SpinButton, scale: &gtk::Scale) {
+    spin_button.set_adjustment(&scale.adjustment());
+    format_volume_spin_button(spin_button);
+}
+
+/// Make a spin button whose value is a volume slider position show it as a percentage and in
+/// decibels.
+pub fn format_volume_spin_button(spin_button: &gtk::SpinButton) {
+    spin_button.set_numeric(false);
+    spin_button.connect_output(|spin_button| {
+        let curve = SONUSMIX_SETTINGS.read().fader_curve;
+        spin_button.set_text(&curve.format_volume(curve.slider_to_volume(spin_button.value())));
+        Propagation::Stop
+    });
+    spin_button.connect_input(|spin_button| {
+        let curve = SONUSMIX_SETTINGS.read().fader_curve;
+        Some(
+            curve
+                .parse_volume(&spin_button.text())
+                .map(|volume| curve.volume_to_slider(volume))
+                .ok_or(()),
+        )
+    });
+}
@@ -1,18 +1,31 @@
 mod components;
+mod osc;
 mod pipewire_api;
+mod rpc;
+mod shortcuts;
 mod state;
 mod tray;
 
 use std::{
     convert::Infallible,
-    sync::atomic::{AtomicI32, Ordering},
+    sync::{
+        atomic::{AtomicI32, Ordering},
+        Arc,
+    },
+    time::SystemTime,
 };
 
 use components::app::{App, Msg};
 use log::debug;
 use pipewire_api::PipewireHandle;
-use relm4::{gtk::prelude::*, prelude::*, MessageBroker, Sender};
-use state::{settings::SonusmixSettings, SonusmixReducer, SONUSMIX_SETTINGS};
+use relm4::{
+    gtk::{gio, prelude::*},
+    prelude::*,
+    MessageBroker, Sender,
+};
+use state::{
+    settings::SonusmixSettings, SonusmixMsg, SonusmixReducer, SonusmixState, SONUSMIX_SETTINGS,
+};
 use tray::SonusmixTray;
 
 const SONUSMIX_APP_ID: &str = "org.sonusmix.Sonusmix";
@@ -47,12 +60,16 @@ pub struct Main {
     _pipewire_handle: Option<PipewireHandle>,
     tray_handle: ksni::Handle<SonusmixTray>,
     app: Option<Controller<App>>,
+    /// When the newest activity log entry that has been checked for lock fights was added
+    last_activity: Option<SystemTime>,
 }
 
 #[derive(Debug, Clone)]
 pub enum MainMsg {
     #[doc(hidden)]
     UpdateSettings(SonusmixSettings),
+    #[doc(hidden)]
+    UpdateState(Arc<SonusmixState>),
     Show,
     Hide,
     Exit,
@@ -82,7 +99,12 @@ impl SimpleComponent for Main {
             .filter(|v| !v.is_empty())
             .is_none()
         {
-            Some(PipewireHandle::init((tx, rx), update_fn).expect("failed to connect to Pipewire"))
+            Some(
+                PipewireHandle::init((tx, rx), update_fn, |events| {
+                    SonusmixReducer::emit(SonusmixMsg::MidiInput(events))
+                })
+                .expect("failed to connect to Pipewire"),
+            )
         } else {
             None
         };
@@ -90,7 +112,12 @@ impl SimpleComponent for Main {
         SONUSMIX_SETTINGS.subscribe(sender.input_sender(), |settings| {
             MainMsg::UpdateSettings(settings.clone())
         });
+        let state = SonusmixReducer::subscribe(sender.input_sender(), MainMsg::UpdateState);
+        relm4::spawn(osc::osc_task());
+        relm4::spawn(rpc::rpc_task());
+        relm4::spawn(shortcuts::shortcuts_task());
         let settings = { SONUSMIX_SETTINGS.read().clone() };
+        let last_activity = state.activity_log.back().map(|entry| entry.time);
 
         let app = (!settings.start_collapsed_to_tray).then(|| {
             App::builder()
@@ -99,7 +126,11 @@ impl SimpleComponent for Main {
                 .detach()
         });
 
-        let tray_service = ksni::TrayService::new(SonusmixTray::new(sender.input_sender().clone()));
+        let tray_service = ksni::TrayService::new(SonusmixTray::new(
+            sender.input_sender().clone(),
+            state,
+            settings.clone(),
+        ));
         let tray_handle = tray_service.handle();
         tray_service.spawn();
 
@@ -108,6 +139,7 @@ impl SimpleComponent for Main {
             _pipewire_handle: pipewire_handle,
             tray_handle,
             app,
+            last_activity,
         };
 
         let widgets = view_output!();
@@ -118,8 +150,14 @@ impl SimpleComponent for Main {
     fn update(&mut self, msg: Self::Input, _sender: ComponentSender<Self>) {
         match msg {
             MainMsg::UpdateSettings(settings) => {
+                self.tray_handle
+                    .update(|tray| tray.settings = settings.clone());
                 self.settings = settings;
             }
+            MainMsg::UpdateState(state) => {
+                self.notify_lock_fights(&state);
+                self.tray_handle.update(|tray| tray.state = state);
+            }
             MainMsg::Show => {
                 if let Some(ref app) = self.app {
                     app.emit(Msg::BringToTop);
@@ -147,7 +185,37 @@ impl SimpleComponent for Main {
     }
 
     fn shutdown(&mut self, _widgets: &mut Self::Widgets, _output: Sender<Self::Output>) {
+        // Stop recordings before the reducer exits, so their files are finalized before Pipewire
+        // shuts down. Solo links and dim gain nodes linger after Sonusmix exits, so they are
+        // removed too.
+        SonusmixReducer::emit(SonusmixMsg::StopAllRecordings);
+        SonusmixReducer::emit(SonusmixMsg::ReleaseAllSolos);
         SonusmixReducer::save_and_exit();
+        rpc::remove_socket();
         self.tray_handle.shutdown();
     }
 }
+
+impl Main {
+    /// Show a notification for each lock that another client started fighting over since the
+    /// last state update.
+    fn notify_lock_fights(&mut self, state: &SonusmixState) {
+        let new_entries = state.activity_log.iter().filter(|entry| {
+            self.last_activity
+                .map_or(true, |last_activity| entry.time > last_activity)
+        });
+        if self.settings.notify_lock_fights {
+            for entry in new_entries.filter(|entry| entry.started_lock_fight()) {
+                let notification = gio::Notification::new("Another program keeps changing a lock");
+                notification.set_body(Some(&match &entry.client {
+                    Some(client) => format!("{} (by {client})", entry.description),
+                    None => entry.description.clone(),
+                }));
+                relm4::main_application().send_notification(None, &notification);
+            }
+        }
+        if let Some(entry) = state.activity_log.back() {
+            self.last_activity = Some(entry.time);
+        }
+    }
+}
@@ -0,0 +1,538 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use log::{debug, error, info, warn};
+use rosc::{OscMessage, OscPacket, OscType};
+use tokio::net::UdpSocket;
+
+use crate::pipewire_api::PortKind;
+use crate::state::settings::SonusmixSettings;
+use crate::state::{
+    set_link_messages, Endpoint, MuteGroupTarget, SonusmixMsg, SonusmixReducer, SonusmixState,
+    SONUSMIX_SETTINGS,
+};
+
+const ENDPOINT_ADDRESS_PREFIX: &str = "/sonusmix/endpoint/";
+const MUTE_GROUP_ADDRESS_PREFIX: &str = "/sonusmix/mute_group/";
+const MUTE_ALL_SOURCES_ADDRESS: &str = "/sonusmix/mute_all_sources";
+/// The most clients feedback is sent to at once. When another one registers, the client that was
+/// heard from least recently is dropped.
+const MAX_CLIENTS: usize = 8;
+/// Clients that haven't sent a message for this long stop getting feedback.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// Convert an endpoint name to the form used in OSC addresses, by replacing characters that OSC
+/// doesn't allow in addresses with underscores.
+fn osc_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_whitespace() || c.is_control() || "#*,/?[]{}".contains(c) {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
+/// Translate an incoming OSC message to Sonusmix messages. Supported addresses are:
+///
+/// | Address | Arguments |
+/// |---------|-----------|
+/// | `/sonusmix/endpoint/<name>/volume` | The slider position, from 0 to 1 |
+/// | `/sonusmix/endpoint/<name>/mute` | Whether to mute, or nothing to toggle |
+/// | `/sonusmix/endpoint/<name>/lock` | Whether to lock the volume, or nothing to toggle |
+/// | `/sonusmix/endpoint/<source>/link/<sink>` | Whether to connect, or nothing to toggle |
+/// | `/sonusmix/mute_group/<name>` | Whether to engage the mute group, or nothing to toggle |
+/// | `/sonusmix/mute_all_sources` | Whether to mute every source, or nothing to toggle |
+///
+/// Names are matched against the endpoints' and mute groups' names as returned by [`osc_name`].
+/// If multiple endpoints or mute groups share a name, all of them are changed. Returns None if the
+/// address isn't one of the above.
+fn handle_message(
+    state: &SonusmixState,
+    settings: &SonusmixSettings,
+    message: &OscMessage,
+) -> Option<Vec<SonusmixMsg>> {
+    let arg = message.args.first();
+    if message.addr == MUTE_ALL_SOURCES_ADDRESS {
+        let target = MuteGroupTarget::AllSources;
+        let engage = arg
+            .and_then(arg_to_bool)
+            .unwrap_or(!state.is_mute_group_engaged(target));
+        return Some(vec![SonusmixMsg::SetMuteGroupEngaged(target, engage)]);
+    }
+    if let Some(name) = message.addr.strip_prefix(MUTE_GROUP_ADDRESS_PREFIX) {
+        return Some(
+            state
+                .mute_groups
+                .values()
+                .filter(|group| osc_name(&group.name) == name)
+                .map(|group| {
+                    let engage = arg.and_then(arg_to_bool).unwrap_or(group.held.is_none());
+                    SonusmixMsg::SetMuteGroupEngaged(MuteGroupTarget::Group(group.id), engage)
+                })
+                .collect(),
+        );
+    }
+    let Some(path) = message.addr.strip_prefix(ENDPOINT_ADDRESS_PREFIX) else {
+        debug!("Unknown OSC address {}", message.addr);
+        return None;
+    };
+    let parts: Vec<&str> = path.split('/').collect();
+    let messages = match parts.as_slice() {
+        [name, "volume"] => {
+            let Some(value) = arg.and_then(arg_to_f32) else {
+                return Some(Vec::new());
+            };
+            let volume = settings
+                .fader_curve
+                .slider_to_volume(value.clamp(0.0, 1.0) as f64 * settings.slider_limit());
+            endpoints_named(state, name)
+                .map(|endpoint| SonusmixMsg::FadeVolume(endpoint.descriptor, volume))
+                .collect()
+        }
+        [name, "mute"] => endpoints_named(state, name)
+            .map(|endpoint| {
+                let muted = endpoint.volume_locked_muted.is_muted().unwrap_or(false);
+                let mute = arg.and_then(arg_to_bool).unwrap_or(!muted);
+                SonusmixMsg::SetMute(endpoint.descriptor, mute)
+            })
+            .collect(),
+        [name, "lock"] => endpoints_named(state, name)
+            .map(|endpoint| {
+                let locked = endpoint.volume_locked_muted.is_locked();
+                let lock = arg.and_then(arg_to_bool).unwrap_or(!locked);
+                SonusmixMsg::SetVolumeLocked(endpoint.descriptor, lock)
+            })
+            .collect(),
+        [source_name, "link", sink_name] => {
+            let sinks: Vec<&Endpoint> = endpoints_named(state, sink_name)
+                .filter(|endpoint| endpoint.descriptor.is_kind(PortKind::Sink))
+                .collect();
+            endpoints_named(state, source_name)
+                .filter(|endpoint| endpoint.descriptor.is_kind(PortKind::Source))
+                .flat_map(|source| sinks.iter().map(move |sink| (source, sink)))
+                .flat_map(|(source, sink)| {
+                    let connected = state.is_link_connected(source.descriptor, sink.descriptor);
+                    let connect = arg.and_then(arg_to_bool).unwrap_or(!connected);
+                    set_link_messages(source.descriptor, sink.descriptor, connect, settings)
+                })
+                .collect()
+        }
+        _ => {
+            debug!("Unknown OSC address {}", message.addr);
+            return None;
+        }
+    };
+    Some(messages)
+}
+
+fn endpoints_named<'a>(
+    state: &'a SonusmixState,
+    name: &'a str,
+) -> impl Iterator<Item = &'a Endpoint> + 'a {
+    state
+        .endpoints
+        .values()
+        .filter(move |endpoint| osc_name(endpoint.custom_or_display_name()) == name)
+}
+
+fn arg_to_f32(arg: &OscType) -> Option<f32> {
+    match *arg {
+        OscType::Float(value) => Some(value),
+        OscType::Double(value) => Some(value as f32),
+        OscType::Int(value) => Some(value as f32),
+        OscType::Long(value) => Some(value as f32),
+        OscType::Bool(value) => Some(if value { 1.0 } else { 0.0 }),
+        _ => None,
+    }
+}
+
+fn arg_to_bool(arg: &OscType) -> Option<bool> {
+    arg_to_f32(arg).map(|value| value >= 0.5)
+}
+
+/// The values of every address that feedback is sent for.
+fn feedback_values(state: &SonusmixState, settings: &SonusmixSettings) -> HashMap<String, f32> {
+    let toggle_value = |on: bool| if on { 1.0 } else { 0.0 };
+    let mut values = HashMap::new();
+    for endpoint in state.endpoints.values() {
+        let prefix = format!(
+            "{ENDPOINT_ADDRESS_PREFIX}{}",
+            osc_name(endpoint.custom_or_display_name())
+        );
+        let slider =
+            settings.fader_curve.volume_to_slider(endpoint.volume) / settings.slider_limit();
+        values.insert(format!("{prefix}/volume"), slider.clamp(0.0, 1.0) as f32);
+        values.insert(
+            format!("{prefix}/mute"),
+            toggle_value(endpoint.volume_locked_muted.is_muted().unwrap_or(false)),
+        );
+        values.insert(
+            format!("{prefix}/lock"),
+            toggle_value(endpoint.volume_locked_muted.is_locked()),
+        );
+    }
+    for link in &state.links {
+        let (Some(source), Some(sink)) = (
+            state.endpoints.get(&link.start),
+            state.endpoints.get(&link.end),
+        ) else {
+            continue;
+        };
+        values.insert(
+            format!(
+                "{ENDPOINT_ADDRESS_PREFIX}{}/link/{}",
+                osc_name(source.custom_or_display_name()),
+                osc_name(sink.custom_or_display_name()),
+            ),
+            toggle_value(link.state.is_connected().unwrap_or(false)),
+        );
+    }
+    for group in state.mute_groups.values() {
+        values.insert(
+            format!("{MUTE_GROUP_ADDRESS_PREFIX}{}", osc_name(&group.name)),
+            toggle_value(group.held.is_some()),
+        );
+    }
+    values.insert(
+        MUTE_ALL_SOURCES_ADDRESS.to_owned(),
+        toggle_value(state.is_mute_group_engaged(MuteGroupTarget::AllSources)),
+    );
+    values
+}
+
+struct OscServer {
+    address: SocketAddr,
+    socket: Arc<UdpSocket>,
+    /// Where to send feedback to, and when each client was last heard from. Clients are added
+    /// when they first send a message with a known address.
+    clients: HashMap<SocketAddr, Instant>,
+    sent_values: HashMap<String, f32>,
+}
+
+impl OscServer {
+    async fn bind(address: SocketAddr) -> std::io::Result<Self> {
+        let socket = UdpSocket::bind(address).await?;
+        info!("Listening for OSC messages on {address}");
+        Ok(Self {
+            address,
+            socket: Arc::new(socket),
+            clients: HashMap::new(),
+            sent_values: HashMap::new(),
+        })
+    }
+
+    /// Stop sending feedback to clients that have gone quiet.
+    fn expire_clients(&mut self) {
+        self.clients
+            .retain(|_, last_seen| last_seen.elapsed() < CLIENT_TIMEOUT);
+    }
+
+    /// Start sending feedback to a client, or note that it is still around. Returns whether it
+    /// is a new client.
+    fn register_client(&mut self, client: SocketAddr) -> bool {
+        self.expire_clients();
+        let is_new = self.clients.insert(client, Instant::now()).is_none();
+        if is_new && self.clients.len() > MAX_CLIENTS {
+            if let Some(oldest) = self
+                .clients
+                .iter()
+                .min_by_key(|(_, last_seen)| **last_seen)
+                .map(|(client, _)| *client)
+            {
+                self.clients.remove(&oldest);
+            }
+        }
+        is_new
+    }
+
+    async fn handle_packet(
+        &mut self,
+        bytes: &[u8],
+        from: SocketAddr,
+        state: &SonusmixState,
+        settings: &SonusmixSettings,
+    ) {
+        let packet = match rosc::decoder::decode_udp(bytes) {
+            Ok((_, packet)) => packet,
+            Err(err) => {
+                debug!("Received invalid OSC packet from {from}: {err:?}");
+                return;
+            }
+        };
+        let mut messages = Vec::new();
+        flatten_packet(packet, &mut messages);
+        let mut recognized = false;
+        for message in messages {
+            if let Some(msgs) = handle_message(state, settings, &message) {
+                recognized = true;
+                for msg in msgs {
+                    SonusmixReducer::emit(msg);
+                }
+            }
+        }
+        // Only reply to clients that actually speak Sonusmix's OSC, so that random packets can't
+        // make it send feedback to arbitrary addresses
+        if !recognized {
+            return;
+        }
+
+        let mut client = from;
+        if settings.osc_feedback_port != 0 {
+            client.set_port(settings.osc_feedback_port);
+        }
+        if self.register_client(client) {
+            // Bring the new client up to date
+            for (address, value) in &self.sent_values {
+                self.send(client, address, *value).await;
+            }
+        }
+    }
+
+    /// Send any values that changed since the last time to all clients.
+    async fn send_feedback(&mut self, state: &SonusmixState, settings: &SonusmixSettings) {
+        let mut values = feedback_values(state, settings);
+        // Addresses that no longer exist (e.g. removed links) are reset
+        for (address, value) in &self.sent_values {
+            if !values.contains_key(address) && *value != 0.0 {
+                values.insert(address.clone(), 0.0);
+            }
+        }
+        let changed: Vec<(String, f32)> = values
+            .iter()
+            .filter(|(address, value)| self.sent_values.get(*address) != Some(value))
+            .map(|(address, value)| (address.clone(), *value))
+            .collect();
+        self.expire_clients();
+        let clients: Vec<SocketAddr> = self.clients.keys().copied().collect();
+        for client in clients {
+            for (address, value) in &changed {
+                self.send(client, address, *value).await;
+            }
+        }
+        self.sent_values = values;
+    }
+
+    async fn send(&self, client: SocketAddr, address: &str, value: f32) {
+        let packet = OscPacket::Message(OscMessage {
+            addr: address.to_owned(),
+            args: vec![OscType::Float(value)],
+        });
+        let result = match rosc::encoder::encode(&packet) {
+            Ok(bytes) => self.socket.send_to(&bytes, client).await.map(|_| ()),
+            Err(err) => {
+                error!("Failed to encode OSC message for {address}: {err:?}");
+                return;
+            }
+        };
+        if let Err(err) = result {
+            debug!("Failed to send OSC feedback to {client}: {err:?}");
+        }
+    }
+}
+
+fn flatten_packet(packet: OscPacket, messages: &mut Vec<OscMessage>) {
+    match packet {
+        OscPacket::Message(message) => messages.push(message),
+        OscPacket::Bundle(bundle) => {
+            for packet in bundle.content {
+                flatten_packet(packet, messages);
+            }
+        }
+    }
+}
+
+/// Runs the OSC server while it is enabled in the settings, restarting it whenever its address or
+/// port changes.
+pub async fn osc_task() {
+    let (state_tx, state_rx) = relm4::channel();
+    let (settings_tx, settings_rx) = relm4::channel();
+
+    let mut state = SonusmixReducer::subscribe(&state_tx, |state| state);
+    let mut settings = { SONUSMIX_SETTINGS.read().clone() };
+    SONUSMIX_SETTINGS.subscribe(&settings_tx, Clone::clone);
+    let mut server: Option<OscServer> = None;
+    let mut warned_invalid_address = false;
+
+    loop {
+        let bind_address = settings.osc_bind_address();
+        if settings.osc_enabled && bind_address.is_none() && !warned_invalid_address {
+            warn!(
+                "Not starting the OSC server, as '{}' is not a valid address",
+                settings.osc_bind_address
+            );
+        }
+        warned_invalid_address = settings.osc_enabled && bind_address.is_none();
+        let address = bind_address
+            .filter(|_| settings.osc_enabled)
+            .map(|ip| SocketAddr::new(ip, settings.osc_port));
+        if server.as_ref().map(|server| server.address) != address {
+            server = None;
+            if let Some(address) = address {
+                match OscServer::bind(address).await {
+                    Ok(new_server) => server = Some(new_server),
+                    Err(err) => error!("Failed to start the OSC server on {address}: {err}"),
+                }
+            }
+        }
+        if let Some(server) = &mut server {
+            server.send_feedback(&state, &settings).await;
+        }
+
+        let socket = server.as_ref().map(|server| server.socket.clone());
+        let receive = async move {
+            let Some(socket) = socket else {
+                return std::future::pending().await;
+            };
+            let mut buffer = vec![0; rosc::decoder::MTU];
+            let (length, from) = socket.recv_from(&mut buffer).await?;
+            buffer.truncate(length);
+            Ok::<_, std::io::Error>((buffer, from))
+        };
+
+        tokio::select! {
+            Some(new_state) = state_rx.recv() => {
+                state = new_state;
+            }
+            Some(new_settings) = settings_rx.recv() => {
+                settings = new_settings;
+            }
+            result = receive => match result {
+                Ok((bytes, from)) => {
+                    if let Some(server) = &mut server {
+                        server.handle_packet(&bytes, from, &state, &settings).await;
+                    }
+                }
+                Err(err) => error!("Failed to receive OSC message: {err}"),
+            },
+            else => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::EndpointDescriptor;
+
+    fn test_state() -> SonusmixState {
+        let mut state = SonusmixState::default();
+        for (id, kind, name) in [
+            (1, PortKind::Source, "Music Player"),
+            (2, PortKind::Sink, "Speakers"),
+        ] {
+            let descriptor = EndpointDescriptor::EphemeralNode(id, kind);
+            let mut endpoint = Endpoint::new_test(descriptor);
+            endpoint.custom_name = Some(name.to_owned());
+            state.endpoints.insert(descriptor, endpoint);
+        }
+        state
+    }
+
+    fn message(addr: &str, args: Vec<OscType>) -> OscMessage {
+        OscMessage {
+            addr: addr.to_owned(),
+            args,
+        }
+    }
+
+    #[test]
+    fn osc_messages() {
+        let state = test_state();
+        let settings = SonusmixSettings::default();
+        let source = EndpointDescriptor::EphemeralNode(1, PortKind::Source);
+        let sink = EndpointDescriptor::EphemeralNode(2, PortKind::Sink);
+
+        let messages = handle_message(
+            &state,
+            &settings,
+            &message(
+                "/sonusmix/endpoint/Music_Player/volume",
+                vec![OscType::Float(1.0)],
+            ),
+        )
+        .unwrap();
+        assert!(matches!(
+            messages.as_slice(),
+            [SonusmixMsg::FadeVolume(desc, volume)] if *desc == source && *volume == 1.0
+        ));
+
+        // Without an argument, mute is toggled
+        let messages = handle_message(
+            &state,
+            &settings,
+            &message("/sonusmix/endpoint/Speakers/mute", Vec::new()),
+        )
+        .unwrap();
+        assert!(matches!(
+            messages.as_slice(),
+            [SonusmixMsg::SetMute(desc, true)] if *desc == sink
+        ));
+
+        let messages = handle_message(
+            &state,
+            &settings,
+            &message(
+                "/sonusmix/endpoint/Music_Player/link/Speakers",
+                vec![OscType::Int(1)],
+            ),
+        )
+        .unwrap();
+        assert!(matches!(
+            messages.as_slice(),
+            [SonusmixMsg::Link(start, end)] if *start == source && *end == sink
+        ));
+
+        // Links must go from a source to a sink
+        let messages = handle_message(
+            &state,
+            &settings,
+            &message("/sonusmix/endpoint/Speakers/link/Music_Player", Vec::new()),
+        )
+        .unwrap();
+        assert!(messages.is_empty());
+
+        let messages = handle_message(
+            &state,
+            &settings,
+            &message("/sonusmix/mute_all_sources", Vec::new()),
+        )
+        .unwrap();
+        assert!(matches!(
+            messages.as_slice(),
+            [SonusmixMsg::SetMuteGroupEngaged(
+                MuteGroupTarget::AllSources,
+                true
+            )]
+        ));
+    }
+
+    #[test]
+    fn unknown_osc_addresses_are_not_recognized() {
+        let state = test_state();
+        let settings = SonusmixSettings::default();
+        assert!(handle_message(&state, &settings, &message("/other/thing", Vec::new())).is_none());
+        assert!(handle_message(
+            &state,
+            &settings,
+            &message("/sonusmix/endpoint/Speakers/unknown", Vec::new())
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn osc_feedback_values() {
+        let state = test_state();
+        let values = feedback_values(&state, &SonusmixSettings::default());
+        assert_eq!(values["/sonusmix/endpoint/Speakers/mute"], 0.0);
+        assert_eq!(values["/sonusmix/endpoint/Music_Player/lock"], 0.0);
+        assert!(values.contains_key("/sonusmix/endpoint/Music_Player/volume"));
+        assert_eq!(values["/sonusmix/mute_all_sources"], 0.0);
+    }
+}
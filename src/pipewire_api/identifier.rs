@@ -23,6 +23,8 @@ pub struct NodeIdentifier {
     #[serde(skip)]
     media_title: Option<String>,
     #[serde(skip)]
+    media_role: Option<MediaRole>,
+    #[serde(skip)]
     device_id: Option<u32>,
     #[serde(skip)]
     route_name: Option<String>,
@@ -50,8 +52,9 @@ impl NodeIdentifier {
             object_path: props.get(*OBJECT_PATH).map(ToOwned::to_owned),
             application_name: props.get(*APP_NAME).map(ToOwned::to_owned),
             binary_name: props.get(*APP_PROCESS_BINARY).map(ToOwned::to_owned),
-            media_name: props.get(*OBJECT_PATH).map(ToOwned::to_owned),
+            media_name: props.get(*MEDIA_NAME).map(ToOwned::to_owned),
             media_title: props.get(*MEDIA_TITLE).map(ToOwned::to_owned),
+            media_role: props.get(*MEDIA_ROLE).and_then(MediaRole::from_prop),
             device_id: props.get(*DEVICE_ID).and_then(|id| id.parse().ok()),
             route_name: None,
             app_icon_name: props.get(*APP_ICON_NAME).map(ToOwned::to_owned),
@@ -75,6 +78,7 @@ impl NodeIdentifier {
             binary_name: None,
             media_name: None,
             media_title: None,
+            media_role: None,
             device_id: None,
             route_name: None,
             app_icon_name: None,
@@ -86,6 +90,24 @@ impl NodeIdentifier {
         }
     }
 
+    #[cfg(test)]
+    pub fn with_node_name(mut self, node_name: &str) -> Self {
+        self.node_name = Some(node_name.to_owned());
+        self
+    }
+
+    #[cfg(test)]
+    pub fn with_media_name(mut self, media_name: &str) -> Self {
+        self.media_name = Some(media_name.to_owned());
+        self
+    }
+
+    #[cfg(test)]
+    pub fn with_media_role(mut self, media_role: MediaRole) -> Self {
+        self.media_role = Some(media_role);
+        self
+    }
+
     pub fn update_is_monitor(&mut self, is_monitor: bool) {
         self.is_monitor = is_monitor;
         self.human_name_source.take();
@@ -102,6 +124,7 @@ impl NodeIdentifier {
         self.binary_name       = props.get(*APP_PROCESS_BINARY) .map(ToOwned::to_owned).or(self.binary_name.take());
         self.media_name        = props.get(*MEDIA_NAME)         .map(ToOwned::to_owned).or(self.media_name.take());
         self.media_title       = props.get(*MEDIA_TITLE)        .map(ToOwned::to_owned).or(self.media_title.take());
+        self.media_role        = props.get(*MEDIA_ROLE)         .and_then(MediaRole::from_prop).or(self.media_role);
         self.app_icon_name     = props.get(*APP_ICON_NAME)      .map(ToOwned::to_owned).or(self.app_icon_name.take());
         self.device_id         = props.get(*DEVICE_ID)          .and_then(|id| id.parse().ok()).or(self.device_id);
 
@@ -112,6 +135,22 @@ impl NodeIdentifier {
         self.details_.take();
     }
 
+    pub fn node_name(&self) -> Option<&str> {
+        self.node_name.as_deref()
+    }
+
+    pub fn media_name(&self) -> Option<&str> {
+        self.media_name.as_deref()
+    }
+
+    pub fn media_title(&self) -> Option<&str> {
+        self.media_title.as_deref()
+    }
+
+    pub fn media_role(&self) -> Option<MediaRole> {
+        self.media_role
+    }
+
     pub fn icon_name(&self) -> &str {
         self.icon_name_.get_or_init(|| {
             self.app_icon_name
@@ -128,7 +167,6 @@ impl NodeIdentifier {
         })
     }
 
-    #[allow(dead_code)] // This will be used for persistent nodes
     pub fn identifier(&self) -> &str {
         self.identifier_.get_or_init(|| {
             self.node_name
@@ -201,3 +239,57 @@ impl NodeIdentifier {
         }
     }
 }
+
+/// What a stream is used for, from its `media.role` property. Only the roles that Sonusmix offers
+/// endpoints for are recognized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum MediaRole {
+    Music,
+    Movie,
+    Game,
+    Communication,
+    Notification,
+}
+
+impl MediaRole {
+    pub const ALL: [Self; 5] = [
+        Self::Music,
+        Self::Movie,
+        Self::Game,
+        Self::Communication,
+        Self::Notification,
+    ];
+
+    /// Parse a `media.role` value. Also accepts the lowercase PulseAudio role names, in case a
+    /// stream's role wasn't translated.
+    pub fn from_prop(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "music" => Some(Self::Music),
+            "movie" | "video" => Some(Self::Movie),
+            "game" => Some(Self::Game),
+            "communication" | "phone" => Some(Self::Communication),
+            "notification" | "event" => Some(Self::Notification),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Music => "Music",
+            Self::Movie => "Movie",
+            Self::Game => "Game",
+            Self::Communication => "Communication",
+            Self::Notification => "Notification",
+        }
+    }
+
+    pub fn icon_name(self) -> &'static str {
+        match self {
+            Self::Music => "audio-x-generic",
+            Self::Movie => "video-x-generic",
+            Self::Game => "input-gaming",
+            Self::Communication => "call-start",
+            Self::Notification => "preferences-system-notifications",
+        }
+    }
+}
@@ -1,4 +1,7 @@
-use std::{cell::RefCell, rc::Rc, sync::mpsc, thread::JoinHandle};
+use std::{
+    cell::RefCell, ffi::CString, path::PathBuf, ptr::NonNull, rc::Rc, sync::mpsc,
+    thread::JoinHandle,
+};
 
 use anyhow::{anyhow, Context, Result};
 
@@ -10,9 +13,19 @@ use pipewire::{
 };
 use ulid::Ulid;
 
-use crate::{pipewire_api::SONUSMIX_APP_NAME, state::GroupNodeKind, SONUSMIX_APP_ID};
+use crate::{
+    pipewire_api::SONUSMIX_APP_NAME,
+    state::{GroupNodeKind, LoopbackConfig},
+    SONUSMIX_APP_ID,
+};
 
-use super::{object::Port, store::Store, FromPipewireMessage, Graph, PortKind, ToPipewireMessage};
+use super::{
+    midi::{MidiEvent, MidiPorts},
+    object::{GroupNode, LocalNode, LoopbackModule, LoopbackNode, Port},
+    recording::Recording,
+    store::Store,
+    FromPipewireMessage, Graph, PortKind, ToPipewireMessage,
+};
 
 /// # Master
 ///
@@ -21,6 +34,7 @@ use super::{object::Port, store::Store, FromPipewireMessage, Graph, PortKind, To
 /// it gets.
 struct Master {
     store: Rc<RefCell<Store>>,
+    context: Rc<PwContext>,
     pw_core: Rc<Core>,
     registry: Rc<Registry>,
     sender: pipewire::channel::Sender<ToPipewireMessage>,
@@ -29,12 +43,14 @@ struct Master {
 impl Master {
     fn new(
         store: Rc<RefCell<Store>>,
+        context: Rc<PwContext>,
         pw_core: Rc<Core>,
         registry: Rc<Registry>,
         sender: pipewire::channel::Sender<ToPipewireMessage>,
     ) -> Self {
         Master {
             store,
+            context,
             pw_core,
             registry,
             sender,
@@ -85,7 +101,14 @@ impl Master {
                                     init_node_listeners(store.clone(), sender.clone(), global.id);
                                 }
                                 ObjectType::Device => {
-                                    init_device_listeners(store.clone(), global.id);
+                                    init_device_listeners(store.clone(), sender.clone(), global.id);
+                                }
+                                ObjectType::Metadata => {
+                                    init_metadata_listeners(
+                                        store.clone(),
+                                        sender.clone(),
+                                        global.id,
+                                    );
                                 }
                                 _ => {}
                             }
@@ -223,56 +246,221 @@ impl Master {
         Ok(())
     }
 
+    /// Create the node of a group node in Sonusmix's own context and export it to the server.
+    /// Hosting it here, rather than asking the server to create it, means its name can be
+    /// changed while streams are using it. Its id is filled in by the store once it shows up in
+    /// the registry.
     fn create_group_node(&self, name: String, id: Ulid, kind: GroupNodeKind) -> Result<()> {
+        let props = properties! {
+            *FACTORY_NAME => "support.null-audio-sink",
+            *NODE_NAME => GroupNode::node_name(id),
+            *NODE_NICK => &*name,
+            *NODE_DESCRIPTION => &*name,
+            *APP_ICON_NAME => SONUSMIX_APP_ID,
+            *MEDIA_ICON_NAME => SONUSMIX_APP_ID,
+            *DEVICE_ICON_NAME => SONUSMIX_APP_ID,
+            "icon_name" => SONUSMIX_APP_ID,
+            *APP_NAME => SONUSMIX_APP_NAME,
+            *MEDIA_CLASS => match kind {
+                GroupNodeKind::Source => "Audio/Source/Virtual",
+                GroupNodeKind::Duplex => "Audio/Duplex",
+                GroupNodeKind::Sink => "Audio/Sink",
+            },
+            "audio.position" => "FL,FR",
+            "monitor.channel-volumes" => "true",
+            "monitor.passthrough" => "true",
+        };
+        let factory_name =
+            CString::new("adapter").expect("factory name should not contain null bytes");
+        let node_type = CString::new("PipeWire:Interface:Node")
+            .expect("interface type should not contain null bytes");
+
+        // SAFETY: The context is valid for as long as the Pipewire thread runs. The factory takes
+        // ownership of the properties.
+        let node = unsafe {
+            let factory = pipewire::sys::pw_context_find_factory(
+                self.context.as_raw_ptr(),
+                factory_name.as_ptr(),
+            );
+            if factory.is_null() {
+                return Err(anyhow!("The adapter factory is not available"));
+            }
+            pipewire::sys::pw_impl_factory_create_object(
+                factory,
+                std::ptr::null_mut(),
+                node_type.as_ptr(),
+                pipewire::sys::PW_VERSION_NODE,
+                props.into_raw(),
+                0,
+            )
+        };
+        let node = NonNull::new(node.cast::<pipewire::sys::pw_impl_node>())
+            .with_context(|| format!("Failed to create group node '{name}'"))?;
+
+        // SAFETY: The node was just created, and is only destroyed after the proxy
+        let proxy = unsafe {
+            pipewire::sys::pw_core_export(
+                self.pw_core.as_raw_ptr(),
+                node_type.as_ptr(),
+                std::ptr::null(),
+                node.as_ptr().cast(),
+                0,
+            )
+        };
+        let Some(proxy) = NonNull::new(proxy) else {
+            // SAFETY: The node was just created and nothing else refers to it
+            unsafe { pipewire::sys::pw_impl_node_destroy(node.as_ptr()) };
+            return Err(anyhow!("Failed to export group node '{name}'"));
+        };
+
+        self.store.borrow_mut().group_nodes.insert(
+            id,
+            GroupNode {
+                id: None,
+                name,
+                kind,
+                // SAFETY: The node and its proxy were just created, and are only dropped on this
+                // thread
+                proxy: unsafe { LocalNode::from_raw(node, proxy) },
+                listener: (),
+            },
+        );
+        Ok(())
+    }
+
+    fn remove_group_node(&self, id: Ulid) -> Result<()> {
+        let mut store = self.store.borrow_mut();
+        let group_node = store
+            .group_nodes
+            .remove(&id)
+            .with_context(|| format!("Group node with id '{id}' does not exist"))?;
+
+        // Dropping the node removes it from the server
+        drop(group_node);
+
+        Ok(())
+    }
+
+    /// Create a new node for a group node, keeping the old one alive so its links can be moved
+    /// over before it is removed. Both nodes have the same name, so only streams that the
+    /// session manager would move back to the old node by its id are retargeted.
+    fn replace_group_node(&self, name: String, id: Ulid, kind: GroupNodeKind) -> Result<()> {
+        let old_node_id = {
+            let mut store = self.store.borrow_mut();
+            let group_node = store
+                .group_nodes
+                .remove(&id)
+                .with_context(|| format!("Group node with id '{id}' does not exist"))?;
+            // If the node is replaced again before the last replacement finished, the oldest
+            // node is the one that still has the links, so the one in between is dropped
+            if store.replaced_group_nodes.contains_key(&id) {
+                drop(group_node);
+            } else {
+                store.replaced_group_nodes.insert(id, group_node);
+            }
+            store
+                .replaced_group_nodes
+                .get(&id)
+                .and_then(|group_node| group_node.id)
+        };
+        self.create_group_node(name, id, kind)?;
+
+        let store = self.store.borrow();
+        let (Some(old_node), Some(metadata)) = (
+            old_node_id.and_then(|old_id| store.nodes.get(&old_id)),
+            store.default_metadata.as_ref(),
+        ) else {
+            return Ok(());
+        };
+        let old_id = old_node.id.to_string();
+        let old_serial = old_node.props.get("object.serial");
+        let node_name = GroupNode::node_name(id);
+        for (subject, targets) in &store.stream_targets {
+            if targets
+                .values()
+                .any(|target| *target == old_id || Some(target) == old_serial)
+            {
+                metadata.proxy.set_property(
+                    *subject,
+                    "target.object",
+                    Some("Spa:String"),
+                    Some(&node_name),
+                );
+                metadata
+                    .proxy
+                    .set_property(*subject, "target.node", None, None);
+            }
+        }
+        Ok(())
+    }
+
+    fn remove_replaced_group_node(&self, id: Ulid) -> Result<()> {
+        let group_node = self
+            .store
+            .borrow_mut()
+            .replaced_group_nodes
+            .remove(&id)
+            .with_context(|| format!("Replaced group node with id '{id}' does not exist"))?;
+
+        // Dropping the node removes it from the server
+        drop(group_node);
+
+        Ok(())
+    }
+
+    /// Create the hidden node that a link with a send level is routed through. Its volume is the
+    /// gain of the link. Its media class isn't one of a device, so that other programs and the
+    /// session manager don't offer it as a sink or source, or make it the default.
+    fn create_send_node(&self, name: String, id: Ulid) -> Result<()> {
+        // The node may still be on its way if it was asked for again
+        if self.store.borrow().send_nodes.contains_key(&id) {
+            return Ok(());
+        }
         let proxy = self
             .pw_core
             .create_object::<pipewire::node::Node>(
                 "adapter",
                 &properties! {
                     *FACTORY_NAME => "support.null-audio-sink",
-                    *NODE_NAME => format!("sonusmix.group.{id}"),
+                    *NODE_NAME => format!("sonusmix.send.{id}"),
                     *NODE_NICK => &*name,
                     *NODE_DESCRIPTION => &*name,
                     *APP_ICON_NAME => SONUSMIX_APP_ID,
-                    *MEDIA_ICON_NAME => SONUSMIX_APP_ID,
-                    *DEVICE_ICON_NAME => SONUSMIX_APP_ID,
-                    "icon_name" => SONUSMIX_APP_ID,
                     *APP_NAME => SONUSMIX_APP_NAME,
-                    *MEDIA_CLASS => match kind {
-                        GroupNodeKind::Source => "Audio/Source/Virtual",
-                        GroupNodeKind::Duplex => "Audio/Duplex",
-                        GroupNodeKind::Sink => "Audio/Sink",
-                    },
+                    *MEDIA_CLASS => "Audio/Sink/Internal",
+                    "node.virtual" => "true",
+                    "node.passive" => "true",
+                    "priority.session" => "0",
                     "audio.position" => "FL,FR",
                     "monitor.channel-volumes" => "true",
                     "monitor.passthrough" => "true",
                 },
             )
-            .with_context(|| format!("Failed to create group node '{name}'"))?;
+            .context("Failed to create send node")?;
         let listener = proxy
             .upcast_ref()
             .add_listener_local()
             .bound({
                 let store = self.store.clone();
                 move |global_id| {
-                    if let Some(group_node) = store.borrow_mut().group_nodes.get_mut(&id) {
-                        group_node.id = Some(global_id);
+                    if let Some(send_node) = store.borrow_mut().send_nodes.get_mut(&id) {
+                        send_node.id = Some(global_id);
                     }
                 }
             })
             .removed({
                 let store = self.store.clone();
                 move || {
-                    store.borrow_mut().group_nodes.remove(&id);
+                    store.borrow_mut().send_nodes.remove(&id);
                 }
             })
             .register();
-        self.store.borrow_mut().group_nodes.insert(
+        self.store.borrow_mut().send_nodes.insert(
             id,
-            super::object::GroupNode {
+            GroupNode {
                 id: None,
                 name,
-                kind,
+                kind: GroupNodeKind::Duplex,
                 proxy,
                 listener,
             },
@@ -280,18 +468,186 @@ impl Master {
         Ok(())
     }
 
-    fn remove_group_node(&self, id: Ulid) -> Result<()> {
-        let mut store = self.store.borrow_mut();
-        let group_node = store
-            .group_nodes
+    fn remove_send_node(&self, id: Ulid) -> Result<()> {
+        let send_node = self
+            .store
+            .borrow_mut()
+            .send_nodes
             .remove(&id)
-            .with_context(|| format!("Group node with id '{id}' does not exist"))?;
+            .with_context(|| format!("Send node with id '{id}' does not exist"))?;
 
         // Dropping the proxy deletes the object on the server
-        drop(group_node);
+        drop(send_node);
+
+        Ok(())
+    }
+
+    /// Load a loopback module into Sonusmix's context. The capture and playback nodes are named
+    /// after the loopback's id so that the store can recognize them when they are added.
+    fn create_loopback(&self, name: String, id: Ulid, config: LoopbackConfig) -> Result<()> {
+        let latency = format!("{}/48000", config.latency_ms.max(1).saturating_mul(48));
+        let args = format!(
+            "{{ node.description = {description} \
+            capture.props = {{ node.name = {capture_name} node.description = {capture_description} \
+            node.latency = {latency} node.autoconnect = false node.dont-reconnect = true \
+            audio.channels = {capture_channels_len} audio.position = {capture_channels} }} \
+            playback.props = {{ node.name = {playback_name} node.description = {playback_description} \
+            node.latency = {latency} node.autoconnect = false node.dont-reconnect = true \
+            audio.channels = {playback_channels_len} audio.position = {playback_channels} }} }}",
+            description = spa_json_string(&name),
+            capture_name = spa_json_string(&LoopbackNode::capture_node_name(id)),
+            capture_description = spa_json_string(&format!("{name} (Input)")),
+            capture_channels_len = config.capture_channels.len(),
+            capture_channels = spa_json_array(&config.capture_channels),
+            playback_name = spa_json_string(&LoopbackNode::playback_node_name(id)),
+            playback_description = spa_json_string(&format!("{name} (Output)")),
+            playback_channels_len = config.playback_channels.len(),
+            playback_channels = spa_json_array(&config.playback_channels),
+            latency = spa_json_string(&latency),
+        );
+        let module_name = CString::new("libpipewire-module-loopback")
+            .expect("module name should not contain null bytes");
+        let args = CString::new(args).context("Loopback arguments contained a null byte")?;
+
+        // SAFETY: The context outlives the module, since the module is owned by the store, which
+        // is dropped before the context at the end of the Pipewire thread.
+        let module = unsafe {
+            pipewire::sys::pw_context_load_module(
+                self.context.as_raw_ptr(),
+                module_name.as_ptr(),
+                args.as_ptr(),
+                std::ptr::null_mut(),
+            )
+        };
+        let module = NonNull::new(module)
+            .with_context(|| format!("Failed to load loopback module for '{name}'"))?;
+
+        self.store.borrow_mut().loopbacks.insert(
+            id,
+            LoopbackNode {
+                capture_id: None,
+                playback_id: None,
+                name,
+                config,
+                // SAFETY: The module was just loaded and is only dropped on this thread
+                module: unsafe { LoopbackModule::from_raw(module) },
+            },
+        );
+        Ok(())
+    }
+
+    fn remove_loopback(&self, id: Ulid) -> Result<()> {
+        let mut store = self.store.borrow_mut();
+        let loopback = store
+            .loopbacks
+            .remove(&id)
+            .with_context(|| format!("Loopback with id '{id}' does not exist"))?;
+
+        // Dropping the module unloads it, which removes its nodes from the server
+        drop(loopback);
 
         Ok(())
     }
+
+    fn start_recording(&self, id: Ulid, node_ids: Vec<u32>, path: PathBuf) -> Result<()> {
+        let recording = Recording::start(&self.pw_core, id, node_ids, path)?;
+        self.store.borrow_mut().recordings.insert(id, recording);
+        Ok(())
+    }
+
+    #[cfg(test)]
+    fn link_recordings(&self) {}
+
+    /// Link the nodes being recorded to their recording streams, once the streams' ports exist.
+    /// Sinks are recorded through their monitor ports. Nodes that are linked are removed from the
+    /// recording's unlinked nodes, so this only does anything while a recording is starting.
+    #[cfg(not(test))]
+    fn link_recordings(&self) {
+        let mut port_pairs = Vec::new();
+        {
+            let mut store = self.store.borrow_mut();
+            let store = &mut *store;
+            for recording in store.recordings.values_mut() {
+                let Some(recording_id) = recording.id else {
+                    continue;
+                };
+                let end_ports: Vec<&Port> = store
+                    .ports
+                    .values()
+                    .filter(|port| port.node == recording_id && port.kind == PortKind::Sink)
+                    .collect();
+                if end_ports.is_empty() {
+                    continue;
+                }
+                recording.unlinked_nodes.retain(|node_id| {
+                    let Some(node) = store.nodes.get(node_id) else {
+                        debug!("Recorded node {node_id} no longer exists");
+                        return false;
+                    };
+                    let start_ports: Vec<&Port> = node
+                        .ports
+                        .iter()
+                        .filter(|(_, kind, _)| *kind == PortKind::Source)
+                        .filter_map(|(port_id, _, _)| store.ports.get(port_id))
+                        .collect();
+                    if start_ports.is_empty() {
+                        return true;
+                    }
+                    port_pairs.extend(map_ports(start_ports, end_ports.clone()));
+                    false
+                });
+            }
+        }
+        for (start_port, end_port) in port_pairs {
+            if let Err(err) = self.create_port_link(start_port, end_port) {
+                error!("Error linking node to recording: {err:?}");
+            }
+        }
+    }
+
+    fn stop_recording(&self, id: Ulid) -> Result<()> {
+        let recording = self
+            .store
+            .borrow_mut()
+            .recordings
+            .remove(&id)
+            .with_context(|| format!("Recording with id '{id}' does not exist"))?;
+
+        // Dropping the recording disconnects the stream and finalizes the file
+        drop(recording);
+
+        Ok(())
+    }
+
+    /// Create Sonusmix's MIDI input and output ports. Events received on the input port are
+    /// passed to `on_input`.
+    fn init_midi_ports(&self, on_input: impl Fn(Vec<MidiEvent>) + 'static) -> Result<()> {
+        let midi_ports = MidiPorts::new(&self.pw_core, on_input)?;
+        self.store.borrow_mut().midi_ports = Some(midi_ports);
+        Ok(())
+    }
+
+    fn send_midi(&self, events: Vec<MidiEvent>) -> Result<()> {
+        self.store
+            .borrow()
+            .midi_ports
+            .as_ref()
+            .context("MIDI ports were not created")?
+            .send(events);
+        Ok(())
+    }
+}
+
+/// Quote a string for use in the SPA JSON arguments of a module.
+fn spa_json_string(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Format a list of strings as a SPA-JSON array, quoting each one so that user input such as
+/// channel names can't change the rest of the arguments.
+fn spa_json_array(items: &[String]) -> String {
+    let items: Vec<String> = items.iter().map(|item| spa_json_string(item)).collect();
+    format!("[ {} ]", items.join(" "))
 }
 
 /// Maps two different list of ports to a list of mappings.
@@ -372,7 +728,11 @@ pub fn init_node_listeners(
     }
 }
 
-pub fn init_device_listeners(store: Rc<RefCell<Store>>, id: u32) {
+pub fn init_device_listeners(
+    store: Rc<RefCell<Store>>,
+    sender: pipewire::channel::Sender<ToPipewireMessage>,
+    id: u32,
+) {
     if let Some(device) = store.clone().borrow_mut().devices.get_mut(&id) {
         device.listener = Some(
             device
@@ -384,20 +744,62 @@ pub fn init_device_listeners(store: Rc<RefCell<Store>>, id: u32) {
                         store
                             .borrow_mut()
                             .update_device_param(type_, id, index, pod);
+                        let _ = sender.send(ToPipewireMessage::Update);
                     }
                 })
                 .register(),
         );
-        device
-            .proxy
-            .enum_params(0, Some(ParamType::Route), 0, u32::MAX);
-        device.proxy.subscribe_params(&[ParamType::Route]);
+        const DEVICE_PARAMS: [ParamType; 4] = [
+            ParamType::Route,
+            ParamType::EnumProfile,
+            ParamType::Profile,
+            ParamType::EnumRoute,
+        ];
+        for param in DEVICE_PARAMS {
+            device.proxy.enum_params(0, Some(param), 0, u32::MAX);
+        }
+        device.proxy.subscribe_params(&DEVICE_PARAMS);
+    }
+}
+
+/// Listen for the default nodes and the targets of streams changing. Does nothing if the metadata
+/// object is not the one holding the default nodes.
+pub fn init_metadata_listeners(
+    store: Rc<RefCell<Store>>,
+    sender: pipewire::channel::Sender<ToPipewireMessage>,
+    id: u32,
+) {
+    if let Some(metadata) = store
+        .clone()
+        .borrow_mut()
+        .default_metadata
+        .as_mut()
+        .filter(|metadata| metadata.id == id)
+    {
+        metadata.listener = Some(
+            metadata
+                .proxy
+                .add_listener_local()
+                .property(move |subject, key, _type, value| {
+                    // Only properties on the core object (id 0) are about the default nodes, the
+                    // others say which node a stream should be moved to
+                    if subject == 0 {
+                        store.borrow_mut().update_default_node(key, value);
+                        let _ = sender.send(ToPipewireMessage::Update);
+                    } else {
+                        store.borrow_mut().update_stream_target(subject, key, value);
+                    }
+                    0
+                })
+                .register(),
+        );
     }
 }
 
 #[cfg(test)]
 pub(super) fn init_mainloop(
     update_fn: impl Fn(Box<Graph>) + Send + 'static,
+    midi_fn: impl Fn(Vec<MidiEvent>) + Send + 'static,
 ) -> Result<(
     JoinHandle<()>,
     pipewire::channel::Sender<ToPipewireMessage>,
@@ -409,6 +811,7 @@ pub(super) fn init_mainloop(
 #[cfg(not(test))]
 pub(super) fn init_mainloop(
     update_fn: impl Fn(Box<Graph>) + Send + 'static,
+    midi_fn: impl Fn(Vec<MidiEvent>) + Send + 'static,
 ) -> Result<(
     JoinHandle<()>,
     pipewire::channel::Sender<ToPipewireMessage>,
@@ -441,7 +844,7 @@ pub(super) fn init_mainloop(
             Ok((mainloop, context, pw_core, registry))
         })();
         // If there was an error, report it and exit
-        let (mainloop, _context, pw_core, registry) = match init_result {
+        let (mainloop, context, pw_core, registry) = match init_result {
             Ok(result) => {
                 init_status_tx.send(Ok(())).expect(
                     "If the init_status receiver has been dropped something has gone very wrong",
@@ -456,21 +859,35 @@ pub(super) fn init_mainloop(
             }
         };
         let mainloop = Rc::new(mainloop);
+        // The context must be declared before the core so that it is dropped after it
+        let context = Rc::new(context);
         let pw_core = Rc::new(pw_core);
         let registry = Rc::new(registry);
 
         // init registry listener
-        let mut master = Master::new(store.clone(), pw_core.clone(), registry, to_pw_tx_clone);
+        let mut master = Master::new(
+            store.clone(),
+            context.clone(),
+            pw_core.clone(),
+            registry,
+            to_pw_tx_clone,
+        );
 
         let _listener = master.registry_listener();
         let _remove_listener = master.registry_remove_listener();
         let _core_listeners = master.init_core_listeners();
+        if let Err(err) = master.init_midi_ports(midi_fn) {
+            error!("Error creating MIDI ports: {err:?}");
+        }
 
         let _receiver = receiver.attach(mainloop.loop_(), {
             let mainloop = mainloop.clone();
             let store = store.clone();
             move |message| match message {
-                ToPipewireMessage::Update => update_fn(Box::new(store.borrow().dump_graph())),
+                ToPipewireMessage::Update => {
+                    master.link_recordings();
+                    update_fn(Box::new(store.borrow().dump_graph()));
+                }
                 ToPipewireMessage::NodeVolume(id, volume) => {
                     if let Err(err) = store.borrow_mut().set_node_volume(id, volume) {
                         error!("Error setting volume: {err:?}");
@@ -511,6 +928,70 @@ pub(super) fn init_mainloop(
                         error!("Error removing group node: {err:?}");
                     }
                 }
+                ToPipewireMessage::RenameGroupNode(id, name) => {
+                    if let Err(err) = store.borrow_mut().rename_group_node(id, name) {
+                        error!("Error renaming group node: {err:?}");
+                    }
+                }
+                ToPipewireMessage::ReplaceGroupNode(name, id, kind) => {
+                    if let Err(err) = master.replace_group_node(name, id, kind) {
+                        error!("Error replacing group node: {err:?}");
+                    }
+                }
+                ToPipewireMessage::RemoveReplacedGroupNode(id) => {
+                    if let Err(err) = master.remove_replaced_group_node(id) {
+                        error!("Error removing replaced group node: {err:?}");
+                    }
+                }
+                ToPipewireMessage::CreateSendNode(name, id) => {
+                    if let Err(err) = master.create_send_node(name, id) {
+                        error!("Error creating send node: {err:?}");
+                    }
+                }
+                ToPipewireMessage::RemoveSendNode(id) => {
+                    if let Err(err) = master.remove_send_node(id) {
+                        error!("Error removing send node: {err:?}");
+                    }
+                }
+                ToPipewireMessage::SetDeviceProfile(id, profile_index) => {
+                    if let Err(err) = store.borrow_mut().set_device_profile(id, profile_index) {
+                        error!("Error setting device profile: {err:?}");
+                    }
+                }
+                ToPipewireMessage::SetDeviceRoute(id, route_index, device_index) => {
+                    if let Err(err) =
+                        store
+                            .borrow_mut()
+                            .set_device_route(id, route_index, device_index)
+                    {
+                        error!("Error setting device route: {err:?}");
+                    }
+                }
+                ToPipewireMessage::CreateLoopback(name, id, config) => {
+                    if let Err(err) = master.create_loopback(name, id, config) {
+                        error!("Error creating loopback: {err:?}");
+                    }
+                }
+                ToPipewireMessage::RemoveLoopback(id) => {
+                    if let Err(err) = master.remove_loopback(id) {
+                        error!("Error removing loopback: {err:?}");
+                    }
+                }
+                ToPipewireMessage::StartRecording { id, node_ids, path } => {
+                    if let Err(err) = master.start_recording(id, node_ids, path) {
+                        error!("Error starting recording: {err:?}");
+                    }
+                }
+                ToPipewireMessage::StopRecording(id) => {
+                    if let Err(err) = master.stop_recording(id) {
+                        error!("Error stopping recording: {err:?}");
+                    }
+                }
+                ToPipewireMessage::SendMidi(events) => {
+                    if let Err(err) = master.send_midi(events) {
+                        error!("Error sending MIDI: {err:?}");
+                    }
+                }
                 ToPipewireMessage::Exit => mainloop.quit(),
             }
         });
@@ -518,6 +999,14 @@ pub(super) fn init_mainloop(
         println!("mainloop initialization done");
 
         mainloop.run();
+
+        // Finish any recordings that are still running, so their files are complete
+        store.borrow_mut().recordings.clear();
+        store.borrow_mut().midi_ports = None;
+
+        // Destroying the context also destroys any modules loaded into it, so unload the
+        // loopbacks while the context still exists.
+        store.borrow_mut().loopbacks.clear();
     });
 
     match init_status_rx.recv() {
@@ -531,6 +1020,15 @@ pub(super) fn init_mainloop(
 mod tests {
     use super::*;
 
+    #[test]
+    fn spa_json_arrays_are_quoted() {
+        assert_eq!(
+            spa_json_array(&["FL".to_owned(), "FR ] } node.name = x".to_owned()]),
+            r#"[ "FL" "FR ] } node.name = x" ]"#
+        );
+        assert_eq!(spa_json_array(&[r#"A"B"#.to_owned()]), r#"[ "A\"B" ]"#);
+    }
+
     fn ch5_1() -> (Vec<Port<()>>, Vec<Port<()>>) {
         let mut start = vec![
             Port::new_test(1, 0, PortKind::Source, false),
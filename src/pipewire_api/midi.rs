@@ -0,0 +1,388 @@
+use std::{cell::RefCell, rc::Rc};
+
+use anyhow::{Context, Result};
+use pipewire::{
+    core::Core,
+    keys::*,
+    properties::properties,
+    spa::{
+        sys::{SPA_CONTROL_Midi, SPA_TYPE_Bytes, SPA_TYPE_Sequence},
+        utils::Direction,
+    },
+    stream::{Stream, StreamFlags, StreamListener},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::SONUSMIX_APP_ID;
+
+use super::{pod::build_midi_format_pod, SONUSMIX_APP_NAME};
+
+/// The prefix of the names of Sonusmix's MIDI nodes.
+pub(super) const MIDI_NODE_NAME_PREFIX: &str = "sonusmix.midi.";
+
+/// A MIDI channel message that can be mapped to a control in Sonusmix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MidiEvent {
+    ControlChange {
+        channel: u8,
+        controller: u8,
+        value: u8,
+    },
+    NoteOn {
+        channel: u8,
+        note: u8,
+        velocity: u8,
+    },
+    NoteOff {
+        channel: u8,
+        note: u8,
+    },
+}
+
+impl MidiEvent {
+    /// Parse a raw MIDI message. Returns None for messages Sonusmix doesn't use.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let [status, data1, data2, ..] = *bytes else {
+            return None;
+        };
+        let channel = status & 0x0F;
+        let (data1, data2) = (data1 & 0x7F, data2 & 0x7F);
+        match status & 0xF0 {
+            0x80 => Some(Self::NoteOff {
+                channel,
+                note: data1,
+            }),
+            // A note on with no velocity is the same as a note off
+            0x90 if data2 == 0 => Some(Self::NoteOff {
+                channel,
+                note: data1,
+            }),
+            0x90 => Some(Self::NoteOn {
+                channel,
+                note: data1,
+                velocity: data2,
+            }),
+            0xB0 => Some(Self::ControlChange {
+                channel,
+                controller: data1,
+                value: data2,
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn to_bytes(self) -> [u8; 3] {
+        match self {
+            Self::ControlChange {
+                channel,
+                controller,
+                value,
+            } => [0xB0 | (channel & 0x0F), controller & 0x7F, value & 0x7F],
+            Self::NoteOn {
+                channel,
+                note,
+                velocity,
+            } => [0x90 | (channel & 0x0F), note & 0x7F, velocity & 0x7F],
+            Self::NoteOff { channel, note } => [0x80 | (channel & 0x0F), note & 0x7F, 0],
+        }
+    }
+
+    /// Whether both events are sent by the same control, so the newer one replaces the older one.
+    fn same_control(&self, other: &Self) -> bool {
+        match (*self, *other) {
+            (
+                Self::ControlChange {
+                    channel,
+                    controller,
+                    ..
+                },
+                Self::ControlChange {
+                    channel: other_channel,
+                    controller: other_controller,
+                    ..
+                },
+            ) => channel == other_channel && controller == other_controller,
+            (
+                Self::NoteOn { channel, note, .. } | Self::NoteOff { channel, note },
+                Self::NoteOn {
+                    channel: other_channel,
+                    note: other_note,
+                    ..
+                }
+                | Self::NoteOff {
+                    channel: other_channel,
+                    note: other_note,
+                },
+            ) => channel == other_channel && note == other_note,
+            _ => false,
+        }
+    }
+}
+
+/// The size of a control in a sequence: its offset and type, then a bytes pod with a 3-byte MIDI
+/// message padded to 8 bytes.
+const MIDI_CONTROL_SIZE: usize = 24;
+/// The size of a sequence pod's header and body header.
+const SEQUENCE_HEADER_SIZE: usize = 16;
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_ne_bytes(
+        bytes.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+/// Read the MIDI messages out of a sequence pod received on the input port.
+fn parse_midi_sequence(bytes: &[u8]) -> Vec<MidiEvent> {
+    let mut events = Vec::new();
+    let (Some(size), Some(type_)) = (read_u32(bytes, 0), read_u32(bytes, 4)) else {
+        return events;
+    };
+    if type_ != SPA_TYPE_Sequence {
+        return events;
+    }
+    let end = (8 + size as usize).min(bytes.len());
+    let mut offset = SEQUENCE_HEADER_SIZE;
+    while offset + 16 <= end {
+        let (Some(control_type), Some(value_size), Some(value_type)) = (
+            read_u32(bytes, offset + 4),
+            read_u32(bytes, offset + 8),
+            read_u32(bytes, offset + 12),
+        ) else {
+            break;
+        };
+        let value_start = offset + 16;
+        let value_end = value_start + value_size as usize;
+        if value_end > end {
+            break;
+        }
+        if control_type == SPA_CONTROL_Midi && value_type == SPA_TYPE_Bytes {
+            events.extend(MidiEvent::from_bytes(&bytes[value_start..value_end]));
+        }
+        // Pod values are padded to 8 bytes
+        offset = value_start + (value_size as usize).next_multiple_of(8);
+    }
+    events
+}
+
+/// Write as many events as fit into `buffer` as a sequence pod. Returns the number of bytes
+/// written and the number of events that were written.
+fn write_midi_sequence(events: &[MidiEvent], buffer: &mut [u8]) -> (usize, usize) {
+    if buffer.len() < SEQUENCE_HEADER_SIZE {
+        return (0, 0);
+    }
+    let count = events
+        .len()
+        .min((buffer.len() - SEQUENCE_HEADER_SIZE) / MIDI_CONTROL_SIZE);
+    let body_size = 8 + count * MIDI_CONTROL_SIZE;
+
+    let mut write = |offset: usize, value: u32| {
+        buffer[offset..offset + 4].copy_from_slice(&value.to_ne_bytes());
+    };
+    write(0, body_size as u32);
+    write(4, SPA_TYPE_Sequence);
+    // Unit and padding of the sequence body
+    write(8, 0);
+    write(12, 0);
+    for (index, event) in events[..count].iter().enumerate() {
+        let offset = SEQUENCE_HEADER_SIZE + index * MIDI_CONTROL_SIZE;
+        write(offset, 0);
+        write(offset + 4, SPA_CONTROL_Midi);
+        write(offset + 8, 3);
+        write(offset + 12, SPA_TYPE_Bytes);
+        let mut value = [0; 8];
+        value[..3].copy_from_slice(&event.to_bytes());
+        buffer[offset + 16..offset + 24].copy_from_slice(&value);
+    }
+    (SEQUENCE_HEADER_SIZE - 8 + body_size, count)
+}
+
+/// A MIDI input port for controllers to send to, and a MIDI output port to send feedback to them
+/// (e.g. lighting LEDs and moving motorized faders). Users connect these to their controller with
+/// a patchbay.
+pub(super) struct MidiPorts {
+    _input: Stream,
+    _input_listener: StreamListener<()>,
+    _output: Stream,
+    _output_listener: StreamListener<()>,
+    pending: Rc<RefCell<Vec<MidiEvent>>>,
+}
+
+impl MidiPorts {
+    pub(super) fn new(core: &Core, on_input: impl Fn(Vec<MidiEvent>) + 'static) -> Result<Self> {
+        let format = build_midi_format_pod();
+
+        let input = Stream::new(
+            core,
+            "sonusmix-midi-input",
+            properties! {
+                *MEDIA_TYPE => "Midi",
+                *MEDIA_CATEGORY => "Capture",
+                *MEDIA_ROLE => "DSP",
+                *NODE_NAME => "sonusmix.midi.input",
+                *NODE_DESCRIPTION => "Sonusmix MIDI Input",
+                *NODE_AUTOCONNECT => "false",
+                *APP_NAME => SONUSMIX_APP_NAME,
+                *APP_ICON_NAME => SONUSMIX_APP_ID,
+            },
+        )
+        .context("Failed to create MIDI input stream")?;
+        let input_listener = input
+            .add_local_listener_with_user_data(())
+            .process(move |stream, _| {
+                let Some(mut buffer) = stream.dequeue_buffer() else {
+                    return;
+                };
+                let Some(data) = buffer.datas_mut().first_mut() else {
+                    return;
+                };
+                let offset = data.chunk().offset() as usize;
+                let size = data.chunk().size() as usize;
+                let Some(bytes) = data
+                    .data()
+                    .and_then(|bytes| bytes.get(offset..offset + size))
+                else {
+                    return;
+                };
+                let events = parse_midi_sequence(bytes);
+                if !events.is_empty() {
+                    on_input(events);
+                }
+            })
+            .register()
+            .context("Failed to register MIDI input stream listener")?;
+        input
+            .connect(
+                Direction::Input,
+                None,
+                StreamFlags::MAP_BUFFERS,
+                &mut [format.pod()],
+            )
+            .context("Failed to connect MIDI input stream")?;
+
+        let output = Stream::new(
+            core,
+            "sonusmix-midi-output",
+            properties! {
+                *MEDIA_TYPE => "Midi",
+                *MEDIA_CATEGORY => "Playback",
+                *MEDIA_ROLE => "DSP",
+                *NODE_NAME => "sonusmix.midi.output",
+                *NODE_DESCRIPTION => "Sonusmix MIDI Feedback",
+                *NODE_AUTOCONNECT => "false",
+                *APP_NAME => SONUSMIX_APP_NAME,
+                *APP_ICON_NAME => SONUSMIX_APP_ID,
+            },
+        )
+        .context("Failed to create MIDI output stream")?;
+        let pending = Rc::new(RefCell::new(Vec::new()));
+        let output_listener = output
+            .add_local_listener_with_user_data(())
+            .process({
+                let pending = pending.clone();
+                move |stream, _| {
+                    let Some(mut buffer) = stream.dequeue_buffer() else {
+                        return;
+                    };
+                    let Some(data) = buffer.datas_mut().first_mut() else {
+                        return;
+                    };
+                    // Always write a sequence, even if it's empty, so nothing stale is sent
+                    let written = match data.data() {
+                        Some(bytes) => {
+                            let mut pending = pending.borrow_mut();
+                            let (written, count) = write_midi_sequence(&pending, bytes);
+                            pending.drain(..count);
+                            written
+                        }
+                        None => 0,
+                    };
+                    let chunk = data.chunk_mut();
+                    *chunk.offset_mut() = 0;
+                    *chunk.stride_mut() = 1;
+                    *chunk.size_mut() = written as u32;
+                }
+            })
+            .register()
+            .context("Failed to register MIDI output stream listener")?;
+        output
+            .connect(
+                Direction::Output,
+                None,
+                StreamFlags::MAP_BUFFERS,
+                &mut [format.pod()],
+            )
+            .context("Failed to connect MIDI output stream")?;
+
+        Ok(Self {
+            _input: input,
+            _input_listener: input_listener,
+            _output: output,
+            _output_listener: output_listener,
+            pending,
+        })
+    }
+
+    /// Queue events to be sent on the output port. If an event for the same control is still
+    /// waiting to be sent, it is replaced, so a disconnected port doesn't build up a backlog.
+    pub(super) fn send(&self, events: Vec<MidiEvent>) {
+        let mut pending = self.pending.borrow_mut();
+        for event in events {
+            pending.retain(|pending_event| !pending_event.same_control(&event));
+            pending.push(event);
+        }
+    }
+}
+
+impl std::fmt::Debug for MidiPorts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MidiPorts")
+            .field("pending", &self.pending.borrow())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn midi_sequence_round_trip() {
+        let events = [
+            MidiEvent::ControlChange {
+                channel: 0,
+                controller: 7,
+                value: 100,
+            },
+            MidiEvent::NoteOn {
+                channel: 9,
+                note: 36,
+                velocity: 127,
+            },
+            MidiEvent::NoteOff {
+                channel: 9,
+                note: 36,
+            },
+        ];
+        let mut buffer = [0; 128];
+        let (written, count) = write_midi_sequence(&events, &mut buffer);
+        assert_eq!(count, 3);
+        assert_eq!(parse_midi_sequence(&buffer[..written]), events);
+
+        // Only as many events as fit should be written
+        let mut small_buffer = [0; SEQUENCE_HEADER_SIZE + MIDI_CONTROL_SIZE];
+        let (written, count) = write_midi_sequence(&events, &mut small_buffer);
+        assert_eq!(count, 1);
+        assert_eq!(parse_midi_sequence(&small_buffer[..written]), events[..1]);
+    }
+
+    #[test]
+    fn note_on_without_velocity_is_note_off() {
+        assert_eq!(
+            MidiEvent::from_bytes(&[0x91, 60, 0]),
+            Some(MidiEvent::NoteOff {
+                channel: 1,
+                note: 60
+            })
+        );
+    }
+}
@@ -1,24 +1,29 @@
 mod identifier;
 mod mainloop;
+mod midi;
 #[cfg(not(test))]
 mod object;
 #[cfg(test)]
 pub mod object;
 mod pod;
+mod recording;
 mod store;
 
-use std::{collections::HashMap, sync::mpsc, thread};
+use std::{collections::HashMap, path::PathBuf, sync::mpsc, thread};
 
 use anyhow::{Context, Result};
 use log::error;
 use mainloop::init_mainloop;
+use serde::Serialize;
 use thiserror::Error;
 
-pub use identifier::NodeIdentifier;
-pub use object::PortKind;
+pub use identifier::{MediaRole, NodeIdentifier};
+pub use midi::MidiEvent;
+pub use object::{EndpointId, PortKind};
+pub use pod::{DeviceProfile, DeviceRoute};
 use ulid::Ulid;
 
-use crate::state::GroupNodeKind;
+use crate::state::{GroupNodeKind, LoopbackConfig};
 
 const SONUSMIX_APP_NAME: &str = "sonusmix";
 
@@ -35,11 +40,12 @@ impl PipewireHandle {
             mpsc::Receiver<ToPipewireMessage>,
         ),
         update_fn: impl Fn(Box<Graph>) + Send + 'static,
+        midi_fn: impl Fn(Vec<MidiEvent>) + Send + 'static,
     ) -> Result<Self> {
         // TODO: Decide if we actually need a dedicated channel and message type to communicate
         // from Pipewire to the main thread, or if the graph updates are enough
         let (pipewire_thread_handle, pw_sender, _from_pw_receiver) =
-            init_mainloop(update_fn).context("Error initializing the Pipewire thread")?;
+            init_mainloop(update_fn, midi_fn).context("Error initializing the Pipewire thread")?;
         let adapter_thread_handle = init_adapter(to_pw_channel.1, pw_sender);
         Ok(Self {
             pipewire_thread_handle: Some(pipewire_thread_handle),
@@ -66,20 +72,28 @@ impl Drop for PipewireHandle {
 }
 
 pub type GroupNode = object::GroupNode<(), ()>;
+pub type LoopbackNode = object::LoopbackNode<()>;
 pub type Client = object::Client<()>;
 pub type Device = object::Device<(), ()>;
 pub type Node = object::Node<(), ()>;
 pub type Port = object::Port<()>;
 pub type Link = object::Link<()>;
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct Graph {
     pub group_nodes: HashMap<Ulid, GroupNode>,
+    pub send_nodes: HashMap<Ulid, GroupNode>,
+    pub loopbacks: HashMap<Ulid, LoopbackNode>,
     pub clients: HashMap<u32, Client>,
     pub devices: HashMap<u32, Device>,
     pub nodes: HashMap<u32, Node>,
     pub ports: HashMap<u32, Port>,
     pub links: HashMap<u32, Link>,
+    /// The `node.name` of the node the session manager currently uses as the default sink
+    pub default_sink: Option<String>,
+    /// The `node.name` of the node the session manager currently uses as the default source
+    pub default_source: Option<String>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -99,6 +113,28 @@ pub enum ToPipewireMessage {
     RemoveNodeLinks { start_id: u32, end_id: u32 },
     CreateGroupNode(String, Ulid, GroupNodeKind),
     RemoveGroupNode(Ulid),
+    /// Change the name of a group node on the live node
+    RenameGroupNode(Ulid, String),
+    /// Create a new node for a group node with a different kind, keeping the old node until
+    /// `RemoveReplacedGroupNode` is sent
+    ReplaceGroupNode(String, Ulid, GroupNodeKind),
+    RemoveReplacedGroupNode(Ulid),
+    /// Create the hidden node a link with a send level is routed through, with its description
+    CreateSendNode(String, Ulid),
+    RemoveSendNode(Ulid),
+    /// Device id, profile index
+    SetDeviceProfile(u32, i32),
+    /// Device id, route index, device index
+    SetDeviceRoute(u32, i32, i32),
+    CreateLoopback(String, Ulid, LoopbackConfig),
+    RemoveLoopback(Ulid),
+    /// Record the output of some nodes, mixed together, to a WAV file. Sinks are recorded through
+    /// their monitor ports.
+    #[rustfmt::skip]
+    StartRecording { id: Ulid, node_ids: Vec<u32>, path: PathBuf },
+    StopRecording(Ulid),
+    /// Send events on Sonusmix's MIDI output port
+    SendMidi(Vec<MidiEvent>),
     Exit,
 }
 
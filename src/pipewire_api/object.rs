@@ -1,4 +1,4 @@
-use std::{fmt::Debug, str::FromStr};
+use std::{collections::BTreeMap, fmt::Debug, str::FromStr};
 
 use derivative::Derivative;
 use pipewire::{
@@ -10,9 +10,12 @@ use pipewire::{
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-use crate::state::GroupNodeKind;
+use crate::state::{GroupNodeKind, LoopbackConfig};
 
-use super::{identifier::NodeIdentifier, pod::DeviceActiveRoute};
+use super::{
+    identifier::NodeIdentifier,
+    pod::{DeviceActiveRoute, DeviceProfile, DeviceRoute},
+};
 
 #[derive(Error, Debug)]
 pub enum ObjectConvertError {
@@ -40,6 +43,14 @@ pub enum ObjectConvertError {
     PipewireError(#[from] pipewire::Error),
 }
 
+/// Copy all of an object's Pipewire properties, so they can be shown when debugging.
+fn props_map(props: &DictRef) -> BTreeMap<String, String> {
+    props
+        .iter()
+        .map(|(key, value)| (key.to_owned(), value.to_owned()))
+        .collect()
+}
+
 trait ObjectConvertErrorExt: Debug {
     fn missing_field(&self, field: &'static str) -> ObjectConvertError {
         ObjectConvertError::MissingField {
@@ -115,20 +126,23 @@ impl<'a> ObjectConvertErrorExt for GlobalObject<&'a DictRef> {
     }
 }
 
-#[derive(Derivative, Clone)]
+#[derive(Derivative, Clone, Serialize)]
 #[derivative(Debug)]
+#[serde(rename_all = "camelCase")]
 pub struct GroupNode<P = pipewire::node::Node, L = pipewire::proxy::ProxyListener> {
     pub id: Option<u32>,
     pub name: String,
     pub kind: GroupNodeKind,
+    #[serde(skip)]
     pub(super) proxy: P,
     #[derivative(Debug = "ignore")]
+    #[serde(skip)]
     #[allow(unused)]
     // This just needs to be stored here because dropping it removes the listener
     pub(super) listener: L,
 }
 
-impl GroupNode {
+impl<P, L> GroupNode<P, L> {
     pub fn without_proxy(&self) -> GroupNode<(), ()> {
         GroupNode {
             id: self.id,
@@ -140,6 +154,142 @@ impl GroupNode {
     }
 }
 
+impl GroupNode<LocalNode, ()> {
+    /// The `node.name` of a group node. It stays the same for as long as the group node exists,
+    /// so that programs and the session manager can remember it.
+    pub fn node_name(id: impl std::fmt::Display) -> String {
+        format!("sonusmix.group.{id}")
+    }
+}
+
+impl GroupNode<(), ()> {
+    #[cfg(test)]
+    pub fn new_test(id: Option<u32>, kind: GroupNodeKind) -> GroupNode<(), ()> {
+        GroupNode {
+            id,
+            name: "TESTING GROUP NODE".to_string(),
+            kind,
+            proxy: (),
+            listener: (),
+        }
+    }
+}
+
+/// A loopback created by Sonusmix through the Pipewire loopback module. The module creates two
+/// stream nodes: a capture node that audio is routed into, and a playback node that plays that
+/// audio back out. Their ids are filled in once the nodes show up in the registry.
+#[derive(Derivative, Clone, Serialize)]
+#[derivative(Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LoopbackNode<M = LoopbackModule> {
+    pub capture_id: Option<u32>,
+    pub playback_id: Option<u32>,
+    pub name: String,
+    pub config: LoopbackConfig,
+    #[derivative(Debug = "ignore")]
+    #[serde(skip)]
+    #[allow(unused)]
+    // This just needs to be stored here because dropping it unloads the module
+    pub(super) module: M,
+}
+
+impl LoopbackNode {
+    pub fn without_module(&self) -> LoopbackNode<()> {
+        LoopbackNode {
+            capture_id: self.capture_id,
+            playback_id: self.playback_id,
+            name: self.name.clone(),
+            config: self.config.clone(),
+            module: (),
+        }
+    }
+
+    pub fn capture_node_name(id: impl std::fmt::Display) -> String {
+        format!("sonusmix.loopback.{id}.capture")
+    }
+
+    pub fn playback_node_name(id: impl std::fmt::Display) -> String {
+        format!("sonusmix.loopback.{id}.playback")
+    }
+}
+
+impl LoopbackNode<()> {
+    #[cfg(test)]
+    pub fn new_test(capture_id: Option<u32>, playback_id: Option<u32>) -> LoopbackNode<()> {
+        LoopbackNode {
+            capture_id,
+            playback_id,
+            name: "TESTING LOOPBACK".to_string(),
+            config: LoopbackConfig::default(),
+            module: (),
+        }
+    }
+}
+
+/// Owns a node created in Sonusmix's Pipewire context and exported to the server, so that its
+/// properties can be changed while it is running. The node is removed when this is dropped.
+#[derive(Debug)]
+pub struct LocalNode {
+    node: std::ptr::NonNull<pipewire::sys::pw_impl_node>,
+    proxy: std::ptr::NonNull<pipewire::sys::pw_proxy>,
+}
+
+impl LocalNode {
+    /// # Safety
+    /// `node` must be a node created in Sonusmix's context, and `proxy` the proxy returned by
+    /// `pw_core_export()` for it. Neither may have been destroyed yet, and this must be dropped
+    /// on the Pipewire thread.
+    pub(super) unsafe fn from_raw(
+        node: std::ptr::NonNull<pipewire::sys::pw_impl_node>,
+        proxy: std::ptr::NonNull<pipewire::sys::pw_proxy>,
+    ) -> Self {
+        Self { node, proxy }
+    }
+
+    /// Change properties of the node. The server is told about them through the exported proxy.
+    pub(super) fn update_properties(&self, properties: &pipewire::properties::Properties) {
+        // SAFETY: The node is valid from the constructor's contract, and the properties outlive
+        // the call
+        unsafe {
+            pipewire::sys::pw_impl_node_update_properties(
+                self.node.as_ptr(),
+                &(*properties.as_raw_ptr()).dict,
+            );
+        }
+    }
+}
+
+impl Drop for LocalNode {
+    fn drop(&mut self) {
+        // SAFETY: The pointers are valid from the constructor's contract. The proxy is destroyed
+        // first, which removes the node from the server and stops it from using the node.
+        unsafe {
+            pipewire::sys::pw_proxy_destroy(self.proxy.as_ptr());
+            pipewire::sys::pw_impl_node_destroy(self.node.as_ptr());
+        }
+    }
+}
+
+/// Owns a module loaded into Sonusmix's Pipewire context. The module is unloaded when this is
+/// dropped.
+pub struct LoopbackModule(std::ptr::NonNull<pipewire::sys::pw_impl_module>);
+
+impl LoopbackModule {
+    /// # Safety
+    /// `ptr` must be a module returned by `pw_context_load_module()` that has not been destroyed
+    /// yet, and this must be dropped on the Pipewire thread.
+    pub(super) unsafe fn from_raw(ptr: std::ptr::NonNull<pipewire::sys::pw_impl_module>) -> Self {
+        Self(ptr)
+    }
+}
+
+impl Drop for LoopbackModule {
+    fn drop(&mut self) {
+        // SAFETY: The pointer is known to be a valid module from the constructor's contract
+        unsafe { pipewire::sys::pw_impl_module_destroy(self.0.as_ptr()) }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Client<P = pipewire::client::Client> {
@@ -147,6 +297,8 @@ pub struct Client<P = pipewire::client::Client> {
     pub name: String,
     pub is_sonusmix: bool,
     pub nodes: Vec<u32>,
+    /// All of the client's Pipewire properties
+    pub props: BTreeMap<String, String>,
     #[serde(skip)]
     pub(super) _proxy: P,
 }
@@ -177,6 +329,7 @@ impl Client<pipewire::client::Client> {
             name: name.to_owned(),
             is_sonusmix: false,
             nodes: Vec::new(),
+            props: props_map(props),
             _proxy: proxy,
         })
     }
@@ -187,6 +340,7 @@ impl Client<pipewire::client::Client> {
             name: self.name.clone(),
             is_sonusmix: self.is_sonusmix,
             nodes: self.nodes.clone(),
+            props: self.props.clone(),
             _proxy: (),
         }
     }
@@ -198,21 +352,30 @@ impl Client<pipewire::client::Client> {
             name: "TESTING CLIENT".to_string(),
             is_sonusmix,
             nodes,
+            props: BTreeMap::new(),
             _proxy: (),
         }
     }
 }
 
-#[derive(Derivative, Clone)]
+#[derive(Derivative, Clone, Serialize)]
 #[derivative(Debug)]
+#[serde(rename_all = "camelCase")]
 pub struct Device<P = pipewire::device::Device, L = Option<pipewire::device::DeviceListener>> {
     pub id: u32,
     pub name: String,
     pub client: u32,
     pub nodes: Vec<u32>,
     pub active_routes: Vec<DeviceActiveRoute>,
+    pub profiles: Vec<DeviceProfile>,
+    pub active_profile: Option<i32>,
+    pub routes: Vec<DeviceRoute>,
+    /// All of the device's Pipewire properties
+    pub props: BTreeMap<String, String>,
+    #[serde(skip)]
     pub(super) proxy: P,
     #[derivative(Debug = "ignore")]
+    #[serde(skip)]
     pub(super) listener: L,
 }
 
@@ -237,6 +400,10 @@ impl Device {
             client: object.parse_fields([*CLIENT_ID], "integer")?,
             nodes: Vec::new(),
             active_routes: Vec::new(),
+            profiles: Vec::new(),
+            active_profile: None,
+            routes: Vec::new(),
+            props: props_map(props),
             proxy,
             listener: None,
         })
@@ -249,14 +416,38 @@ impl Device {
             client: self.client,
             nodes: self.nodes.clone(),
             active_routes: self.active_routes.clone(),
+            profiles: self.profiles.clone(),
+            active_profile: self.active_profile,
+            routes: self.routes.clone(),
+            props: self.props.clone(),
             proxy: (),
             listener: (),
         }
     }
 }
 
-#[derive(Derivative, Clone)]
+impl Device<(), ()> {
+    #[cfg(test)]
+    pub fn new_test(id: u32, profiles: Vec<DeviceProfile>, active_profile: Option<i32>) -> Self {
+        Device {
+            id,
+            name: "TESTING DEVICE".to_string(),
+            client: 0,
+            nodes: Vec::new(),
+            active_routes: Vec::new(),
+            profiles,
+            active_profile,
+            routes: Vec::new(),
+            props: BTreeMap::new(),
+            proxy: (),
+            listener: (),
+        }
+    }
+}
+
+#[derive(Derivative, Clone, Serialize)]
 #[derivative(Debug)]
+#[serde(rename_all = "camelCase")]
 pub struct Node<P = pipewire::node::Node, L = Option<pipewire::node::NodeListener>> {
     pub id: u32,
     pub identifier: NodeIdentifier,
@@ -266,9 +457,13 @@ pub struct Node<P = pipewire::node::Node, L = Option<pipewire::node::NodeListene
     // #[serde(skip)]
     pub channel_volumes: Vec<f32>,
     pub mute: bool,
+    /// All of the node's Pipewire properties
+    pub props: BTreeMap<String, String>,
+    #[serde(skip)]
     pub(super) proxy: P,
     // listener is set by mainloop
     #[derivative(Debug = "ignore")]
+    #[serde(skip)]
     pub(super) listener: L,
 }
 
@@ -334,6 +529,7 @@ impl Node {
             ports: Vec::new(),
             channel_volumes: Vec::new(),
             mute: false,
+            props: props_map(props),
             proxy,
             listener: None,
         })
@@ -347,6 +543,7 @@ impl Node {
             ports: self.ports.clone(),
             channel_volumes: self.channel_volumes.clone(),
             mute: self.mute,
+            props: self.props.clone(),
             proxy: (),
             listener: (),
         }
@@ -361,6 +558,7 @@ impl Node {
             ports: Vec::new(),
             channel_volumes: Vec::new(),
             mute: false,
+            props: BTreeMap::new(),
             proxy: (),
             listener: (),
         }
@@ -377,6 +575,8 @@ pub struct Port<P = pipewire::port::Port> {
     pub kind: PortKind,
     pub is_monitor: bool,
     pub links: Vec<u32>,
+    /// All of the port's Pipewire properties
+    pub props: BTreeMap<String, String>,
     #[serde(skip)]
     pub(super) _proxy: P,
 }
@@ -404,6 +604,7 @@ impl Port {
             kind: object.parse_fields([*PORT_DIRECTION], "'in' or 'out'")?,
             is_monitor: props.get(*PORT_MONITOR) == Some("true"),
             links: Vec::new(),
+            props: props_map(props),
             _proxy: proxy,
         })
     }
@@ -417,6 +618,7 @@ impl Port {
             kind: self.kind,
             is_monitor: self.is_monitor,
             links: self.links.clone(),
+            props: self.props.clone(),
             _proxy: (),
         }
     }
@@ -431,6 +633,7 @@ impl Port {
             kind,
             is_monitor,
             links: Vec::new(),
+            props: BTreeMap::new(),
             _proxy: (),
         }
     }
@@ -463,6 +666,8 @@ pub struct Link<P = pipewire::link::Link> {
     pub start_port: u32,
     pub end_node: u32,
     pub end_port: u32,
+    /// All of the link's Pipewire properties
+    pub props: BTreeMap<String, String>,
     #[serde(skip)]
     pub(super) proxy: P,
 }
@@ -473,6 +678,7 @@ impl Link {
         object: &GlobalObject<&DictRef>,
     ) -> Result<Self, ObjectConvertError> {
         object.check_type(ObjectType::Link, "Link")?;
+        let props = object.get_props()?;
         let proxy = registry.bind(object)?;
 
         Ok(Self {
@@ -483,6 +689,7 @@ impl Link {
             start_port: object.parse_fields([*LINK_OUTPUT_PORT], "integer")?,
             end_node: object.parse_fields([*LINK_INPUT_NODE], "integer")?,
             end_port: object.parse_fields([*LINK_INPUT_PORT], "integer")?,
+            props: props_map(props),
             proxy,
         })
     }
@@ -494,6 +701,7 @@ impl Link {
             start_port: self.start_port,
             end_node: self.end_node,
             end_port: self.end_port,
+            props: self.props.clone(),
             proxy: (),
         }
     }
@@ -512,7 +720,46 @@ impl Link {
             start_port,
             end_node,
             end_port,
+            props: BTreeMap::new(),
             proxy: (),
         }
     }
 }
+
+/// The name of the metadata object the session manager keeps the default nodes in.
+const DEFAULT_METADATA_NAME: &str = "default";
+
+/// The metadata object that says which nodes are the default sink and source. Only the proxy is
+/// kept here; the default nodes themselves are stored in the store as they change.
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct DefaultMetadata {
+    pub id: u32,
+    #[derivative(Debug = "ignore")]
+    pub(super) proxy: pipewire::metadata::Metadata,
+    #[derivative(Debug = "ignore")]
+    #[allow(unused)]
+    // This just needs to be stored here because dropping it removes the listener
+    pub(super) listener: Option<pipewire::metadata::MetadataListener>,
+}
+
+impl DefaultMetadata {
+    /// Returns `None` if the metadata object is not the one holding the default nodes.
+    pub(super) fn from_global(
+        registry: &Registry,
+        object: &GlobalObject<&DictRef>,
+    ) -> Result<Option<Self>, ObjectConvertError> {
+        object.check_type(ObjectType::Metadata, "Metadata")?;
+        let props = object.get_props()?;
+        if props.get("metadata.name") != Some(DEFAULT_METADATA_NAME) {
+            return Ok(None);
+        }
+        let proxy = registry.bind(object)?;
+
+        Ok(Some(Self {
+            id: object.id,
+            proxy,
+            listener: None,
+        }))
+    }
+}
@@ -1,12 +1,25 @@
 use pipewire::spa::{
-    param::ParamType,
-    pod::{object, Pod, Property, Value, ValueArray},
+    param::{
+        audio::{AudioFormat, AudioInfoRaw},
+        ParamType,
+    },
+    pod::{object, Object, Pod, Property, Value, ValueArray},
     sys::{
-        SPA_PARAM_ROUTE_device, SPA_PARAM_ROUTE_index, SPA_PARAM_ROUTE_info, SPA_PARAM_ROUTE_props,
-        SPA_PARAM_ROUTE_save, SPA_PROP_channelVolumes, SPA_PROP_mute,
+        SPA_FORMAT_mediaSubtype, SPA_FORMAT_mediaType, SPA_MEDIA_SUBTYPE_control,
+        SPA_MEDIA_TYPE_application, SPA_PARAM_AVAILABILITY_no, SPA_PARAM_PROFILE_available,
+        SPA_PARAM_PROFILE_description, SPA_PARAM_PROFILE_index, SPA_PARAM_PROFILE_name,
+        SPA_PARAM_PROFILE_save, SPA_PARAM_ROUTE_available, SPA_PARAM_ROUTE_description,
+        SPA_PARAM_ROUTE_device, SPA_PARAM_ROUTE_devices, SPA_PARAM_ROUTE_direction,
+        SPA_PARAM_ROUTE_index, SPA_PARAM_ROUTE_info, SPA_PARAM_ROUTE_name,
+        SPA_PARAM_ROUTE_profiles, SPA_PARAM_ROUTE_props, SPA_PARAM_ROUTE_save,
+        SPA_PROP_channelVolumes, SPA_PROP_mute, SPA_AUDIO_CHANNEL_FL, SPA_AUDIO_CHANNEL_FR,
+        SPA_AUDIO_MAX_CHANNELS, SPA_DIRECTION_INPUT, SPA_DIRECTION_OUTPUT,
     },
-    utils::SpaTypes,
+    utils::{Id, SpaTypes},
 };
+use serde::Serialize;
+
+use super::PortKind;
 
 pub mod parse {
     use std::{ffi::CStr, io::Cursor};
@@ -47,6 +60,7 @@ pub mod parse {
 
     pub trait PodValueExt {
         fn parse_int(&self) -> Option<i32>;
+        fn parse_id(&self) -> Option<u32>;
         fn parse_string(&self) -> Option<&str>;
         fn parse_value_array(&self) -> Option<&ValueArray>;
         fn parse_struct(&self) -> Option<&[Value]>;
@@ -61,6 +75,12 @@ pub mod parse {
                 _ => None,
             }
         }
+        fn parse_id(&self) -> Option<u32> {
+            match self {
+                Value::Id(id) => Some(id.0),
+                _ => None,
+            }
+        }
         fn parse_string(&self) -> Option<&str> {
             match self {
                 Value::String(s) => Some(s),
@@ -98,6 +118,7 @@ pub mod parse {
 
     pub trait PodValueArrayExt {
         fn parse_floats(&self) -> Option<&[f32]>;
+        fn parse_ints(&self) -> Option<&[i32]>;
     }
     impl PodValueArrayExt for ValueArray {
         fn parse_floats(&self) -> Option<&[f32]> {
@@ -106,6 +127,12 @@ pub mod parse {
                 _ => None,
             }
         }
+        fn parse_ints(&self) -> Option<&[i32]> {
+            match self {
+                ValueArray::Int(i) => Some(i),
+                _ => None,
+            }
+        }
     }
 
     pub trait PodStructExt {
@@ -194,7 +221,8 @@ pub fn build_node_mute_pod(mute: bool) -> (ParamType, PodBytes) {
     (ParamType::Props, pod)
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct DeviceActiveRoute {
     pub route_index: i32,
     pub device_index: i32,
@@ -252,3 +280,158 @@ impl DeviceActiveRoute {
         (ParamType::Route, pod)
     }
 }
+
+/// An entry from a device's `EnumProfile` params.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceProfile {
+    pub index: i32,
+    pub name: String,
+    pub description: String,
+    pub available: bool,
+}
+
+impl DeviceProfile {
+    pub fn from_value(pod: &Pod) -> Option<Self> {
+        let value = pod.deserialize_value()?;
+        let obj = value.parse_object()?;
+        Some(Self {
+            index: obj.get_key(SPA_PARAM_PROFILE_index)?.parse_int()?,
+            name: obj
+                .get_key(SPA_PARAM_PROFILE_name)?
+                .parse_string()?
+                .to_owned(),
+            description: obj
+                .get_key(SPA_PARAM_PROFILE_description)
+                .and_then(|v| v.parse_string())
+                .unwrap_or_default()
+                .to_owned(),
+            // Profiles with unknown availability are still selectable
+            available: obj
+                .get_key(SPA_PARAM_PROFILE_available)
+                .and_then(|v| v.parse_id())
+                .map(|available| available != SPA_PARAM_AVAILABILITY_no)
+                .unwrap_or(true),
+        })
+    }
+
+    /// Reads only the index from a device's active `Profile` param.
+    pub fn active_index_from_value(pod: &Pod) -> Option<i32> {
+        pod.deserialize_value()?
+            .parse_object()?
+            .get_key(SPA_PARAM_PROFILE_index)?
+            .parse_int()
+    }
+}
+
+/// `Profile '{ index: <index>, save: true }'`
+pub fn build_device_profile_pod(index: i32) -> (ParamType, PodBytes) {
+    let pod = Value::Object(object! {
+        SpaTypes::ObjectParamProfile,
+        ParamType::Profile,
+        Property::new(SPA_PARAM_PROFILE_index, Value::Int(index)),
+        Property::new(SPA_PARAM_PROFILE_save, Value::Bool(true)),
+    })
+    .serialize();
+    (ParamType::Profile, pod)
+}
+
+/// Build the `EnumFormat` param for a recording stream. The stream isn't connected to a target
+/// that it could take its channels from, so it is fixed to stereo, and the nodes being recorded
+/// are linked to it by channel. The rate is left open to follow the graph.
+pub fn build_capture_format_pod() -> PodBytes {
+    let mut audio_info = AudioInfoRaw::new();
+    audio_info.set_format(AudioFormat::F32LE);
+    audio_info.set_channels(2);
+    let mut position = [0; SPA_AUDIO_MAX_CHANNELS as usize];
+    position[0] = SPA_AUDIO_CHANNEL_FL;
+    position[1] = SPA_AUDIO_CHANNEL_FR;
+    audio_info.set_position(position);
+    Value::Object(Object {
+        type_: SpaTypes::ObjectParamFormat.as_raw(),
+        id: ParamType::EnumFormat.as_raw(),
+        properties: audio_info.into(),
+    })
+    .serialize()
+}
+
+/// Build a format pod for a stream that sends or receives MIDI, as control sequences.
+pub fn build_midi_format_pod() -> PodBytes {
+    Value::Object(object! {
+        SpaTypes::ObjectParamFormat,
+        ParamType::EnumFormat,
+        Property::new(SPA_FORMAT_mediaType, Value::Id(Id(SPA_MEDIA_TYPE_application))),
+        Property::new(SPA_FORMAT_mediaSubtype, Value::Id(Id(SPA_MEDIA_SUBTYPE_control))),
+    })
+    .serialize()
+}
+
+/// An entry from a device's `EnumRoute` params, i.e. a port that a device's node can use.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceRoute {
+    pub index: i32,
+    pub kind: PortKind,
+    pub name: String,
+    pub description: String,
+    pub available: bool,
+    /// The profiles this route can be used with
+    pub profiles: Vec<i32>,
+    /// The device indices (see `card.profile.device`) this route can be used with
+    pub devices: Vec<i32>,
+}
+
+impl DeviceRoute {
+    pub fn from_value(pod: &Pod) -> Option<Self> {
+        let value = pod.deserialize_value()?;
+        let obj = value.parse_object()?;
+        Some(Self {
+            index: obj.get_key(SPA_PARAM_ROUTE_index)?.parse_int()?,
+            // Output routes play audio, so their nodes are sinks, and vice versa
+            kind: match obj.get_key(SPA_PARAM_ROUTE_direction)?.parse_id()? {
+                SPA_DIRECTION_OUTPUT => PortKind::Sink,
+                SPA_DIRECTION_INPUT => PortKind::Source,
+                _ => return None,
+            },
+            name: obj
+                .get_key(SPA_PARAM_ROUTE_name)?
+                .parse_string()?
+                .to_owned(),
+            description: obj
+                .get_key(SPA_PARAM_ROUTE_description)
+                .and_then(|v| v.parse_string())
+                .unwrap_or_default()
+                .to_owned(),
+            available: obj
+                .get_key(SPA_PARAM_ROUTE_available)
+                .and_then(|v| v.parse_id())
+                .map(|available| available != SPA_PARAM_AVAILABILITY_no)
+                .unwrap_or(true),
+            profiles: obj
+                .get_key(SPA_PARAM_ROUTE_profiles)
+                .and_then(|v| v.parse_value_array())
+                .and_then(|v| v.parse_ints())
+                .map(ToOwned::to_owned)
+                .unwrap_or_default(),
+            devices: obj
+                .get_key(SPA_PARAM_ROUTE_devices)
+                .and_then(|v| v.parse_value_array())
+                .and_then(|v| v.parse_ints())
+                .map(ToOwned::to_owned)
+                .unwrap_or_default(),
+        })
+    }
+
+    /// `Route '{ index: <route_index>, device: <device_index>, save: true }'`
+    pub fn build_select_pod(&self, device_index: i32) -> (ParamType, PodBytes) {
+        let pod = Value::Object(object! {
+            SpaTypes::ObjectParamRoute,
+            ParamType::Route,
+            Property::new(SPA_PARAM_ROUTE_index, Value::Int(self.index)),
+            Property::new(SPA_PARAM_ROUTE_device, Value::Int(device_index)),
+            Property::new(SPA_PARAM_ROUTE_save, Value::Bool(true)),
+        })
+        .serialize();
+        (ParamType::Route, pod)
+    }
+}
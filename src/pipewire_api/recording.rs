@@ -0,0 +1,196 @@
+use std::{cell::RefCell, fs::File, io::BufWriter, path::PathBuf, rc::Rc};
+
+use anyhow::{Context, Result};
+use hound::{SampleFormat, WavSpec, WavWriter};
+use log::{debug, error};
+use pipewire::{
+    core::Core,
+    keys::*,
+    properties::properties,
+    spa::{
+        param::{
+            audio::AudioInfoRaw,
+            format::{MediaSubtype, MediaType},
+            format_utils, ParamType,
+        },
+        utils::Direction,
+    },
+    stream::{Stream, StreamFlags, StreamListener},
+};
+use ulid::Ulid;
+
+use crate::SONUSMIX_APP_ID;
+
+use super::{pod::build_capture_format_pod, SONUSMIX_APP_NAME};
+
+type Writer = Rc<RefCell<Option<WavWriter<BufWriter<File>>>>>;
+
+/// A capture stream that writes everything it receives to a WAV file. The stream isn't connected
+/// to anything by itself. Instead, the nodes being recorded are linked into it once its ports
+/// exist, so that several nodes are mixed into one file. The file is finalized when this is
+/// dropped.
+pub(super) struct Recording {
+    /// The id of the stream's node, once it has appeared in the registry
+    pub(super) id: Option<u32>,
+    /// The nodes being recorded that have not been linked to the stream yet
+    #[cfg_attr(test, allow(dead_code))] // Linking is skipped in tests
+    pub(super) unlinked_nodes: Vec<u32>,
+    stream: Stream,
+    _listener: StreamListener<RecordingData>,
+    writer: Writer,
+}
+
+struct RecordingData {
+    path: PathBuf,
+    format: AudioInfoRaw,
+    writer: Writer,
+}
+
+impl Recording {
+    pub fn node_name(id: impl std::fmt::Display) -> String {
+        format!("sonusmix.recording.{id}")
+    }
+
+    /// Start recording the nodes in `node_ids` to the file at `path`.
+    pub(super) fn start(core: &Core, id: Ulid, node_ids: Vec<u32>, path: PathBuf) -> Result<Self> {
+        let stream = Stream::new(
+            core,
+            &Self::node_name(id),
+            properties! {
+                *MEDIA_TYPE => "Audio",
+                *MEDIA_CATEGORY => "Capture",
+                *MEDIA_ROLE => "Production",
+                *NODE_NAME => Self::node_name(id),
+                *APP_NAME => SONUSMIX_APP_NAME,
+                *APP_ICON_NAME => SONUSMIX_APP_ID,
+                *NODE_DONT_RECONNECT => "true",
+            },
+        )
+        .context("Failed to create recording stream")?;
+
+        let writer = Writer::default();
+        let listener = stream
+            .add_local_listener_with_user_data(RecordingData {
+                path,
+                format: AudioInfoRaw::new(),
+                writer: writer.clone(),
+            })
+            .param_changed(|_, data, id, param| {
+                let Some(param) = param else {
+                    return;
+                };
+                if id != ParamType::Format.as_raw() {
+                    return;
+                }
+                if !matches!(
+                    format_utils::parse_format(param),
+                    Ok((MediaType::Audio, MediaSubtype::Raw))
+                ) {
+                    return;
+                }
+                if let Err(err) = data.format.parse(param) {
+                    error!("Failed to parse recording format: {err:?}");
+                    return;
+                }
+                data.create_writer();
+            })
+            .process(|stream, data| {
+                let Some(mut buffer) = stream.dequeue_buffer() else {
+                    return;
+                };
+                let Some(buffer_data) = buffer.datas_mut().first_mut() else {
+                    return;
+                };
+                let offset = buffer_data.chunk().offset() as usize;
+                let size = buffer_data.chunk().size() as usize;
+                let Some(bytes) = buffer_data.data() else {
+                    return;
+                };
+                let Some(bytes) = bytes.get(offset..offset + size) else {
+                    return;
+                };
+                data.write_samples(bytes);
+            })
+            .register()
+            .context("Failed to register recording stream listener")?;
+
+        let format = build_capture_format_pod();
+        stream
+            .connect(
+                Direction::Input,
+                None,
+                StreamFlags::MAP_BUFFERS,
+                &mut [format.pod()],
+            )
+            .context("Failed to connect recording stream")?;
+
+        Ok(Self {
+            id: None,
+            unlinked_nodes: node_ids,
+            stream,
+            _listener: listener,
+            writer,
+        })
+    }
+}
+
+impl std::fmt::Debug for Recording {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Recording")
+            .field("recording", &self.writer.borrow().is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for Recording {
+    fn drop(&mut self) {
+        if let Err(err) = self.stream.disconnect() {
+            error!("Failed to disconnect recording stream: {err:?}");
+        }
+        if let Some(writer) = self.writer.borrow_mut().take() {
+            if let Err(err) = writer.finalize() {
+                error!("Failed to finalize recording: {err:?}");
+            }
+        }
+    }
+}
+
+impl RecordingData {
+    /// Create the WAV file once the format is known. If the format changes afterwards, the file
+    /// keeps the first format, since a WAV file can only have one.
+    fn create_writer(&mut self) {
+        let mut writer = self.writer.borrow_mut();
+        if writer.is_some() {
+            debug!("Recording format changed, keeping the original format");
+            return;
+        }
+        let spec = WavSpec {
+            channels: self.format.channels() as u16,
+            sample_rate: self.format.rate(),
+            bits_per_sample: 32,
+            sample_format: SampleFormat::Float,
+        };
+        match WavWriter::create(&self.path, spec) {
+            Ok(new_writer) => *writer = Some(new_writer),
+            Err(err) => error!("Failed to create recording file {:?}: {err:?}", self.path),
+        }
+    }
+
+    fn write_samples(&mut self, bytes: &[u8]) {
+        let mut writer = self.writer.borrow_mut();
+        let Some(wav_writer) = writer.as_mut() else {
+            return;
+        };
+        for sample in bytes.chunks_exact(std::mem::size_of::<f32>()) {
+            let sample = f32::from_le_bytes(sample.try_into().expect("Chunks are the right size"));
+            if let Err(err) = wav_writer.write_sample(sample) {
+                error!("Failed to write to recording file {:?}: {err:?}", self.path);
+                // Stop writing, but keep what was written so far
+                if let Some(wav_writer) = writer.take() {
+                    let _ = wav_writer.finalize();
+                }
+                return;
+            }
+        }
+    }
+}
@@ -4,7 +4,9 @@ use std::{collections::HashMap, fmt::Debug};
 use ulid::Ulid;
 
 use pipewire::{
+    keys::{NODE_DESCRIPTION, NODE_NICK},
     node::NodeInfoRef,
+    properties::properties,
     registry::{GlobalObject, Registry},
     spa::{
         param::ParamType,
@@ -15,25 +17,53 @@ use pipewire::{
 };
 
 use super::{
+    midi::{MidiPorts, MIDI_NODE_NAME_PREFIX},
     object::{
-        Client, Device, EndpointId, GroupNode, Link, Node, ObjectConvertError, Port, PortKind,
+        Client, DefaultMetadata, Device, EndpointId, GroupNode, Link, LocalNode, LoopbackNode,
+        Node, ObjectConvertError, Port, PortKind,
     },
-    pod::{build_node_mute_pod, build_node_volume_pod, DeviceActiveRoute, NodeProps},
+    pod::{
+        build_device_profile_pod, build_node_mute_pod, build_node_volume_pod, DeviceActiveRoute,
+        DeviceProfile, DeviceRoute, NodeProps,
+    },
+    recording::Recording,
     Graph,
 };
 
 #[derive(Debug)]
 pub(super) struct Store {
     pub(super) sonusmix_client_id: Option<u32>,
-    /// This map is used to store proxies of group nodes created by Sonusmix. They will be
-    /// duplicated elsewhere in the store, but to avoid overcomplicating that code, we will store
-    /// them here since dropping these copies deletes the object on the server.
-    pub(super) group_nodes: HashMap<Ulid, GroupNode>,
+    /// This map is used to store the nodes of group nodes, which Sonusmix hosts itself. They will
+    /// be duplicated elsewhere in the store, but to avoid overcomplicating that code, we will
+    /// store them here since dropping these copies removes the node from the server.
+    pub(super) group_nodes: HashMap<Ulid, GroupNode<LocalNode, ()>>,
+    /// Group nodes that are being replaced by a new node with a different kind. They are kept
+    /// until their links have been moved to the new node.
+    pub(super) replaced_group_nodes: HashMap<Ulid, GroupNode<LocalNode, ()>>,
+    /// Hidden nodes that links with a send level are routed through, so their gain can be
+    /// changed. Stored for the same reason as `group_nodes`.
+    pub(super) send_nodes: HashMap<Ulid, GroupNode>,
+    /// Like `group_nodes`, this stores the modules of loopbacks created by Sonusmix, since
+    /// dropping them unloads the module and removes its nodes.
+    pub(super) loopbacks: HashMap<Ulid, LoopbackNode>,
+    /// Recording streams are kept out of the graph, and only stored here so they can be stopped.
+    pub(super) recordings: HashMap<Ulid, Recording>,
+    /// Sonusmix's MIDI ports, if they could be created.
+    pub(super) midi_ports: Option<MidiPorts>,
     pub(super) clients: HashMap<u32, Client>,
     pub(super) devices: HashMap<u32, Device>,
     pub(super) nodes: HashMap<u32, Node>,
     pub(super) ports: HashMap<u32, Port>,
     pub(super) links: HashMap<u32, Link>,
+    /// The metadata object holding the default nodes, if the session manager has created it
+    pub(super) default_metadata: Option<DefaultMetadata>,
+    /// The `node.name` of the current default sink
+    pub(super) default_sink: Option<String>,
+    /// The `node.name` of the current default source
+    pub(super) default_source: Option<String>,
+    /// The `target.object` and `target.node` properties set on streams in the default metadata,
+    /// by stream id and key, so streams can be retargeted when a group node is replaced
+    pub(super) stream_targets: HashMap<u32, HashMap<String, String>>,
 }
 
 impl Store {
@@ -41,11 +71,20 @@ impl Store {
         Self {
             sonusmix_client_id: None,
             group_nodes: HashMap::new(),
+            replaced_group_nodes: HashMap::new(),
+            send_nodes: HashMap::new(),
+            loopbacks: HashMap::new(),
+            recordings: HashMap::new(),
+            midi_ports: None,
             clients: HashMap::new(),
             devices: HashMap::new(),
             nodes: HashMap::new(),
             ports: HashMap::new(),
             links: HashMap::new(),
+            default_metadata: None,
+            default_sink: None,
+            default_source: None,
+            stream_targets: HashMap::new(),
         }
     }
 
@@ -73,6 +112,7 @@ impl Store {
             ObjectType::Node => self.add_node(registry, object)?,
             ObjectType::Port => self.add_port(registry, object)?,
             ObjectType::Link => self.add_link(registry, object)?,
+            ObjectType::Metadata => return self.add_metadata(registry, object),
             _ => return Ok(false),
         }
         Ok(true)
@@ -87,6 +127,23 @@ impl Store {
         } else if let Some(_device) = self.devices.remove(&id) {
             // Nothing else to do (for now)
         } else if let Some(node) = self.nodes.remove(&id) {
+            // If a group node's node was removed from outside of Sonusmix, drop it, so that it
+            // is created again
+            self.group_nodes
+                .retain(|_, group_node| group_node.id != Some(node.id));
+            self.replaced_group_nodes
+                .retain(|_, group_node| group_node.id != Some(node.id));
+
+            // If the node was one half of a loopback, forget its id
+            for loopback in self.loopbacks.values_mut() {
+                if loopback.capture_id == Some(node.id) {
+                    loopback.capture_id = None;
+                }
+                if loopback.playback_id == Some(node.id) {
+                    loopback.playback_id = None;
+                }
+            }
+
             // If the endpoint the node belongs to exists, remove the node from it
             match node.endpoint {
                 EndpointId::Device { id, .. } => {
@@ -106,6 +163,15 @@ impl Store {
                 node.ports.retain(|(id, _, _)| *id != port.id);
                 node.identifier.update_is_monitor(node.is_source_monitor());
             }
+        } else if self
+            .default_metadata
+            .as_ref()
+            .is_some_and(|metadata| metadata.id == id)
+        {
+            self.default_metadata = None;
+            self.stream_targets.clear();
+            self.default_sink = None;
+            self.default_source = None;
         } else if let Some(link) = self.links.remove(&id) {
             // If the ports the link belongs to exist, remove the link from them
             if let Some(port) = self.ports.get_mut(&link.start_port) {
@@ -175,6 +241,23 @@ impl Store {
         // Create the node
         let mut node = Node::from_global(registry, object)?;
 
+        // Sonusmix's own recording and MIDI streams should not show up as endpoints, so leave
+        // them out. Their ports and links will be ignored since they don't belong to a known node,
+        // but recordings need their node's id to link the recorded nodes to them.
+        if let Some(name) = node.identifier.node_name() {
+            if name.starts_with(&Recording::node_name("")) {
+                for (id, recording) in self.recordings.iter_mut() {
+                    if name == Recording::node_name(id) {
+                        recording.id = Some(node.id);
+                    }
+                }
+                return Ok(());
+            }
+            if name.starts_with(MIDI_NODE_NAME_PREFIX) {
+                return Ok(());
+            }
+        }
+
         // Find and add any ports belonging to the node
         node.ports = self
             .ports
@@ -199,6 +282,30 @@ impl Store {
             }
         }
 
+        if let Some(node_name) = node.identifier.node_name() {
+            // If the node belongs to a group node, record its id. While a group node is being
+            // replaced, the old and new nodes have the same name, but the old one already has
+            // its id.
+            for (id, group_node) in self.group_nodes.iter_mut() {
+                let replaced_id = self
+                    .replaced_group_nodes
+                    .get(id)
+                    .and_then(|replaced| replaced.id);
+                if node_name == GroupNode::node_name(id) && replaced_id != Some(node.id) {
+                    group_node.id = Some(node.id);
+                }
+            }
+
+            // If the node is one half of a loopback, record its id on the loopback
+            for (id, loopback) in self.loopbacks.iter_mut() {
+                if node_name == LoopbackNode::capture_node_name(id) {
+                    loopback.capture_id = Some(node.id);
+                } else if node_name == LoopbackNode::playback_node_name(id) {
+                    loopback.playback_id = Some(node.id);
+                }
+            }
+        }
+
         // Add the node
         self.nodes.insert(node.id, node);
         Ok(())
@@ -256,6 +363,68 @@ impl Store {
         Ok(())
     }
 
+    /// Only the metadata object holding the default nodes is kept, so the returned boolean is
+    /// false for any other metadata.
+    pub(super) fn add_metadata(
+        &mut self,
+        registry: &Registry,
+        object: &GlobalObject<&DictRef>,
+    ) -> Result<bool, ObjectConvertError> {
+        let Some(metadata) = DefaultMetadata::from_global(registry, object)? else {
+            return Ok(false);
+        };
+        self.default_metadata = Some(metadata);
+        Ok(true)
+    }
+
+    /// Handle a property changing on the default metadata. A missing key means every property
+    /// was cleared, and a missing value means the key was removed. Values look like
+    /// `{ "name": "alsa_output.pci-0000_00_1f.3.analog-stereo" }`.
+    pub(super) fn update_default_node(&mut self, key: Option<&str>, value: Option<&str>) {
+        let name = value.and_then(|value| {
+            let value: serde_json::Value = serde_json::from_str(value)
+                .map_err(|err| error!("Invalid default node value '{value}': {err}"))
+                .ok()?;
+            value.get("name")?.as_str().map(ToOwned::to_owned)
+        });
+        match key {
+            Some("default.audio.sink") => self.default_sink = name,
+            Some("default.audio.source") => self.default_source = name,
+            Some(_) => {}
+            None => {
+                self.default_sink = None;
+                self.default_source = None;
+            }
+        }
+    }
+
+    /// Handle a property changing on a stream in the default metadata. Like with
+    /// `update_default_node`, a missing key clears every property of the stream.
+    pub(super) fn update_stream_target(
+        &mut self,
+        subject: u32,
+        key: Option<&str>,
+        value: Option<&str>,
+    ) {
+        match (key, value) {
+            (Some(key @ ("target.object" | "target.node")), Some(value)) => {
+                self.stream_targets
+                    .entry(subject)
+                    .or_default()
+                    .insert(key.to_owned(), value.to_owned());
+            }
+            (Some(key @ ("target.object" | "target.node")), None) => {
+                if let Some(targets) = self.stream_targets.get_mut(&subject) {
+                    targets.remove(key);
+                }
+            }
+            (Some(_), _) => {}
+            (None, _) => {
+                self.stream_targets.remove(&subject);
+            }
+        }
+    }
+
     pub(super) fn update_node_param(&mut self, _type_: ParamType, id: u32, pod: Option<&Pod>) {
         // abort if no pod is available
         let pod = match pod {
@@ -364,9 +533,62 @@ impl Store {
         Ok(())
     }
 
+    /// Rename a group node on the server, without re-creating it.
+    pub(super) fn rename_group_node(&mut self, id: Ulid, name: String) -> Result<()> {
+        let group_node = self
+            .group_nodes
+            .get_mut(&id)
+            .ok_or_else(|| anyhow!("Group node with id '{id}' does not exist"))?;
+        group_node.proxy.update_properties(&properties! {
+            *NODE_DESCRIPTION => &*name,
+            *NODE_NICK => &*name,
+        });
+        group_node.name = name;
+        Ok(())
+    }
+
+    pub(super) fn set_device_profile(&mut self, id: u32, profile_index: i32) -> Result<()> {
+        let device = self
+            .devices
+            .get(&id)
+            .ok_or_else(|| anyhow!("Device {id} not found"))?;
+        if !device
+            .profiles
+            .iter()
+            .any(|profile| profile.index == profile_index)
+        {
+            return Err(anyhow!(
+                "Device {id} does not have a profile with index {profile_index}"
+            ));
+        }
+        let (param_type, pod) = build_device_profile_pod(profile_index);
+        device.proxy.set_param(param_type, 0, pod.pod());
+        Ok(())
+    }
+
+    pub(super) fn set_device_route(
+        &mut self,
+        id: u32,
+        route_index: i32,
+        device_index: i32,
+    ) -> Result<()> {
+        let device = self
+            .devices
+            .get(&id)
+            .ok_or_else(|| anyhow!("Device {id} not found"))?;
+        let route = device
+            .routes
+            .iter()
+            .find(|route| route.index == route_index)
+            .ok_or_else(|| anyhow!("Device {id} does not have a route with index {route_index}"))?;
+        let (param_type, pod) = route.build_select_pod(device_index);
+        device.proxy.set_param(param_type, 0, pod.pod());
+        Ok(())
+    }
+
     pub(super) fn update_device_param(
         &mut self,
-        _type_: ParamType,
+        type_: ParamType,
         id: u32,
         index: u32,
         pod: Option<&Pod>,
@@ -376,9 +598,15 @@ impl Store {
             .get_mut(&id)
             .expect("The device was destroyed unexpectedly");
 
-        // If index is 0, clear as we assume more routes will be coming later if there are more
+        // If index is 0, clear as we assume more params will be coming later if there are more
         if index == 0 {
-            device.active_routes.clear();
+            match type_ {
+                ParamType::Route => device.active_routes.clear(),
+                ParamType::EnumProfile => device.profiles.clear(),
+                ParamType::Profile => device.active_profile = None,
+                ParamType::EnumRoute => device.routes.clear(),
+                _ => {}
+            }
         }
 
         // abort if no pod is available
@@ -388,10 +616,32 @@ impl Store {
         };
 
         // Deserialize the pod
-        if let Some(route) = DeviceActiveRoute::from_value(pod) {
-            device.active_routes.push(route);
-        } else {
-            error!("Failed to find needed fields on device {id}'s active route param.");
+        match type_ {
+            ParamType::Route => {
+                if let Some(route) = DeviceActiveRoute::from_value(pod) {
+                    device.active_routes.push(route);
+                } else {
+                    error!("Failed to find needed fields on device {id}'s active route param.");
+                }
+            }
+            ParamType::EnumProfile => {
+                if let Some(profile) = DeviceProfile::from_value(pod) {
+                    device.profiles.push(profile);
+                } else {
+                    error!("Failed to find needed fields on device {id}'s profile param.");
+                }
+            }
+            ParamType::Profile => {
+                device.active_profile = DeviceProfile::active_index_from_value(pod);
+            }
+            ParamType::EnumRoute => {
+                if let Some(route) = DeviceRoute::from_value(pod) {
+                    device.routes.push(route);
+                } else {
+                    error!("Failed to find needed fields on device {id}'s route param.");
+                }
+            }
+            _ => {}
         }
     }
 
@@ -399,11 +649,15 @@ impl Store {
     pub fn dump_graph(&self) -> Graph {
         Graph {
             group_nodes: self.group_nodes.iter().map(|(id, group_node)| (*id, group_node.without_proxy())).collect(),
+            send_nodes: self.send_nodes.iter().map(|(id, send_node)| (*id, send_node.without_proxy())).collect(),
+            loopbacks: self.loopbacks.iter().map(|(id, loopback)| (*id, loopback.without_module())).collect(),
             clients: self.clients.iter().map(|(id, client)| (*id, client.without_proxy())).collect(),
             devices: self.devices.iter().map(|(id, device)| (*id, device.without_proxy())).collect(),
             nodes: self.nodes.iter().map(|(id, node)| (*id, node.without_proxy())).collect(),
             ports: self.ports.iter().map(|(id, port)| (*id, port.without_proxy())).collect(),
             links: self.links.iter().map(|(id, link)| (*id, link.without_proxy())).collect(),
+            default_sink: self.default_sink.clone(),
+            default_source: self.default_source.clone(),
         }
     }
 }
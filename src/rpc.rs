@@ -0,0 +1,416 @@
+//! A [JSON-RPC 2.0](https://www.jsonrpc.org/specification) control socket, so that scripts and
+//! other tools can control Sonusmix.
+//!
+//! The socket is created at `$XDG_RUNTIME_DIR/sonusmix/rpc.sock`. Each request and response is a
+//! single line of JSON. The API follows [`API_VERSION`], which is only incremented when a change
+//! could break existing clients. It can be checked with the `GetVersion` method.
+//!
+//! # Methods
+//!
+//! | Method | Params | Result |
+//! |--------|--------|--------|
+//! | `GetVersion` | none | `{ "api": <API version>, "sonusmix": <app version> }` |
+//! | `GetState` | none | The state (see below) |
+//! | `GetEndpoints` | none | A list of all endpoints |
+//! | `GetEndpoint` | `[<descriptor>]` | The endpoint, or error `-32001` if it doesn't exist |
+//! | `GetLinks` | none | A list of all links between endpoints |
+//! | `Subscribe` | none | The current state. Afterwards, `StateChanged` notifications are sent |
+//! | `Unsubscribe` | none | `true` |
+//!
+//! Every variant of `SonusmixMsg` is also a method, with the variant's fields as params, except
+//! for the ones Sonusmix only sends to itself (`MidiInput`, `ReleasePushToTalk` and
+//! `RetryLocks`), e.g.
+//! `{"jsonrpc": "2.0", "id": 1, "method": "SetMute", "params": [{"GroupNode": "<id>"}, true]}`.
+//! These return `null` once the message is queued, and the change can be seen in the next state.
+//! The built-in mute group that mutes every source is engaged with
+//! `{"jsonrpc": "2.0", "id": 1, "method": "SetMuteGroupEngaged", "params": ["AllSources", true]}`.
+//!
+//! Endpoints are identified by descriptors, such as `{"EphemeralNode": [42, "source"]}`,
+//! `{"GroupNode": "<id>"}` or `{"Application": ["<id>", "sink"]}`.
+//!
+//! # State
+//!
+//! `GetState`, `Subscribe` and `StateChanged` return the state as an object with the fields
+//! `active_sources`, `active_sinks` (lists of descriptors in the order they are shown), `endpoints`,
+//! `links`, `group_nodes`, `loopbacks`, `applications`, `midi_mappings`, `sections`, `vcas` and
+//! `mute_groups` (lists of objects), as well as `cue_endpoint`, the `soloed` endpoints and
+//! `all_sources_muted`, which is true while the built-in "all sources" mute group is engaged.
+//!
+//! # Notifications
+//!
+//! After subscribing, the notification
+//! `{"jsonrpc": "2.0", "method": "StateChanged", "params": {"message": <message>, "state": <state>}}`
+//! is sent whenever the state changes. `message` is the change to the state's structure that
+//! caused the update, such as `{"EndpointAdded": <descriptor>}`, or `null`.
+
+use std::{os::unix::fs::PermissionsExt, path::PathBuf, sync::Arc};
+
+use anyhow::{Context, Result};
+use log::{debug, error, info};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+};
+
+use crate::state::{
+    Application, Endpoint, EndpointDescriptor, GroupNode, Link, Loopback, MidiMapping, MuteGroup,
+    MuteGroupTarget, Section, SonusmixMsg, SonusmixOutputMsg, SonusmixReducer, SonusmixState, Vca,
+};
+use crate::APP_VERSION;
+
+/// The version of the API. Incremented whenever a change could break existing clients.
+pub const API_VERSION: u32 = 2;
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const ENDPOINT_NOT_FOUND: i64 = -32001;
+
+fn socket_path() -> Option<PathBuf> {
+    Some(dirs::runtime_dir()?.join("sonusmix").join("rpc.sock"))
+}
+
+/// The state as it is sent to clients. Maps are sent as lists, since JSON only allows strings as
+/// map keys.
+#[derive(Debug, Serialize)]
+pub(crate) struct StateView<'a> {
+    active_sources: &'a [EndpointDescriptor],
+    active_sinks: &'a [EndpointDescriptor],
+    endpoints: Vec<&'a Endpoint>,
+    links: &'a [Link],
+    group_nodes: Vec<&'a GroupNode>,
+    loopbacks: Vec<&'a Loopback>,
+    applications: Vec<&'a Application>,
+    midi_mappings: &'a [MidiMapping],
+    sections: Vec<&'a Section>,
+    vcas: Vec<&'a Vca>,
+    cue_endpoint: Option<EndpointDescriptor>,
+    soloed: &'a [EndpointDescriptor],
+    mute_groups: Vec<&'a MuteGroup>,
+    all_sources_muted: bool,
+}
+
+impl<'a> StateView<'a> {
+    pub(crate) fn new(state: &'a SonusmixState) -> Self {
+        Self {
+            active_sources: &state.active_sources,
+            active_sinks: &state.active_sinks,
+            endpoints: state.endpoints.values().collect(),
+            links: &state.links,
+            group_nodes: state.group_nodes.values().collect(),
+            loopbacks: state.loopbacks.values().collect(),
+            applications: state.applications.values().collect(),
+            midi_mappings: &state.midi_mappings,
+            sections: state.sections.values().collect(),
+            vcas: state.vcas.values().collect(),
+            cue_endpoint: state.cue_endpoint,
+            soloed: &state.soloed,
+            mute_groups: state.mute_groups.values().collect(),
+            all_sources_muted: state.is_mute_group_engaged(MuteGroupTarget::AllSources),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Request {
+    jsonrpc: String,
+    method: String,
+    #[serde(default)]
+    params: Value,
+    /// Requests without an id are notifications, which don't get a response
+    #[serde(default)]
+    id: Option<Value>,
+}
+
+#[derive(Debug)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl ToString) -> Self {
+        Self {
+            code,
+            message: message.to_string(),
+        }
+    }
+}
+
+/// Per-connection state.
+#[derive(Debug, Default)]
+struct Connection {
+    subscribed: bool,
+}
+
+impl Connection {
+    /// Handle a line received from a client. Returns the response to send, if any, and the
+    /// message to send to the reducer, if any.
+    fn handle_line(
+        &mut self,
+        line: &str,
+        state: &SonusmixState,
+    ) -> (Option<Value>, Option<SonusmixMsg>) {
+        let request: Request = match serde_json::from_str(line) {
+            Ok(request) => request,
+            Err(err) => {
+                let code = if err.is_syntax() || err.is_eof() {
+                    PARSE_ERROR
+                } else {
+                    INVALID_REQUEST
+                };
+                return (
+                    Some(error_response(Value::Null, RpcError::new(code, err))),
+                    None,
+                );
+            }
+        };
+        let id = request.id.clone();
+        if request.jsonrpc != "2.0" {
+            let error = RpcError::new(INVALID_REQUEST, "Only JSON-RPC 2.0 is supported");
+            return (Some(error_response(id.unwrap_or_default(), error)), None);
+        }
+
+        let (result, message) = match self.handle_request(request, state) {
+            Ok((result, message)) => (Ok(result), message),
+            Err(err) => (Err(err), None),
+        };
+        let response = id.map(|id| match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(err) => error_response(id, err),
+        });
+        (response, message)
+    }
+
+    fn handle_request(
+        &mut self,
+        request: Request,
+        state: &SonusmixState,
+    ) -> Result<(Value, Option<SonusmixMsg>), RpcError> {
+        let result = match request.method.as_str() {
+            "GetVersion" => json!({ "api": API_VERSION, "sonusmix": APP_VERSION }),
+            "GetState" => to_value(StateView::new(state))?,
+            "GetEndpoints" => to_value(state.endpoints.values().collect::<Vec<_>>())?,
+            "GetEndpoint" => {
+                let descriptor: EndpointDescriptor =
+                    serde_json::from_value(variant_params(request.params))
+                        .map_err(|err| RpcError::new(INVALID_PARAMS, err))?;
+                let endpoint = state.endpoints.get(&descriptor).ok_or_else(|| {
+                    RpcError::new(ENDPOINT_NOT_FOUND, "The endpoint does not exist")
+                })?;
+                to_value(endpoint)?
+            }
+            "GetLinks" => to_value(&state.links)?,
+            "Subscribe" => {
+                self.subscribed = true;
+                to_value(StateView::new(state))?
+            }
+            "Unsubscribe" => {
+                self.subscribed = false;
+                Value::Bool(true)
+            }
+            method => {
+                let message: SonusmixMsg = serde_json::from_value(json!({
+                    method: variant_params(request.params)
+                }))
+                .map_err(|err| {
+                    if err.to_string().starts_with("unknown variant") {
+                        RpcError::new(METHOD_NOT_FOUND, format!("Unknown method {method}"))
+                    } else {
+                        RpcError::new(INVALID_PARAMS, err)
+                    }
+                })?;
+                if message.is_internal() {
+                    return Err(RpcError::new(
+                        METHOD_NOT_FOUND,
+                        format!("Unknown method {method}"),
+                    ));
+                }
+                return Ok((Value::Null, Some(message)));
+            }
+        };
+        Ok((result, None))
+    }
+}
+
+/// Params are always given as a list of the fields of a message. Serde represents messages with
+/// no fields as null and messages with one field as just the field, so convert those.
+fn variant_params(params: Value) -> Value {
+    match params {
+        Value::Array(mut fields) if fields.len() <= 1 => fields.pop().unwrap_or(Value::Null),
+        params => params,
+    }
+}
+
+fn to_value(value: impl Serialize) -> Result<Value, RpcError> {
+    serde_json::to_value(value).map_err(|err| RpcError::new(-32603, err))
+}
+
+fn error_response(id: Value, error: RpcError) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": error.code, "message": error.message },
+    })
+}
+
+fn bind() -> Result<UnixListener> {
+    let path = socket_path().context("Could not resolve the runtime dir")?;
+    let dir = path.parent().expect("the socket path has a parent");
+    std::fs::create_dir_all(dir).context("Failed to create the socket directory")?;
+    std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o700))
+        .context("Failed to restrict access to the socket directory")?;
+    // Only one instance of Sonusmix runs at a time, so any existing socket is stale
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path).context("Failed to bind the socket")?;
+    info!("Listening for JSON-RPC requests at {path:?}");
+    Ok(listener)
+}
+
+/// Remove the socket file, so that clients don't try to connect after Sonusmix exits.
+pub fn remove_socket() {
+    if let Some(path) = socket_path() {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+/// Accepts connections to the control socket and handles each on its own task.
+pub async fn rpc_task() {
+    let listener = match bind() {
+        Ok(listener) => listener,
+        Err(err) => {
+            error!("Failed to start the control socket: {err:#}");
+            return;
+        }
+    };
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                relm4::spawn(handle_connection(stream));
+            }
+            Err(err) => error!("Failed to accept a control socket connection: {err}"),
+        }
+    }
+}
+
+async fn handle_connection(stream: UnixStream) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let (state_tx, state_rx) = relm4::channel();
+    let mut state: Arc<SonusmixState> =
+        SonusmixReducer::subscribe_msg(&state_tx, |state, msg| (state, msg));
+    let mut connection = Connection::default();
+
+    loop {
+        let output = tokio::select! {
+            line = lines.next_line() => match line {
+                Ok(Some(line)) if line.trim().is_empty() => None,
+                Ok(Some(line)) => {
+                    let (response, message) = connection.handle_line(&line, &state);
+                    if let Some(message) = message {
+                        SonusmixReducer::emit(message);
+                    }
+                    response
+                }
+                Ok(None) => break,
+                Err(err) => {
+                    debug!("Control socket connection closed: {err}");
+                    break;
+                }
+            },
+            Some((new_state, message)) = state_rx.recv() => {
+                state = new_state;
+                connection
+                    .subscribed
+                    .then(|| state_changed_notification(&state, message))
+            }
+            else => break,
+        };
+        if let Some(output) = output {
+            let mut line = output.to_string();
+            line.push('\n');
+            if let Err(err) = writer.write_all(line.as_bytes()).await {
+                debug!("Control socket connection closed: {err}");
+                break;
+            }
+        }
+    }
+}
+
+fn state_changed_notification(state: &SonusmixState, message: Option<SonusmixOutputMsg>) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "StateChanged",
+        "params": { "message": message, "state": StateView::new(state) },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipewire_api::PortKind;
+
+    #[test]
+    fn rpc_requests() {
+        let mut state = SonusmixState::default();
+        let descriptor = EndpointDescriptor::EphemeralNode(1, PortKind::Source);
+        state
+            .endpoints
+            .insert(descriptor, Endpoint::new_test(descriptor));
+        let mut connection = Connection::default();
+
+        let (response, _) = connection.handle_line(
+            r#"{"jsonrpc": "2.0", "id": 1, "method": "GetEndpoint", "params": [{"EphemeralNode": [1, "source"]}]}"#,
+            &state,
+        );
+        assert_eq!(
+            response.unwrap()["result"]["display_name"],
+            "TESTING_ENDPOINT"
+        );
+
+        let (response, message) = connection.handle_line(
+            r#"{"jsonrpc": "2.0", "id": 2, "method": "SetMute", "params": [{"EphemeralNode": [1, "source"]}, true]}"#,
+            &state,
+        );
+        assert_eq!(response.unwrap()["result"], Value::Null);
+        assert!(matches!(message, Some(SonusmixMsg::SetMute(desc, true)) if desc == descriptor));
+
+        // Messages without fields take no params, and notifications get no response
+        let (response, message) = connection.handle_line(
+            r#"{"jsonrpc": "2.0", "method": "StopAllRecordings"}"#,
+            &state,
+        );
+        assert!(response.is_none());
+        assert!(matches!(message, Some(SonusmixMsg::StopAllRecordings)));
+
+        let (response, _) = connection.handle_line(
+            r#"{"jsonrpc": "2.0", "id": 3, "method": "DoesNotExist"}"#,
+            &state,
+        );
+        assert_eq!(response.unwrap()["error"]["code"], METHOD_NOT_FOUND);
+
+        // Messages Sonusmix only sends to itself can't be called
+        let (response, message) = connection.handle_line(
+            r#"{"jsonrpc": "2.0", "id": 5, "method": "RetryLocks"}"#,
+            &state,
+        );
+        assert_eq!(response.unwrap()["error"]["code"], METHOD_NOT_FOUND);
+        assert!(message.is_none());
+
+        let (response, _) = connection.handle_line("{", &state);
+        assert_eq!(response.unwrap()["error"]["code"], PARSE_ERROR);
+
+        let (response, _) = connection.handle_line(
+            r#"{"jsonrpc": "2.0", "id": 4, "method": "Subscribe"}"#,
+            &state,
+        );
+        assert!(connection.subscribed);
+        assert_eq!(
+            response.unwrap()["result"]["active_sources"],
+            Value::Array(Vec::new())
+        );
+    }
+}
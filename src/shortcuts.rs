@@ -0,0 +1,133 @@
+//! Global keyboard shortcuts for push to talk, using the XDG GlobalShortcuts portal. The desktop
+//! decides which keys trigger each shortcut, and may ask the user to choose them.
+
+use std::pin::pin;
+
+use ashpd::desktop::{
+    global_shortcuts::{GlobalShortcuts, NewShortcut},
+    Session,
+};
+use futures_util::StreamExt;
+use log::{error, info};
+
+use crate::state::{
+    EndpointDescriptor, PushToTalkMode, SonusmixMsg, SonusmixReducer, SonusmixState,
+};
+
+const SHORTCUT_ID_PREFIX: &str = "push-to-talk:";
+
+/// Shortcut ids are derived from the endpoint, so that the desktop remembers the keys chosen for
+/// each endpoint.
+fn shortcut_id(endpoint_desc: EndpointDescriptor) -> String {
+    format!(
+        "{SHORTCUT_ID_PREFIX}{}",
+        serde_json::to_string(&endpoint_desc).expect("descriptors can be serialized")
+    )
+}
+
+fn shortcut_endpoint(id: &str) -> Option<EndpointDescriptor> {
+    serde_json::from_str(id.strip_prefix(SHORTCUT_ID_PREFIX)?).ok()
+}
+
+fn push_to_talk_endpoints(state: &SonusmixState) -> Vec<EndpointDescriptor> {
+    state
+        .active_sources
+        .iter()
+        .chain(state.active_sinks.iter())
+        .filter(|desc| {
+            state
+                .endpoints
+                .get(desc)
+                .is_some_and(|endpoint| endpoint.push_to_talk.is_some())
+        })
+        .copied()
+        .collect()
+}
+
+async fn bind_shortcuts<'a>(
+    portal: &GlobalShortcuts<'a>,
+    state: &SonusmixState,
+    endpoints: &[EndpointDescriptor],
+) -> ashpd::Result<Session<'a, GlobalShortcuts<'a>>> {
+    let session = portal.create_session().await?;
+    let shortcuts: Vec<NewShortcut> = endpoints
+        .iter()
+        .filter_map(|desc| {
+            let endpoint = state.endpoints.get(desc)?;
+            let action = match endpoint.push_to_talk? {
+                PushToTalkMode::PushToTalk => "Push to talk",
+                PushToTalkMode::PushToMute => "Push to mute",
+            };
+            Some(NewShortcut::new(
+                shortcut_id(*desc),
+                format!("{action}: {}", endpoint.custom_or_display_name()),
+            ))
+        })
+        .collect();
+    portal
+        .bind_shortcuts(&session, &shortcuts, None)
+        .await?
+        .response()?;
+    Ok(session)
+}
+
+/// Bind a global shortcut for each endpoint with push to talk, and send their presses and
+/// releases to the reducer. Does nothing if the portal is not available.
+pub async fn shortcuts_task() {
+    let portal = match GlobalShortcuts::new().await {
+        Ok(portal) => portal,
+        Err(err) => {
+            info!("Global shortcuts are not available: {err}");
+            return;
+        }
+    };
+    let (Ok(activated), Ok(deactivated)) = (
+        portal.receive_activated().await,
+        portal.receive_deactivated().await,
+    ) else {
+        error!("Failed to listen for global shortcuts");
+        return;
+    };
+    let mut activated = pin!(activated);
+    let mut deactivated = pin!(deactivated);
+
+    let (state_tx, state_rx) = relm4::channel();
+    let mut state = SonusmixReducer::subscribe(&state_tx, |state| state);
+    let mut bound_endpoints = Vec::new();
+    let mut session: Option<Session<GlobalShortcuts>> = None;
+
+    loop {
+        // Shortcuts can only be bound once per session, so start a new session whenever the
+        // endpoints with push to talk change
+        let endpoints = push_to_talk_endpoints(&state);
+        if endpoints != bound_endpoints {
+            if let Some(session) = session.take() {
+                let _ = session.close().await;
+            }
+            if !endpoints.is_empty() {
+                match bind_shortcuts(&portal, &state, &endpoints).await {
+                    Ok(new_session) => session = Some(new_session),
+                    Err(err) => error!("Failed to bind push to talk shortcuts: {err}"),
+                }
+            }
+            bound_endpoints = endpoints;
+        }
+
+        tokio::select! {
+            Some(new_state) = state_rx.recv() => {
+                state = new_state;
+            }
+            Some(event) = activated.next() => {
+                if let Some(endpoint_desc) = shortcut_endpoint(event.shortcut_id()) {
+                    SonusmixReducer::emit(SonusmixMsg::PushToTalk(endpoint_desc, true));
+                }
+            }
+            Some(event) = deactivated.next() => {
+                if let Some(endpoint_desc) = shortcut_endpoint(event.shortcut_id()) {
+                    SonusmixReducer::emit(SonusmixMsg::PushToTalk(endpoint_desc, false));
+                }
+            }
+            else => break,
+        }
+    }
+}
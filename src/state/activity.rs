@@ -0,0 +1,291 @@
+use std::time::{Duration, Instant, SystemTime};
+
+use serde::{Deserialize, Serialize};
+use tokio::time::sleep_until;
+
+use crate::pipewire_api::{Graph, Link as PwLink, Node as PwNode};
+
+use super::{EndpointDescriptor, SonusmixMsg, SonusmixReducer, SonusmixState};
+
+/// The most entries kept in the activity log. The oldest entries are dropped first.
+const MAX_ACTIVITY_ENTRIES: usize = 200;
+/// How long ago a lock must have been reverted to still count towards a lock fight
+const LOCK_FIGHT_WINDOW: Duration = Duration::from_secs(60);
+/// How many times a lock must be reverted within [`LOCK_FIGHT_WINDOW`] to count as a lock fight
+const LOCK_FIGHT_THRESHOLD: usize = 3;
+/// How long Sonusmix waits before restoring a lock the first time it is found to be fought over.
+/// The wait doubles every time the lock has to be restored again.
+const LOCK_BACKOFF_BASE: Duration = Duration::from_secs(1);
+/// The longest Sonusmix waits before restoring a lock that is fought over
+const LOCK_BACKOFF_MAX: Duration = Duration::from_secs(300);
+
+/// Something locked by Sonusmix that another client can change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum LockTarget {
+    Link(EndpointDescriptor, EndpointDescriptor),
+    Volume(EndpointDescriptor),
+    Mute(EndpointDescriptor),
+}
+
+impl LockTarget {
+    /// Whether the target belongs to an endpoint, including links to or from it.
+    pub fn involves(self, endpoint_desc: EndpointDescriptor) -> bool {
+        match self {
+            Self::Link(source, sink) => source == endpoint_desc || sink == endpoint_desc,
+            Self::Volume(desc) | Self::Mute(desc) => desc == endpoint_desc,
+        }
+    }
+
+    /// The message to unlock the target. Volume and mute are locked together, so unlocking
+    /// either unlocks both.
+    pub fn unlock_message(self) -> SonusmixMsg {
+        match self {
+            Self::Link(source, sink) => SonusmixMsg::SetLinkLocked(source, sink, false),
+            Self::Volume(desc) | Self::Mute(desc) => SonusmixMsg::SetVolumeLocked(desc, false),
+        }
+    }
+}
+
+/// A lock that another client keeps changing. Sonusmix waits longer and longer before restoring
+/// it, so the two don't keep changing it back and forth as fast as they can.
+#[derive(Debug, Clone)]
+pub struct LockBackoff {
+    /// The lock won't be restored until this time
+    pub until: Instant,
+    pub delay: Duration,
+    /// The name of the client fighting over the lock, if Pipewire tells us
+    pub client: Option<String>,
+}
+
+impl LockBackoff {
+    /// Whether Sonusmix is currently waiting to restore the lock.
+    pub fn is_active(&self) -> bool {
+        Instant::now() < self.until
+    }
+
+    /// Whether the fight is still going on. Once the lock hasn't needed restoring for
+    /// [`LOCK_FIGHT_WINDOW`] after the wait, it is no longer counted as being fought over.
+    fn is_current(&self, now: Instant) -> bool {
+        now < self.until + LOCK_FIGHT_WINDOW
+    }
+}
+
+/// A change made by another client that Sonusmix reverted because it was locked.
+#[derive(Debug, Clone)]
+pub struct ActivityEntry {
+    /// Increases with every entry, so new entries can be told apart from ones already seen
+    pub id: u64,
+    pub time: SystemTime,
+    pub target: LockTarget,
+    pub description: String,
+    /// The name of the client that made the change, if Pipewire tells us
+    pub client: Option<String>,
+    /// How many times this lock was reverted within [`LOCK_FIGHT_WINDOW`], including this time
+    pub repeat_count: usize,
+}
+
+impl ActivityEntry {
+    /// Whether another client keeps changing the locked target back.
+    pub fn is_lock_fight(&self) -> bool {
+        self.repeat_count >= LOCK_FIGHT_THRESHOLD
+    }
+
+    /// Whether this is the entry that made the lock count as being fought, so each fight is only
+    /// reported once.
+    pub fn started_lock_fight(&self) -> bool {
+        self.repeat_count == LOCK_FIGHT_THRESHOLD
+    }
+}
+
+impl SonusmixState {
+    /// Add an entry to the activity log.
+    pub(super) fn record_activity(
+        &mut self,
+        target: LockTarget,
+        description: String,
+        client: Option<String>,
+    ) {
+        let time = SystemTime::now();
+        let repeat_count = 1 + self
+            .activity_log
+            .iter()
+            .filter(|entry| entry.target == target)
+            .filter(|entry| {
+                time.duration_since(entry.time)
+                    .map_or(true, |age| age <= LOCK_FIGHT_WINDOW)
+            })
+            .count();
+        let id = self.activity_log.back().map_or(0, |entry| entry.id + 1);
+        self.activity_log.push_back(ActivityEntry {
+            id,
+            time,
+            target,
+            description,
+            client,
+            repeat_count,
+        });
+        while self.activity_log.len() > MAX_ACTIVITY_ENTRIES {
+            self.activity_log.pop_front();
+        }
+        let client = self
+            .activity_log
+            .back()
+            .and_then(|entry| entry.client.clone());
+
+        // Back off from locks that are being fought over, unless the user chose to keep fighting
+        if self.keep_fighting.contains(&target) {
+            return;
+        }
+        let now = Instant::now();
+        let previous = self
+            .lock_backoffs
+            .get(&target)
+            .filter(|backoff| backoff.is_current(now));
+        let delay = match previous {
+            Some(backoff) => (backoff.delay * 2).min(LOCK_BACKOFF_MAX),
+            None if repeat_count >= LOCK_FIGHT_THRESHOLD => LOCK_BACKOFF_BASE,
+            None => return,
+        };
+        let client = client.or_else(|| previous.and_then(|backoff| backoff.client.clone()));
+        self.lock_backoffs.insert(
+            target,
+            LockBackoff {
+                until: now + delay,
+                delay,
+                client,
+            },
+        );
+    }
+
+    /// Forget about fights over locks that no longer exist or that have ended.
+    pub(super) fn clean_up_lock_backoffs(&mut self) {
+        let now = Instant::now();
+        let backoffs = std::mem::take(&mut self.lock_backoffs);
+        self.lock_backoffs = backoffs
+            .into_iter()
+            .filter(|(target, backoff)| backoff.is_current(now) && self.is_locked(*target))
+            .collect();
+        let keep_fighting = std::mem::take(&mut self.keep_fighting);
+        self.keep_fighting = keep_fighting
+            .into_iter()
+            .filter(|target| self.is_locked(*target))
+            .collect();
+    }
+
+    /// Restore a lock immediately from now on, even though another client keeps changing it.
+    pub(super) fn keep_fighting(&mut self, target: LockTarget) {
+        if self.is_locked(target) {
+            self.lock_backoffs.remove(&target);
+            self.keep_fighting.insert(target);
+        }
+    }
+
+    fn is_locked(&self, target: LockTarget) -> bool {
+        match target {
+            LockTarget::Link(source, sink) => self
+                .links
+                .iter()
+                .any(|link| link.start == source && link.end == sink && link.state.is_locked()),
+            LockTarget::Volume(desc) | LockTarget::Mute(desc) => self
+                .endpoints
+                .get(&desc)
+                .is_some_and(|endpoint| endpoint.volume_locked_muted.is_locked()),
+        }
+    }
+
+    /// The locks of an endpoint that other clients are fighting over.
+    pub fn lock_fights(
+        &self,
+        endpoint_desc: EndpointDescriptor,
+    ) -> impl Iterator<Item = (LockTarget, &LockBackoff)> {
+        self.lock_backoffs
+            .iter()
+            .filter(move |(target, _)| target.involves(endpoint_desc))
+            .map(|(target, backoff)| (*target, backoff))
+    }
+
+    /// A human-readable name for a lock target.
+    pub fn describe_lock_target(&self, target: LockTarget) -> String {
+        match target {
+            LockTarget::Link(source, sink) => format!(
+                "The connection from {} to {}",
+                self.activity_name(&source),
+                self.activity_name(&sink)
+            ),
+            LockTarget::Volume(desc) => format!("The volume of {}", self.activity_name(&desc)),
+            LockTarget::Mute(desc) => format!("The mute state of {}", self.activity_name(&desc)),
+        }
+    }
+
+    /// The name of an endpoint, for activity log entries.
+    pub(super) fn activity_name(&self, endpoint_desc: &EndpointDescriptor) -> String {
+        self.endpoints
+            .get(endpoint_desc)
+            .map(|endpoint| endpoint.custom_or_display_name().to_owned())
+            .unwrap_or_else(|| "an unknown endpoint".to_owned())
+    }
+}
+
+/// The name of the client that created a link, if Pipewire exposes it.
+pub(super) fn link_client_name(graph: &Graph, link: &PwLink) -> Option<String> {
+    let client_id: u32 = link.props.get("client.id")?.parse().ok()?;
+    graph
+        .clients
+        .get(&client_id)
+        .map(|client| client.name.clone())
+}
+
+/// The name of the client that probably moved a stream away from a locked link: the creator of
+/// another link from one of the link's source nodes, or into one of its sink nodes.
+pub(super) fn competing_link_client(
+    graph: &Graph,
+    source: &[&PwNode],
+    sink: &[&PwNode],
+) -> Option<String> {
+    graph
+        .links
+        .values()
+        .filter(|link| {
+            source.iter().any(|node| node.id == link.start_node)
+                != sink.iter().any(|node| node.id == link.end_node)
+        })
+        .find_map(|link| link_client_name(graph, link))
+}
+
+/// Diff the graph again whenever Sonusmix is done waiting to restore a lock that is being fought
+/// over, or once the fight has ended.
+pub(super) async fn lock_backoff_task() {
+    let (state_tx, state_rx) = relm4::channel();
+    let mut state = SonusmixReducer::subscribe(&state_tx, |state| state);
+
+    loop {
+        let now = Instant::now();
+        let next_retry = state
+            .lock_backoffs
+            .values()
+            .map(|backoff| {
+                if backoff.until > now {
+                    backoff.until
+                } else {
+                    backoff.until + LOCK_FIGHT_WINDOW
+                }
+            })
+            .min()
+            .map(tokio::time::Instant::from_std);
+
+        tokio::select! {
+            Some(new_state) = state_rx.recv() => {
+                state = new_state;
+            }
+            _ = sleep_until(next_retry.unwrap_or_else(tokio::time::Instant::now)), if next_retry.is_some() => {
+                SonusmixReducer::emit(SonusmixMsg::RetryLocks);
+                // Wait for the retry to be applied before checking the backoffs again
+                match state_rx.recv().await {
+                    Some(new_state) => state = new_state,
+                    None => break,
+                }
+            }
+            else => break,
+        }
+    }
+}
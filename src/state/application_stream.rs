@@ -0,0 +1,200 @@
+use std::collections::HashSet;
+
+use log::error;
+use serde::{Deserialize, Serialize};
+
+use crate::pipewire_api::{Graph, NodeIdentifier, PortKind, ToPipewireMessage};
+
+use super::{settings::SonusmixSettings, ApplicationId, EndpointDescriptor, SonusmixState};
+
+/// Matches the streams of an application by their media name and title. Excluding a stream from
+/// an application endpoint stores one of these rather than the node ID, so that the stream stays
+/// excluded when it is recreated.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StreamMatcher {
+    #[serde(default)]
+    pub media_name: Option<String>,
+    #[serde(default)]
+    pub media_title: Option<String>,
+}
+
+impl StreamMatcher {
+    /// A matcher for the stream with this identifier, if it has a media name or title to match
+    pub fn for_stream(identifier: &NodeIdentifier) -> Option<Self> {
+        let matcher = Self {
+            media_name: identifier.media_name().map(ToOwned::to_owned),
+            media_title: identifier.media_title().map(ToOwned::to_owned),
+        };
+        (matcher.media_name.is_some() || matcher.media_title.is_some()).then_some(matcher)
+    }
+
+    pub fn matches(&self, identifier: &NodeIdentifier) -> bool {
+        (self.media_name.is_some() || self.media_title.is_some())
+            && self
+                .media_name
+                .as_deref()
+                .map_or(true, |name| identifier.media_name() == Some(name))
+            && self
+                .media_title
+                .as_deref()
+                .map_or(true, |title| identifier.media_title() == Some(title))
+    }
+
+    /// The media name and title, for showing in the UI
+    pub fn description(&self) -> String {
+        match (&self.media_name, &self.media_title) {
+            (Some(name), Some(title)) if name != title => format!("{name} — {title}"),
+            (Some(name), _) => name.clone(),
+            (None, Some(title)) => title.clone(),
+            (None, None) => String::new(),
+        }
+    }
+}
+
+/// How an application endpoint treats one of the application's streams.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StreamStatus {
+    /// The stream is controlled by the application endpoint
+    Included,
+    /// The stream matches one of the application's excluded streams
+    Excluded(StreamMatcher),
+    /// The stream was added as its own endpoint, and is an exception of the application
+    SeparateEndpoint(EndpointDescriptor),
+}
+
+/// A stream of an application, as shown in the application endpoint's streams popover.
+#[derive(Debug, Clone)]
+pub struct ApplicationStream {
+    pub node_id: u32,
+    pub identifier: NodeIdentifier,
+    pub status: StreamStatus,
+}
+
+impl SonusmixState {
+    /// List the streams of an application, and whether each is controlled by its endpoint.
+    pub fn application_streams(
+        &self,
+        graph: &Graph,
+        id: ApplicationId,
+        settings: &SonusmixSettings,
+    ) -> Vec<ApplicationStream> {
+        let Some(application) = self.applications.get(&id) else {
+            return Vec::new();
+        };
+        let exceptions: Vec<(EndpointDescriptor, u32)> = application
+            .exceptions
+            .iter()
+            .filter(|exception| matches!(exception, EndpointDescriptor::EphemeralNode(..)))
+            .flat_map(|exception| {
+                self.resolve_endpoint(*exception, graph, settings)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|node| (*exception, node.id))
+            })
+            .collect();
+
+        let mut streams: Vec<ApplicationStream> = graph
+            .nodes
+            .values()
+            .filter(|node| application.matches(&node.identifier, application.kind))
+            .filter(|node| {
+                application.kind != PortKind::Source
+                    || settings.application_sources_include_monitors
+                    || !node.is_source_monitor()
+            })
+            .map(|node| {
+                let exception = exceptions
+                    .iter()
+                    .find(|(_, id)| *id == node.id)
+                    .map(|(exception, _)| *exception);
+                let matcher = application
+                    .excluded_streams
+                    .iter()
+                    .find(|matcher| matcher.matches(&node.identifier));
+                let status = match (exception, matcher) {
+                    (Some(exception), _) => StreamStatus::SeparateEndpoint(exception),
+                    (None, Some(matcher)) => StreamStatus::Excluded(matcher.clone()),
+                    (None, None) => StreamStatus::Included,
+                };
+                ApplicationStream {
+                    node_id: node.id,
+                    identifier: node.identifier.clone(),
+                    status,
+                }
+            })
+            .collect();
+        streams.sort_by_key(|stream| stream.node_id);
+        streams
+    }
+
+    /// Exclude streams matching the matcher from an application endpoint, or include them again.
+    /// The node links of the endpoint's connected links are moved along with them, so excluded
+    /// streams are disconnected and included streams are connected the same way as the rest of
+    /// the application.
+    pub(super) fn set_stream_excluded(
+        &mut self,
+        graph: &Graph,
+        id: ApplicationId,
+        matcher: StreamMatcher,
+        excluded: bool,
+        settings: &SonusmixSettings,
+    ) -> Vec<ToPipewireMessage> {
+        let Some(kind) = self
+            .applications
+            .get(&id)
+            .map(|application| application.kind)
+        else {
+            error!("Cannot change the excluded streams of application {id:?} as it does not exist");
+            return Vec::new();
+        };
+        let endpoint_desc = EndpointDescriptor::Application(id, kind);
+        let resolve_ids = |state: &Self| -> HashSet<u32> {
+            state
+                .resolve_endpoint(endpoint_desc, graph, settings)
+                .unwrap_or_default()
+                .iter()
+                .map(|node| node.id)
+                .collect()
+        };
+
+        let before = resolve_ids(self);
+        let Some(application) = self.applications.get_mut(&id) else {
+            return Vec::new();
+        };
+        if application.excluded_streams.contains(&matcher) == excluded {
+            return Vec::new();
+        }
+        if excluded {
+            application.excluded_streams.push(matcher);
+        } else {
+            application.excluded_streams.retain(|m| *m != matcher);
+        }
+        if !self.endpoints.contains_key(&endpoint_desc) {
+            return Vec::new();
+        }
+        let after = resolve_ids(self);
+
+        let pair = |stream: u32, neighbour: u32| match kind {
+            PortKind::Source => (stream, neighbour),
+            PortKind::Sink => (neighbour, stream),
+        };
+        let mut messages = Vec::new();
+        for (i, neighbours) in self.connected_link_neighbours(endpoint_desc, graph, settings) {
+            let messages_before = messages.len();
+            for neighbour in neighbours {
+                for stream in after.difference(&before) {
+                    let (start_id, end_id) = pair(*stream, neighbour);
+                    messages.push(ToPipewireMessage::CreateNodeLinks { start_id, end_id });
+                }
+                for stream in before.difference(&after) {
+                    let (start_id, end_id) = pair(*stream, neighbour);
+                    messages.push(ToPipewireMessage::RemoveNodeLinks { start_id, end_id });
+                }
+            }
+            if messages.len() > messages_before {
+                self.links[i].pending = true;
+            }
+        }
+        messages
+    }
+}
@@ -0,0 +1,111 @@
+use std::collections::HashSet;
+
+use crate::pipewire_api::{Graph, Node as PwNode, PortKind, ToPipewireMessage};
+
+use super::{settings::SonusmixSettings, EndpointDescriptor, SonusmixState};
+
+impl SonusmixState {
+    /// Move the links of the default output and input endpoints to the new default node when it
+    /// changes. Otherwise the old node would stay connected, and links that aren't locked would
+    /// look like they had been disconnected and be forgotten.
+    pub(super) fn follow_default_nodes(
+        &mut self,
+        graph: &Graph,
+        settings: &SonusmixSettings,
+    ) -> Vec<ToPipewireMessage> {
+        let mut messages = Vec::new();
+        for kind in [PortKind::Sink, PortKind::Source] {
+            let endpoint_desc = EndpointDescriptor::DefaultNode(kind);
+            if !self.endpoints.contains_key(&endpoint_desc) {
+                self.default_nodes.remove(&kind);
+                continue;
+            }
+            // If there is no default right now, remember the last one in case it comes back
+            let Some(new_node) = self
+                .resolve_endpoint(endpoint_desc, graph, settings)
+                .and_then(|nodes| nodes.first().copied())
+            else {
+                continue;
+            };
+            let Some(new_name) = new_node.identifier.node_name() else {
+                continue;
+            };
+            let Some(old_name) = self.default_nodes.insert(kind, new_name.to_owned()) else {
+                continue;
+            };
+            if old_name == new_name {
+                continue;
+            }
+            // The old node is found by name, so that links left over from a default that
+            // changed while Sonusmix wasn't running are moved too
+            let old_node = graph.nodes.values().find(|node| {
+                node.identifier.node_name() == Some(old_name.as_str()) && node.has_port_kind(kind)
+            });
+            // Links that other endpoints need between the old node and its neighbours are kept
+            let needed_neighbours = old_node
+                .map(|old_node| {
+                    self.other_link_neighbours(endpoint_desc, old_node, kind, graph, settings)
+                })
+                .unwrap_or_default();
+
+            for (i, neighbours) in self.connected_link_neighbours(endpoint_desc, graph, settings) {
+                let pair = |default_id: u32, neighbour: u32| match kind {
+                    PortKind::Sink => (neighbour, default_id),
+                    PortKind::Source => (default_id, neighbour),
+                };
+                let messages_before = messages.len();
+                for neighbour in neighbours {
+                    let (start_id, end_id) = pair(new_node.id, neighbour);
+                    messages.push(ToPipewireMessage::CreateNodeLinks { start_id, end_id });
+                    if let Some(old_node) =
+                        old_node.filter(|_| !needed_neighbours.contains(&neighbour))
+                    {
+                        let (start_id, end_id) = pair(old_node.id, neighbour);
+                        messages.push(ToPipewireMessage::RemoveNodeLinks { start_id, end_id });
+                    }
+                }
+                if messages.len() > messages_before {
+                    self.links[i].pending = true;
+                }
+            }
+        }
+        messages
+    }
+
+    /// The nodes that the connected links of endpoints other than `endpoint_desc` connect `node`
+    /// to, on its `kind` side: the gain node of a link routed through one, otherwise the other
+    /// endpoint's nodes.
+    fn other_link_neighbours(
+        &self,
+        endpoint_desc: EndpointDescriptor,
+        node: &PwNode,
+        kind: PortKind,
+        graph: &Graph,
+        settings: &SonusmixSettings,
+    ) -> HashSet<u32> {
+        self.links
+            .iter()
+            .filter(|link| link.state.is_connected() != Some(false))
+            .filter_map(|link| {
+                let (own, other) = match kind {
+                    PortKind::Source => (link.start, link.end),
+                    PortKind::Sink => (link.end, link.start),
+                };
+                let owns_node = own != endpoint_desc
+                    && self
+                        .resolve_endpoint(own, graph, settings)
+                        .is_some_and(|nodes| nodes.iter().any(|n| n.id == node.id));
+                owns_node.then(
+                    || match link.send.as_ref().and_then(|send| send.node(graph)) {
+                        Some(send_node) => vec![send_node],
+                        None => self
+                            .resolve_endpoint(other, graph, settings)
+                            .unwrap_or_default(),
+                    },
+                )
+            })
+            .flatten()
+            .map(|node| node.id)
+            .collect()
+    }
+}
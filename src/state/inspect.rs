@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::pipewire_api::{Graph, PortKind};
+
+use super::{
+    settings::SonusmixSettings, ApplicationId, EndpointDescriptor, SonusmixState, StreamStatus,
+};
+
+/// Why Sonusmix does or doesn't use a Pipewire node. Shown in the debug view, to help figure out
+/// why an endpoint is not controlling the nodes it should.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum NodeResolution {
+    /// The node is one of the nodes an endpoint resolves to
+    Endpoint(EndpointDescriptor),
+    /// The node matches an application, but is controlled by its own endpoint instead
+    Exception {
+        application: ApplicationId,
+        endpoint: EndpointDescriptor,
+    },
+    /// The node matches an application, but was excluded from its endpoint
+    ExcludedStream { application: ApplicationId },
+    /// The node is not part of any endpoint, so it can be added as one
+    Candidate(PortKind),
+}
+
+impl SonusmixState {
+    /// Find out which endpoints each Pipewire node resolves to, and which nodes are candidates or
+    /// application exceptions. Nodes that are not in the returned map are not used by Sonusmix
+    /// at all.
+    pub fn node_resolutions(
+        &self,
+        graph: &Graph,
+        settings: &SonusmixSettings,
+    ) -> HashMap<u32, Vec<NodeResolution>> {
+        let mut resolutions: HashMap<u32, Vec<NodeResolution>> = HashMap::new();
+
+        for endpoint_desc in self.endpoints.keys() {
+            // Persistent nodes and devices can't be resolved yet
+            if matches!(
+                endpoint_desc,
+                EndpointDescriptor::PersistentNode(..) | EndpointDescriptor::Device(..)
+            ) {
+                continue;
+            }
+            for node in self
+                .resolve_endpoint(*endpoint_desc, graph, settings)
+                .unwrap_or_default()
+            {
+                resolutions
+                    .entry(node.id)
+                    .or_default()
+                    .push(NodeResolution::Endpoint(*endpoint_desc));
+            }
+        }
+
+        for application in self.applications.values() {
+            for exception in &application.exceptions {
+                if let EndpointDescriptor::EphemeralNode(id, _) = exception {
+                    if graph.nodes.contains_key(id) {
+                        resolutions
+                            .entry(*id)
+                            .or_default()
+                            .push(NodeResolution::Exception {
+                                application: application.id,
+                                endpoint: *exception,
+                            });
+                    }
+                }
+            }
+            if !application.is_active {
+                continue;
+            }
+            for stream in self.application_streams(graph, application.id, settings) {
+                if let StreamStatus::Excluded(_) = stream.status {
+                    resolutions.entry(stream.node_id).or_default().push(
+                        NodeResolution::ExcludedStream {
+                            application: application.id,
+                        },
+                    );
+                }
+            }
+        }
+
+        for (id, kind, _) in &self.candidates {
+            resolutions
+                .entry(*id)
+                .or_default()
+                .push(NodeResolution::Candidate(*kind));
+        }
+
+        resolutions
+    }
+
+    /// A human-readable explanation of a node resolution.
+    pub fn describe_resolution(&self, resolution: &NodeResolution) -> String {
+        let endpoint_name = |desc: &EndpointDescriptor| {
+            self.endpoints
+                .get(desc)
+                .map(|endpoint| endpoint.custom_or_display_name().to_owned())
+                .unwrap_or_else(|| format!("{desc:?}"))
+        };
+
+        match resolution {
+            NodeResolution::Endpoint(desc) => {
+                let reason = match desc {
+                    EndpointDescriptor::EphemeralNode(id, _) => format!("it is node {id}"),
+                    EndpointDescriptor::GroupNode(_) => {
+                        "it is a group node created by Sonusmix".to_owned()
+                    }
+                    EndpointDescriptor::Loopback(_, PortKind::Sink) => {
+                        "it is the capture half of a loopback".to_owned()
+                    }
+                    EndpointDescriptor::Loopback(_, PortKind::Source) => {
+                        "it is the playback half of a loopback".to_owned()
+                    }
+                    EndpointDescriptor::Application(id, _) => self
+                        .applications
+                        .get(id)
+                        .map(|application| {
+                            format!(
+                                "it matches the application name '{}' and binary '{}'",
+                                application.name, application.binary
+                            )
+                        })
+                        .unwrap_or_else(|| "it matches the application".to_owned()),
+                    EndpointDescriptor::DefaultNode(PortKind::Sink) => {
+                        "it is the default output".to_owned()
+                    }
+                    EndpointDescriptor::DefaultNode(PortKind::Source) => {
+                        "it is the default input".to_owned()
+                    }
+                    EndpointDescriptor::MediaRole(role, _) => {
+                        format!("its media role is '{}'", role.name())
+                    }
+                    EndpointDescriptor::PersistentNode(..) | EndpointDescriptor::Device(..) => {
+                        "it matches the endpoint's identifier".to_owned()
+                    }
+                };
+                format!("Endpoint '{}': {reason}", endpoint_name(desc))
+            }
+            NodeResolution::Exception {
+                application,
+                endpoint,
+            } => {
+                let application_name = self
+                    .applications
+                    .get(application)
+                    .map(|application| application.name.as_str())
+                    .unwrap_or("unknown");
+                format!(
+                    "Exception of application '{application_name}': it is controlled by the \
+                     endpoint '{}' instead",
+                    endpoint_name(endpoint)
+                )
+            }
+            NodeResolution::ExcludedStream { application } => {
+                let application_name = self
+                    .applications
+                    .get(application)
+                    .map(|application| application.name.as_str())
+                    .unwrap_or("unknown");
+                format!(
+                    "Excluded from application '{application_name}': its media name or title \
+                     matches one of the application's excluded streams"
+                )
+            }
+            NodeResolution::Candidate(kind) => format!(
+                "Candidate {}: it is not part of any endpoint, so it can be added as one",
+                match kind {
+                    PortKind::Source => "source",
+                    PortKind::Sink => "sink",
+                }
+            ),
+        }
+    }
+}
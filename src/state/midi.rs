@@ -0,0 +1,280 @@
+use std::{collections::HashMap, sync::mpsc};
+
+use serde::{Deserialize, Serialize};
+
+use crate::pipewire_api::{MidiEvent, ToPipewireMessage};
+
+use super::{
+    set_link_messages, settings::SonusmixSettings, EndpointDescriptor, SonusmixMsg,
+    SonusmixReducer, SonusmixState, SONUSMIX_SETTINGS,
+};
+
+/// A control on a MIDI controller: either a knob or fader that sends control changes, or a button
+/// or pad that sends notes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum MidiControl {
+    ControlChange { channel: u8, controller: u8 },
+    Note { channel: u8, note: u8 },
+}
+
+impl MidiControl {
+    /// Get the control that sent an event, and the value it was set to.
+    pub fn from_event(event: MidiEvent) -> (Self, u8) {
+        match event {
+            MidiEvent::ControlChange {
+                channel,
+                controller,
+                value,
+            } => (
+                Self::ControlChange {
+                    channel,
+                    controller,
+                },
+                value,
+            ),
+            MidiEvent::NoteOn {
+                channel,
+                note,
+                velocity,
+            } => (Self::Note { channel, note }, velocity),
+            MidiEvent::NoteOff { channel, note } => (Self::Note { channel, note }, 0),
+        }
+    }
+
+    /// Build an event that sets this control to a value, for feedback.
+    fn to_event(self, value: u8) -> MidiEvent {
+        match self {
+            Self::ControlChange {
+                channel,
+                controller,
+            } => MidiEvent::ControlChange {
+                channel,
+                controller,
+                value,
+            },
+            // Controllers expect a note on with a velocity of 0 to turn a light off
+            Self::Note { channel, note } => MidiEvent::NoteOn {
+                channel,
+                note,
+                velocity: value,
+            },
+        }
+    }
+}
+
+/// What happens when a mapped MIDI control is used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum MidiAction {
+    Volume(EndpointDescriptor),
+    /// Toggle an endpoint's mute state
+    Mute(EndpointDescriptor),
+    /// Toggle whether an endpoint's volume is locked
+    VolumeLock(EndpointDescriptor),
+    /// Toggle the link between a source and a sink
+    Link(EndpointDescriptor, EndpointDescriptor),
+    /// Use the control as an endpoint's push to talk key
+    PushToTalk(EndpointDescriptor),
+}
+
+impl MidiAction {
+    pub fn involves(&self, endpoint_desc: EndpointDescriptor) -> bool {
+        match *self {
+            Self::Volume(desc)
+            | Self::Mute(desc)
+            | Self::VolumeLock(desc)
+            | Self::PushToTalk(desc) => desc == endpoint_desc,
+            Self::Link(source, sink) => source == endpoint_desc || sink == endpoint_desc,
+        }
+    }
+
+    fn replace_endpoint(&mut self, old: EndpointDescriptor, new: EndpointDescriptor) {
+        match self {
+            Self::Volume(desc)
+            | Self::Mute(desc)
+            | Self::VolumeLock(desc)
+            | Self::PushToTalk(desc) => {
+                if *desc == old {
+                    *desc = new;
+                }
+            }
+            Self::Link(source, sink) => {
+                if *source == old {
+                    *source = new;
+                }
+                if *sink == old {
+                    *sink = new;
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MidiMapping {
+    pub control: MidiControl,
+    pub action: MidiAction,
+}
+
+impl SonusmixState {
+    /// Handle events from the MIDI input port. If waiting for a control to learn, the first
+    /// event is used to create a mapping. Otherwise, returns the messages that the mapped
+    /// controls translate to.
+    pub(super) fn midi_input(
+        &mut self,
+        events: Vec<MidiEvent>,
+        settings: &SonusmixSettings,
+    ) -> Vec<SonusmixMsg> {
+        let mut messages = Vec::new();
+        for event in events {
+            let (control, value) = MidiControl::from_event(event);
+
+            if let Some(action) = self.midi_learn {
+                // Releasing a button shouldn't be learned, since it was already pressed
+                if matches!(event, MidiEvent::NoteOff { .. }) {
+                    continue;
+                }
+                self.midi_learn = None;
+                self.midi_mappings
+                    .retain(|mapping| mapping.control != control && mapping.action != action);
+                self.midi_mappings.push(MidiMapping { control, action });
+                continue;
+            }
+
+            let pressed = match control {
+                MidiControl::ControlChange { .. } => value >= 64,
+                MidiControl::Note { .. } => value > 0,
+            };
+            for mapping in self
+                .midi_mappings
+                .iter()
+                .filter(|mapping| mapping.control == control)
+            {
+                match mapping.action {
+                    MidiAction::Volume(desc) => {
+                        let slider = value as f64 / 127.0 * settings.slider_limit();
+                        messages.push(SonusmixMsg::FadeVolume(
+                            desc,
+                            settings.fader_curve.slider_to_volume(slider),
+                        ));
+                    }
+                    MidiAction::Mute(desc) if pressed => {
+                        if let Some(endpoint) = self.endpoints.get(&desc) {
+                            let muted = endpoint.volume_locked_muted.is_muted().unwrap_or(false);
+                            messages.push(SonusmixMsg::SetMute(desc, !muted));
+                        }
+                    }
+                    MidiAction::VolumeLock(desc) if pressed => {
+                        if let Some(endpoint) = self.endpoints.get(&desc) {
+                            let locked = endpoint.volume_locked_muted.is_locked();
+                            messages.push(SonusmixMsg::SetVolumeLocked(desc, !locked));
+                        }
+                    }
+                    MidiAction::Link(source, sink) if pressed => {
+                        let connected = self.is_link_connected(source, sink);
+                        messages.extend(set_link_messages(source, sink, !connected, settings));
+                    }
+                    MidiAction::PushToTalk(desc) => {
+                        messages.push(SonusmixMsg::PushToTalk(desc, pressed));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        messages
+    }
+
+    /// The value to send to a mapped control so that it shows the current state of its action.
+    /// Returns None if the action's endpoints don't exist.
+    fn midi_feedback_value(&self, action: MidiAction, settings: &SonusmixSettings) -> Option<u8> {
+        let toggle_value = |on: bool| if on { 127 } else { 0 };
+        match action {
+            MidiAction::Volume(desc) => {
+                let endpoint = self.endpoints.get(&desc)?;
+                let value = settings.fader_curve.volume_to_slider(endpoint.volume)
+                    / settings.slider_limit()
+                    * 127.0;
+                Some(value.round().clamp(0.0, 127.0) as u8)
+            }
+            MidiAction::Mute(desc) => Some(toggle_value(
+                self.endpoints
+                    .get(&desc)?
+                    .volume_locked_muted
+                    .is_muted()
+                    .unwrap_or(false),
+            )),
+            MidiAction::VolumeLock(desc) => Some(toggle_value(
+                self.endpoints.get(&desc)?.volume_locked_muted.is_locked(),
+            )),
+            MidiAction::Link(source, sink) => {
+                if !self.endpoints.contains_key(&source) || !self.endpoints.contains_key(&sink) {
+                    return None;
+                }
+                Some(toggle_value(self.is_link_connected(source, sink)))
+            }
+            MidiAction::PushToTalk(desc) => {
+                Some(toggle_value(self.endpoints.get(&desc)?.push_to_talk_active))
+            }
+        }
+    }
+
+    /// Forget all MIDI mappings for an endpoint, e.g. when it is removed.
+    pub(super) fn remove_midi_mappings(&mut self, endpoint_desc: EndpointDescriptor) {
+        self.midi_mappings
+            .retain(|mapping| !mapping.action.involves(endpoint_desc));
+        if self
+            .midi_learn
+            .is_some_and(|action| action.involves(endpoint_desc))
+        {
+            self.midi_learn = None;
+        }
+    }
+
+    pub(super) fn replace_midi_mappings(
+        &mut self,
+        old: EndpointDescriptor,
+        new: EndpointDescriptor,
+    ) {
+        for mapping in &mut self.midi_mappings {
+            mapping.action.replace_endpoint(old, new);
+        }
+    }
+}
+
+/// Keep mapped controls in sync with the state, so that controllers with LEDs or motorized faders
+/// show the current state. Only values that changed since they were last sent are sent again.
+pub(super) async fn midi_feedback_task(pw_sender: mpsc::Sender<ToPipewireMessage>) {
+    let (state_tx, state_rx) = relm4::channel();
+    let (settings_tx, settings_rx) = relm4::channel();
+
+    let mut state = SonusmixReducer::subscribe(&state_tx, |state| state);
+    let mut settings = { SONUSMIX_SETTINGS.read().clone() };
+    SONUSMIX_SETTINGS.subscribe(&settings_tx, Clone::clone);
+    let mut sent_values: HashMap<MidiControl, u8> = HashMap::new();
+
+    loop {
+        let events: Vec<MidiEvent> = state
+            .midi_mappings
+            .iter()
+            .filter_map(|mapping| {
+                let value = state.midi_feedback_value(mapping.action, &settings)?;
+                (sent_values.insert(mapping.control, value) != Some(value))
+                    .then(|| mapping.control.to_event(value))
+            })
+            .collect();
+        if !events.is_empty() && pw_sender.send(ToPipewireMessage::SendMidi(events)).is_err() {
+            break;
+        }
+
+        tokio::select! {
+            Some(new_state) = state_rx.recv() => {
+                state = new_state;
+            }
+            Some(new_settings) = settings_rx.recv() => {
+                settings = new_settings;
+                // The volume limit changes what volume values are sent
+                sent_values.clear();
+            }
+            else => break,
+        }
+    }
+}
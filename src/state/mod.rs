@@ -1,43 +1,182 @@
+mod activity;
+mod application_stream;
+mod default_node;
+mod inspect;
+mod midi;
+mod mute_group;
+mod organization;
 mod persistence;
+mod portable;
+mod push_to_talk;
+mod ramp;
 mod reducer;
+mod send;
 pub mod settings;
+mod solo;
+mod vca;
 
+pub use activity::{ActivityEntry, LockBackoff, LockTarget};
+pub use application_stream::{ApplicationStream, StreamMatcher, StreamStatus};
 use indexmap::IndexMap;
+pub use inspect::NodeResolution;
 use log::{error, warn};
+pub use midi::{MidiAction, MidiControl, MidiMapping};
+pub use mute_group::{MuteGroup, MuteGroupId, MuteGroupTarget};
+pub use organization::{EndpointColor, Section, SectionId};
+pub use portable::{ImportMode, ImportPreview, PortableConfig};
+pub use push_to_talk::PushToTalkMode;
+pub use ramp::VolumeRamp;
 pub use reducer::{SonusmixReducer, SONUSMIX_SETTINGS};
-use settings::SonusmixSettings;
-
-use std::collections::{HashMap, HashSet};
+pub use send::{LinkSend, SendId};
+use settings::{FaderCurve, SonusmixSettings};
+pub use solo::DimmedCueLink;
+pub use vca::{Vca, VcaId, VCA_MAX_DB, VCA_MIN_DB};
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    ops::RangeInclusive,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
 
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 use ulid::Ulid;
 
 use crate::pipewire_api::{
-    Graph, Link as PwLink, Node as PwNode, NodeIdentifier, PortKind, ToPipewireMessage,
+    EndpointId, Graph, Link as PwLink, MediaRole, MidiEvent, Node as PwNode, NodeIdentifier,
+    PortKind, ToPipewireMessage,
 };
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum SonusmixMsg {
     AddEphemeralNode(u32, PortKind),
     AddApplication(ApplicationId, PortKind),
     AddGroupNode(String, GroupNodeKind),
+    AddLoopback(String, LoopbackConfig),
+    /// Add the "Default Output" (sink) or "Default Input" (source) endpoint, which follows
+    /// whichever node is currently the default
+    AddDefaultNode(PortKind),
+    /// Add an endpoint for all streams with a media role, across applications
+    AddMediaRole(MediaRole, PortKind),
     RemoveEndpoint(EndpointDescriptor),
+    /// Move a placeholder endpoint, along with its name, volume, locks and links, to a node,
+    /// application or default endpoint that isn't an endpoint yet
+    RebindEndpoint(EndpointDescriptor, EndpointDescriptor),
     SetVolume(EndpointDescriptor, f32),
+    /// Set an endpoint's volume, fading to it over the time set for remote controls
+    FadeVolume(EndpointDescriptor, f32),
     SetMute(EndpointDescriptor, bool),
     SetVolumeLocked(EndpointDescriptor, bool),
+    /// Set or clear the highest volume an endpoint can be set to
+    SetMaxVolume(EndpointDescriptor, Option<f32>),
+    /// Set or clear the volume a persistent or application endpoint is set to when it reappears
+    SetStartVolume(EndpointDescriptor, Option<f32>),
+    /// Move the first endpoint to the position and section of the second one
+    MoveEndpoint(EndpointDescriptor, EndpointDescriptor),
+    SetEndpointColor(EndpointDescriptor, Option<EndpointColor>),
+    /// If the icon name is None, then reset the icon
+    SetEndpointIcon(EndpointDescriptor, Option<String>),
+    AddSection(String, PortKind),
+    RenameSection(SectionId, String),
+    RemoveSection(SectionId),
+    SetSectionCollapsed(SectionId, bool),
+    /// Move an endpoint into a section, or out of any section if None
+    SetEndpointSection(EndpointDescriptor, Option<SectionId>),
     /// If the parameter is None, then reset the name
     RenameEndpoint(EndpointDescriptor, Option<String>),
     ChangeGroupNodeKind(GroupNodeId, GroupNodeKind),
+    ChangeLoopbackConfig(LoopbackId, LoopbackConfig),
+    /// Switch the profile of the device an endpoint belongs to, by profile index
+    SetDeviceProfile(EndpointDescriptor, i32),
+    /// Switch the port of the device an endpoint belongs to, by route index
+    SetDeviceRoute(EndpointDescriptor, i32),
+    SetDeviceProfileLocked(EndpointDescriptor, bool),
+    /// Record an endpoint to a WAV file at the given path
+    StartRecording(EndpointDescriptor, PathBuf),
+    StopRecording(EndpointDescriptor),
+    StopAllRecordings,
     Link(EndpointDescriptor, EndpointDescriptor),
     RemoveLink(EndpointDescriptor, EndpointDescriptor),
     SetLinkLocked(EndpointDescriptor, EndpointDescriptor, bool),
+    /// Set the gain of a link, routing it through a gain node if it isn't already
+    SetLinkVolume(EndpointDescriptor, EndpointDescriptor, f32),
+    SetLinkMute(EndpointDescriptor, EndpointDescriptor, bool),
+    /// Connect the endpoints of a link directly again, at full level
+    RemoveLinkSend(EndpointDescriptor, EndpointDescriptor),
+    /// Events received on the MIDI input port
+    #[doc(hidden)]
+    MidiInput(Vec<MidiEvent>),
+    /// Map the next MIDI control that is used to an action
+    StartMidiLearn(MidiAction),
+    CancelMidiLearn,
+    /// Forget all MIDI mappings for an endpoint
+    RemoveMidiMappings(EndpointDescriptor),
+    /// Choose the endpoint whose volume is changed by scrolling on the tray icon
+    SetPrimaryEndpoint(Option<EndpointDescriptor>),
+    /// Set or clear an endpoint's push to talk mode
+    SetPushToTalk(EndpointDescriptor, Option<PushToTalkMode>),
+    /// An endpoint's push to talk key was pressed (true) or released (false)
+    PushToTalk(EndpointDescriptor, bool),
+    /// Sent once the release delay has passed after a push to talk key was released
+    #[doc(hidden)]
+    ReleasePushToTalk(EndpointDescriptor),
+    /// Import a portable configuration, merging it into or replacing the current state
+    ImportConfig(Box<PortableConfig>, ImportMode),
+    ClearActivityLog,
+    /// Restore a lock immediately from now on, even though another client keeps changing it
+    KeepFighting(LockTarget),
+    /// Sent once Sonusmix is done waiting to restore a lock that is being fought over
+    #[doc(hidden)]
+    RetryLocks,
+    AddVca(String),
+    RemoveVca(VcaId),
+    RenameVca(VcaId, String),
+    /// Set the gain of a VCA, in dB
+    SetVcaGain(VcaId, f32),
+    SetVcaMute(VcaId, bool),
+    /// Add an endpoint to a VCA (true), or remove it (false)
+    SetVcaMember(VcaId, EndpointDescriptor, bool),
+    /// Choose the sink that soloed endpoints are sent to, releasing any solos
+    SetCueEndpoint(Option<EndpointDescriptor>),
+    /// Send a source to the cue endpoint (true), or stop sending it there (false)
+    SetSolo(EndpointDescriptor, bool),
+    /// Release every solo, removing the solo links and undoing the dims
+    ReleaseAllSolos,
+    AddMuteGroup(String),
+    RemoveMuteGroup(MuteGroupId),
+    RenameMuteGroup(MuteGroupId, String),
+    /// Add an endpoint to a mute group (true), or remove it (false)
+    SetMuteGroupMember(MuteGroupId, EndpointDescriptor, bool),
+    /// Mute and lock every member of a mute group (true), or give them back the state they had
+    /// before (false)
+    SetMuteGroupEngaged(MuteGroupTarget, bool),
+    /// Exclude an application's streams matching the matcher from its endpoint (true), or include
+    /// them again (false)
+    SetStreamExcluded(ApplicationId, StreamMatcher, bool),
 }
 
-#[derive(Debug, Clone)]
+impl SonusmixMsg {
+    /// Whether Sonusmix only sends the message to itself, so other programs shouldn't send it.
+    pub fn is_internal(&self) -> bool {
+        matches!(
+            self,
+            Self::MidiInput(_) | Self::ReleasePushToTalk(_) | Self::RetryLocks
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub enum SonusmixOutputMsg {
     EndpointAdded(EndpointDescriptor),
     EndpointRemoved(EndpointDescriptor),
+    /// Both halves of a loopback were added
+    LoopbackAdded(LoopbackId),
+    /// Both halves of a loopback were removed
+    LoopbackRemoved(LoopbackId),
+    /// An endpoint was moved to a new descriptor, keeping its settings and links. Sent when a
+    /// device's nodes are re-created after switching profiles, or a placeholder is rebound.
+    EndpointReplaced(EndpointDescriptor, EndpointDescriptor),
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -54,6 +193,73 @@ pub struct SonusmixState {
     pub applications: HashMap<ApplicationId, Application>,
     pub devices: HashMap<DeviceId, Device>,
     pub group_nodes: IndexMap<GroupNodeId, GroupNode>,
+    #[serde(default)]
+    pub loopbacks: IndexMap<LoopbackId, Loopback>,
+    /// Maps device names to the name of the profile they are locked to
+    #[serde(default)]
+    pub locked_device_profiles: HashMap<String, String>,
+    /// Device ids mapped to profiles that have been requested but not applied yet
+    #[serde(skip)]
+    pub pending_device_profiles: HashMap<u32, PendingDeviceProfile>,
+    /// Endpoints waiting to be moved to one of their device's nodes once it switches profiles
+    #[serde(skip)]
+    pub device_rebinds: Vec<DeviceRebind>,
+    /// Endpoints that are currently being recorded
+    #[serde(skip)]
+    pub recordings: HashMap<EndpointDescriptor, Recording>,
+    #[serde(default)]
+    pub midi_mappings: Vec<MidiMapping>,
+    /// An action waiting for a MIDI control to be mapped to it
+    #[serde(skip)]
+    pub midi_learn: Option<MidiAction>,
+    /// The endpoint whose volume is changed by scrolling on the tray icon
+    #[serde(default)]
+    pub primary_endpoint: Option<EndpointDescriptor>,
+    /// User-defined sections of the sources and sinks lists, in the order they are shown
+    #[serde(default)]
+    pub sections: IndexMap<SectionId, Section>,
+    /// Changes made by other clients that were reverted because they were locked, oldest first
+    #[serde(skip)]
+    pub activity_log: VecDeque<ActivityEntry>,
+    /// The nodes that were in the graph the last time it was diffed. Locks on nodes that just
+    /// appeared are applied without being logged, since nothing changed them yet.
+    #[serde(skip)]
+    pub known_nodes: HashSet<u32>,
+    /// Changes made by other clients that the current diff reverted, waiting to be logged
+    #[serde(skip)]
+    pub reverted_changes: Vec<(LockTarget, String, Option<String>)>,
+    /// Locks that other clients keep changing, which are restored less and less often
+    #[serde(skip)]
+    pub lock_backoffs: HashMap<LockTarget, LockBackoff>,
+    /// Locks the user chose to keep restoring immediately, even though they are fought over
+    #[serde(skip)]
+    pub keep_fighting: HashSet<LockTarget>,
+    /// Virtual faders that scale the volumes of their members together
+    #[serde(default)]
+    pub vcas: IndexMap<VcaId, Vca>,
+    /// The sink that soloed endpoints are sent to, such as the engineer's headphones
+    #[serde(default)]
+    pub cue_endpoint: Option<EndpointDescriptor>,
+    /// Endpoints that are soloed to the cue endpoint
+    #[serde(skip)]
+    pub soloed: Vec<EndpointDescriptor>,
+    /// Links to the cue endpoint that are dimmed while other endpoints are soloed
+    #[serde(skip)]
+    pub dimmed_cue_links: Vec<DimmedCueLink>,
+    /// Named sets of endpoints that can all be muted at once
+    #[serde(default)]
+    pub mute_groups: IndexMap<MuteGroupId, MuteGroup>,
+    /// While every source is muted, the state each source had before
+    #[serde(default)]
+    pub all_sources_muted: Option<Vec<(EndpointDescriptor, VolumeLockMuteState)>>,
+    /// Endpoints whose nodes are fading to a new volume or mute state
+    #[serde(skip)]
+    pub volume_ramps: HashMap<EndpointDescriptor, VolumeRamp>,
+    /// The name of the node each default endpoint resolved to the last time the graph was
+    /// diffed, so its links can be moved when the default changes. This is saved, so links are
+    /// also moved if the default changed while Sonusmix wasn't running.
+    #[serde(default)]
+    pub default_nodes: HashMap<PortKind, String>,
 }
 
 impl SonusmixState {
@@ -90,7 +296,7 @@ impl SonusmixState {
                                 .collect(),
                         )
                         .with_volume(
-                            average_volumes(&node.channel_volumes),
+                            average_volumes(&node.channel_volumes, settings.fader_curve),
                             !node.channel_volumes.iter().all_equal(),
                         )
                         .with_mute_unlocked(node.mute);
@@ -121,6 +327,7 @@ impl SonusmixState {
                             kind,
                             pipewire_id: None,
                             pending: true,
+                            replaced_pipewire_id: None,
                         },
                     );
                     self.endpoints.insert(
@@ -130,6 +337,37 @@ impl SonusmixState {
                     pipewire_messages.push(ToPipewireMessage::CreateGroupNode(name, id.0, kind));
                     Some(SonusmixOutputMsg::EndpointAdded(descriptor))
                 }
+                SonusmixMsg::AddLoopback(name, config) => {
+                    let id = LoopbackId::new();
+                    let loopback = Loopback {
+                        id,
+                        name,
+                        config: config.clamped(),
+                        pending: true,
+                    };
+                    // The capture half takes audio in, so it acts as a sink, and the playback
+                    // half plays it back out, so it acts as a source.
+                    for kind in [PortKind::Sink, PortKind::Source] {
+                        let descriptor = EndpointDescriptor::Loopback(id, kind);
+                        self.endpoints.insert(
+                            descriptor,
+                            Endpoint::new(descriptor)
+                                .with_display_name(loopback.name_with_tag())
+                                .with_icon_name("media-playlist-repeat".to_owned()),
+                        );
+                        match kind {
+                            PortKind::Source => self.active_sources.push(descriptor),
+                            PortKind::Sink => self.active_sinks.push(descriptor),
+                        }
+                    }
+                    pipewire_messages.push(ToPipewireMessage::CreateLoopback(
+                        loopback.name.clone(),
+                        id.0,
+                        loopback.config.clone(),
+                    ));
+                    self.loopbacks.insert(id, loopback);
+                    Some(SonusmixOutputMsg::LoopbackAdded(id))
+                }
                 SonusmixMsg::AddApplication(id, kind) => {
                     let Some(mut application) = self.applications.get(&id).cloned() else {
                         // If the application doesn't exist, exit
@@ -176,6 +414,73 @@ impl SonusmixState {
 
                     Some(SonusmixOutputMsg::EndpointAdded(descriptor))
                 }
+                SonusmixMsg::AddDefaultNode(kind) => {
+                    let descriptor = EndpointDescriptor::DefaultNode(kind);
+                    if self.endpoints.contains_key(&descriptor) {
+                        break 'handler None;
+                    }
+                    let (name, icon_name) = match kind {
+                        PortKind::Sink => ("Default Output", "audio-speakers"),
+                        PortKind::Source => ("Default Input", "audio-input-microphone"),
+                    };
+                    self.endpoints.insert(
+                        descriptor,
+                        Endpoint::new(descriptor)
+                            .with_display_name(name.to_owned())
+                            .with_icon_name(icon_name.to_owned()),
+                    );
+                    match kind {
+                        PortKind::Source => self.active_sources.push(descriptor),
+                        PortKind::Sink => self.active_sinks.push(descriptor),
+                    }
+
+                    Some(SonusmixOutputMsg::EndpointAdded(descriptor))
+                }
+                SonusmixMsg::AddMediaRole(role, kind) => {
+                    let descriptor = EndpointDescriptor::MediaRole(role, kind);
+                    if self.endpoints.contains_key(&descriptor) {
+                        break 'handler None;
+                    }
+                    self.endpoints.insert(
+                        descriptor,
+                        Endpoint::new(descriptor)
+                            .with_display_name(media_role_name_with_tag(role))
+                            .with_icon_name(role.icon_name().to_owned()),
+                    );
+                    match kind {
+                        PortKind::Source => self.active_sources.push(descriptor),
+                        PortKind::Sink => self.active_sinks.push(descriptor),
+                    }
+
+                    Some(SonusmixOutputMsg::EndpointAdded(descriptor))
+                }
+                SonusmixMsg::RemoveEndpoint(EndpointDescriptor::Loopback(id, _)) => {
+                    // Both halves of a loopback are removed together, since neither can exist
+                    // without the other
+                    if self.loopbacks.shift_remove(&id).is_none() {
+                        error!("Cannot remove loopback {id:?} as it does not exist");
+                        break 'handler None;
+                    }
+                    for kind in [PortKind::Sink, PortKind::Source] {
+                        let descriptor = EndpointDescriptor::Loopback(id, kind);
+                        pipewire_messages.extend(self.stop_recording(descriptor));
+                        self.remove_midi_mappings(descriptor);
+                        self.remove_vca_member(descriptor);
+                        self.remove_mute_group_member(descriptor);
+                        pipewire_messages
+                            .extend(self.forget_solo_endpoint(graph, descriptor, settings));
+                        if self.primary_endpoint == Some(descriptor) {
+                            self.primary_endpoint = None;
+                        }
+                        self.endpoints.remove(&descriptor);
+                        self.active_sources
+                            .retain(|endpoint| *endpoint != descriptor);
+                        self.active_sinks.retain(|endpoint| *endpoint != descriptor);
+                    }
+                    pipewire_messages.push(ToPipewireMessage::RemoveLoopback(id.0));
+
+                    Some(SonusmixOutputMsg::LoopbackRemoved(id))
+                }
                 SonusmixMsg::RemoveEndpoint(endpoint_desc) => {
                     if self.endpoints.remove(&endpoint_desc).is_none() {
                         error!("Cannot remove endpoint {endpoint_desc:?} as it does not exist");
@@ -186,6 +491,18 @@ impl SonusmixState {
                         .retain(|endpoint| *endpoint != endpoint_desc);
                     self.active_sinks
                         .retain(|endpoint| *endpoint != endpoint_desc);
+                    pipewire_messages.extend(self.stop_recording(endpoint_desc));
+                    self.remove_midi_mappings(endpoint_desc);
+                    self.remove_vca_member(endpoint_desc);
+                    self.remove_mute_group_member(endpoint_desc);
+                    pipewire_messages.extend(self.forget_solo_endpoint(
+                        graph,
+                        endpoint_desc,
+                        settings,
+                    ));
+                    if self.primary_endpoint == Some(endpoint_desc) {
+                        self.primary_endpoint = None;
+                    }
 
                     // Remove the endpoint from any applications that might have it as an exception
                     for application in self.applications.values_mut() {
@@ -222,22 +539,43 @@ impl SonusmixState {
                                 self.applications.remove(&id);
                             }
                         }
+                        EndpointDescriptor::DefaultNode(kind) => {
+                            self.default_nodes.remove(&kind);
+                        }
+                        EndpointDescriptor::MediaRole(..) => {
+                            // Nothing else to do, the streams it controlled become candidates
+                            // again in diff()
+                        }
                         _ => todo!(),
                     }
 
                     Some(SonusmixOutputMsg::EndpointRemoved(endpoint_desc))
                 }
+                SonusmixMsg::RebindEndpoint(placeholder, target) => {
+                    let Some(messages) =
+                        self.rebind_placeholder(graph, placeholder, target, settings)
+                    else {
+                        break 'handler None;
+                    };
+                    pipewire_messages.extend(messages);
+
+                    Some(SonusmixOutputMsg::EndpointReplaced(placeholder, target))
+                }
                 SonusmixMsg::SetVolume(endpoint_desc, volume) => {
+                    // If the endpoint is fading out, its nodes are set to the new volume once
+                    // that is done
+                    let set_nodes = self.stop_volume_ramp(endpoint_desc);
                     // Resolve here instead of later so we don't have overlapping borrows
                     let nodes = self.resolve_endpoint(endpoint_desc, graph, settings);
                     let Some(endpoint) = self.endpoints.get_mut(&endpoint_desc) else {
                         // If the endpoint doesn't exist, exit
                         break 'handler None;
                     };
-                    endpoint.volume = volume;
+                    endpoint.volume = endpoint.clamp_volume(volume);
                     endpoint.volume_mixed = false;
+                    let volume = endpoint.applied_volume();
 
-                    if let Some(nodes) = nodes {
+                    if let Some(nodes) = nodes.filter(|_| set_nodes) {
                         // Set all channels on all nodes to the volume
                         let messages: Vec<ToPipewireMessage> = nodes
                             .into_iter()
@@ -256,15 +594,60 @@ impl SonusmixState {
 
                     None
                 }
+                SonusmixMsg::FadeVolume(endpoint_desc, volume) => {
+                    let from = self.current_volume(endpoint_desc, settings);
+                    let (_, messages) = self.update(
+                        graph,
+                        SonusmixMsg::SetVolume(endpoint_desc, volume),
+                        settings,
+                    );
+                    match from {
+                        Some(from)
+                            if settings.remote_volume_fade_ms > 0
+                                && !self.volume_ramps.contains_key(&endpoint_desc) =>
+                        {
+                            pipewire_messages.extend(self.start_volume_ramp(
+                                graph,
+                                endpoint_desc,
+                                from,
+                                settings.remote_volume_fade_ms,
+                                None,
+                                settings,
+                            ));
+                        }
+                        _ => pipewire_messages.extend(messages),
+                    }
+                    None
+                }
                 SonusmixMsg::SetMute(endpoint_desc, muted) => {
-                    // Resolve here instead of later so we don't have overlapping borrows
-                    let nodes = self.resolve_endpoint(endpoint_desc, graph, settings);
+                    if !muted && self.refuse_mute_group_change(endpoint_desc) {
+                        break 'handler None;
+                    }
                     let Some(endpoint) = self.endpoints.get_mut(&endpoint_desc) else {
                         // If the endpoint doesn't exist, exit
                         break 'handler None;
                     };
+                    let applied_muted_before = endpoint.applied_mute_state();
                     endpoint.volume_locked_muted = endpoint.volume_locked_muted.with_mute(muted);
+                    let muted = endpoint.applied_mute(muted);
+
+                    // Fade out before muting, or fade in after unmuting, so that live audio
+                    // doesn't pop
+                    if applied_muted_before != Some(muted) {
+                        if let Some(messages) =
+                            self.fade_mute(graph, endpoint_desc, muted, settings)
+                        {
+                            pipewire_messages.extend(messages);
+                            break 'handler None;
+                        }
+                    }
 
+                    // Resolve here instead of later so we don't have overlapping borrows
+                    let nodes = self.resolve_endpoint(endpoint_desc, graph, settings);
+                    let endpoint = self
+                        .endpoints
+                        .get_mut(&endpoint_desc)
+                        .expect("endpoint should exist as it was just looked up");
                     if let Some(nodes) = nodes {
                         // Set all nodes to the mute state
                         let messages: Vec<ToPipewireMessage> = nodes
@@ -279,7 +662,84 @@ impl SonusmixState {
 
                     None
                 }
+                SonusmixMsg::SetMaxVolume(endpoint_desc, max_volume) => {
+                    let Some(endpoint) = self.endpoints.get_mut(&endpoint_desc) else {
+                        break 'handler None;
+                    };
+                    endpoint.max_volume = max_volume;
+                    // Bring the volume down if it's above the new limit
+                    let volume = endpoint.volume;
+                    if endpoint.clamp_volume(volume) < volume {
+                        let (_, messages) = self.update(
+                            graph,
+                            SonusmixMsg::SetVolume(endpoint_desc, volume),
+                            settings,
+                        );
+                        pipewire_messages.extend(messages);
+                    }
+                    None
+                }
+                SonusmixMsg::SetStartVolume(endpoint_desc, start_volume) => {
+                    if let Some(endpoint) = self.endpoints.get_mut(&endpoint_desc) {
+                        endpoint.start_volume = start_volume;
+                    }
+                    None
+                }
+                SonusmixMsg::MoveEndpoint(endpoint_desc, target_desc) => {
+                    self.move_endpoint(endpoint_desc, target_desc);
+                    None
+                }
+                SonusmixMsg::SetEndpointColor(endpoint_desc, color) => {
+                    if let Some(endpoint) = self.endpoints.get_mut(&endpoint_desc) {
+                        endpoint.color = color;
+                    }
+                    None
+                }
+                SonusmixMsg::SetEndpointIcon(endpoint_desc, icon_name) => {
+                    if let Some(endpoint) = self.endpoints.get_mut(&endpoint_desc) {
+                        endpoint.custom_icon_name = icon_name.filter(|name| !name.is_empty());
+                    }
+                    None
+                }
+                SonusmixMsg::AddSection(name, list) => {
+                    self.add_section(name, list);
+                    None
+                }
+                SonusmixMsg::RenameSection(id, name) => {
+                    if let Some(section) = self.sections.get_mut(&id) {
+                        section.name = name;
+                    }
+                    None
+                }
+                SonusmixMsg::RemoveSection(id) => {
+                    self.remove_section(id);
+                    None
+                }
+                SonusmixMsg::SetSectionCollapsed(id, collapsed) => {
+                    if let Some(section) = self.sections.get_mut(&id) {
+                        section.collapsed = collapsed;
+                    }
+                    None
+                }
+                SonusmixMsg::SetEndpointSection(endpoint_desc, section_id) => {
+                    // Sections belong to either the sources or sinks list
+                    let section_list = section_id
+                        .and_then(|id| self.sections.get(&id))
+                        .map(|section| section.list);
+                    if section_id.is_some()
+                        && !section_list.is_some_and(|list| endpoint_desc.is_list(list))
+                    {
+                        break 'handler None;
+                    }
+                    if let Some(endpoint) = self.endpoints.get_mut(&endpoint_desc) {
+                        endpoint.section = section_id;
+                    }
+                    None
+                }
                 SonusmixMsg::SetVolumeLocked(endpoint_desc, locked) => {
+                    if !locked && self.refuse_mute_group_change(endpoint_desc) {
+                        break 'handler None;
+                    }
                     // Resolve here instead of later so we don't have overlapping borrows
                     let nodes = self.resolve_endpoint(endpoint_desc, graph, settings);
                     let Some(endpoint) = self.endpoints.get_mut(&endpoint_desc) else {
@@ -306,10 +766,11 @@ impl SonusmixState {
 
                         // If the volume of all nodes equals the endpoint volume and there are no
                         // pending updates, then we're done, so exit
+                        let volume = endpoint.applied_volume();
                         if !endpoint.volume_pending
-                            && nodes.iter().all(|node| {
-                                node.channel_volumes.iter().all(|v| *v == endpoint.volume)
-                            })
+                            && nodes
+                                .iter()
+                                .all(|node| node.channel_volumes.iter().all(|v| *v == volume))
                         {
                             break 'handler None;
                         }
@@ -321,7 +782,7 @@ impl SonusmixState {
                             .map(|node| {
                                 ToPipewireMessage::NodeVolume(
                                     node.id,
-                                    vec![endpoint.volume; node.channel_volumes.len()],
+                                    vec![volume; node.channel_volumes.len()],
                                 )
                             })
                             .collect();
@@ -342,7 +803,7 @@ impl SonusmixState {
                         break 'handler None;
                     }
 
-                    // If either of these is None, then the loop will iterate 0 times
+                    // If either of these is None, then no links will be created
                     let source_nodes = self
                         .resolve_endpoint(source, graph, settings)
                         .unwrap_or_default();
@@ -350,22 +811,32 @@ impl SonusmixState {
                         .resolve_endpoint(sink, graph, settings)
                         .unwrap_or_default();
 
-                    let mut messages: Vec<ToPipewireMessage> = Vec::new();
-                    for source in &source_nodes {
-                        for sink in &sink_nodes {
-                            messages.push(ToPipewireMessage::CreateNodeLinks {
-                                start_id: source.id,
-                                end_id: sink.id,
+                    // Links with a send level are connected through their gain node. If it
+                    // doesn't exist yet, connect the endpoints directly until the diff moves the
+                    // link over to it.
+                    let send_node = self
+                        .links
+                        .iter()
+                        .find(|link| link.start == source && link.end == sink)
+                        .and_then(|link| link.send)
+                        .and_then(|send| send.node(graph));
+                    let messages: Vec<ToPipewireMessage> =
+                        send::node_pairs(&send::link_hops(&source_nodes, &sink_nodes, send_node))
+                            .into_iter()
+                            .map(|(start_id, end_id)| ToPipewireMessage::CreateNodeLinks {
+                                start_id,
+                                end_id,
                             })
-                        }
-                    }
+                            .collect();
 
                     if let Some(link) = self
                         .links
                         .iter_mut()
                         .find(|link| link.start == source && link.end == sink)
                     {
-                        // If the link already exists in the state, update it
+                        // If the link already exists in the state, update it. Connecting a
+                        // solo link makes it permanent.
+                        link.temporary = false;
                         match link.state {
                             LinkState::PartiallyConnected => {
                                 link.state = LinkState::ConnectedUnlocked
@@ -384,6 +855,8 @@ impl SonusmixState {
                             start: source,
                             end: sink,
                             state: LinkState::ConnectedUnlocked,
+                            send: None,
+                            temporary: false,
                             pending: !messages.is_empty(),
                         });
                     }
@@ -406,6 +879,11 @@ impl SonusmixState {
                         break 'handler None;
                     };
 
+                    // Removing the gain node removes the links through it
+                    if let Some(send) = &mut self.links[link_position].send {
+                        pipewire_messages.extend(send.detach(graph));
+                    }
+
                     match self.links[link_position].state {
                         LinkState::PartiallyConnected | LinkState::ConnectedUnlocked => {
                             // If the link is unlocked, it gets removed entirely
@@ -440,6 +918,10 @@ impl SonusmixState {
                         .links
                         .iter_mut()
                         .position(|link| link.start == source && link.end == sink);
+                    if link_position.is_some_and(|idx| self.links[idx].temporary) {
+                        error!("Cannot lock a link that only exists while an endpoint is soloed");
+                        break 'handler None;
+                    }
 
                     match (
                         link_position.map(|idx| (idx, self.links[idx].state)),
@@ -460,6 +942,8 @@ impl SonusmixState {
                                 start: source,
                                 end: sink,
                                 state: LinkState::DisconnectedLocked,
+                                send: None,
+                                temporary: false,
                                 pending: false,
                             });
                         }
@@ -480,117 +964,544 @@ impl SonusmixState {
 
                     None
                 }
+                SonusmixMsg::SetLinkVolume(source, sink, volume) => {
+                    pipewire_messages.extend(self.set_link_send(
+                        graph,
+                        source,
+                        sink,
+                        Some(volume),
+                        None,
+                    ));
+                    None
+                }
+                SonusmixMsg::SetLinkMute(source, sink, muted) => {
+                    pipewire_messages.extend(self.set_link_send(
+                        graph,
+                        source,
+                        sink,
+                        None,
+                        Some(muted),
+                    ));
+                    None
+                }
+                SonusmixMsg::RemoveLinkSend(source, sink) => {
+                    pipewire_messages.extend(self.remove_link_send(graph, source, sink, settings));
+                    None
+                }
                 SonusmixMsg::ChangeGroupNodeKind(id, kind) => {
-                    if let Some(group_node) = self.group_nodes.get_mut(&id) {
-                        if kind != group_node.kind {
-                            // Remove the node, once it's gone the diffing algorithm will re-create
-                            // it with the changed kind
-                            pipewire_messages.push(ToPipewireMessage::RemoveGroupNode(id.0));
-                            group_node.kind = kind;
-                            group_node.pending = false;
-                        }
+                    let Some(group_node) = self.group_nodes.get_mut(&id) else {
+                        break 'handler None;
+                    };
+                    if kind == group_node.kind {
+                        break 'handler None;
                     }
+                    group_node.kind = kind;
+                    pipewire_messages.extend(self.replace_group_node(id));
 
                     None
                 }
-                SonusmixMsg::RenameEndpoint(
-                    descriptor @ EndpointDescriptor::GroupNode(id),
-                    name,
-                ) => {
-                    if let (Some(endpoint), Some(group_node)) = (
-                        self.endpoints.get_mut(&descriptor),
-                        self.group_nodes.get_mut(&id),
-                    ) {
-                        if let Some(name) = name.filter(|name| *name != endpoint.display_name) {
-                            // If the new name exists and is different from the existing name,
-                            // remove the node. Once it's gone the diffing algorithm will re-create
-                            // it with the changed name
-                            pipewire_messages.push(ToPipewireMessage::RemoveGroupNode(id.0));
-                            endpoint.display_name = name;
-                            group_node.pending = false;
+                SonusmixMsg::ChangeLoopbackConfig(id, config) => {
+                    let config = config.clamped();
+                    if let Some(loopback) = self.loopbacks.get_mut(&id) {
+                        if config != loopback.config {
+                            // Like group nodes, the module can't be changed once it's loaded, so
+                            // remove it and let the diffing algorithm re-create it
+                            pipewire_messages.push(ToPipewireMessage::RemoveLoopback(id.0));
+                            loopback.config = config;
+                            loopback.pending = false;
                         }
                     }
 
                     None
                 }
-                SonusmixMsg::RenameEndpoint(endpoint_desc, name) => {
-                    if let Some(endpoint) = self.endpoints.get_mut(&endpoint_desc) {
-                        match name {
-                            // Unset the custom name if it is the same as the display name
-                            Some(name) if name == endpoint.display_name => {
-                                endpoint.custom_name = None
-                            }
-                            _ => endpoint.custom_name = name,
-                        }
-                    }
+                SonusmixMsg::SetDeviceProfile(endpoint_desc, profile_index) => {
+                    // Fade the device out first, so that switching doesn't cut off its audio
+                    let messages = self
+                        .fade_device_profile(graph, endpoint_desc, profile_index, settings)
+                        .unwrap_or_else(|| {
+                            self.set_device_profile(graph, endpoint_desc, profile_index)
+                        });
+                    pipewire_messages.extend(messages);
                     None
                 }
-            }
-        };
+                SonusmixMsg::SetDeviceRoute(endpoint_desc, route_index) => {
+                    // The device index is needed to tell the device which of its nodes the route
+                    // applies to
+                    let Some((device_id, device_index)) = self
+                        .resolve_endpoint(endpoint_desc, graph, settings)
+                        .and_then(|nodes| {
+                            nodes.into_iter().find_map(|node| match node.endpoint {
+                                EndpointId::Device {
+                                    id,
+                                    device_index: Some(device_index),
+                                } => Some((id, device_index)),
+                                _ => None,
+                            })
+                        })
+                    else {
+                        break 'handler None;
+                    };
 
-        (output_message, pipewire_messages)
-    }
+                    pipewire_messages.push(ToPipewireMessage::SetDeviceRoute(
+                        device_id,
+                        route_index,
+                        device_index,
+                    ));
 
-    // Diffs the Sonusmix state and the pipewire state. Returns a list of messages for Pipewire
-    // to try and match the Sonusmix state as closely as possible, and marks any endpoints in the
-    // Sonusmix state that cannot be found in the Pipewire graph as placeholders. This is only done
-    // after updates from the backend.
-    fn diff(&mut self, graph: &Graph, settings: &SonusmixSettings) -> Vec<ToPipewireMessage> {
-        let endpoint_nodes = self.diff_nodes(graph, settings);
-        let mut messages = self.diff_group_nodes(&endpoint_nodes);
-        messages.extend(self.diff_properties(&endpoint_nodes));
-        messages.extend(self.diff_links(graph, &endpoint_nodes));
-        messages
-    }
+                    None
+                }
+                SonusmixMsg::SetDeviceProfileLocked(endpoint_desc, locked) => {
+                    let Some(device) = self
+                        .endpoints
+                        .get(&endpoint_desc)
+                        .and_then(|endpoint| endpoint.device.as_ref())
+                        .and_then(|device| graph.devices.get(&device.id))
+                    else {
+                        break 'handler None;
+                    };
 
-    /// Try to resolve each endpoint in the Sonusmix state to one or mode nodes in the Pipewire
-    /// graph, and mark endpoints that could not be resolved as placeholders.
-    ///
-    /// Basically, this means that every hardware endpoint that exists on both states is returned, while other
-    /// hardware endpoints (only existing on the sonusmix state) are marked as a placeholder, so the UI can
-    /// display the endpoint as deactivated.
-    fn diff_nodes<'a>(
-        &mut self,
-        graph: &'a Graph,
-        settings: &SonusmixSettings,
-    ) -> HashMap<EndpointDescriptor, Vec<&'a PwNode>> {
-        let mut remaining_nodes: HashSet<(u32, PortKind)> = graph
-            .nodes
-            .values()
-            .flat_map(|node| {
-                // Add the node as a source and a sink if it has source and sink ports, respectively
-                [
-                    node.has_port_kind(PortKind::Source)
-                        .then_some((node.id, PortKind::Source)),
-                    node.has_port_kind(PortKind::Sink)
-                        .then_some((node.id, PortKind::Sink)),
-                ]
-            })
-            .flatten()
-            .collect();
-        let mut endpoint_nodes = HashMap::new();
-        for endpoint in self.endpoints.keys().copied().collect::<Vec<_>>() {
-            if let Some(nodes) = self.resolve_endpoint(endpoint, graph, settings) {
-                // Mark the endpoint's nodes as seen
-                for node in &nodes {
-                    if endpoint.is_single() && endpoint.is_kind(PortKind::Source) {
-                        remaining_nodes.remove(&(node.id, PortKind::Source));
-                    }
-                    if endpoint.is_single() && endpoint.is_kind(PortKind::Sink) {
-                        remaining_nodes.remove(&(node.id, PortKind::Sink));
+                    if locked {
+                        let Some(profile) = device.active_profile.and_then(|index| {
+                            device
+                                .profiles
+                                .iter()
+                                .find(|profile| profile.index == index)
+                        }) else {
+                            error!("Cannot lock device {} without an active profile", device.id);
+                            break 'handler None;
+                        };
+                        self.locked_device_profiles
+                            .insert(device.name.clone(), profile.name.clone());
+                    } else {
+                        self.locked_device_profiles.remove(&device.name);
                     }
+
+                    None
                 }
+                SonusmixMsg::StartRecording(endpoint_desc, path) => {
+                    if self.recordings.contains_key(&endpoint_desc) {
+                        break 'handler None;
+                    }
+                    // Every node of the endpoint is linked into the same stream, so they are
+                    // mixed into one file
+                    let node_ids: Vec<u32> = self
+                        .resolve_endpoint(endpoint_desc, graph, settings)
+                        .map(|nodes| nodes.into_iter().map(|node| node.id).collect())
+                        .unwrap_or_default();
+                    if node_ids.is_empty() {
+                        error!("Cannot record {endpoint_desc:?} as it has no nodes");
+                        break 'handler None;
+                    }
 
-                if let Some(endpoint) = self.endpoints.get_mut(&endpoint) {
-                    // Copy the details from the first resolved node that has any
-                    let mut details: Vec<String> = nodes
-                        .iter()
-                        .filter_map(|node| node.identifier.details())
-                        .map(ToOwned::to_owned)
-                        .collect();
-                    details.sort_unstable();
-                    endpoint.details = details;
+                    let recording = Recording {
+                        id: Ulid::new(),
+                        path: path.clone(),
+                        started_at: Instant::now(),
+                    };
+                    pipewire_messages.push(ToPipewireMessage::StartRecording {
+                        id: recording.id,
+                        node_ids,
+                        path,
+                    });
+                    self.recordings.insert(endpoint_desc, recording);
 
+                    None
+                }
+                SonusmixMsg::StopRecording(endpoint_desc) => {
+                    pipewire_messages.extend(self.stop_recording(endpoint_desc));
+                    None
+                }
+                SonusmixMsg::StopAllRecordings => {
+                    pipewire_messages.extend(
+                        self.recordings
+                            .drain()
+                            .map(|(_, recording)| ToPipewireMessage::StopRecording(recording.id)),
+                    );
+                    None
+                }
+                SonusmixMsg::MidiInput(events) => {
+                    for message in self.midi_input(events, settings) {
+                        let (_, messages) = self.update(graph, message, settings);
+                        pipewire_messages.extend(messages);
+                    }
+                    None
+                }
+                SonusmixMsg::StartMidiLearn(action) => {
+                    self.midi_learn = Some(action);
+                    None
+                }
+                SonusmixMsg::CancelMidiLearn => {
+                    self.midi_learn = None;
+                    None
+                }
+                SonusmixMsg::RemoveMidiMappings(endpoint_desc) => {
+                    self.remove_midi_mappings(endpoint_desc);
+                    None
+                }
+                SonusmixMsg::SetPrimaryEndpoint(endpoint_desc) => {
+                    if endpoint_desc.is_some_and(|desc| !self.endpoints.contains_key(&desc)) {
+                        break 'handler None;
+                    }
+                    self.primary_endpoint = endpoint_desc;
+                    None
+                }
+                SonusmixMsg::SetPushToTalk(endpoint_desc, mode) => {
+                    if let Some(message) = self.set_push_to_talk(endpoint_desc, mode) {
+                        let (_, messages) = self.update(graph, message, settings);
+                        pipewire_messages.extend(messages);
+                    }
+                    None
+                }
+                SonusmixMsg::PushToTalk(endpoint_desc, held) => {
+                    if let Some(message) = self.push_to_talk(endpoint_desc, held, settings) {
+                        let (_, messages) = self.update(graph, message, settings);
+                        pipewire_messages.extend(messages);
+                    }
+                    None
+                }
+                SonusmixMsg::ReleasePushToTalk(endpoint_desc) => {
+                    if let Some(message) = self.release_push_to_talk(endpoint_desc) {
+                        let (_, messages) = self.update(graph, message, settings);
+                        pipewire_messages.extend(messages);
+                    }
+                    None
+                }
+                SonusmixMsg::ImportConfig(config, mode) => {
+                    pipewire_messages.extend(self.import_config(graph, *config, mode, settings));
+                    None
+                }
+                SonusmixMsg::ClearActivityLog => {
+                    self.activity_log.clear();
+                    None
+                }
+                SonusmixMsg::KeepFighting(target) => {
+                    self.keep_fighting(target);
+                    None
+                }
+                // The graph is diffed after every message, which restores the lock
+                SonusmixMsg::RetryLocks => None,
+                SonusmixMsg::AddVca(name) => {
+                    self.add_vca(name);
+                    None
+                }
+                SonusmixMsg::RemoveVca(id) => {
+                    self.vcas.shift_remove(&id);
+                    pipewire_messages.extend(self.apply_vcas(graph, settings));
+                    None
+                }
+                SonusmixMsg::RenameVca(id, name) => {
+                    if let Some(vca) = self.vcas.get_mut(&id) {
+                        vca.name = name;
+                    }
+                    None
+                }
+                SonusmixMsg::SetVcaGain(id, gain_db) => {
+                    if gain_db.is_nan() {
+                        error!("Cannot set the gain of VCA {id:?} to NaN");
+                        break 'handler None;
+                    }
+                    if let Some(vca) = self.vcas.get_mut(&id) {
+                        vca.gain_db = gain_db.clamp(VCA_MIN_DB, VCA_MAX_DB);
+                    }
+                    pipewire_messages.extend(self.apply_vcas(graph, settings));
+                    None
+                }
+                SonusmixMsg::SetVcaMute(id, muted) => {
+                    if let Some(vca) = self.vcas.get_mut(&id) {
+                        vca.muted = muted;
+                    }
+                    pipewire_messages.extend(self.apply_vcas(graph, settings));
+                    None
+                }
+                SonusmixMsg::SetVcaMember(id, endpoint_desc, member) => {
+                    self.set_vca_member(id, endpoint_desc, member);
+                    pipewire_messages.extend(self.apply_vcas(graph, settings));
+                    None
+                }
+                SonusmixMsg::SetCueEndpoint(cue) => {
+                    pipewire_messages.extend(self.set_cue_endpoint(graph, cue, settings));
+                    None
+                }
+                SonusmixMsg::SetSolo(endpoint_desc, soloed) => {
+                    pipewire_messages.extend(self.set_solo(graph, endpoint_desc, soloed, settings));
+                    None
+                }
+                SonusmixMsg::ReleaseAllSolos => {
+                    pipewire_messages.extend(self.release_solos(graph, settings));
+                    None
+                }
+                SonusmixMsg::AddMuteGroup(name) => {
+                    self.add_mute_group(name);
+                    None
+                }
+                SonusmixMsg::RemoveMuteGroup(id) => {
+                    pipewire_messages.extend(self.remove_mute_group(graph, id, settings));
+                    None
+                }
+                SonusmixMsg::RenameMuteGroup(id, name) => {
+                    if let Some(group) = self.mute_groups.get_mut(&id) {
+                        group.name = name;
+                    }
+                    None
+                }
+                SonusmixMsg::SetMuteGroupMember(id, endpoint_desc, member) => {
+                    pipewire_messages.extend(self.set_mute_group_member(
+                        graph,
+                        id,
+                        endpoint_desc,
+                        member,
+                        settings,
+                    ));
+                    None
+                }
+                SonusmixMsg::SetMuteGroupEngaged(target, engaged) => {
+                    pipewire_messages
+                        .extend(self.set_mute_group_engaged(graph, target, engaged, settings));
+                    None
+                }
+                SonusmixMsg::SetStreamExcluded(id, matcher, excluded) => {
+                    pipewire_messages
+                        .extend(self.set_stream_excluded(graph, id, matcher, excluded, settings));
+                    None
+                }
+                SonusmixMsg::RenameEndpoint(
+                    descriptor @ EndpointDescriptor::GroupNode(id),
+                    name,
+                ) => {
+                    if let Some(endpoint) = self.endpoints.get_mut(&descriptor) {
+                        if let Some(name) = name.filter(|name| *name != endpoint.display_name) {
+                            // Rename the live node, so that streams using it aren't interrupted
+                            pipewire_messages
+                                .push(ToPipewireMessage::RenameGroupNode(id.0, name.clone()));
+                            endpoint.display_name = name;
+                        }
+                    }
+
+                    None
+                }
+                SonusmixMsg::RenameEndpoint(endpoint_desc, name) => {
+                    if let Some(endpoint) = self.endpoints.get_mut(&endpoint_desc) {
+                        match name {
+                            // Unset the custom name if it is the same as the display name
+                            Some(name) if name == endpoint.display_name => {
+                                endpoint.custom_name = None
+                            }
+                            _ => endpoint.custom_name = name,
+                        }
+                    }
+                    None
+                }
+            }
+        };
+
+        (output_message, pipewire_messages)
+    }
+
+    // Diffs the Sonusmix state and the pipewire state. Returns a list of messages for Pipewire
+    // to try and match the Sonusmix state as closely as possible, and marks any endpoints in the
+    // Sonusmix state that cannot be found in the Pipewire graph as placeholders. Changes made by
+    // other clients are only logged if `graph_updated` is set, as a diff after any other update
+    // still sees the graph from before Sonusmix's last changes were applied.
+    fn diff(
+        &mut self,
+        graph: &Graph,
+        settings: &SonusmixSettings,
+        graph_updated: bool,
+    ) -> (Vec<SonusmixOutputMsg>, Vec<ToPipewireMessage>) {
+        let output_messages = self.diff_device_rebinds(graph);
+        self.clean_up_lock_backoffs();
+        let endpoint_nodes = self.diff_nodes(graph, settings);
+        let mut messages = self.follow_default_nodes(graph, settings);
+        messages.extend(self.diff_group_nodes(graph, &endpoint_nodes));
+        messages.extend(self.apply_vcas(graph, settings));
+        messages.extend(self.hold_mute_groups(graph, settings));
+        messages.extend(self.start_waiting_volume_ramps(graph, settings));
+        messages.extend(self.dim_cue_links(graph, settings));
+        messages.extend(self.diff_loopbacks(&endpoint_nodes));
+        messages.extend(self.diff_device_profiles(graph));
+        messages.extend(self.diff_properties(&endpoint_nodes, settings));
+        messages.extend(self.diff_links(graph, &endpoint_nodes));
+        for (target, description, client) in std::mem::take(&mut self.reverted_changes) {
+            if graph_updated {
+                self.record_activity(target, description, client);
+            }
+        }
+        self.known_nodes = graph.nodes.keys().copied().collect();
+        (output_messages, messages)
+    }
+
+    /// Move endpoints whose device has switched profiles to the device's new nodes. Once a
+    /// device switches profiles, its old nodes are removed and new ones are created, so without
+    /// this its endpoints would turn into placeholders.
+    fn diff_device_rebinds(&mut self, graph: &Graph) -> Vec<SonusmixOutputMsg> {
+        let mut output_messages = Vec::new();
+        let mut remaining = Vec::new();
+        for rebind in std::mem::take(&mut self.device_rebinds) {
+            // Forget about endpoints that have been removed in the meantime
+            if !self.endpoints.contains_key(&rebind.endpoint) {
+                continue;
+            }
+            let EndpointDescriptor::EphemeralNode(old_id, kind) = rebind.endpoint else {
+                continue;
+            };
+            // If the old node still exists, the device hasn't switched yet
+            if graph.nodes.contains_key(&old_id) {
+                remaining.push(rebind);
+                continue;
+            }
+
+            let candidates: Vec<&PwNode> = graph
+                .nodes
+                .values()
+                .filter(|node| {
+                    matches!(node.endpoint, EndpointId::Device { id, .. } if id == rebind.device_id)
+                        && node.has_port_kind(kind)
+                        && !self
+                            .endpoints
+                            .contains_key(&EndpointDescriptor::EphemeralNode(node.id, kind))
+                })
+                .collect();
+            // Prefer a node with the same identifier, otherwise take the oldest one
+            let new_node = candidates
+                .iter()
+                .find(|node| node.identifier.identifier() == rebind.identifier)
+                .or_else(|| candidates.iter().min_by_key(|node| node.id));
+
+            let Some(new_node) = new_node else {
+                // The device may not have any nodes of this kind with its current profile. Keep
+                // waiting in case it switches back.
+                remaining.push(rebind);
+                continue;
+            };
+            let new_desc = EndpointDescriptor::EphemeralNode(new_node.id, kind);
+            if self.replace_endpoint(rebind.endpoint, new_desc) {
+                if let Some(endpoint) = self.endpoints.get_mut(&new_desc) {
+                    endpoint.display_name = new_node.identifier.human_name(kind).to_owned();
+                    endpoint.icon_name = new_node.identifier.icon_name().to_owned();
+                }
+                output_messages.push(SonusmixOutputMsg::EndpointReplaced(
+                    rebind.endpoint,
+                    new_desc,
+                ));
+            }
+        }
+        self.device_rebinds = remaining;
+        output_messages
+    }
+
+    /// Switch any devices that aren't on their locked profile back to it.
+    fn diff_device_profiles(&mut self, graph: &Graph) -> Vec<ToPipewireMessage> {
+        // Clear out requests that have been applied, and ones the device rejected or ignored: it
+        // switched to another profile instead, or never switched at all
+        self.pending_device_profiles.retain(|device_id, pending| {
+            graph.devices.get(device_id).is_some_and(|device| {
+                device.active_profile == pending.from
+                    && pending.requested_at.elapsed() < DEVICE_PROFILE_TIMEOUT
+            })
+        });
+
+        let mut messages = Vec::new();
+        for device in graph.devices.values() {
+            let Some(locked_profile) = self.locked_device_profiles.get(&device.name) else {
+                continue;
+            };
+            if self.pending_device_profiles.contains_key(&device.id) {
+                continue;
+            }
+            let Some(profile) = device
+                .profiles
+                .iter()
+                .find(|profile| profile.name == *locked_profile && profile.available)
+            else {
+                continue;
+            };
+            if device.active_profile.is_some() && device.active_profile != Some(profile.index) {
+                self.switch_device_profile(device.id, profile.index, graph);
+                messages.push(ToPipewireMessage::SetDeviceProfile(
+                    device.id,
+                    profile.index,
+                ));
+            }
+        }
+        messages
+    }
+
+    /// Try to resolve each endpoint in the Sonusmix state to one or mode nodes in the Pipewire
+    /// graph, and mark endpoints that could not be resolved as placeholders.
+    ///
+    /// Basically, this means that every hardware endpoint that exists on both states is returned, while other
+    /// hardware endpoints (only existing on the sonusmix state) are marked as a placeholder, so the UI can
+    /// display the endpoint as deactivated.
+    fn diff_nodes<'a>(
+        &mut self,
+        graph: &'a Graph,
+        settings: &SonusmixSettings,
+    ) -> HashMap<EndpointDescriptor, Vec<&'a PwNode>> {
+        let mut remaining_nodes: HashSet<(u32, PortKind)> = graph
+            .nodes
+            .values()
+            .flat_map(|node| {
+                // Add the node as a source and a sink if it has source and sink ports, respectively
+                [
+                    node.has_port_kind(PortKind::Source)
+                        .then_some((node.id, PortKind::Source)),
+                    node.has_port_kind(PortKind::Sink)
+                        .then_some((node.id, PortKind::Sink)),
+                ]
+            })
+            .flatten()
+            .collect();
+        // Send nodes are managed by Sonusmix and never shown
+        for id in graph
+            .send_nodes
+            .values()
+            .filter_map(|send_node| send_node.id)
+        {
+            remaining_nodes.remove(&(id, PortKind::Source));
+            remaining_nodes.remove(&(id, PortKind::Sink));
+        }
+        let mut endpoint_nodes = HashMap::new();
+        for endpoint in self.endpoints.keys().copied().collect::<Vec<_>>() {
+            if let Some(nodes) = self.resolve_endpoint(endpoint, graph, settings) {
+                // Mark the endpoint's nodes as seen
+                for node in &nodes {
+                    if endpoint.is_single() && endpoint.is_kind(PortKind::Source) {
+                        remaining_nodes.remove(&(node.id, PortKind::Source));
+                    }
+                    if endpoint.is_single() && endpoint.is_kind(PortKind::Sink) {
+                        remaining_nodes.remove(&(node.id, PortKind::Sink));
+                    }
+                }
+
+                if let Some(endpoint) = self.endpoints.get_mut(&endpoint) {
+                    // Copy the details from the first resolved node that has any
+                    let mut details: Vec<String> = nodes
+                        .iter()
+                        .filter_map(|node| node.identifier.details())
+                        .map(ToOwned::to_owned)
+                        .collect();
+                    details.sort_unstable();
+                    endpoint.details = details;
+                    endpoint.device = EndpointDevice::from_nodes(
+                        &nodes,
+                        endpoint.descriptor,
+                        graph,
+                        &self.locked_device_profiles,
+                    );
+
+                    // Persistent, application and media role endpoints that reappear start at
+                    // their start volume
+                    if endpoint.is_placeholder
+                        && endpoint.start_volume.is_some()
+                        && matches!(
+                            endpoint.descriptor,
+                            EndpointDescriptor::PersistentNode(..)
+                                | EndpointDescriptor::Application(..)
+                                | EndpointDescriptor::MediaRole(..)
+                        )
+                    {
+                        endpoint.start_volume_pending = true;
+                    }
                     endpoint.is_placeholder = false;
                 }
 
@@ -655,8 +1566,36 @@ impl SonusmixState {
         endpoint_nodes
     }
 
+    /// Replace the node of a group node after its kind changed, since the kind of a node can't be
+    /// changed.
+    fn replace_group_node(&mut self, id: GroupNodeId) -> Vec<ToPipewireMessage> {
+        let (Some(group_node), Some(endpoint)) = (
+            self.group_nodes.get_mut(&id),
+            self.endpoints.get(&EndpointDescriptor::GroupNode(id)),
+        ) else {
+            return Vec::new();
+        };
+        if let Some(pipewire_id) = group_node.pipewire_id {
+            // The old node is kept until the diffing algorithm has moved its links to the new
+            // one, so that streams using it aren't interrupted. If it is already being replaced,
+            // the oldest node is the one that still has the links.
+            group_node.replaced_pipewire_id.get_or_insert(pipewire_id);
+            group_node.pending = true;
+            vec![ToPipewireMessage::ReplaceGroupNode(
+                endpoint.display_name.clone(),
+                id.0,
+                group_node.kind,
+            )]
+        } else {
+            // Remove the node, once it's gone the diffing algorithm will re-create it
+            group_node.pending = false;
+            vec![ToPipewireMessage::RemoveGroupNode(id.0)]
+        }
+    }
+
     fn diff_group_nodes(
         &mut self,
+        graph: &Graph,
         endpoint_nodes: &HashMap<EndpointDescriptor, Vec<&PwNode>>,
     ) -> Vec<ToPipewireMessage> {
         // Check that all of the group nodes have a corresponding node. If there isn't one, create it.
@@ -671,12 +1610,52 @@ impl SonusmixState {
                     .group_nodes
                     .get_mut(&id)
                     .expect("We know the group node must exist");
+                // Until the node replacing an old one shows up, wait for it
+                if group_node.replaced_pipewire_id == Some(node.id) {
+                    continue;
+                }
                 if group_node.pending {
                     group_node.pending = false;
                 }
                 if group_node.pipewire_id != Some(node.id) {
                     group_node.pipewire_id = Some(node.id)
                 }
+                // Once the node that replaces an old one exists, move the old node's links over
+                // to it before removing the old node
+                if let Some(old_id) = group_node.replaced_pipewire_id.take() {
+                    messages.extend(
+                        graph
+                            .links
+                            .values()
+                            // Only links the new node has ports for can be moved
+                            .filter_map(|link| {
+                                if link.start_node == old_id && node.has_port_kind(PortKind::Source)
+                                {
+                                    Some((node.id, link.end_node))
+                                } else if link.end_node == old_id
+                                    && node.has_port_kind(PortKind::Sink)
+                                {
+                                    Some((link.start_node, node.id))
+                                } else {
+                                    None
+                                }
+                            })
+                            .unique()
+                            .map(|(start_id, end_id)| ToPipewireMessage::CreateNodeLinks {
+                                start_id,
+                                end_id,
+                            }),
+                    );
+                    messages.push(ToPipewireMessage::RemoveReplacedGroupNode(id.0));
+                    // Wait for the moved links to show up before checking them
+                    for link in self
+                        .links
+                        .iter_mut()
+                        .filter(|link| link.start == endpoint_desc || link.end == endpoint_desc)
+                    {
+                        link.pending = true;
+                    }
+                }
             } else {
                 let group_node = self
                     .group_nodes
@@ -699,73 +1678,446 @@ impl SonusmixState {
         messages
     }
 
-    /// Check if the properties on the backend nodes match the Sonusmix endpoints, and change one
-    /// or the other appropriately based on whether the endpoint is locked.
-    fn diff_properties(
+    fn diff_loopbacks(
         &mut self,
         endpoint_nodes: &HashMap<EndpointDescriptor, Vec<&PwNode>>,
     ) -> Vec<ToPipewireMessage> {
+        // Check that every loopback has at least one of its halves. If neither exists, load the
+        // module again.
         let mut messages = Vec::new();
-        for (endpoint, nodes) in endpoint_nodes {
-            let Some(endpoint) = self.endpoints.get_mut(endpoint) else {
+        for loopback in self.loopbacks.values_mut() {
+            let exists = [PortKind::Sink, PortKind::Source].into_iter().any(|kind| {
+                endpoint_nodes.contains_key(&EndpointDescriptor::Loopback(loopback.id, kind))
+            });
+            if exists {
+                loopback.pending = false;
+            } else if !loopback.pending {
+                loopback.pending = true;
+                messages.push(ToPipewireMessage::CreateLoopback(
+                    loopback.name.clone(),
+                    loopback.id.0,
+                    loopback.config.clone(),
+                ));
+            }
+        }
+        messages
+    }
+
+    /// Switch the profile of the device an endpoint belongs to, by profile index.
+    fn set_device_profile(
+        &mut self,
+        graph: &Graph,
+        endpoint_desc: EndpointDescriptor,
+        profile_index: i32,
+    ) -> Vec<ToPipewireMessage> {
+        let Some(device) = self
+            .endpoints
+            .get(&endpoint_desc)
+            .and_then(|endpoint| endpoint.device.as_ref())
+            .and_then(|device| graph.devices.get(&device.id))
+        else {
+            return Vec::new();
+        };
+        let Some(profile) = device
+            .profiles
+            .iter()
+            .find(|profile| profile.index == profile_index)
+        else {
+            error!(
+                "Device {} has no profile with index {profile_index}",
+                device.id
+            );
+            return Vec::new();
+        };
+
+        // Move a lock along with the profile, so that it doesn't switch back
+        if let Some(locked_profile) = self.locked_device_profiles.get_mut(&device.name) {
+            *locked_profile = profile.name.clone();
+        }
+        self.switch_device_profile(device.id, profile_index, graph);
+        vec![ToPipewireMessage::SetDeviceProfile(
+            device.id,
+            profile_index,
+        )]
+    }
+
+    /// Record that a device is switching profiles, so that its endpoints can be moved to its new
+    /// nodes once they appear.
+    fn switch_device_profile(&mut self, device_id: u32, profile_index: i32, graph: &Graph) {
+        self.pending_device_profiles.insert(
+            device_id,
+            PendingDeviceProfile {
+                index: profile_index,
+                from: graph
+                    .devices
+                    .get(&device_id)
+                    .and_then(|device| device.active_profile),
+                requested_at: Instant::now(),
+            },
+        );
+        for descriptor in self.endpoints.keys() {
+            let EndpointDescriptor::EphemeralNode(id, _) = descriptor else {
                 continue;
             };
-            let num_messages_before = messages.len();
-            // If the volume has pending changes, only check if the states match. If they do,
-            // remove the pending marker.
-            if endpoint.volume_pending {
-                // Locked endpoints require that every channel on every node has the same volume.
-                let volumes_match = if endpoint.volume_locked_muted.is_locked() {
-                    nodes
-                        .iter()
-                        .flat_map(|node| &node.channel_volumes)
-                        .all(|vol| *vol == endpoint.volume)
-                } else {
-                    // Unlocked endpoints are a little less strict, and only require that the
-                    // average volume matches.
-                    average_volumes(nodes.iter().flat_map(|node| &node.channel_volumes))
-                        == endpoint.volume
-                };
-                let mute_states_match = endpoint.volume_locked_muted.is_muted()
-                    == aggregate_bools(nodes.iter().map(|node| &node.mute));
-                if volumes_match && mute_states_match {
-                    endpoint.volume_pending = false;
-                }
-
+            let Some(node) = graph.nodes.get(id).filter(
+                |node| matches!(node.endpoint, EndpointId::Device { id, .. } if id == device_id),
+            ) else {
+                continue;
+            };
+            if self
+                .device_rebinds
+                .iter()
+                .all(|rebind| rebind.endpoint != *descriptor)
+            {
+                self.device_rebinds.push(DeviceRebind {
+                    endpoint: *descriptor,
+                    device_id,
+                    identifier: node.identifier.identifier().to_owned(),
+                });
+            }
+        }
+    }
+
+    /// Move an endpoint to a new descriptor, carrying over its settings, links and position.
+    /// Returns false if the old endpoint doesn't exist or the new one already does.
+    fn replace_endpoint(&mut self, old: EndpointDescriptor, new: EndpointDescriptor) -> bool {
+        if self.endpoints.contains_key(&new) {
+            return false;
+        }
+        let Some(mut endpoint) = self.endpoints.remove(&old) else {
+            return false;
+        };
+        endpoint.descriptor = new;
+        self.endpoints.insert(new, endpoint);
+
+        for descriptor in self
+            .active_sources
+            .iter_mut()
+            .chain(self.active_sinks.iter_mut())
+            .chain(
+                self.applications
+                    .values_mut()
+                    .flat_map(|application| application.exceptions.iter_mut()),
+            )
+        {
+            if *descriptor == old {
+                *descriptor = new;
+            }
+        }
+        for link in &mut self.links {
+            if link.start == old {
+                link.start = new;
+            }
+            if link.end == old {
+                link.end = new;
+            }
+        }
+        if let Some(recording) = self.recordings.remove(&old) {
+            self.recordings.insert(new, recording);
+        }
+        if let Some(ramp) = self.volume_ramps.remove(&old) {
+            self.volume_ramps.insert(new, ramp);
+        }
+        self.replace_midi_mappings(old, new);
+        self.replace_vca_member(old, new);
+        self.replace_mute_group_member(old, new);
+        self.replace_solo_endpoint(old, new);
+        if self.primary_endpoint == Some(old) {
+            self.primary_endpoint = Some(new);
+        }
+        true
+    }
+
+    /// Move a placeholder endpoint to a node or application the user chose instead, such as when
+    /// a device was swapped for a different one. The target is added the same way as when it is
+    /// chosen normally, so it gets its own name and icon, and then the placeholder's settings and
+    /// links take its place. Returns None if the endpoint can't be rebound to the target.
+    fn rebind_placeholder(
+        &mut self,
+        graph: &Graph,
+        placeholder: EndpointDescriptor,
+        target: EndpointDescriptor,
+        settings: &SonusmixSettings,
+    ) -> Option<Vec<ToPipewireMessage>> {
+        if !self
+            .endpoints
+            .get(&placeholder)
+            .is_some_and(|endpoint| endpoint.is_placeholder)
+        {
+            error!("Cannot rebind {placeholder:?} as it is not a placeholder");
+            return None;
+        }
+        let same_kind = [PortKind::Source, PortKind::Sink]
+            .into_iter()
+            .all(|kind| placeholder.is_kind(kind) == target.is_kind(kind));
+        if !same_kind || self.endpoints.contains_key(&target) {
+            error!("Cannot rebind {placeholder:?} to {target:?}");
+            return None;
+        }
+        let add_message = match target {
+            EndpointDescriptor::EphemeralNode(id, kind) => SonusmixMsg::AddEphemeralNode(id, kind),
+            EndpointDescriptor::Application(id, kind) => SonusmixMsg::AddApplication(id, kind),
+            EndpointDescriptor::DefaultNode(kind) => SonusmixMsg::AddDefaultNode(kind),
+            EndpointDescriptor::MediaRole(role, kind) => SonusmixMsg::AddMediaRole(role, kind),
+            _ => {
+                error!("Cannot rebind {placeholder:?} to {target:?}");
+                return None;
+            }
+        };
+        let (output, mut messages) = self.update(graph, add_message, settings);
+        if !matches!(output, Some(SonusmixOutputMsg::EndpointAdded(_))) {
+            return None;
+        }
+        let added = self.endpoints.remove(&target)?;
+        self.active_sources.retain(|endpoint| *endpoint != target);
+        self.active_sinks.retain(|endpoint| *endpoint != target);
+        self.replace_endpoint(placeholder, target);
+
+        let nodes = self
+            .resolve_endpoint(target, graph, settings)
+            .unwrap_or_default();
+        let endpoint = self.endpoints.get_mut(&target)?;
+        endpoint.display_name = added.display_name;
+        endpoint.icon_name = added.icon_name;
+        endpoint.details = added.details;
+        endpoint.is_placeholder = false;
+        // The new nodes take on the placeholder's volume and mute state, whether or not they are
+        // locked
+        let volume = endpoint.applied_volume();
+        let muted = endpoint.applied_mute_state();
+        for node in nodes {
+            messages.push(ToPipewireMessage::NodeVolume(
+                node.id,
+                vec![volume; node.channel_volumes.len()],
+            ));
+            if let Some(muted) = muted {
+                messages.push(ToPipewireMessage::NodeMute(node.id, muted));
+            }
+            endpoint.volume_pending = true;
+        }
+        Some(messages)
+    }
+
+    /// Whether the link between two endpoints is fully connected.
+    pub fn is_link_connected(&self, source: EndpointDescriptor, sink: EndpointDescriptor) -> bool {
+        self.links
+            .iter()
+            .find(|link| link.start == source && link.end == sink)
+            .and_then(|link| link.state.is_connected())
+            .unwrap_or(false)
+    }
+
+    /// Stop recording an endpoint, if it is being recorded.
+    fn stop_recording(&mut self, endpoint_desc: EndpointDescriptor) -> Option<ToPipewireMessage> {
+        self.recordings
+            .remove(&endpoint_desc)
+            .map(|recording| ToPipewireMessage::StopRecording(recording.id))
+    }
+
+    /// Check if the properties on the backend nodes match the Sonusmix endpoints, and change one
+    /// or the other appropriately based on whether the endpoint is locked.
+    fn diff_properties(
+        &mut self,
+        endpoint_nodes: &HashMap<EndpointDescriptor, Vec<&PwNode>>,
+        settings: &SonusmixSettings,
+    ) -> Vec<ToPipewireMessage> {
+        let mut messages = Vec::new();
+        let mut reverted = Vec::new();
+        let held_muted: HashSet<EndpointDescriptor> = endpoint_nodes
+            .keys()
+            .copied()
+            .filter(|desc| self.is_held_by_mute_group(*desc))
+            .collect();
+        for (endpoint_desc, nodes) in endpoint_nodes {
+            // Fading nodes are in between volumes on purpose, so their volumes are left alone until
+            // the fade is done. A locked mute state is still kept, unless the nodes are fading out
+            // before being muted.
+            if let Some(ramp) = self.volume_ramps.get(endpoint_desc) {
+                let locked_mute = self
+                    .endpoints
+                    .get(endpoint_desc)
+                    .filter(|_| !ramp.fades_out())
+                    .filter(|endpoint| endpoint.volume_locked_muted.is_locked())
+                    .and_then(|endpoint| {
+                        let muted = endpoint.volume_locked_muted.is_muted()?;
+                        Some(endpoint.applied_mute(muted))
+                    });
+                if let Some(muted) = locked_mute {
+                    messages.extend(
+                        nodes
+                            .iter()
+                            .filter(|node| node.mute != muted)
+                            .map(|node| ToPipewireMessage::NodeMute(node.id, muted)),
+                    );
+                }
+                continue;
+            }
+            let Some(endpoint) = self.endpoints.get_mut(endpoint_desc) else {
+                continue;
+            };
+            let num_messages_before = messages.len();
+            // If the endpoint just reappeared, set all of its nodes to its start volume
+            if endpoint.start_volume_pending {
+                endpoint.start_volume_pending = false;
+                if let Some(start_volume) = endpoint.start_volume {
+                    endpoint.volume = endpoint.clamp_volume(start_volume);
+                    endpoint.volume_mixed = false;
+                    let volume = endpoint.applied_volume();
+                    messages.extend(nodes.iter().map(|node| {
+                        ToPipewireMessage::NodeVolume(
+                            node.id,
+                            vec![volume; node.channel_volumes.len()],
+                        )
+                    }));
+                }
+            // If the volume has pending changes, only check if the states match. If they do,
+            // remove the pending marker.
+            } else if endpoint.volume_pending {
+                // Locked endpoints require that every channel on every node has the same volume.
+                let volume = endpoint.applied_volume();
+                let volumes_match = if endpoint.volume_locked_muted.is_locked() {
+                    nodes
+                        .iter()
+                        .flat_map(|node| &node.channel_volumes)
+                        .all(|vol| *vol == volume)
+                } else {
+                    // Unlocked endpoints are a little less strict, and only require that the
+                    // average volume matches.
+                    average_volumes(
+                        nodes.iter().flat_map(|node| &node.channel_volumes),
+                        settings.fader_curve,
+                    ) == volume
+                };
+                let mute_states_match = endpoint.applied_mute_state()
+                    == aggregate_bools(nodes.iter().map(|node| &node.mute));
+                if volumes_match && mute_states_match {
+                    endpoint.volume_pending = false;
+                }
+
                 // If the volume is locked, ensure all channels on all nodes are set to the endpoint
                 // volume, and all the nodes' mute states are the same as the endpoint's. Otherwise,
                 // make the endpoint's state match the average volume and mute state of the nodes.
             } else if endpoint.volume_locked_muted.is_locked() {
+                let volume_before = endpoint.volume;
+                let muted_before = endpoint.volume_locked_muted.is_muted();
+                // Push to talk decides the mute state, rather than the state that was locked,
+                // unless a mute group is holding the endpoint muted
+                if let Some(muted) = endpoint
+                    .push_to_talk_muted()
+                    .filter(|_| !held_muted.contains(endpoint_desc))
+                {
+                    endpoint.volume_locked_muted = endpoint.volume_locked_muted.with_mute(muted);
+                }
                 // Tell any nodes that don't have all channels matching the endpoint volume to set
-                // their channel volumes to the endpoint volume
+                // their channel volumes to the endpoint volume, which can't go past the
+                // endpoint's limit
+                endpoint.volume = endpoint.clamp_volume(endpoint.volume);
                 endpoint.volume_mixed = false;
-                messages.extend(
-                    nodes
-                        .iter()
-                        .filter(|node| node.channel_volumes.iter().any(|cv| *cv != endpoint.volume))
-                        .map(|node| {
-                            ToPipewireMessage::NodeVolume(
-                                node.id,
-                                vec![endpoint.volume; node.channel_volumes.len()],
-                            )
-                        }),
-                );
+                let volume = endpoint.applied_volume();
+
+                // Log the changes another client made to nodes that already existed, as long as
+                // Sonusmix didn't just change the endpoint itself
+                let mut changed_nodes = nodes
+                    .iter()
+                    .filter(|node| self.known_nodes.contains(&node.id));
+                // Wait before restoring locks that another client keeps changing. A mute group's
+                // hold is never given up to another client, however often it unmutes the nodes.
+                let held = held_muted.contains(endpoint_desc);
+                let volume_backed_off = !held
+                    && self
+                        .lock_backoffs
+                        .get(&LockTarget::Volume(*endpoint_desc))
+                        .is_some_and(LockBackoff::is_active);
+                let mute_backed_off = !held
+                    && self
+                        .lock_backoffs
+                        .get(&LockTarget::Mute(*endpoint_desc))
+                        .is_some_and(LockBackoff::is_active);
+                if !volume_backed_off && endpoint.volume == volume_before {
+                    if let Some(node) = changed_nodes
+                        .clone()
+                        .find(|node| node.channel_volumes.iter().any(|cv| *cv != volume))
+                    {
+                        reverted.push((
+                            LockTarget::Volume(*endpoint_desc),
+                            format!(
+                                "Volume of {} changed to {} by another client, reverted",
+                                endpoint.custom_or_display_name(),
+                                settings.fader_curve.format_volume(average_volumes(
+                                    &node.channel_volumes,
+                                    settings.fader_curve
+                                )),
+                            ),
+                        ));
+                    }
+                }
+                if !mute_backed_off && endpoint.volume_locked_muted.is_muted() == muted_before {
+                    let applied_muted_before =
+                        muted_before.map(|muted| endpoint.applied_mute(muted));
+                    if let Some(node) =
+                        changed_nodes.find(|node| Some(node.mute) != applied_muted_before)
+                    {
+                        reverted.push((
+                            LockTarget::Mute(*endpoint_desc),
+                            format!(
+                                "{} was {} by another client, reverted",
+                                endpoint.custom_or_display_name(),
+                                if node.mute { "muted" } else { "unmuted" },
+                            ),
+                        ));
+                    }
+                }
+
+                if !volume_backed_off {
+                    messages.extend(
+                        nodes
+                            .iter()
+                            .filter(|node| node.channel_volumes.iter().any(|cv| *cv != volume))
+                            .map(|node| {
+                                ToPipewireMessage::NodeVolume(
+                                    node.id,
+                                    vec![volume; node.channel_volumes.len()],
+                                )
+                            }),
+                    );
+                }
                 // Tell any nodes whose mute state doesn't match the endpoint's to change it
-                let endpoint_muted = endpoint
-                    .volume_locked_muted
-                    .is_muted()
-                    .expect("mute should not be mixed as we know it's locked");
-                messages.extend(
-                    nodes
-                        .iter()
-                        .filter(|node| node.mute != endpoint_muted)
-                        .map(|node| ToPipewireMessage::NodeMute(node.id, endpoint_muted)),
+                let endpoint_muted = endpoint.applied_mute(
+                    endpoint
+                        .volume_locked_muted
+                        .is_muted()
+                        .expect("mute should not be mixed as we know it's locked"),
                 );
+                if !mute_backed_off {
+                    messages.extend(
+                        nodes
+                            .iter()
+                            .filter(|node| node.mute != endpoint_muted)
+                            .map(|node| ToPipewireMessage::NodeMute(node.id, endpoint_muted)),
+                    );
+                }
             } else {
-                endpoint.volume_locked_muted =
-                    VolumeLockMuteState::from_bools_unlocked(nodes.iter().map(|node| &node.mute));
-                endpoint.volume =
-                    average_volumes(nodes.iter().flat_map(|node| &node.channel_volumes));
+                // While a VCA mutes the endpoint, its nodes' mute states aren't its own, and the
+                // VCA keeps them muted
+                if endpoint.vca_muted {
+                    messages.extend(
+                        nodes
+                            .iter()
+                            .filter(|node| !node.mute)
+                            .map(|node| ToPipewireMessage::NodeMute(node.id, true)),
+                    );
+                } else {
+                    endpoint.volume_locked_muted = VolumeLockMuteState::from_bools_unlocked(
+                        nodes.iter().map(|node| &node.mute),
+                    );
+                }
+                endpoint.read_applied_volume(
+                    average_volumes(
+                        nodes.iter().flat_map(|node| &node.channel_volumes),
+                        settings.fader_curve,
+                    ),
+                    settings,
+                );
                 // check if the volume is mixed. An unlocked volume can be in both states.
                 // A locked volume can not.
                 for node in nodes {
@@ -780,6 +2132,12 @@ impl SonusmixState {
             }
         }
 
+        self.reverted_changes.extend(
+            reverted
+                .into_iter()
+                .map(|(target, description)| (target, description, None)),
+        );
+
         messages
     }
 
@@ -793,6 +2151,9 @@ impl SonusmixState {
 
         let mut messages = Vec::new();
         let mut to_remove_indices = Vec::new();
+        // Locked links that another client changed, with whether they were removed and the
+        // client that created them
+        let mut reverted = Vec::new();
         for (i, link) in self.links.iter_mut().enumerate() {
             // Remove the link from `remaining_endpoint_links` because it is now known to be in the
             // state
@@ -815,21 +2176,66 @@ impl SonusmixState {
             //     continue;
             // }
 
+            // Links with a send level are routed through their gain node while they are
+            // connected. Wait for the link to be moved over to it before checking the link.
+            let messages_before_send = messages.len();
+            let send_node = match link.send.as_mut() {
+                Some(send) if link.state.is_connected() != Some(false) => {
+                    let endpoint_name = |desc: &EndpointDescriptor| {
+                        self.endpoints
+                            .get(desc)
+                            .map_or("unknown", |endpoint| endpoint.custom_or_display_name())
+                    };
+                    let description = format!(
+                        "Sonusmix Send ({} → {})",
+                        endpoint_name(&link.start),
+                        endpoint_name(&link.end)
+                    );
+                    match send::diff_send_node(
+                        send,
+                        &description,
+                        source,
+                        sink,
+                        graph,
+                        &mut messages,
+                    ) {
+                        Some(send_node) => Some(send_node),
+                        None => {
+                            if messages.len() > messages_before_send {
+                                link.pending = true;
+                            }
+                            continue;
+                        }
+                    }
+                }
+                _ => None,
+            };
+            let hops = send::link_hops(source, sink, send_node);
+
             // If the link has pending changes, simply check if the states match, and if so, remove
             // the pending marker
             if link.pending {
-                if are_endpoints_connected(source, sink, &node_links) == link.state.is_connected() {
+                if are_hops_connected(&hops, &node_links) == link.state.is_connected() {
                     link.pending = false;
                 }
                 continue;
             }
 
+            // Wait before restoring locks that another client keeps changing
+            if self
+                .lock_backoffs
+                .get(&LockTarget::Link(link.start, link.end))
+                .is_some_and(LockBackoff::is_active)
+            {
+                continue;
+            }
+
             let num_messages_before = messages.len();
 
             match link.state {
                 LinkState::PartiallyConnected => {
                     // Check if link should actually now be disconnected or fully connected
-                    match are_endpoints_connected(source, sink, &node_links) {
+                    match are_hops_connected(&hops, &node_links) {
                         Some(true) => link.state = LinkState::ConnectedUnlocked,
                         Some(false) => to_remove_indices.push(i),
                         None => {}
@@ -838,18 +2244,29 @@ impl SonusmixState {
                 LinkState::ConnectedUnlocked => {
                     // Check if all necessary links are still there, if not, change to partially
                     // connected or disconnected
-                    match are_endpoints_connected(source, sink, &node_links) {
+                    match are_hops_connected(&hops, &node_links) {
                         Some(true) => {}
                         Some(false) => to_remove_indices.push(i),
                         None => link.state = LinkState::PartiallyConnected,
                     }
                 }
                 LinkState::ConnectedLocked => {
+                    // Log if links between nodes that already existed were removed
+                    if hop_node_pairs(&hops).any(|(source, sink)| {
+                        self.known_nodes.contains(&source.id)
+                            && self.known_nodes.contains(&sink.id)
+                            && are_nodes_connected(source, sink, &node_links) != Some(true)
+                    }) {
+                        reverted.push((
+                            link.start,
+                            link.end,
+                            true,
+                            activity::competing_link_client(graph, source, sink),
+                        ));
+                    }
                     // Check if any necessary links are missing. If so, create them.
                     messages.extend(
-                        source
-                            .iter()
-                            .cartesian_product(sink.iter())
+                        hop_node_pairs(&hops)
                             .filter(|(source, sink)| {
                                 are_nodes_connected(source, sink, &node_links) != Some(true)
                             })
@@ -863,8 +2280,39 @@ impl SonusmixState {
                                 }
                             }),
                     );
+                    // Links routed through a gain node must not also connect their endpoints
+                    // directly
+                    if send_node.is_some() {
+                        messages.extend(
+                            source
+                                .iter()
+                                .cartesian_product(sink.iter())
+                                .filter(|(source, sink)| {
+                                    are_nodes_connected(source, sink, &node_links) != Some(false)
+                                })
+                                .map(|(source, sink)| ToPipewireMessage::RemoveNodeLinks {
+                                    start_id: source.id,
+                                    end_id: sink.id,
+                                }),
+                        );
+                    }
                 }
                 LinkState::DisconnectedLocked => {
+                    // Log the client that created any links that exist
+                    let created_link = source
+                        .iter()
+                        .cartesian_product(sink.iter())
+                        .filter_map(|(source, sink)| node_links.get(&(source.id, sink.id)))
+                        .flatten()
+                        .next();
+                    if let Some(created_link) = created_link {
+                        reverted.push((
+                            link.start,
+                            link.end,
+                            false,
+                            activity::link_client_name(graph, created_link),
+                        ));
+                    }
                     // Check if any links exist. If so, remove them.
                     messages.extend(
                         source
@@ -894,7 +2342,22 @@ impl SonusmixState {
         // Remove any links whose endpoints no longer exist. Iterate in reverse to preserve
         // the indices of the remaining elements to remove.
         for i in to_remove_indices.into_iter().rev() {
-            self.links.swap_remove(i);
+            let mut link = self.links.swap_remove(i);
+            if let Some(send) = &mut link.send {
+                messages.extend(send.detach(graph));
+            }
+        }
+
+        for (start, end, removed, client) in reverted {
+            let description = format!(
+                "Link {} → {} {} externally, {}",
+                self.activity_name(&start),
+                self.activity_name(&end),
+                if removed { "removed" } else { "created" },
+                if removed { "restored" } else { "removed again" },
+            );
+            self.reverted_changes
+                .push((LockTarget::Link(start, end), description, client));
         }
 
         // Check if any links now exist between two endpoints that were not previously connected.
@@ -911,12 +2374,16 @@ impl SonusmixState {
                     start: source_desc,
                     end: sink_desc,
                     state: LinkState::ConnectedUnlocked,
+                    send: None,
+                    temporary: false,
                     pending: false,
                 }),
                 None => self.links.push(Link {
                     start: source_desc,
                     end: sink_desc,
                     state: LinkState::PartiallyConnected,
+                    send: None,
+                    temporary: false,
                     pending: false,
                 }),
                 Some(false) => {}
@@ -971,6 +2438,29 @@ impl SonusmixState {
                 .and_then(|id| graph.nodes.get(&id))
                 .filter(|node| !node.ports.is_empty())
                 .map(|node| vec![node]),
+            EndpointDescriptor::Loopback(id, kind) => graph
+                .loopbacks
+                .get(&id.0)
+                .and_then(|loopback| match kind {
+                    PortKind::Sink => loopback.capture_id,
+                    PortKind::Source => loopback.playback_id,
+                })
+                .and_then(|id| graph.nodes.get(&id))
+                .filter(|node| node.has_port_kind(kind))
+                .map(|node| vec![node]),
+            EndpointDescriptor::DefaultNode(kind) => {
+                let name = match kind {
+                    PortKind::Sink => graph.default_sink.as_deref(),
+                    PortKind::Source => graph.default_source.as_deref(),
+                }?;
+                graph
+                    .nodes
+                    .values()
+                    .find(|node| {
+                        node.identifier.node_name() == Some(name) && node.has_port_kind(kind)
+                    })
+                    .map(|node| vec![node])
+            }
             EndpointDescriptor::Application(id, kind) => {
                 let application = self.applications.get(&id)?;
                 // Resolve all the exceptions. Exceptions should only be an ephemeral or persistent
@@ -1002,6 +2492,32 @@ impl SonusmixState {
                     })
                     // Filter out nodes matching the exceptions
                     .filter(|node| !exceptions.iter().any(|n| n.id == node.id))
+                    // Filter out streams the user excluded
+                    .filter(|node| !application.is_stream_excluded(&node.identifier))
+                    .collect();
+
+                (!nodes.is_empty()).then_some(nodes)
+            }
+            EndpointDescriptor::MediaRole(role, kind) => {
+                // Streams that are controlled by any other endpoint, such as their own or their
+                // application's, are left to that endpoint
+                let claimed: HashSet<u32> = self
+                    .endpoints
+                    .keys()
+                    .filter(|desc| {
+                        desc.is_kind(kind) && !matches!(desc, EndpointDescriptor::MediaRole(..))
+                    })
+                    .filter_map(|desc| self.resolve_endpoint(*desc, graph, settings))
+                    .flatten()
+                    .map(|node| node.id)
+                    .collect();
+                let nodes: Vec<&PwNode> = graph
+                    .nodes
+                    .values()
+                    .filter(|node| {
+                        node.identifier.media_role() == Some(role) && node.has_port_kind(kind)
+                    })
+                    .filter(|node| !claimed.contains(&node.id))
                     .collect();
 
                 (!nodes.is_empty()).then_some(nodes)
@@ -1010,6 +2526,39 @@ impl SonusmixState {
         }
     }
 
+    /// The nodes on the other side of each connected link of an endpoint, along with the index of
+    /// the link: its gain node if it is routed through one, otherwise the other endpoint's nodes.
+    /// Used to move node links when the nodes an endpoint resolves to change.
+    fn connected_link_neighbours(
+        &self,
+        endpoint_desc: EndpointDescriptor,
+        graph: &Graph,
+        settings: &SonusmixSettings,
+    ) -> Vec<(usize, Vec<u32>)> {
+        self.links
+            .iter()
+            .enumerate()
+            .filter(|(_, link)| link.state.is_connected() != Some(false))
+            .filter_map(|(i, link)| {
+                let other = if link.start == endpoint_desc {
+                    link.end
+                } else if link.end == endpoint_desc {
+                    link.start
+                } else {
+                    return None;
+                };
+                let neighbours: Vec<&PwNode> =
+                    match link.send.as_ref().and_then(|send| send.node(graph)) {
+                        Some(send_node) => vec![send_node],
+                        None => self
+                            .resolve_endpoint(other, graph, settings)
+                            .unwrap_or_default(),
+                    };
+                Some((i, neighbours.iter().map(|node| node.id).collect()))
+            })
+            .collect()
+    }
+
     /// Find all of the Pipewire links between any two active endpoints and collect them into the
     /// returned data structures.
     fn find_relevant_links<'a>(
@@ -1120,6 +2669,42 @@ pub struct Endpoint {
     pub volume_locked_muted: VolumeLockMuteState,
     #[serde(skip)]
     pub volume_pending: bool,
+    /// The device this endpoint's nodes belong to, if they all belong to the same one
+    #[serde(skip)]
+    pub device: Option<EndpointDevice>,
+    /// The highest volume this endpoint can be set to, if lower than the global limit
+    #[serde(default)]
+    pub max_volume: Option<f32>,
+    /// The volume this endpoint is set to when it reappears. Only used for persistent and
+    /// application endpoints.
+    #[serde(default)]
+    pub start_volume: Option<f32>,
+    /// Whether the endpoint just reappeared and should be set to its start volume
+    #[serde(skip)]
+    pub start_volume_pending: bool,
+    #[serde(default)]
+    pub push_to_talk: Option<PushToTalkMode>,
+    #[serde(default)]
+    pub color: Option<EndpointColor>,
+    /// An icon chosen by the user, used instead of `icon_name`
+    #[serde(default)]
+    pub custom_icon_name: Option<String>,
+    /// The section of the sources or sinks list this endpoint is shown in
+    #[serde(default)]
+    pub section: Option<SectionId>,
+    /// Whether the push to talk key is held down
+    #[serde(skip)]
+    pub push_to_talk_held: bool,
+    /// Whether the endpoint is in the state for when the push to talk key is held. Stays true
+    /// after the key is released until the release delay has passed.
+    #[serde(skip)]
+    pub push_to_talk_active: bool,
+    /// The total gain of the VCAs this endpoint belongs to, in dB
+    #[serde(skip)]
+    pub vca_gain_db: f32,
+    /// Whether any of the VCAs this endpoint belongs to is muted
+    #[serde(skip)]
+    pub vca_muted: bool,
 }
 
 impl Endpoint {
@@ -1136,6 +2721,18 @@ impl Endpoint {
             volume_mixed: false,
             volume_locked_muted: VolumeLockMuteState::UnmutedUnlocked,
             volume_pending: false,
+            device: None,
+            max_volume: None,
+            start_volume: None,
+            start_volume_pending: false,
+            push_to_talk: None,
+            push_to_talk_held: false,
+            push_to_talk_active: false,
+            color: None,
+            custom_icon_name: None,
+            section: None,
+            vca_gain_db: 0.0,
+            vca_muted: false,
         }
     }
 
@@ -1169,10 +2766,30 @@ impl Endpoint {
         self
     }
 
+    /// Limit a volume to this endpoint's max volume.
+    pub fn clamp_volume(&self, volume: f32) -> f32 {
+        self.max_volume
+            .map_or(volume, |max_volume| volume.min(max_volume))
+    }
+
+    /// The position of the end of this endpoint's volume slider.
+    pub fn slider_limit(&self, settings: &SonusmixSettings) -> f64 {
+        self.max_volume
+            .map_or(settings.slider_limit(), |max_volume| {
+                settings
+                    .slider_limit()
+                    .min(settings.fader_curve.volume_to_slider(max_volume))
+            })
+    }
+
     pub fn custom_or_display_name(&self) -> &str {
         self.custom_name.as_ref().unwrap_or(&self.display_name)
     }
 
+    pub fn custom_or_default_icon_name(&self) -> &str {
+        self.custom_icon_name.as_ref().unwrap_or(&self.icon_name)
+    }
+
     pub fn details_short(&self) -> String {
         match self.details.first() {
             Some(details) => details.clone(),
@@ -1192,11 +2809,135 @@ impl Endpoint {
     }
 }
 
+/// The profiles and ports of the device an endpoint belongs to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EndpointDevice {
+    pub id: u32,
+    pub name: String,
+    pub profiles: Vec<DeviceOption>,
+    pub active_profile: Option<i32>,
+    pub profile_locked: bool,
+    /// Only the routes that can be used by the endpoint's node with the active profile
+    pub routes: Vec<DeviceOption>,
+    pub active_route: Option<i32>,
+}
+
+/// A profile or route that can be chosen for a device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceOption {
+    pub index: i32,
+    pub description: String,
+    pub available: bool,
+}
+
+impl EndpointDevice {
+    fn from_nodes(
+        nodes: &[&PwNode],
+        descriptor: EndpointDescriptor,
+        graph: &Graph,
+        locked_device_profiles: &HashMap<String, String>,
+    ) -> Option<Self> {
+        let (device_id, device_index) = nodes
+            .iter()
+            .map(|node| match node.endpoint {
+                EndpointId::Device { id, device_index } => Some((id, device_index)),
+                EndpointId::Client(_) => None,
+            })
+            .all_equal_value()
+            .ok()??;
+        let device = graph.devices.get(&device_id)?;
+        let active_profile = device.active_profile.and_then(|index| {
+            device
+                .profiles
+                .iter()
+                .find(|profile| profile.index == index)
+        });
+
+        let routes = device
+            .routes
+            .iter()
+            .filter(|route| {
+                descriptor.is_kind(route.kind)
+                    && device_index
+                        .map(|device_index| route.devices.contains(&device_index))
+                        .unwrap_or(true)
+                    && active_profile
+                        .map(|profile| route.profiles.contains(&profile.index))
+                        .unwrap_or(true)
+            })
+            .map(|route| DeviceOption {
+                index: route.index,
+                description: route.description.clone(),
+                available: route.available,
+            })
+            .collect();
+        let active_route = device
+            .active_routes
+            .iter()
+            .find(|route| Some(route.device_index) == device_index)
+            .map(|route| route.route_index);
+
+        Some(Self {
+            id: device.id,
+            name: device.name.clone(),
+            profiles: device
+                .profiles
+                .iter()
+                .map(|profile| DeviceOption {
+                    index: profile.index,
+                    description: profile.description.clone(),
+                    available: profile.available,
+                })
+                .collect(),
+            active_profile: device.active_profile,
+            profile_locked: active_profile
+                .zip(locked_device_profiles.get(&device.name))
+                .map(|(profile, locked)| profile.name == *locked)
+                .unwrap_or(false),
+            routes,
+            active_route,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Recording {
+    pub id: Ulid,
+    pub path: PathBuf,
+    pub started_at: Instant,
+}
+
+/// How long to wait for a device to switch to a requested profile before requesting it again
+const DEVICE_PROFILE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy)]
+pub struct PendingDeviceProfile {
+    pub index: i32,
+    /// The profile the device was on when the switch was requested
+    pub from: Option<i32>,
+    pub requested_at: Instant,
+}
+
+#[derive(Debug, Clone)]
+pub struct DeviceRebind {
+    pub endpoint: EndpointDescriptor,
+    pub device_id: u32,
+    /// The identifier of the node the endpoint used before the switch
+    pub identifier: String,
+}
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
 pub struct Link {
     pub start: EndpointDescriptor,
     pub end: EndpointDescriptor,
     pub state: LinkState,
+    /// The send level of the link, if it has one
+    #[serde(default)]
+    pub send: Option<LinkSend>,
+    /// Whether the link only exists while an endpoint is soloed. Temporary links are never saved
+    /// or locked.
+    #[serde(default)]
+    pub temporary: bool,
     #[serde(skip)]
     pending: bool,
 }
@@ -1336,6 +3077,14 @@ pub enum EndpointDescriptor {
     /// Represents all sources or sinks (except those that are explicitly excluded) belonging to a
     /// particular device. These will be managed and routed together.
     Device(DeviceId, PortKind),
+    /// Represents one half of a loopback created and managed by Sonusmix. The sink half is the
+    /// loopback's capture stream, and the source half is its playback stream.
+    Loopback(LoopbackId, PortKind),
+    /// Represents whichever node is currently the default sink or source. Links and locks on it
+    /// move to the new node when the default changes, such as when a laptop is docked.
+    DefaultNode(PortKind),
+    /// Represents all streams with a particular `media.role`, across all applications.
+    MediaRole(MediaRole, PortKind),
 }
 
 impl EndpointDescriptor {
@@ -1345,7 +3094,10 @@ impl EndpointDescriptor {
             Self::EphemeralNode(_, kind_)
             | Self::PersistentNode(_, kind_)
             | Self::Application(_, kind_)
-            | Self::Device(_, kind_) => *kind_ == kind,
+            | Self::Device(_, kind_)
+            | Self::Loopback(_, kind_)
+            | Self::DefaultNode(kind_)
+            | Self::MediaRole(_, kind_) => *kind_ == kind,
         }
     }
 
@@ -1355,14 +3107,24 @@ impl EndpointDescriptor {
             Self::EphemeralNode(_, kind_)
             | Self::PersistentNode(_, kind_)
             | Self::Application(_, kind_)
-            | Self::Device(_, kind_) => *kind_ == kind,
+            | Self::Device(_, kind_)
+            | Self::Loopback(_, kind_)
+            | Self::DefaultNode(kind_)
+            | Self::MediaRole(_, kind_) => *kind_ == kind,
         }
     }
 
     pub fn is_single(&self) -> bool {
         match self {
-            Self::EphemeralNode(..) | Self::PersistentNode(..) | Self::GroupNode(_) => true,
-            Self::Application(..) | Self::Device(..) => false,
+            Self::EphemeralNode(..)
+            | Self::PersistentNode(..)
+            | Self::GroupNode(_)
+            | Self::Loopback(..) => true,
+            // A default endpoint only follows a node, so the node can still be added by itself
+            Self::Application(..)
+            | Self::Device(..)
+            | Self::DefaultNode(_)
+            | Self::MediaRole(..) => false,
         }
     }
 }
@@ -1394,6 +3156,10 @@ pub struct GroupNode {
     pub pipewire_id: Option<u32>,
     #[serde(skip)]
     pub pending: bool,
+    /// The node this group node had before its kind was changed. It is kept until its links
+    /// have been moved to the new node.
+    #[serde(skip)]
+    pub replaced_pipewire_id: Option<u32>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
@@ -1404,6 +3170,72 @@ pub enum GroupNodeKind {
     Sink,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct LoopbackId(Ulid);
+
+impl LoopbackId {
+    fn new() -> Self {
+        Self(Ulid::new())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Loopback {
+    pub id: LoopbackId,
+    pub name: String,
+    pub config: LoopbackConfig,
+    #[serde(skip)]
+    pub pending: bool,
+}
+
+impl Loopback {
+    pub fn name_with_tag(&self) -> String {
+        // Uses unicode "fullwidth" brackets which I personally think look nicer
+        format!("［Loopback］{}", self.name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LoopbackConfig {
+    /// The latency of both halves of the loopback, in milliseconds
+    pub latency_ms: u32,
+    /// The channel positions of the capture half, e.g. `["FL", "FR"]`
+    pub capture_channels: Vec<String>,
+    /// The channel positions of the playback half, e.g. `["FL", "FR"]`
+    pub playback_channels: Vec<String>,
+}
+
+impl LoopbackConfig {
+    /// The latencies a loopback can be set to, in milliseconds
+    pub const LATENCY_RANGE_MS: RangeInclusive<u32> = 1..=1000;
+
+    /// Clamp the latency into [`Self::LATENCY_RANGE_MS`], and fall back to the default channels
+    /// for a half with no channels, since the module can't be loaded without them.
+    fn clamped(mut self) -> Self {
+        self.latency_ms = self.latency_ms.clamp(
+            *Self::LATENCY_RANGE_MS.start(),
+            *Self::LATENCY_RANGE_MS.end(),
+        );
+        for channels in [&mut self.capture_channels, &mut self.playback_channels] {
+            channels.retain(|channel| !channel.trim().is_empty());
+            if channels.is_empty() {
+                *channels = Self::default().capture_channels;
+            }
+        }
+        self
+    }
+}
+
+impl Default for LoopbackConfig {
+    fn default() -> Self {
+        Self {
+            latency_ms: 10,
+            capture_channels: vec!["FL".to_owned(), "FR".to_owned()],
+            playback_channels: vec!["FL".to_owned(), "FR".to_owned()],
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct ApplicationId(Ulid);
 
@@ -1423,6 +3255,9 @@ pub struct Application {
     pub binary: String,
     pub icon_name: String,
     pub exceptions: Vec<EndpointDescriptor>,
+    /// Streams that are left out of the endpoint, matched by their media name and title
+    #[serde(default)]
+    pub excluded_streams: Vec<StreamMatcher>,
 }
 
 impl Application {
@@ -1440,6 +3275,7 @@ impl Application {
             binary,
             icon_name,
             exceptions: Vec::new(),
+            excluded_streams: Vec::new(),
         }
     }
 
@@ -1449,26 +3285,58 @@ impl Application {
             && identifier.binary_name.as_ref() == Some(&self.binary)
     }
 
+    pub fn is_stream_excluded(&self, identifier: &NodeIdentifier) -> bool {
+        self.excluded_streams
+            .iter()
+            .any(|matcher| matcher.matches(identifier))
+    }
+
     pub fn name_with_tag(&self) -> String {
         // Uses unicode "fullwidth" brackets which I personally think look nicer
         format!("［App］{}", self.name)
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+/// The display name of a media role endpoint
+pub fn media_role_name_with_tag(role: MediaRole) -> String {
+    format!("［Role］{}", role.name())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct DeviceId(Ulid);
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Device;
 
-fn average_volumes<'a>(volumes: impl IntoIterator<Item = &'a f32>) -> f32 {
+/// The messages to connect or disconnect two endpoints, locking or unlocking the link according to
+/// the settings, the same as connecting them in the UI.
+pub fn set_link_messages(
+    source: EndpointDescriptor,
+    sink: EndpointDescriptor,
+    connect: bool,
+    settings: &SonusmixSettings,
+) -> Vec<SonusmixMsg> {
+    let mut messages = vec![if connect {
+        SonusmixMsg::Link(source, sink)
+    } else {
+        SonusmixMsg::RemoveLink(source, sink)
+    }];
+    if settings.lock_new_link(source, sink) {
+        messages.push(SonusmixMsg::SetLinkLocked(source, sink, connect));
+    }
+    messages
+}
+
+/// Average volumes by their positions on the volume sliders, so that the average is where the
+/// user would expect it to be.
+fn average_volumes<'a>(volumes: impl IntoIterator<Item = &'a f32>, curve: FaderCurve) -> f32 {
     let mut count: usize = 0;
     let mut total = 0.0;
     for volume in volumes {
         count += 1;
-        total += volume.powf(1.0 / 3.0);
+        total += curve.volume_to_slider(*volume);
     }
-    (total / count.max(1) as f32).powf(3.0)
+    curve.slider_to_volume(total / count.max(1) as f64)
 }
 
 fn volumes_mixed<'a>(volumes: impl IntoIterator<Item = &'a f32>) -> bool {
@@ -1548,10 +3416,32 @@ fn are_endpoints_connected(
     iter.all(|x| x == Some(first)).then_some(first)
 }
 
+/// Like [`are_endpoints_connected`], but for every hop of a link. A link is only completely
+/// connected if every hop is.
+fn are_hops_connected(
+    hops: &[(Vec<&PwNode>, Vec<&PwNode>)],
+    node_links: &HashMap<(u32, u32), Vec<&PwLink>>,
+) -> Option<bool> {
+    let mut iter = hops
+        .iter()
+        .map(|(source, sink)| are_endpoints_connected(source, sink, node_links));
+    let first = iter.next()??;
+    iter.all(|x| x == Some(first)).then_some(first)
+}
+
+/// Every pair of nodes that should be connected for the hops of a link.
+fn hop_node_pairs<'a, 'b>(
+    hops: &'a [(Vec<&'b PwNode>, Vec<&'b PwNode>)],
+) -> impl Iterator<Item = (&'a &'b PwNode, &'a &'b PwNode)> {
+    hops.iter()
+        .flat_map(|(source, sink)| source.iter().cartesian_product(sink.iter()))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::pipewire_api::object::{Link, *};
+    use crate::pipewire_api::{DeviceProfile, GroupNode as PwGroupNode};
 
     /// Basic setup for a graph:
     ///
@@ -1567,11 +3457,15 @@ mod tests {
 
         let pipewire_state = Graph {
             group_nodes: HashMap::new(),
+            send_nodes: HashMap::new(),
+            loopbacks: HashMap::new(),
             clients: HashMap::from([(0, client_of_node); 1]),
             devices: HashMap::new(),
             nodes: HashMap::from([(1, pipewire_node); 1]),
             ports: HashMap::from([(2, port_of_node); 1]),
             links: HashMap::new(),
+            default_sink: None,
+            default_source: None,
         };
 
         let sonusmix_node = EndpointDescriptor::EphemeralNode(1, PortKind::Source);
@@ -1586,6 +3480,28 @@ mod tests {
             applications: HashMap::new(),
             devices: HashMap::new(),
             group_nodes: IndexMap::new(),
+            loopbacks: IndexMap::new(),
+            locked_device_profiles: HashMap::new(),
+            pending_device_profiles: HashMap::new(),
+            device_rebinds: Vec::new(),
+            recordings: HashMap::new(),
+            midi_mappings: Vec::new(),
+            midi_learn: None,
+            primary_endpoint: None,
+            sections: IndexMap::new(),
+            activity_log: VecDeque::new(),
+            known_nodes: HashSet::new(),
+            reverted_changes: Vec::new(),
+            lock_backoffs: HashMap::new(),
+            keep_fighting: HashSet::new(),
+            vcas: IndexMap::new(),
+            cue_endpoint: None,
+            soloed: Vec::new(),
+            dimmed_cue_links: Vec::new(),
+            mute_groups: IndexMap::new(),
+            all_sources_muted: None,
+            volume_ramps: HashMap::new(),
+            default_nodes: HashMap::new(),
         };
 
         (pipewire_state, sonusmix_state)
@@ -1637,11 +3553,15 @@ mod tests {
 
             Graph {
                 group_nodes: HashMap::new(),
+                send_nodes: HashMap::new(),
+                loopbacks: HashMap::new(),
                 clients,
                 devices: HashMap::new(),
                 nodes,
                 ports,
                 links,
+                default_sink: None,
+                default_source: None,
             }
         };
 
@@ -1656,6 +3576,8 @@ mod tests {
                 start: source_node,
                 end: sink_node,
                 state: LinkState::ConnectedUnlocked,
+                send: None,
+                temporary: false,
                 pending: false,
             };
 
@@ -1817,7 +3739,7 @@ mod tests {
         assert!(endpoint_nodes.get(&sonusmix_node).is_some());
 
         // Compare properties.
-        let pipewire_messages = sonusmix_state.diff_properties(&endpoint_nodes);
+        let pipewire_messages = sonusmix_state.diff_properties(&endpoint_nodes, &settings);
 
         assert!(pipewire_messages.is_empty());
     }
@@ -1861,7 +3783,7 @@ mod tests {
         assert!(endpoint_nodes.get(&sonusmix_node).is_some());
 
         // Compare properties.
-        let pipewire_messages = sonusmix_state.diff_properties(&endpoint_nodes);
+        let pipewire_messages = sonusmix_state.diff_properties(&endpoint_nodes, &settings);
 
         // message should be empty and sonusmix state should be updated
         assert!(pipewire_messages.is_empty());
@@ -1915,7 +3837,7 @@ mod tests {
         assert!(endpoint_nodes.get(&sonusmix_node).is_some());
 
         // Compare properties.
-        let pipewire_messages = sonusmix_state.diff_properties(&endpoint_nodes);
+        let pipewire_messages = sonusmix_state.diff_properties(&endpoint_nodes, &settings);
 
         let endpoint = sonusmix_state
             .endpoints
@@ -1937,7 +3859,10 @@ mod tests {
             // the sonusmix state should mark the volume as mixed.
             assert!(endpoint.volume_mixed);
             // create an average of both values
-            assert_eq!(endpoint.volume, average_volumes(&new_volume));
+            assert_eq!(
+                endpoint.volume,
+                average_volumes(&new_volume, settings.fader_curve)
+            );
             // message should be empty as there is nothing to do
             assert!(pipewire_messages.is_empty());
         }
@@ -1988,6 +3913,8 @@ mod tests {
             start: source,
             end: sink,
             state: LinkState::ConnectedUnlocked,
+            send: None,
+            temporary: false,
             pending: true,
         };
 
@@ -2063,6 +3990,8 @@ mod tests {
             start: source,
             end: sink,
             state: LinkState::DisconnectedLocked,
+            send: None,
+            temporary: false,
             pending: false,
         };
 
@@ -2109,6 +4038,8 @@ mod tests {
             start: source,
             end: sink,
             state: LinkState::ConnectedLocked,
+            send: None,
+            temporary: false,
             pending: false,
         };
 
@@ -2176,4 +4107,1927 @@ mod tests {
         assert!(messages.is_empty());
         assert!(sonusmix_state.links.is_empty());
     }
+
+    #[test]
+    fn loopback_halves_resolve_by_kind() {
+        let settings = SonusmixSettings::default();
+        let mut sonusmix_state = SonusmixState::default();
+        let (msg, messages) = sonusmix_state.update(
+            &Graph::default(),
+            SonusmixMsg::AddLoopback("Test".to_owned(), LoopbackConfig::default()),
+            &settings,
+        );
+        let Some(SonusmixOutputMsg::LoopbackAdded(id)) = msg else {
+            panic!("Loopback was not added");
+        };
+        assert!(matches!(
+            messages.as_slice(),
+            [ToPipewireMessage::CreateLoopback(_, ulid, _)] if *ulid == id.0
+        ));
+
+        let mut capture_node = Node::new_test(1, EndpointId::Client(0));
+        capture_node.ports = vec![(3, PortKind::Sink, false)];
+        let mut playback_node = Node::new_test(2, EndpointId::Client(0));
+        playback_node.ports = vec![(4, PortKind::Source, false)];
+        let pipewire_state = Graph {
+            loopbacks: HashMap::from([(id.0, LoopbackNode::new_test(Some(1), Some(2)))]),
+            nodes: HashMap::from([(1, capture_node), (2, playback_node)]),
+            ..Default::default()
+        };
+
+        let endpoint_nodes = sonusmix_state.diff_nodes(&pipewire_state, &settings);
+        let sink = endpoint_nodes
+            .get(&EndpointDescriptor::Loopback(id, PortKind::Sink))
+            .expect("Capture half was not resolved");
+        assert_eq!(sink.iter().map(|node| node.id).collect::<Vec<_>>(), [1]);
+        let source = endpoint_nodes
+            .get(&EndpointDescriptor::Loopback(id, PortKind::Source))
+            .expect("Playback half was not resolved");
+        assert_eq!(source.iter().map(|node| node.id).collect::<Vec<_>>(), [2]);
+
+        // Both halves exist, so the loopback should not be created again
+        assert!(sonusmix_state.diff_loopbacks(&endpoint_nodes).is_empty());
+        assert!(!sonusmix_state.loopbacks[&id].pending);
+    }
+
+    #[test]
+    fn diff_loopbacks_recreates_missing_loopback() {
+        let settings = SonusmixSettings::default();
+        let mut sonusmix_state = SonusmixState::default();
+        let (msg, _) = sonusmix_state.update(
+            &Graph::default(),
+            SonusmixMsg::AddLoopback("Test".to_owned(), LoopbackConfig::default()),
+            &settings,
+        );
+        let Some(SonusmixOutputMsg::LoopbackAdded(id)) = msg else {
+            panic!("Loopback was not added");
+        };
+        let pipewire_state = Graph::default();
+
+        // While the loopback is pending, it should not be created again
+        let endpoint_nodes = sonusmix_state.diff_nodes(&pipewire_state, &settings);
+        assert!(sonusmix_state.diff_loopbacks(&endpoint_nodes).is_empty());
+
+        // Once it is no longer pending, a missing loopback should be created again
+        sonusmix_state.loopbacks[&id].pending = false;
+        let messages = sonusmix_state.diff_loopbacks(&endpoint_nodes);
+        assert!(matches!(
+            messages.as_slice(),
+            [ToPipewireMessage::CreateLoopback(_, ulid, _)] if *ulid == id.0
+        ));
+
+        // Removing either half removes the whole loopback
+        let (msg, messages) = sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::RemoveEndpoint(EndpointDescriptor::Loopback(id, PortKind::Source)),
+            &settings,
+        );
+        assert!(matches!(msg, Some(SonusmixOutputMsg::LoopbackRemoved(removed)) if removed == id));
+        assert_eq!(messages, [ToPipewireMessage::RemoveLoopback(id.0)]);
+        assert!(sonusmix_state.endpoints.is_empty());
+        assert!(sonusmix_state.active_sinks.is_empty());
+    }
+
+    #[test]
+    fn loopback_config_is_clamped() {
+        let settings = SonusmixSettings::default();
+        let mut sonusmix_state = SonusmixState::default();
+        let (msg, messages) = sonusmix_state.update(
+            &Graph::default(),
+            SonusmixMsg::AddLoopback(
+                "Test".to_owned(),
+                LoopbackConfig {
+                    latency_ms: u32::MAX,
+                    capture_channels: Vec::new(),
+                    playback_channels: vec![" ".to_owned(), "MONO".to_owned()],
+                },
+            ),
+            &settings,
+        );
+        let Some(SonusmixOutputMsg::LoopbackAdded(id)) = msg else {
+            panic!("Loopback was not added");
+        };
+        let expected = LoopbackConfig {
+            latency_ms: 1000,
+            capture_channels: LoopbackConfig::default().capture_channels,
+            playback_channels: vec!["MONO".to_owned()],
+        };
+        assert!(matches!(
+            messages.as_slice(),
+            [ToPipewireMessage::CreateLoopback(_, _, config)] if *config == expected
+        ));
+
+        sonusmix_state.update(
+            &Graph::default(),
+            SonusmixMsg::ChangeLoopbackConfig(
+                id,
+                LoopbackConfig {
+                    latency_ms: 0,
+                    ..expected
+                },
+            ),
+            &settings,
+        );
+        assert_eq!(sonusmix_state.loopbacks[&id].config.latency_ms, 1);
+    }
+
+    #[test]
+    fn device_profile_switch_moves_endpoint_to_new_node() {
+        let settings = SonusmixSettings::default();
+        let (mut pipewire_state, mut sonusmix_state) = advanced_graph_ephermal_node_setup();
+        let old_sink = EndpointDescriptor::EphemeralNode(2, PortKind::Sink);
+        pipewire_state.nodes.get_mut(&2).unwrap().endpoint = EndpointId::Device {
+            id: 10,
+            device_index: Some(0),
+        };
+        sonusmix_state.switch_device_profile(10, 1, &pipewire_state);
+
+        // The old node still exists, so nothing should happen yet
+        assert!(sonusmix_state
+            .diff_device_rebinds(&pipewire_state)
+            .is_empty());
+
+        // The device replaces its node with a new one
+        pipewire_state.nodes.remove(&2);
+        let mut new_node = Node::new_test(
+            7,
+            EndpointId::Device {
+                id: 10,
+                device_index: Some(0),
+            },
+        );
+        new_node.ports = vec![(8, PortKind::Sink, false)];
+        pipewire_state.nodes.insert(7, new_node);
+
+        let new_sink = EndpointDescriptor::EphemeralNode(7, PortKind::Sink);
+        let output_messages = sonusmix_state.diff_device_rebinds(&pipewire_state);
+        assert!(matches!(
+            output_messages.as_slice(),
+            [SonusmixOutputMsg::EndpointReplaced(old, new)] if *old == old_sink && *new == new_sink
+        ));
+        assert!(!sonusmix_state.endpoints.contains_key(&old_sink));
+        assert_eq!(sonusmix_state.endpoints[&new_sink].descriptor, new_sink);
+        assert_eq!(sonusmix_state.active_sinks, [new_sink]);
+        assert_eq!(sonusmix_state.links[0].end, new_sink);
+        assert!(sonusmix_state.device_rebinds.is_empty());
+
+        let endpoint_nodes = sonusmix_state.diff_nodes(&pipewire_state, &settings);
+        assert!(endpoint_nodes.contains_key(&new_sink));
+    }
+
+    #[test]
+    fn locked_device_profile_is_restored() {
+        let profile = |index, name: &str| DeviceProfile {
+            index,
+            name: name.to_owned(),
+            description: name.to_owned(),
+            available: true,
+        };
+        let mut pipewire_state = Graph::default();
+        pipewire_state.devices.insert(
+            10,
+            Device::new_test(
+                10,
+                vec![
+                    profile(0, "off"),
+                    profile(1, "output:analog-stereo"),
+                    profile(2, "output:hdmi-stereo"),
+                ],
+                Some(0),
+            ),
+        );
+        let mut sonusmix_state = SonusmixState::default();
+        sonusmix_state.locked_device_profiles.insert(
+            "TESTING DEVICE".to_owned(),
+            "output:analog-stereo".to_owned(),
+        );
+
+        let messages = sonusmix_state.diff_device_profiles(&pipewire_state);
+        assert_eq!(messages, [ToPipewireMessage::SetDeviceProfile(10, 1)]);
+
+        // The switch is pending, so it should not be requested again
+        assert!(sonusmix_state
+            .diff_device_profiles(&pipewire_state)
+            .is_empty());
+
+        // If the device ignores the request, it is made again after a while
+        sonusmix_state
+            .pending_device_profiles
+            .get_mut(&10)
+            .unwrap()
+            .requested_at -= DEVICE_PROFILE_TIMEOUT;
+        let messages = sonusmix_state.diff_device_profiles(&pipewire_state);
+        assert_eq!(messages, [ToPipewireMessage::SetDeviceProfile(10, 1)]);
+
+        // If it switches to another profile instead, it is made again right away
+        pipewire_state.devices.get_mut(&10).unwrap().active_profile = Some(2);
+        let messages = sonusmix_state.diff_device_profiles(&pipewire_state);
+        assert_eq!(messages, [ToPipewireMessage::SetDeviceProfile(10, 1)]);
+
+        // Once the profile has switched, there is nothing left to do
+        pipewire_state.devices.get_mut(&10).unwrap().active_profile = Some(1);
+        assert!(sonusmix_state
+            .diff_device_profiles(&pipewire_state)
+            .is_empty());
+        assert!(sonusmix_state.pending_device_profiles.is_empty());
+    }
+
+    #[test]
+    fn recording_stops_when_endpoint_removed() {
+        let settings = SonusmixSettings::default();
+        let mut sonusmix_state = SonusmixState::default();
+        let (msg, _) = sonusmix_state.update(
+            &Graph::default(),
+            SonusmixMsg::AddLoopback("Test".to_owned(), LoopbackConfig::default()),
+            &settings,
+        );
+        let Some(SonusmixOutputMsg::LoopbackAdded(id)) = msg else {
+            panic!("Loopback was not added");
+        };
+        let mut capture_node = Node::new_test(1, EndpointId::Client(0));
+        capture_node.ports = vec![(3, PortKind::Sink, false)];
+        let mut playback_node = Node::new_test(2, EndpointId::Client(0));
+        playback_node.ports = vec![(4, PortKind::Source, false)];
+        let pipewire_state = Graph {
+            loopbacks: HashMap::from([(id.0, LoopbackNode::new_test(Some(1), Some(2)))]),
+            nodes: HashMap::from([(1, capture_node), (2, playback_node)]),
+            ..Default::default()
+        };
+
+        let sink = EndpointDescriptor::Loopback(id, PortKind::Sink);
+        let (_, messages) = sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::StartRecording(sink, PathBuf::from("sink.wav")),
+            &settings,
+        );
+        assert!(matches!(
+            messages.as_slice(),
+            [ToPipewireMessage::StartRecording { node_ids, .. }] if node_ids == &[1]
+        ));
+        let source = EndpointDescriptor::Loopback(id, PortKind::Source);
+        let (_, messages) = sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::StartRecording(source, PathBuf::from("source.wav")),
+            &settings,
+        );
+        assert!(matches!(
+            messages.as_slice(),
+            [ToPipewireMessage::StartRecording { node_ids, .. }] if node_ids == &[2]
+        ));
+
+        // Starting again while already recording does nothing
+        let (_, messages) = sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::StartRecording(source, PathBuf::from("again.wav")),
+            &settings,
+        );
+        assert!(messages.is_empty());
+
+        let recording_ids =
+            [sink, source].map(|descriptor| sonusmix_state.recordings[&descriptor].id);
+        let (_, messages) = sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::RemoveEndpoint(source),
+            &settings,
+        );
+        assert_eq!(
+            messages,
+            [
+                ToPipewireMessage::StopRecording(recording_ids[0]),
+                ToPipewireMessage::StopRecording(recording_ids[1]),
+                ToPipewireMessage::RemoveLoopback(id.0),
+            ]
+        );
+        assert!(sonusmix_state.recordings.is_empty());
+    }
+
+    #[test]
+    fn recording_includes_every_node() {
+        let settings = SonusmixSettings::default();
+        let (mut pipewire_state, mut sonusmix_state) = basic_graph_ephermal_node_setup();
+        for id in [3, 4] {
+            let mut node = Node::new_test(id, EndpointId::Client(0));
+            node.ports = vec![(id + 10, PortKind::Source, false)];
+            node.identifier.application_name = Some("Browser".to_owned());
+            node.identifier.binary_name = Some("browser".to_owned());
+            pipewire_state.nodes.insert(id, node);
+        }
+        let application = Application::new_inactive(
+            "Browser".to_owned(),
+            "browser".to_owned(),
+            "browser".to_owned(),
+            PortKind::Source,
+        );
+        let id = application.id;
+        sonusmix_state.applications.insert(id, application);
+        sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::AddApplication(id, PortKind::Source),
+            &settings,
+        );
+
+        let (_, messages) = sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::StartRecording(
+                EndpointDescriptor::Application(id, PortKind::Source),
+                PathBuf::from("browser.wav"),
+            ),
+            &settings,
+        );
+        let [ToPipewireMessage::StartRecording { node_ids, .. }] = messages.as_slice() else {
+            panic!("Recording was not started: {messages:?}");
+        };
+        let mut node_ids = node_ids.clone();
+        node_ids.sort();
+        assert_eq!(node_ids, [3, 4]);
+    }
+
+    #[test]
+    fn midi_learn_and_input() {
+        let settings = SonusmixSettings::default();
+        let (pipewire_state, mut sonusmix_state) = basic_graph_ephermal_node_setup();
+        let endpoint = EndpointDescriptor::EphemeralNode(1, PortKind::Source);
+        let note_on = MidiEvent::NoteOn {
+            channel: 0,
+            note: 36,
+            velocity: 127,
+        };
+        let fader = |value| MidiEvent::ControlChange {
+            channel: 0,
+            controller: 7,
+            value,
+        };
+
+        sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::StartMidiLearn(MidiAction::Mute(endpoint)),
+            &settings,
+        );
+        // The first event is learned, and does not trigger the action
+        let (_, messages) = sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::MidiInput(vec![note_on]),
+            &settings,
+        );
+        assert!(messages.is_empty());
+        assert_eq!(sonusmix_state.midi_learn, None);
+        assert_eq!(
+            sonusmix_state.midi_mappings,
+            vec![MidiMapping {
+                control: MidiControl::Note {
+                    channel: 0,
+                    note: 36
+                },
+                action: MidiAction::Mute(endpoint),
+            }]
+        );
+
+        // Pressing the button toggles mute, releasing it does nothing
+        let (_, messages) = sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::MidiInput(vec![
+                note_on,
+                MidiEvent::NoteOff {
+                    channel: 0,
+                    note: 36,
+                },
+            ]),
+            &settings,
+        );
+        assert_eq!(messages, vec![ToPipewireMessage::NodeMute(1, true)]);
+        assert_eq!(
+            sonusmix_state.endpoints[&endpoint]
+                .volume_locked_muted
+                .is_muted(),
+            Some(true)
+        );
+
+        // Another control can be learned for a different action
+        sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::StartMidiLearn(MidiAction::Volume(endpoint)),
+            &settings,
+        );
+        sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::MidiInput(vec![fader(0)]),
+            &settings,
+        );
+        assert_eq!(sonusmix_state.midi_mappings.len(), 2);
+        sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::MidiInput(vec![fader(127)]),
+            &settings,
+        );
+        assert_eq!(
+            sonusmix_state.endpoints[&endpoint].volume,
+            settings.max_volume()
+        );
+
+        // Mappings are forgotten along with their endpoint
+        sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::RemoveEndpoint(endpoint),
+            &settings,
+        );
+        assert!(sonusmix_state.midi_mappings.is_empty());
+    }
+
+    #[test]
+    fn push_to_talk() {
+        let mut settings = SonusmixSettings::default();
+        let (pipewire_state, mut sonusmix_state) = basic_graph_ephermal_node_setup();
+        let endpoint = EndpointDescriptor::EphemeralNode(1, PortKind::Source);
+
+        // The endpoint is muted except while the key is held
+        let (_, messages) = sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::SetPushToTalk(endpoint, Some(PushToTalkMode::PushToTalk)),
+            &settings,
+        );
+        assert_eq!(messages, vec![ToPipewireMessage::NodeMute(1, true)]);
+        let (_, messages) = sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::PushToTalk(endpoint, true),
+            &settings,
+        );
+        assert_eq!(messages, vec![ToPipewireMessage::NodeMute(1, false)]);
+        let (_, messages) = sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::PushToTalk(endpoint, false),
+            &settings,
+        );
+        assert_eq!(messages, vec![ToPipewireMessage::NodeMute(1, true)]);
+
+        // With a release delay, releasing the key takes effect later
+        settings.push_to_talk_release_delay_ms = 200;
+        sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::PushToTalk(endpoint, true),
+            &settings,
+        );
+        let (_, messages) = sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::PushToTalk(endpoint, false),
+            &settings,
+        );
+        assert!(messages.is_empty());
+        let (_, messages) = sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::ReleasePushToTalk(endpoint),
+            &settings,
+        );
+        assert_eq!(messages, vec![ToPipewireMessage::NodeMute(1, true)]);
+
+        // A locked endpoint is kept in its push to talk state rather than the locked mute state
+        let endpoint_state = sonusmix_state.endpoints.get_mut(&endpoint).unwrap();
+        endpoint_state.volume_locked_muted = VolumeLockMuteState::UnmutedLocked;
+        endpoint_state.volume_pending = false;
+        let (_, messages) = sonusmix_state.diff(&pipewire_state, &settings, true);
+        assert!(messages.contains(&ToPipewireMessage::NodeMute(1, true)));
+        assert_eq!(
+            sonusmix_state.endpoints[&endpoint].volume_locked_muted,
+            VolumeLockMuteState::MutedLocked
+        );
+    }
+
+    #[test]
+    fn volume_limits() {
+        let settings = SonusmixSettings::default();
+        let (mut pipewire_state, mut sonusmix_state) = basic_graph_ephermal_node_setup();
+        let endpoint = EndpointDescriptor::EphemeralNode(1, PortKind::Source);
+        let node = pipewire_state.nodes.get_mut(&1).unwrap();
+        node.channel_volumes = vec![1.0, 1.0];
+        node.identifier.application_name = Some("App".to_owned());
+        node.identifier.binary_name = Some("app".to_owned());
+
+        // Lowering the max volume lowers the volume
+        let (_, messages) = sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::SetMaxVolume(endpoint, Some(0.8)),
+            &settings,
+        );
+        assert_eq!(
+            messages,
+            vec![ToPipewireMessage::NodeVolume(1, vec![0.8; 2])]
+        );
+        assert_eq!(sonusmix_state.endpoints[&endpoint].volume, 0.8);
+
+        // The volume can't be set past the max volume
+        let (_, messages) = sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::SetVolume(endpoint, 1.5),
+            &settings,
+        );
+        assert_eq!(
+            messages,
+            vec![ToPipewireMessage::NodeVolume(1, vec![0.8; 2])]
+        );
+
+        // Other programs can't raise the volume past the max volume while it is locked
+        sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::SetVolumeLocked(endpoint, true),
+            &settings,
+        );
+        sonusmix_state
+            .endpoints
+            .get_mut(&endpoint)
+            .unwrap()
+            .volume_pending = false;
+        pipewire_state.nodes.get_mut(&1).unwrap().channel_volumes = vec![1.5, 1.5];
+        let endpoint_nodes = sonusmix_state.diff_nodes(&pipewire_state, &settings);
+        let messages = sonusmix_state.diff_properties(&endpoint_nodes, &settings);
+        assert_eq!(
+            messages,
+            vec![ToPipewireMessage::NodeVolume(1, vec![0.8; 2])]
+        );
+
+        // Application endpoints are set to their start volume when they reappear
+        sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::RemoveEndpoint(endpoint),
+            &settings,
+        );
+        let application = *sonusmix_state.applications.keys().next().unwrap();
+        let endpoint = EndpointDescriptor::Application(application, PortKind::Source);
+        sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::AddApplication(application, PortKind::Source),
+            &settings,
+        );
+        sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::SetStartVolume(endpoint, Some(0.5)),
+            &settings,
+        );
+        let node = pipewire_state.nodes.remove(&1).unwrap();
+        sonusmix_state.diff_nodes(&pipewire_state, &settings);
+        assert!(sonusmix_state.endpoints[&endpoint].is_placeholder);
+
+        pipewire_state.nodes.insert(1, node);
+        let endpoint_nodes = sonusmix_state.diff_nodes(&pipewire_state, &settings);
+        let messages = sonusmix_state.diff_properties(&endpoint_nodes, &settings);
+        assert_eq!(
+            messages,
+            vec![ToPipewireMessage::NodeVolume(1, vec![0.5; 2])]
+        );
+        assert_eq!(sonusmix_state.endpoints[&endpoint].volume, 0.5);
+        assert!(sonusmix_state.endpoints[&endpoint].volume_pending);
+    }
+
+    #[test]
+    fn endpoint_organization() {
+        let settings = SonusmixSettings::default();
+        let (pipewire_state, mut sonusmix_state) = basic_graph_ephermal_node_setup();
+        let [a, b, c] = [1, 2, 3].map(|id| EndpointDescriptor::EphemeralNode(id, PortKind::Source));
+        sonusmix_state.active_sources = vec![a, b, c];
+        for endpoint in [b, c] {
+            sonusmix_state
+                .endpoints
+                .insert(endpoint, Endpoint::new_test(endpoint));
+        }
+
+        sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::AddSection("Mics".to_owned(), PortKind::Source),
+            &settings,
+        );
+        let section = *sonusmix_state.sections.keys().next().unwrap();
+        sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::SetEndpointSection(c, Some(section)),
+            &settings,
+        );
+        assert_eq!(
+            sonusmix_state.list_layout(PortKind::Source),
+            vec![(None, vec![a, b]), (Some(section), vec![c])]
+        );
+
+        // Moving an endpoint onto another moves it into the other endpoint's section
+        sonusmix_state.update(&pipewire_state, SonusmixMsg::MoveEndpoint(a, c), &settings);
+        assert_eq!(sonusmix_state.active_sources, vec![b, c, a]);
+        assert_eq!(
+            sonusmix_state.list_layout(PortKind::Source),
+            vec![(None, vec![b]), (Some(section), vec![c, a])]
+        );
+
+        // Sources can't be put in sections of the sinks list
+        sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::AddSection("Speakers".to_owned(), PortKind::Sink),
+            &settings,
+        );
+        let sink_section = *sonusmix_state.sections.keys().nth(1).unwrap();
+        sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::SetEndpointSection(b, Some(sink_section)),
+            &settings,
+        );
+        assert_eq!(sonusmix_state.endpoints[&b].section, None);
+        assert_eq!(
+            sonusmix_state.list_layout(PortKind::Sink),
+            vec![(None, vec![]), (Some(sink_section), vec![])]
+        );
+
+        // Removing a section keeps its endpoints
+        sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::RemoveSection(section),
+            &settings,
+        );
+        assert_eq!(
+            sonusmix_state.list_layout(PortKind::Source),
+            vec![(None, vec![b, c, a])]
+        );
+    }
+
+    #[test]
+    fn node_resolutions() {
+        let settings = SonusmixSettings::default();
+        let (mut pipewire_state, mut sonusmix_state) = basic_graph_ephermal_node_setup();
+        for id in [3, 4, 5] {
+            let mut node = Node::new_test(id, EndpointId::Client(0));
+            node.ports = vec![(id + 10, PortKind::Source, false)];
+            if id != 5 {
+                node.identifier.application_name = Some("App".to_owned());
+                node.identifier.binary_name = Some("app".to_owned());
+            }
+            pipewire_state.nodes.insert(id, node);
+        }
+
+        // Node 3 belongs to the application, node 4 is an exception of it, and node 5 is unused
+        let mut application = Application::new_inactive(
+            "App".to_owned(),
+            "app".to_owned(),
+            "app".to_owned(),
+            PortKind::Source,
+        );
+        application.is_active = true;
+        let exception = EndpointDescriptor::EphemeralNode(4, PortKind::Source);
+        application.exceptions.push(exception);
+        let application_desc = EndpointDescriptor::Application(application.id, PortKind::Source);
+        let application_id = application.id;
+        sonusmix_state
+            .applications
+            .insert(application.id, application);
+        for endpoint in [application_desc, exception] {
+            sonusmix_state
+                .endpoints
+                .insert(endpoint, Endpoint::new_test(endpoint));
+        }
+        sonusmix_state.candidates = vec![(
+            5,
+            PortKind::Source,
+            pipewire_state.nodes[&5].identifier.clone(),
+        )];
+
+        let resolutions = sonusmix_state.node_resolutions(&pipewire_state, &settings);
+        assert_eq!(
+            resolutions[&1],
+            vec![NodeResolution::Endpoint(EndpointDescriptor::EphemeralNode(
+                1,
+                PortKind::Source
+            ))]
+        );
+        assert_eq!(
+            resolutions[&3],
+            vec![NodeResolution::Endpoint(application_desc)]
+        );
+        assert_eq!(
+            resolutions[&4],
+            vec![
+                NodeResolution::Endpoint(exception),
+                NodeResolution::Exception {
+                    application: application_id,
+                    endpoint: exception
+                }
+            ]
+        );
+        assert_eq!(
+            resolutions[&5],
+            vec![NodeResolution::Candidate(PortKind::Source)]
+        );
+    }
+
+    #[test]
+    fn export_and_import_config() {
+        let settings = SonusmixSettings::default();
+        let (mut pipewire_state, mut sonusmix_state) = basic_graph_ephermal_node_setup();
+        pipewire_state.nodes.get_mut(&1).unwrap().identifier =
+            NodeIdentifier::new_test().with_node_name("mic");
+        let mut sink_node = Node::new_test(3, EndpointId::Client(0));
+        sink_node.ports = vec![(4, PortKind::Sink, false)];
+        sink_node.identifier = NodeIdentifier::new_test().with_node_name("speakers");
+        pipewire_state.nodes.insert(3, sink_node);
+
+        let source = EndpointDescriptor::EphemeralNode(1, PortKind::Source);
+        let sink = EndpointDescriptor::EphemeralNode(3, PortKind::Sink);
+        for message in [
+            SonusmixMsg::AddEphemeralNode(3, PortKind::Sink),
+            SonusmixMsg::AddGroupNode("Music".to_owned(), GroupNodeKind::Source),
+            SonusmixMsg::SetVolume(source, 0.5),
+            SonusmixMsg::SetVolumeLocked(source, true),
+            SonusmixMsg::SetLinkLocked(source, sink, true),
+        ] {
+            sonusmix_state.update(&pipewire_state, message, &settings);
+        }
+
+        let config = sonusmix_state.export_config(&pipewire_state, &settings);
+        let config: PortableConfig = ron::from_str(&ron::to_string(&config).unwrap()).unwrap();
+        assert_eq!(config.endpoints.len(), 3);
+
+        // The nodes have different IDs in another Pipewire session
+        let mut other_pipewire_state = pipewire_state.clone();
+        let mut mic = other_pipewire_state.nodes.remove(&1).unwrap();
+        mic.id = 11;
+        other_pipewire_state.nodes.insert(11, mic);
+        let mut other_state = SonusmixState::default();
+
+        let preview = other_state.preview_import(&config, &other_pipewire_state, &settings);
+        assert_eq!(preview.added.len(), 3);
+        assert!(!preview.settings_changed);
+        other_state.update(
+            &other_pipewire_state,
+            SonusmixMsg::ImportConfig(Box::new(config.clone()), ImportMode::Merge),
+            &settings,
+        );
+
+        let source = EndpointDescriptor::EphemeralNode(11, PortKind::Source);
+        assert_eq!(other_state.active_sources, vec![source]);
+        assert_eq!(other_state.active_sinks, vec![sink]);
+        assert_eq!(other_state.endpoints[&source].volume, 0.5);
+        assert!(other_state.endpoints[&source]
+            .volume_locked_muted
+            .is_locked());
+        let group_node = other_state.group_nodes.values().next().unwrap();
+        assert_eq!(group_node.kind, GroupNodeKind::Source);
+        assert_eq!(other_state.links.len(), 1);
+        assert_eq!(other_state.links[0].start, source);
+        assert_eq!(other_state.links[0].end, sink);
+        assert_eq!(other_state.links[0].state, LinkState::DisconnectedLocked);
+
+        // Importing again finds the existing endpoints instead of adding new ones
+        let preview = other_state.preview_import(&config, &other_pipewire_state, &settings);
+        assert!(preview.added.is_empty());
+        assert_eq!(preview.existing.len(), 3);
+        let group_node_id = group_node.id;
+        let (_, messages) = other_state.update(
+            &other_pipewire_state,
+            SonusmixMsg::ImportConfig(Box::new(config.clone()), ImportMode::Replace),
+            &settings,
+        );
+        assert_eq!(other_state.active_sources.len(), 1);
+        // The group node is kept instead of being re-created
+        assert_eq!(
+            other_state.group_nodes.keys().collect::<Vec<_>>(),
+            vec![&group_node_id]
+        );
+        assert!(!messages.iter().any(|message| matches!(
+            message,
+            ToPipewireMessage::CreateGroupNode(..) | ToPipewireMessage::RemoveGroupNode(_)
+        )));
+
+        // Settings are only imported when replacing, which the preview points out
+        let other_settings = SonusmixSettings {
+            dim_cue_on_solo: !settings.dim_cue_on_solo,
+            ..settings.clone()
+        };
+        let preview = other_state.preview_import(&config, &other_pipewire_state, &other_settings);
+        assert!(preview.settings_changed);
+        assert!(preview.summary().contains("kept when merging"));
+    }
+
+    #[test]
+    fn export_and_import_excluded_streams() {
+        let settings = SonusmixSettings::default();
+        let (pipewire_state, mut sonusmix_state) = basic_graph_ephermal_node_setup();
+        let application = Application::new_inactive(
+            "Browser".to_owned(),
+            "browser".to_owned(),
+            "browser".to_owned(),
+            PortKind::Source,
+        );
+        let id = application.id;
+        sonusmix_state.applications.insert(id, application);
+        let matcher =
+            StreamMatcher::for_stream(&NodeIdentifier::new_test().with_media_name("Meeting"))
+                .expect("has a name");
+        for message in [
+            SonusmixMsg::AddApplication(id, PortKind::Source),
+            SonusmixMsg::SetStreamExcluded(id, matcher.clone(), true),
+        ] {
+            sonusmix_state.update(&pipewire_state, message, &settings);
+        }
+
+        let config = sonusmix_state.export_config(&pipewire_state, &settings);
+        let config: PortableConfig = ron::from_str(&ron::to_string(&config).unwrap()).unwrap();
+        let exported = config
+            .endpoints
+            .iter()
+            .find(|endpoint| {
+                matches!(
+                    endpoint.endpoint,
+                    portable::PortableEndpointRef::Application { .. }
+                )
+            })
+            .expect("application was exported");
+        assert_eq!(exported.excluded_streams, vec![matcher.clone()]);
+
+        let mut other_state = SonusmixState::default();
+        other_state.update(
+            &pipewire_state,
+            SonusmixMsg::ImportConfig(Box::new(config), ImportMode::Merge),
+            &settings,
+        );
+        let application = other_state
+            .applications
+            .values()
+            .find(|application| application.name == "Browser")
+            .expect("application was imported");
+        assert_eq!(application.excluded_streams, vec![matcher]);
+    }
+
+    /// The basic graph, where another client has changed the locked volume of the source
+    fn locked_volume_changed_setup() -> (Graph, SonusmixState, EndpointDescriptor) {
+        let (mut pipewire_state, mut sonusmix_state) = basic_graph_ephermal_node_setup();
+        let endpoint = EndpointDescriptor::EphemeralNode(1, PortKind::Source);
+        pipewire_state.nodes.get_mut(&1).unwrap().channel_volumes = vec![0.5, 0.5];
+        let endpoint_state = sonusmix_state.endpoints.get_mut(&endpoint).unwrap();
+        endpoint_state.volume = 1.0;
+        endpoint_state.volume_locked_muted = VolumeLockMuteState::UnmutedLocked;
+        (pipewire_state, sonusmix_state, endpoint)
+    }
+
+    /// Diff as if the last restored volume had been applied, and then changed back again
+    fn diff_changed_volume(
+        sonusmix_state: &mut SonusmixState,
+        pipewire_state: &Graph,
+        endpoint: EndpointDescriptor,
+        settings: &SonusmixSettings,
+    ) -> Vec<ToPipewireMessage> {
+        sonusmix_state
+            .endpoints
+            .get_mut(&endpoint)
+            .unwrap()
+            .volume_pending = false;
+        sonusmix_state.diff(pipewire_state, settings, true).1
+    }
+
+    #[test]
+    fn activity_log() {
+        let settings = SonusmixSettings::default();
+        let (pipewire_state, mut sonusmix_state, endpoint) = locked_volume_changed_setup();
+
+        // Locks on nodes that just appeared are applied without being logged
+        let (_, messages) = sonusmix_state.diff(&pipewire_state, &settings, true);
+        assert_eq!(
+            messages,
+            vec![ToPipewireMessage::NodeVolume(1, vec![1.0; 2])]
+        );
+        assert!(sonusmix_state.activity_log.is_empty());
+
+        // Changes to existing nodes are logged each time they are reverted
+        for repeat_count in 1..=3 {
+            let messages =
+                diff_changed_volume(&mut sonusmix_state, &pipewire_state, endpoint, &settings);
+            assert_eq!(
+                messages,
+                vec![ToPipewireMessage::NodeVolume(1, vec![1.0; 2])]
+            );
+            let entry = sonusmix_state.activity_log.back().unwrap();
+            assert_eq!(entry.target, LockTarget::Volume(endpoint));
+            assert_eq!(entry.repeat_count, repeat_count);
+        }
+        assert_eq!(sonusmix_state.activity_log.len(), 3);
+        assert!(sonusmix_state.activity_log[2].started_lock_fight());
+
+        // Diffs after other updates still see the old graph, so they don't log the change again
+        sonusmix_state
+            .endpoints
+            .get_mut(&endpoint)
+            .unwrap()
+            .volume_pending = false;
+        sonusmix_state.diff(&pipewire_state, &settings, false);
+        assert_eq!(sonusmix_state.activity_log.len(), 3);
+
+        let (_, messages) =
+            sonusmix_state.update(&pipewire_state, SonusmixMsg::ClearActivityLog, &settings);
+        assert!(messages.is_empty());
+        assert!(sonusmix_state.activity_log.is_empty());
+    }
+
+    #[test]
+    fn lock_fight_backoff() {
+        let settings = SonusmixSettings::default();
+        let (pipewire_state, mut sonusmix_state, endpoint) = locked_volume_changed_setup();
+        let target = LockTarget::Volume(endpoint);
+        let fight = |sonusmix_state: &mut SonusmixState| {
+            diff_changed_volume(sonusmix_state, &pipewire_state, endpoint, &settings)
+        };
+
+        // Another client changes the volume back every time it is restored
+        for _ in 0..4 {
+            fight(&mut sonusmix_state);
+        }
+        assert!(sonusmix_state.lock_backoffs[&target].is_active());
+        assert_eq!(sonusmix_state.lock_fights(endpoint).count(), 1);
+
+        // While backing off, the lock isn't restored
+        assert!(fight(&mut sonusmix_state).is_empty());
+
+        // Once the user chooses to keep fighting, the lock is restored immediately again
+        sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::KeepFighting(target),
+            &settings,
+        );
+        for _ in 0..4 {
+            assert_eq!(
+                fight(&mut sonusmix_state),
+                vec![ToPipewireMessage::NodeVolume(1, vec![1.0; 2])]
+            );
+        }
+        assert!(sonusmix_state.lock_backoffs.is_empty());
+
+        // Unlocking forgets about the fight
+        sonusmix_state.update(&pipewire_state, target.unlock_message(), &settings);
+        fight(&mut sonusmix_state);
+        assert!(sonusmix_state.keep_fighting.is_empty());
+    }
+
+    #[test]
+    fn group_node_changes_keep_streams() {
+        let settings = SonusmixSettings::default();
+        let mut sonusmix_state = SonusmixState::default();
+        let (msg, _) = sonusmix_state.update(
+            &Graph::default(),
+            SonusmixMsg::AddGroupNode("Group".to_owned(), GroupNodeKind::Duplex),
+            &settings,
+        );
+        let Some(SonusmixOutputMsg::EndpointAdded(endpoint @ EndpointDescriptor::GroupNode(id))) =
+            msg
+        else {
+            panic!("Group node was not added");
+        };
+
+        // An application is playing into the group node, which plays into a device
+        let mut group_node = Node::new_test(1, EndpointId::Client(0));
+        group_node.ports = vec![(3, PortKind::Sink, false), (4, PortKind::Source, false)];
+        let mut application_node = Node::new_test(2, EndpointId::Client(0));
+        application_node.ports = vec![(5, PortKind::Source, false)];
+        let mut device_node = Node::new_test(8, EndpointId::Client(0));
+        device_node.ports = vec![(9, PortKind::Sink, false)];
+        let mut pipewire_state = Graph {
+            clients: HashMap::from([(0, Client::new_test(0, false, vec![1, 2, 8]))]),
+            group_nodes: HashMap::from([(
+                id.0,
+                PwGroupNode::new_test(Some(1), GroupNodeKind::Duplex),
+            )]),
+            nodes: HashMap::from([(1, group_node), (2, application_node), (8, device_node)]),
+            links: HashMap::from([
+                (10, Link::new_test(10, 2, 5, 1, 3)),
+                (11, Link::new_test(11, 1, 4, 8, 9)),
+            ]),
+            ..Default::default()
+        };
+        sonusmix_state.diff(&pipewire_state, &settings, true);
+        assert_eq!(sonusmix_state.group_nodes[&id].pipewire_id, Some(1));
+
+        // Renaming changes the live node instead of re-creating it
+        let (_, messages) = sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::RenameEndpoint(endpoint, Some("Renamed".to_owned())),
+            &settings,
+        );
+        assert_eq!(
+            messages,
+            vec![ToPipewireMessage::RenameGroupNode(
+                id.0,
+                "Renamed".to_owned()
+            )]
+        );
+        assert_eq!(sonusmix_state.group_nodes[&id].replaced_pipewire_id, None);
+
+        // Changing the kind creates a new node, and keeps the old one until the new one exists.
+        // Changing it again before then replaces the new node, and the oldest node is still the
+        // one whose links are moved.
+        for kind in [GroupNodeKind::Source, GroupNodeKind::Sink] {
+            let (_, messages) = sonusmix_state.update(
+                &pipewire_state,
+                SonusmixMsg::ChangeGroupNodeKind(id, kind),
+                &settings,
+            );
+            assert_eq!(
+                messages,
+                vec![ToPipewireMessage::ReplaceGroupNode(
+                    "Renamed".to_owned(),
+                    id.0,
+                    kind
+                )]
+            );
+            assert_eq!(
+                sonusmix_state.group_nodes[&id].replaced_pipewire_id,
+                Some(1)
+            );
+        }
+        let (_, messages) = sonusmix_state.diff(&pipewire_state, &settings, true);
+        assert!(messages.is_empty());
+
+        // Once the new node exists, the old node's links are moved to it before it is removed.
+        // The new node has no source ports, so the link into the device isn't moved.
+        let mut new_group_node = Node::new_test(6, EndpointId::Client(0));
+        new_group_node.ports = vec![(7, PortKind::Sink, false)];
+        pipewire_state.nodes.insert(6, new_group_node);
+        pipewire_state
+            .group_nodes
+            .insert(id.0, PwGroupNode::new_test(Some(6), GroupNodeKind::Sink));
+        let (_, messages) = sonusmix_state.diff(&pipewire_state, &settings, true);
+        assert_eq!(
+            messages,
+            vec![
+                ToPipewireMessage::CreateNodeLinks {
+                    start_id: 2,
+                    end_id: 6
+                },
+                ToPipewireMessage::RemoveReplacedGroupNode(id.0),
+            ]
+        );
+        assert_eq!(sonusmix_state.group_nodes[&id].pipewire_id, Some(6));
+    }
+
+    #[test]
+    fn link_send_level() {
+        let (mut pipewire_state, mut sonusmix_state) = advanced_graph_ephermal_node_setup();
+        let settings = SonusmixSettings::default();
+        let source = sonusmix_state.active_sources[0];
+        let sink = sonusmix_state.active_sinks[0];
+        let diff_links = |sonusmix_state: &mut SonusmixState, pipewire_state: &Graph| {
+            let endpoint_nodes = sonusmix_state.diff_nodes(pipewire_state, &settings);
+            sonusmix_state.diff_links(pipewire_state, &endpoint_nodes)
+        };
+
+        // Setting a send level creates a gain node for the link
+        let (_, messages) = sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::SetLinkVolume(source, sink, 0.5),
+            &settings,
+        );
+        assert!(messages.is_empty());
+        let messages = diff_links(&mut sonusmix_state, &pipewire_state);
+        let [ToPipewireMessage::CreateSendNode(_, send_id)] = messages.as_slice() else {
+            panic!("Send node was not created: {messages:?}");
+        };
+        let send_id = *send_id;
+        assert!(diff_links(&mut sonusmix_state, &pipewire_state).is_empty());
+
+        // Once the gain node exists, the link is routed through it at the send level
+        let mut send_node = Node::new_test(7, EndpointId::Client(2));
+        send_node.ports = vec![(8, PortKind::Sink, false), (9, PortKind::Source, false)];
+        send_node.channel_volumes = vec![1.0, 1.0];
+        pipewire_state.nodes.insert(7, send_node);
+        pipewire_state.send_nodes.insert(
+            send_id,
+            PwGroupNode::new_test(Some(7), GroupNodeKind::Duplex),
+        );
+        assert_eq!(
+            diff_links(&mut sonusmix_state, &pipewire_state),
+            vec![
+                ToPipewireMessage::CreateNodeLinks {
+                    start_id: 1,
+                    end_id: 7
+                },
+                ToPipewireMessage::CreateNodeLinks {
+                    start_id: 7,
+                    end_id: 2
+                },
+                ToPipewireMessage::RemoveNodeLinks {
+                    start_id: 1,
+                    end_id: 2
+                },
+                ToPipewireMessage::NodeVolume(7, vec![0.5; 2]),
+                ToPipewireMessage::NodeMute(7, false),
+            ]
+        );
+        assert!(!sonusmix_state.candidates.iter().any(|(id, ..)| *id == 7));
+
+        // Simulate Pipewire applying the changes
+        pipewire_state.links = HashMap::from([
+            (10, Link::new_test(10, 1, 3, 7, 8)),
+            (11, Link::new_test(11, 7, 9, 2, 5)),
+        ]);
+        pipewire_state.nodes.get_mut(&7).unwrap().channel_volumes = vec![0.5, 0.5];
+        assert!(diff_links(&mut sonusmix_state, &pipewire_state).is_empty());
+        assert!(!sonusmix_state.links[0].pending);
+        assert_eq!(sonusmix_state.links[0].state, LinkState::ConnectedUnlocked);
+
+        // The gain is restored if something else changes it
+        pipewire_state.nodes.get_mut(&7).unwrap().channel_volumes = vec![1.0, 1.0];
+        assert_eq!(
+            diff_links(&mut sonusmix_state, &pipewire_state),
+            vec![
+                ToPipewireMessage::NodeVolume(7, vec![0.5; 2]),
+                ToPipewireMessage::NodeMute(7, false),
+            ]
+        );
+
+        // Removing the send level connects the endpoints directly again
+        let (_, messages) = sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::RemoveLinkSend(source, sink),
+            &settings,
+        );
+        assert_eq!(
+            messages,
+            vec![
+                ToPipewireMessage::CreateNodeLinks {
+                    start_id: 1,
+                    end_id: 2
+                },
+                ToPipewireMessage::RemoveSendNode(send_id),
+            ]
+        );
+        assert!(sonusmix_state.links[0].send.is_none());
+    }
+
+    #[test]
+    fn vca_gain_respects_max_volume() {
+        let settings = SonusmixSettings::default();
+        let (mut pipewire_state, mut sonusmix_state) = basic_graph_ephermal_node_setup();
+        let endpoint = EndpointDescriptor::EphemeralNode(1, PortKind::Source);
+        pipewire_state.nodes.get_mut(&1).unwrap().channel_volumes = vec![0.8, 0.8];
+        let endpoint_state = sonusmix_state.endpoints.get_mut(&endpoint).unwrap();
+        endpoint_state.volume = 0.8;
+        endpoint_state.max_volume = Some(0.8);
+
+        sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::AddVca("Music".to_owned()),
+            &settings,
+        );
+        let vca = *sonusmix_state.vcas.keys().next().unwrap();
+        sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::SetVcaMember(vca, endpoint, true),
+            &settings,
+        );
+
+        // Raising the VCA doesn't push the nodes past the max volume
+        let (_, messages) = sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::SetVcaGain(vca, VCA_MAX_DB),
+            &settings,
+        );
+        assert_eq!(
+            messages,
+            vec![ToPipewireMessage::NodeVolume(1, vec![0.8; 2])]
+        );
+
+        // Reading the capped volume back doesn't change the member's own volume
+        sonusmix_state
+            .endpoints
+            .get_mut(&endpoint)
+            .unwrap()
+            .volume_pending = false;
+        let endpoint_nodes = sonusmix_state.diff_nodes(&pipewire_state, &settings);
+        let messages = sonusmix_state.diff_properties(&endpoint_nodes, &settings);
+        assert!(messages.is_empty());
+        assert_eq!(sonusmix_state.endpoints[&endpoint].volume, 0.8);
+    }
+
+    #[test]
+    fn vca_scales_member_volumes() {
+        let settings = SonusmixSettings::default();
+        let (mut pipewire_state, mut sonusmix_state) = basic_graph_ephermal_node_setup();
+        let endpoint = EndpointDescriptor::EphemeralNode(1, PortKind::Source);
+        pipewire_state.nodes.get_mut(&1).unwrap().channel_volumes = vec![0.5, 0.5];
+        sonusmix_state.endpoints.get_mut(&endpoint).unwrap().volume = 0.5;
+
+        sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::AddVca("Music".to_owned()),
+            &settings,
+        );
+        let vca = *sonusmix_state.vcas.keys().next().unwrap();
+        sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::SetVcaMember(vca, endpoint, true),
+            &settings,
+        );
+        let (_, messages) = sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::SetVcaGain(vca, -20.0),
+            &settings,
+        );
+        let applied = 0.5 * settings::db_to_volume(-20.0);
+        assert_eq!(
+            messages,
+            vec![ToPipewireMessage::NodeVolume(1, vec![applied; 2])]
+        );
+
+        // Reading the scaled volume back doesn't change the member's own volume
+        pipewire_state.nodes.get_mut(&1).unwrap().channel_volumes = vec![applied; 2];
+        sonusmix_state
+            .endpoints
+            .get_mut(&endpoint)
+            .unwrap()
+            .volume_pending = false;
+        let endpoint_nodes = sonusmix_state.diff_nodes(&pipewire_state, &settings);
+        let messages = sonusmix_state.diff_properties(&endpoint_nodes, &settings);
+        assert!(messages.is_empty());
+        assert_eq!(sonusmix_state.endpoints[&endpoint].volume, 0.5);
+
+        // The member's own volume is scaled too
+        let (_, messages) = sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::SetVolume(endpoint, 1.0),
+            &settings,
+        );
+        assert_eq!(
+            messages,
+            vec![ToPipewireMessage::NodeVolume(
+                1,
+                vec![settings::db_to_volume(-20.0); 2]
+            )]
+        );
+
+        // The gain is kept within the fader's range, and an outside change read back through a
+        // VCA that is turned far down doesn't become a huge own volume
+        sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::SetVcaGain(vca, -100.0),
+            &settings,
+        );
+        assert_eq!(sonusmix_state.vcas[&vca].gain_db, VCA_MIN_DB);
+        pipewire_state.nodes.get_mut(&1).unwrap().channel_volumes = vec![1.0; 2];
+        sonusmix_state
+            .endpoints
+            .get_mut(&endpoint)
+            .unwrap()
+            .volume_pending = false;
+        let endpoint_nodes = sonusmix_state.diff_nodes(&pipewire_state, &settings);
+        sonusmix_state.diff_properties(&endpoint_nodes, &settings);
+        assert_eq!(
+            sonusmix_state.endpoints[&endpoint].volume,
+            settings.max_volume()
+        );
+        sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::SetVcaGain(vca, -20.0),
+            &settings,
+        );
+        sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::SetVolume(endpoint, 1.0),
+            &settings,
+        );
+        pipewire_state.nodes.get_mut(&1).unwrap().channel_volumes =
+            vec![settings::db_to_volume(-20.0); 2];
+
+        // Muting the VCA mutes its members until it is unmuted
+        let (_, messages) = sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::SetVcaMute(vca, true),
+            &settings,
+        );
+        assert!(messages.contains(&ToPipewireMessage::NodeMute(1, true)));
+        assert_eq!(
+            sonusmix_state.endpoints[&endpoint]
+                .volume_locked_muted
+                .is_muted(),
+            Some(false)
+        );
+        let (_, messages) = sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::SetVcaMute(vca, false),
+            &settings,
+        );
+        assert!(messages.contains(&ToPipewireMessage::NodeMute(1, false)));
+
+        // Removing the VCA sets its members back to their own volumes
+        let (_, messages) =
+            sonusmix_state.update(&pipewire_state, SonusmixMsg::RemoveVca(vca), &settings);
+        assert_eq!(
+            messages,
+            vec![ToPipewireMessage::NodeVolume(1, vec![1.0; 2])]
+        );
+    }
+
+    #[test]
+    fn solo_to_cue_endpoint() {
+        let mut settings = SonusmixSettings::default();
+        let (mut pipewire_state, mut sonusmix_state) = advanced_graph_ephermal_node_setup();
+        let source = EndpointDescriptor::EphemeralNode(1, PortKind::Source);
+        let cue = EndpointDescriptor::EphemeralNode(2, PortKind::Sink);
+        pipewire_state.links.clear();
+        sonusmix_state.links.clear();
+
+        // Sources can't be soloed until there is a cue endpoint
+        let (_, messages) = sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::SetSolo(source, true),
+            &settings,
+        );
+        assert!(messages.is_empty());
+        assert!(sonusmix_state.soloed.is_empty());
+
+        sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::SetCueEndpoint(Some(cue)),
+            &settings,
+        );
+        let (_, messages) = sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::SetSolo(source, true),
+            &settings,
+        );
+        assert_eq!(
+            messages,
+            vec![ToPipewireMessage::CreateNodeLinks {
+                start_id: 1,
+                end_id: 2
+            }]
+        );
+        assert_eq!(sonusmix_state.soloed, vec![source]);
+        assert!(sonusmix_state.links[0].temporary);
+
+        // Solo links can't be locked, and aren't saved
+        sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::SetLinkLocked(source, cue, true),
+            &settings,
+        );
+        assert_eq!(sonusmix_state.links[0].state, LinkState::ConnectedUnlocked);
+        let mut saved = sonusmix_state.clone();
+        saved.forget_solos();
+        assert!(saved.links.is_empty());
+        assert!(saved.soloed.is_empty());
+
+        // Releasing the solo removes the link again
+        let (_, messages) = sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::SetSolo(source, false),
+            &settings,
+        );
+        assert_eq!(
+            messages,
+            vec![ToPipewireMessage::RemoveNodeLinks {
+                start_id: 1,
+                end_id: 2
+            }]
+        );
+        assert!(sonusmix_state.links.is_empty());
+
+        // Other sources connected to the cue endpoint are dimmed while something is soloed
+        settings.dim_cue_on_solo = true;
+        let other = EndpointDescriptor::EphemeralNode(7, PortKind::Source);
+        pipewire_state
+            .nodes
+            .insert(7, Node::new_test(7, EndpointId::Client(2)));
+        sonusmix_state
+            .endpoints
+            .insert(other, Endpoint::new_test(other));
+        sonusmix_state.update(&pipewire_state, SonusmixMsg::Link(other, cue), &settings);
+        sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::SetSolo(source, true),
+            &settings,
+        );
+        let other_send = |state: &SonusmixState| {
+            state
+                .links
+                .iter()
+                .find(|link| link.start == other)
+                .unwrap()
+                .send
+                .map(|send| send.volume)
+        };
+        assert_eq!(
+            other_send(&sonusmix_state),
+            Some(settings::db_to_volume(settings.cue_dim_db))
+        );
+        sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::SetSolo(source, false),
+            &settings,
+        );
+        assert_eq!(other_send(&sonusmix_state), None);
+
+        // Releasing all solos, as is done on shutdown, removes their links and undoes the dims
+        sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::SetSolo(source, true),
+            &settings,
+        );
+        let (_, messages) =
+            sonusmix_state.update(&pipewire_state, SonusmixMsg::ReleaseAllSolos, &settings);
+        assert!(messages.contains(&ToPipewireMessage::RemoveNodeLinks {
+            start_id: 1,
+            end_id: 2
+        }));
+        assert!(sonusmix_state.soloed.is_empty());
+        assert!(sonusmix_state.links.iter().all(|link| !link.temporary));
+        assert_eq!(other_send(&sonusmix_state), None);
+    }
+
+    #[test]
+    fn mute_groups_hold_and_restore() {
+        let settings = SonusmixSettings::default();
+        let (mut pipewire_state, mut sonusmix_state) = basic_graph_ephermal_node_setup();
+        let endpoint = EndpointDescriptor::EphemeralNode(1, PortKind::Source);
+
+        let (_, messages) = sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::SetMuteGroupEngaged(MuteGroupTarget::AllSources, true),
+            &settings,
+        );
+        assert_eq!(messages, vec![ToPipewireMessage::NodeMute(1, true)]);
+        assert_eq!(
+            sonusmix_state.endpoints[&endpoint].volume_locked_muted,
+            VolumeLockMuteState::MutedLocked
+        );
+        pipewire_state.nodes.get_mut(&1).unwrap().mute = true;
+
+        // Held endpoints can't be unmuted or unlocked
+        for message in [
+            SonusmixMsg::SetMute(endpoint, false),
+            SonusmixMsg::SetVolumeLocked(endpoint, false),
+        ] {
+            let (_, messages) = sonusmix_state.update(&pipewire_state, message, &settings);
+            assert!(messages.is_empty());
+        }
+        assert_eq!(
+            sonusmix_state.endpoints[&endpoint].volume_locked_muted,
+            VolumeLockMuteState::MutedLocked
+        );
+
+        // An endpoint held by two groups stays muted until both are released
+        sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::AddMuteGroup("Microphones".to_owned()),
+            &settings,
+        );
+        let group = *sonusmix_state.mute_groups.keys().next().unwrap();
+        sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::SetMuteGroupMember(group, endpoint, true),
+            &settings,
+        );
+        sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::SetMuteGroupEngaged(MuteGroupTarget::Group(group), true),
+            &settings,
+        );
+        let (_, messages) = sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::SetMuteGroupEngaged(MuteGroupTarget::AllSources, false),
+            &settings,
+        );
+        assert!(messages.is_empty());
+        assert!(sonusmix_state.is_held_by_mute_group(endpoint));
+
+        let (_, messages) = sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::SetMuteGroupEngaged(MuteGroupTarget::Group(group), false),
+            &settings,
+        );
+        assert_eq!(messages, vec![ToPipewireMessage::NodeMute(1, false)]);
+        assert_eq!(
+            sonusmix_state.endpoints[&endpoint].volume_locked_muted,
+            VolumeLockMuteState::UnmutedUnlocked
+        );
+    }
+
+    #[test]
+    fn mute_group_holds_ignore_lock_backoff() {
+        let settings = SonusmixSettings::default();
+        let (mut pipewire_state, mut sonusmix_state) = basic_graph_ephermal_node_setup();
+        let endpoint = EndpointDescriptor::EphemeralNode(1, PortKind::Source);
+        sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::SetMuteGroupEngaged(MuteGroupTarget::AllSources, true),
+            &settings,
+        );
+        sonusmix_state
+            .endpoints
+            .get_mut(&endpoint)
+            .unwrap()
+            .volume_pending = false;
+
+        // Another client keeps unmuting the held endpoint, but it is muted again right away
+        for target in [LockTarget::Volume(endpoint), LockTarget::Mute(endpoint)] {
+            sonusmix_state.lock_backoffs.insert(
+                target,
+                LockBackoff {
+                    until: Instant::now() + std::time::Duration::from_secs(60),
+                    delay: std::time::Duration::from_secs(60),
+                    client: None,
+                },
+            );
+        }
+        pipewire_state.nodes.get_mut(&1).unwrap().mute = false;
+        let endpoint_nodes = sonusmix_state.diff_nodes(&pipewire_state, &settings);
+        let messages = sonusmix_state.diff_properties(&endpoint_nodes, &settings);
+        assert!(messages.contains(&ToPipewireMessage::NodeMute(1, true)));
+    }
+
+    #[test]
+    fn mute_fades_out_before_muting() {
+        let settings = SonusmixSettings {
+            mute_fade_ms: 1000,
+            ..Default::default()
+        };
+        let (mut pipewire_state, mut sonusmix_state) = basic_graph_ephermal_node_setup();
+        let endpoint = EndpointDescriptor::EphemeralNode(1, PortKind::Source);
+        pipewire_state.nodes.get_mut(&1).unwrap().channel_volumes = vec![1.0, 1.0];
+
+        // The nodes aren't muted yet, only faded down
+        let (_, messages) = sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::SetMute(endpoint, true),
+            &settings,
+        );
+        assert!(matches!(
+            messages.as_slice(),
+            [ToPipewireMessage::NodeVolume(1, volumes)] if volumes.len() == 2
+        ));
+        assert_eq!(
+            sonusmix_state.endpoints[&endpoint].volume_locked_muted,
+            VolumeLockMuteState::MutedUnlocked
+        );
+
+        // Steps of the fade aren't read back as the endpoint's volume
+        pipewire_state.nodes.get_mut(&1).unwrap().channel_volumes = vec![0.4, 0.4];
+        let endpoint_nodes = sonusmix_state.diff_nodes(&pipewire_state, &settings);
+        let messages = sonusmix_state.diff_properties(&endpoint_nodes, &settings);
+        assert!(messages.is_empty());
+        assert_eq!(sonusmix_state.endpoints[&endpoint].volume, 1.0);
+
+        // Once the fade is done, the nodes are muted and put back at the endpoint's volume
+        let messages = sonusmix_state.step_volume_ramp(
+            &pipewire_state,
+            endpoint,
+            Instant::now() + std::time::Duration::from_secs(2),
+            &settings,
+        );
+        assert_eq!(
+            messages,
+            vec![
+                ToPipewireMessage::NodeVolume(1, vec![1.0; 2]),
+                ToPipewireMessage::NodeMute(1, true),
+            ]
+        );
+        assert!(sonusmix_state.volume_ramps.is_empty());
+        assert!(sonusmix_state.endpoints[&endpoint].volume_pending);
+    }
+
+    #[test]
+    fn mute_locks_are_kept_during_fades() {
+        let settings = SonusmixSettings {
+            remote_volume_fade_ms: 1000,
+            ..Default::default()
+        };
+        let (mut pipewire_state, mut sonusmix_state) = basic_graph_ephermal_node_setup();
+        let endpoint = EndpointDescriptor::EphemeralNode(1, PortKind::Source);
+        pipewire_state.nodes.get_mut(&1).unwrap().channel_volumes = vec![1.0, 1.0];
+        sonusmix_state
+            .endpoints
+            .get_mut(&endpoint)
+            .unwrap()
+            .volume_locked_muted = VolumeLockMuteState::MutedLocked;
+        sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::FadeVolume(endpoint, 0.5),
+            &settings,
+        );
+        assert!(sonusmix_state.volume_ramps.contains_key(&endpoint));
+
+        // Another client unmutes the node partway through the fade
+        pipewire_state.nodes.get_mut(&1).unwrap().channel_volumes = vec![0.8, 0.8];
+        let endpoint_nodes = sonusmix_state.diff_nodes(&pipewire_state, &settings);
+        let messages = sonusmix_state.diff_properties(&endpoint_nodes, &settings);
+        assert_eq!(messages, vec![ToPipewireMessage::NodeMute(1, true)]);
+    }
+
+    #[test]
+    fn default_output_follows_the_default_sink() {
+        let settings = SonusmixSettings::default();
+        let (mut pipewire_state, mut sonusmix_state) = basic_graph_ephermal_node_setup();
+        for (id, port, name) in [(3, 4, "speakers"), (5, 6, "dock")] {
+            let mut sink_node = Node::new_test(id, EndpointId::Client(0));
+            sink_node.ports = vec![(port, PortKind::Sink, false)];
+            sink_node.identifier = NodeIdentifier::new_test().with_node_name(name);
+            pipewire_state.nodes.insert(id, sink_node);
+        }
+        pipewire_state.default_sink = Some("speakers".to_owned());
+
+        let source = EndpointDescriptor::EphemeralNode(1, PortKind::Source);
+        let default_output = EndpointDescriptor::DefaultNode(PortKind::Sink);
+        sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::AddDefaultNode(PortKind::Sink),
+            &settings,
+        );
+        let (_, messages) = sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::Link(source, default_output),
+            &settings,
+        );
+        assert!(messages.contains(&ToPipewireMessage::CreateNodeLinks {
+            start_id: 1,
+            end_id: 3
+        }));
+        sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::SetLinkLocked(source, default_output, true),
+            &settings,
+        );
+        sonusmix_state.diff(&pipewire_state, &settings, true);
+        assert_eq!(sonusmix_state.default_nodes[&PortKind::Sink], "speakers");
+        // The default node can still be added as an endpoint by itself
+        assert!(sonusmix_state
+            .candidates
+            .iter()
+            .any(|(id, kind, _)| *id == 3 && *kind == PortKind::Sink));
+
+        // The dock becomes the default, so the link moves over to it
+        pipewire_state.default_sink = Some("dock".to_owned());
+        let (_, messages) = sonusmix_state.diff(&pipewire_state, &settings, true);
+        assert!(messages.contains(&ToPipewireMessage::CreateNodeLinks {
+            start_id: 1,
+            end_id: 5
+        }));
+        assert!(messages.contains(&ToPipewireMessage::RemoveNodeLinks {
+            start_id: 1,
+            end_id: 3
+        }));
+        assert_eq!(sonusmix_state.default_nodes[&PortKind::Sink], "dock");
+        assert_eq!(sonusmix_state.links[0].end, default_output);
+        assert!(sonusmix_state.links[0].pending);
+
+        // Links to the old default that another endpoint's link needs are left alone
+        let speakers = EndpointDescriptor::EphemeralNode(3, PortKind::Sink);
+        for message in [
+            SonusmixMsg::AddEphemeralNode(3, PortKind::Sink),
+            SonusmixMsg::Link(source, speakers),
+            SonusmixMsg::SetLinkLocked(source, speakers, true),
+        ] {
+            sonusmix_state.update(&pipewire_state, message, &settings);
+        }
+        pipewire_state.default_sink = Some("speakers".to_owned());
+        sonusmix_state.diff(&pipewire_state, &settings, true);
+        pipewire_state.default_sink = Some("dock".to_owned());
+        let (_, messages) = sonusmix_state.diff(&pipewire_state, &settings, true);
+        assert!(messages.contains(&ToPipewireMessage::CreateNodeLinks {
+            start_id: 1,
+            end_id: 5
+        }));
+        assert!(!messages.contains(&ToPipewireMessage::RemoveNodeLinks {
+            start_id: 1,
+            end_id: 3
+        }));
+
+        // The last default is saved, so links are moved if it changed while Sonusmix was closed
+        sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::RemoveLink(source, speakers),
+            &settings,
+        );
+        let mut restarted =
+            persistence::PersistentState::from_state(sonusmix_state.clone()).into_state();
+        assert_eq!(restarted.default_nodes[&PortKind::Sink], "dock");
+        pipewire_state.default_sink = Some("speakers".to_owned());
+        let (_, messages) = restarted.diff(&pipewire_state, &settings, true);
+        assert!(messages.contains(&ToPipewireMessage::RemoveNodeLinks {
+            start_id: 1,
+            end_id: 5
+        }));
+    }
+
+    #[test]
+    fn rebind_placeholder_moves_settings_and_links() {
+        let settings = SonusmixSettings::default();
+        let (mut pipewire_state, mut sonusmix_state) = basic_graph_ephermal_node_setup();
+        let mut sink_node = Node::new_test(5, EndpointId::Client(0));
+        sink_node.ports = vec![(6, PortKind::Sink, false)];
+        pipewire_state.nodes.insert(5, sink_node);
+
+        let old_mic = EndpointDescriptor::EphemeralNode(1, PortKind::Source);
+        let sink = EndpointDescriptor::EphemeralNode(5, PortKind::Sink);
+        for message in [
+            SonusmixMsg::AddEphemeralNode(5, PortKind::Sink),
+            SonusmixMsg::Link(old_mic, sink),
+            SonusmixMsg::SetLinkLocked(old_mic, sink, true),
+            SonusmixMsg::SetVolume(old_mic, 0.5),
+            SonusmixMsg::SetVolumeLocked(old_mic, true),
+            SonusmixMsg::RenameEndpoint(old_mic, Some("Mic".to_owned())),
+        ] {
+            sonusmix_state.update(&pipewire_state, message, &settings);
+        }
+
+        // The old mic is unplugged and a new one is plugged in
+        pipewire_state.nodes.remove(&1);
+        pipewire_state.ports.remove(&2);
+        let mut new_mic_node = Node::new_test(3, EndpointId::Client(0));
+        new_mic_node.ports = vec![(4, PortKind::Source, false)];
+        new_mic_node.channel_volumes = vec![1.0, 1.0];
+        pipewire_state.nodes.insert(3, new_mic_node);
+        sonusmix_state.diff(&pipewire_state, &settings, true);
+        assert!(sonusmix_state.endpoints[&old_mic].is_placeholder);
+
+        let new_mic = EndpointDescriptor::EphemeralNode(3, PortKind::Source);
+        let (output, messages) = sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::RebindEndpoint(old_mic, new_mic),
+            &settings,
+        );
+        assert!(matches!(
+            output,
+            Some(SonusmixOutputMsg::EndpointReplaced(old, new)) if old == old_mic && new == new_mic
+        ));
+        assert!(messages.contains(&ToPipewireMessage::NodeVolume(3, vec![0.5, 0.5])));
+        assert!(!sonusmix_state.endpoints.contains_key(&old_mic));
+        let endpoint = &sonusmix_state.endpoints[&new_mic];
+        assert!(!endpoint.is_placeholder);
+        assert_eq!(endpoint.custom_name.as_deref(), Some("Mic"));
+        assert_eq!(endpoint.volume, 0.5);
+        assert!(endpoint.volume_locked_muted.is_locked());
+        assert_eq!(sonusmix_state.active_sources, [new_mic]);
+        assert_eq!(sonusmix_state.links.len(), 1);
+        assert_eq!(sonusmix_state.links[0].start, new_mic);
+        assert_eq!(sonusmix_state.links[0].state, LinkState::ConnectedLocked);
+
+        // Only placeholders can be rebound
+        let (output, _) = sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::RebindEndpoint(sink, EndpointDescriptor::EphemeralNode(3, PortKind::Sink)),
+            &settings,
+        );
+        assert!(output.is_none());
+    }
+
+    #[test]
+    fn excluded_stream_stays_excluded_when_recreated() {
+        let settings = SonusmixSettings::default();
+        let (mut pipewire_state, mut sonusmix_state) = basic_graph_ephermal_node_setup();
+        let add_stream = |pipewire_state: &mut Graph, id: u32, media_name: &str| {
+            let mut node = Node::new_test(id, EndpointId::Client(0));
+            node.ports = vec![(id + 10, PortKind::Source, false)];
+            node.identifier = NodeIdentifier::new_test().with_media_name(media_name);
+            node.identifier.application_name = Some("Browser".to_owned());
+            node.identifier.binary_name = Some("browser".to_owned());
+            pipewire_state.nodes.insert(id, node);
+        };
+        add_stream(&mut pipewire_state, 3, "Music");
+        add_stream(&mut pipewire_state, 4, "Meeting");
+        let mut sink_node = Node::new_test(5, EndpointId::Client(0));
+        sink_node.ports = vec![(6, PortKind::Sink, false)];
+        pipewire_state.nodes.insert(5, sink_node);
+
+        let application = Application::new_inactive(
+            "Browser".to_owned(),
+            "browser".to_owned(),
+            "browser".to_owned(),
+            PortKind::Source,
+        );
+        let id = application.id;
+        sonusmix_state.applications.insert(id, application);
+        let application_desc = EndpointDescriptor::Application(id, PortKind::Source);
+        let sink = EndpointDescriptor::EphemeralNode(5, PortKind::Sink);
+        for message in [
+            SonusmixMsg::AddApplication(id, PortKind::Source),
+            SonusmixMsg::AddEphemeralNode(5, PortKind::Sink),
+            SonusmixMsg::Link(application_desc, sink),
+        ] {
+            sonusmix_state.update(&pipewire_state, message, &settings);
+        }
+        sonusmix_state.links[0].pending = false;
+
+        let matcher =
+            StreamMatcher::for_stream(&pipewire_state.nodes[&4].identifier).expect("has a name");
+        let (_, messages) = sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::SetStreamExcluded(id, matcher.clone(), true),
+            &settings,
+        );
+        assert_eq!(
+            messages,
+            vec![ToPipewireMessage::RemoveNodeLinks {
+                start_id: 4,
+                end_id: 5
+            }]
+        );
+        assert!(sonusmix_state.links[0].pending);
+        let resolved_ids = |sonusmix_state: &SonusmixState, pipewire_state: &Graph| {
+            sonusmix_state
+                .resolve_endpoint(application_desc, pipewire_state, &settings)
+                .unwrap_or_default()
+                .iter()
+                .map(|node| node.id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(resolved_ids(&sonusmix_state, &pipewire_state), vec![3]);
+
+        // The meeting stream is recreated with a new node, and is still excluded
+        pipewire_state.nodes.remove(&4);
+        add_stream(&mut pipewire_state, 7, "Meeting");
+        assert_eq!(resolved_ids(&sonusmix_state, &pipewire_state), vec![3]);
+        let streams = sonusmix_state.application_streams(&pipewire_state, id, &settings);
+        assert_eq!(streams.len(), 2);
+        assert_eq!(streams[0].status, StreamStatus::Included);
+        assert_eq!(streams[1].node_id, 7);
+        assert_eq!(streams[1].status, StreamStatus::Excluded(matcher.clone()));
+
+        // Including it again connects it like the rest of the application
+        sonusmix_state.links[0].pending = false;
+        let (_, messages) = sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::SetStreamExcluded(id, matcher, false),
+            &settings,
+        );
+        assert_eq!(
+            messages,
+            vec![ToPipewireMessage::CreateNodeLinks {
+                start_id: 7,
+                end_id: 5
+            }]
+        );
+        assert!(sonusmix_state.applications[&id].excluded_streams.is_empty());
+    }
+
+    #[test]
+    fn media_role_endpoint_gathers_streams_across_applications() {
+        let settings = SonusmixSettings::default();
+        let (mut pipewire_state, mut sonusmix_state) = basic_graph_ephermal_node_setup();
+        for (id, application, role) in [
+            (3, "Chat", MediaRole::Notification),
+            (4, "Mail", MediaRole::Notification),
+            (5, "Player", MediaRole::Music),
+        ] {
+            let mut node = Node::new_test(id, EndpointId::Client(0));
+            node.ports = vec![(id + 10, PortKind::Source, false)];
+            node.identifier = NodeIdentifier::new_test().with_media_role(role);
+            node.identifier.application_name = Some(application.to_owned());
+            node.identifier.binary_name = Some(application.to_lowercase());
+            pipewire_state.nodes.insert(id, node);
+        }
+
+        let notifications =
+            EndpointDescriptor::MediaRole(MediaRole::Notification, PortKind::Source);
+        let (output, _) = sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::AddMediaRole(MediaRole::Notification, PortKind::Source),
+            &settings,
+        );
+        assert!(
+            matches!(output, Some(SonusmixOutputMsg::EndpointAdded(desc)) if desc == notifications)
+        );
+        assert_eq!(
+            sonusmix_state.endpoints[&notifications].display_name,
+            "［Role］Notification"
+        );
+        let resolved_ids = |sonusmix_state: &SonusmixState| {
+            sonusmix_state
+                .resolve_endpoint(notifications, &pipewire_state, &settings)
+                .unwrap_or_default()
+                .iter()
+                .map(|node| node.id)
+                .sorted()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(resolved_ids(&sonusmix_state), vec![3, 4]);
+
+        // A stream added as its own endpoint is controlled by that instead
+        sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::AddEphemeralNode(4, PortKind::Source),
+            &settings,
+        );
+        assert_eq!(resolved_ids(&sonusmix_state), vec![3]);
+
+        // So is a stream whose application was added as an endpoint
+        let application = Application::new_inactive(
+            "Chat".to_owned(),
+            "chat".to_owned(),
+            "chat".to_owned(),
+            PortKind::Source,
+        );
+        let id = application.id;
+        sonusmix_state.applications.insert(id, application);
+        sonusmix_state.update(
+            &pipewire_state,
+            SonusmixMsg::AddApplication(id, PortKind::Source),
+            &settings,
+        );
+        assert!(resolved_ids(&sonusmix_state).is_empty());
+        let application_nodes = sonusmix_state
+            .resolve_endpoint(
+                EndpointDescriptor::Application(id, PortKind::Source),
+                &pipewire_state,
+                &settings,
+            )
+            .unwrap_or_default();
+        assert_eq!(application_nodes.len(), 1);
+        assert_eq!(application_nodes[0].id, 3);
+    }
 }
@@ -0,0 +1,325 @@
+use log::warn;
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+
+use crate::pipewire_api::{Graph, ToPipewireMessage};
+
+use super::{settings::SonusmixSettings, EndpointDescriptor, SonusmixState, VolumeLockMuteState};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct MuteGroupId(Ulid);
+
+impl MuteGroupId {
+    fn new() -> Self {
+        Self(Ulid::new())
+    }
+}
+
+/// A named set of endpoints that are all muted at once. While a group is engaged its members are
+/// muted and locked, so nothing else can unmute them, and releasing it puts back the mute and
+/// lock state each member had before.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MuteGroup {
+    pub id: MuteGroupId,
+    pub name: String,
+    pub members: Vec<EndpointDescriptor>,
+    /// The state each member had before the group was engaged, or None if it isn't engaged
+    #[serde(default)]
+    pub held: Option<Vec<(EndpointDescriptor, VolumeLockMuteState)>>,
+}
+
+/// A mute group that can be engaged: either one the user made, or the built-in group of every
+/// source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum MuteGroupTarget {
+    AllSources,
+    Group(MuteGroupId),
+}
+
+impl SonusmixState {
+    pub(super) fn add_mute_group(&mut self, name: String) -> MuteGroupId {
+        let id = MuteGroupId::new();
+        self.mute_groups.insert(
+            id,
+            MuteGroup {
+                id,
+                name,
+                members: Vec::new(),
+                held: None,
+            },
+        );
+        id
+    }
+
+    /// Release a mute group and forget it.
+    pub(super) fn remove_mute_group(
+        &mut self,
+        graph: &Graph,
+        id: MuteGroupId,
+        settings: &SonusmixSettings,
+    ) -> Vec<ToPipewireMessage> {
+        let messages =
+            self.set_mute_group_engaged(graph, MuteGroupTarget::Group(id), false, settings);
+        self.mute_groups.shift_remove(&id);
+        messages
+    }
+
+    /// Add an endpoint to a mute group, or remove it. Endpoints removed from an engaged group are
+    /// released, unless another group still holds them.
+    pub(super) fn set_mute_group_member(
+        &mut self,
+        graph: &Graph,
+        id: MuteGroupId,
+        endpoint_desc: EndpointDescriptor,
+        member: bool,
+        settings: &SonusmixSettings,
+    ) -> Vec<ToPipewireMessage> {
+        let Some(group) = self.mute_groups.get_mut(&id) else {
+            return Vec::new();
+        };
+        let position = group.members.iter().position(|desc| *desc == endpoint_desc);
+        match (member, position) {
+            (true, None) if self.endpoints.contains_key(&endpoint_desc) => {
+                group.members.push(endpoint_desc);
+                self.hold_mute_groups(graph, settings)
+            }
+            (false, Some(position)) => {
+                group.members.remove(position);
+                let prior = group.held.as_mut().and_then(|held| {
+                    let position = held.iter().position(|(desc, _)| *desc == endpoint_desc)?;
+                    Some(held.remove(position))
+                });
+                prior
+                    .map(|prior| self.release_mute_held(graph, prior, settings))
+                    .unwrap_or_default()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Whether a mute group is engaged.
+    pub fn is_mute_group_engaged(&self, target: MuteGroupTarget) -> bool {
+        self.mute_group_held(target).is_some()
+    }
+
+    /// Whether an endpoint is muted by any engaged mute group.
+    pub fn is_held_by_mute_group(&self, endpoint_desc: EndpointDescriptor) -> bool {
+        self.all_sources_muted
+            .iter()
+            .chain(
+                self.mute_groups
+                    .values()
+                    .filter_map(|group| group.held.as_ref()),
+            )
+            .flatten()
+            .any(|(desc, _)| *desc == endpoint_desc)
+    }
+
+    fn mute_group_held(
+        &self,
+        target: MuteGroupTarget,
+    ) -> Option<&Vec<(EndpointDescriptor, VolumeLockMuteState)>> {
+        match target {
+            MuteGroupTarget::AllSources => self.all_sources_muted.as_ref(),
+            MuteGroupTarget::Group(id) => self.mute_groups.get(&id)?.held.as_ref(),
+        }
+    }
+
+    fn mute_group_held_mut(
+        &mut self,
+        target: MuteGroupTarget,
+    ) -> Option<&mut Option<Vec<(EndpointDescriptor, VolumeLockMuteState)>>> {
+        match target {
+            MuteGroupTarget::AllSources => Some(&mut self.all_sources_muted),
+            MuteGroupTarget::Group(id) => Some(&mut self.mute_groups.get_mut(&id)?.held),
+        }
+    }
+
+    /// Engage or release a mute group. Its members are muted and locked by the next call to
+    /// [`Self::hold_mute_groups`], which happens right away.
+    pub(super) fn set_mute_group_engaged(
+        &mut self,
+        graph: &Graph,
+        target: MuteGroupTarget,
+        engaged: bool,
+        settings: &SonusmixSettings,
+    ) -> Vec<ToPipewireMessage> {
+        let Some(held) = self.mute_group_held_mut(target) else {
+            return Vec::new();
+        };
+        if held.is_some() == engaged {
+            return Vec::new();
+        }
+        if engaged {
+            *held = Some(Vec::new());
+            return self.hold_mute_groups(graph, settings);
+        }
+
+        let mut messages = Vec::new();
+        for prior in held.take().unwrap_or_default() {
+            messages.extend(self.release_mute_held(graph, prior, settings));
+        }
+        messages
+    }
+
+    /// Mute and lock every member of every engaged mute group that isn't held yet, including
+    /// sources that appeared after the built-in group was engaged.
+    pub(super) fn hold_mute_groups(
+        &mut self,
+        graph: &Graph,
+        settings: &SonusmixSettings,
+    ) -> Vec<ToPipewireMessage> {
+        let mut to_hold: Vec<(MuteGroupTarget, EndpointDescriptor)> = Vec::new();
+        if let Some(held) = &self.all_sources_muted {
+            to_hold.extend(
+                self.active_sources
+                    .iter()
+                    .filter(|desc| !held.iter().any(|(held, _)| held == *desc))
+                    .map(|desc| (MuteGroupTarget::AllSources, *desc)),
+            );
+        }
+        for group in self.mute_groups.values() {
+            if let Some(held) = &group.held {
+                to_hold.extend(
+                    group
+                        .members
+                        .iter()
+                        .filter(|desc| !held.iter().any(|(held, _)| held == *desc))
+                        .map(|desc| (MuteGroupTarget::Group(group.id), *desc)),
+                );
+            }
+        }
+
+        let mut messages = Vec::new();
+        for (target, endpoint_desc) in to_hold {
+            let Some(endpoint) = self.endpoints.get(&endpoint_desc) else {
+                continue;
+            };
+            // If another group already holds the endpoint, what it had before that group muted
+            // it is what should come back once every group has let go
+            let prior = self
+                .all_sources_muted
+                .iter()
+                .chain(
+                    self.mute_groups
+                        .values()
+                        .filter_map(|group| group.held.as_ref()),
+                )
+                .flatten()
+                .find(|(desc, _)| *desc == endpoint_desc)
+                .map_or(endpoint.volume_locked_muted, |(_, prior)| *prior);
+            if let Some(Some(held)) = self.mute_group_held_mut(target) {
+                held.push((endpoint_desc, prior));
+            }
+            messages.extend(self.set_mute_state(
+                graph,
+                endpoint_desc,
+                VolumeLockMuteState::MutedLocked,
+                settings,
+            ));
+        }
+        messages
+    }
+
+    /// Give an endpoint a mute group let go of its state back, unless another group still holds
+    /// it.
+    fn release_mute_held(
+        &mut self,
+        graph: &Graph,
+        (endpoint_desc, prior): (EndpointDescriptor, VolumeLockMuteState),
+        settings: &SonusmixSettings,
+    ) -> Vec<ToPipewireMessage> {
+        if self.is_held_by_mute_group(endpoint_desc) {
+            return Vec::new();
+        }
+        // There's no way to get back to a mix of muted and unmuted nodes, so those are unmuted
+        let prior = match prior {
+            VolumeLockMuteState::MuteMixed => VolumeLockMuteState::UnmutedUnlocked,
+            prior => prior,
+        };
+        self.set_mute_state(graph, endpoint_desc, prior, settings)
+    }
+
+    /// Set an endpoint's mute and lock state, and its nodes' mute state to match.
+    fn set_mute_state(
+        &mut self,
+        graph: &Graph,
+        endpoint_desc: EndpointDescriptor,
+        state: VolumeLockMuteState,
+        settings: &SonusmixSettings,
+    ) -> Vec<ToPipewireMessage> {
+        let nodes = self
+            .resolve_endpoint(endpoint_desc, graph, settings)
+            .unwrap_or_default();
+        let Some(endpoint) = self.endpoints.get_mut(&endpoint_desc) else {
+            return Vec::new();
+        };
+        endpoint.volume_locked_muted = state;
+        let Some(muted) = endpoint.applied_mute_state() else {
+            return Vec::new();
+        };
+        let messages: Vec<ToPipewireMessage> = nodes
+            .iter()
+            .filter(|node| node.mute != muted)
+            .map(|node| ToPipewireMessage::NodeMute(node.id, muted))
+            .collect();
+        if !messages.is_empty() {
+            endpoint.volume_pending = true;
+        }
+        messages
+    }
+
+    /// Forget an endpoint that was removed from every mute group, without restoring it.
+    pub(super) fn remove_mute_group_member(&mut self, endpoint_desc: EndpointDescriptor) {
+        for group in self.mute_groups.values_mut() {
+            group.members.retain(|desc| *desc != endpoint_desc);
+        }
+        for held in self.all_sources_muted.iter_mut().chain(
+            self.mute_groups
+                .values_mut()
+                .filter_map(|group| group.held.as_mut()),
+        ) {
+            held.retain(|(desc, _)| *desc != endpoint_desc);
+        }
+    }
+
+    pub(super) fn replace_mute_group_member(
+        &mut self,
+        old: EndpointDescriptor,
+        new: EndpointDescriptor,
+    ) {
+        for desc in self
+            .mute_groups
+            .values_mut()
+            .flat_map(|group| group.members.iter_mut())
+        {
+            if *desc == old {
+                *desc = new;
+            }
+        }
+        for (desc, _) in self
+            .all_sources_muted
+            .iter_mut()
+            .chain(
+                self.mute_groups
+                    .values_mut()
+                    .filter_map(|group| group.held.as_mut()),
+            )
+            .flatten()
+        {
+            if *desc == old {
+                *desc = new;
+            }
+        }
+    }
+
+    /// Log and refuse a change to an endpoint that a mute group is holding muted. Returns
+    /// whether it was refused.
+    pub(super) fn refuse_mute_group_change(&self, endpoint_desc: EndpointDescriptor) -> bool {
+        let held = self.is_held_by_mute_group(endpoint_desc);
+        if held {
+            warn!("{endpoint_desc:?} is held muted by a mute group, release the group first");
+        }
+        held
+    }
+}
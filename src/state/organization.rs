@@ -0,0 +1,151 @@
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+
+use crate::pipewire_api::PortKind;
+
+use super::{EndpointDescriptor, SonusmixState};
+
+/// A colour the user can tag an endpoint with. The colours come from the GNOME palette, so they
+/// look reasonable on both light and dark themes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EndpointColor {
+    Blue,
+    Green,
+    Yellow,
+    Orange,
+    Red,
+    Purple,
+    Brown,
+}
+
+impl EndpointColor {
+    pub fn css_class(self) -> &'static str {
+        match self {
+            Self::Blue => "endpoint-color-blue",
+            Self::Green => "endpoint-color-green",
+            Self::Yellow => "endpoint-color-yellow",
+            Self::Orange => "endpoint-color-orange",
+            Self::Red => "endpoint-color-red",
+            Self::Purple => "endpoint-color-purple",
+            Self::Brown => "endpoint-color-brown",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SectionId(Ulid);
+
+impl SectionId {
+    fn new() -> Self {
+        Self(Ulid::new())
+    }
+}
+
+/// A user-defined, collapsible group of endpoints in the sources or sinks list. Unlike group
+/// nodes, these only affect how the endpoints are shown.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Section {
+    pub id: SectionId,
+    pub name: String,
+    pub list: PortKind,
+    pub collapsed: bool,
+}
+
+impl SonusmixState {
+    /// Move an endpoint to the position of another endpoint in the same list, and into the other
+    /// endpoint's section. Group nodes are reordered among the other group nodes.
+    pub(super) fn move_endpoint(
+        &mut self,
+        endpoint_desc: EndpointDescriptor,
+        target_desc: EndpointDescriptor,
+    ) {
+        if endpoint_desc == target_desc {
+            return;
+        }
+        if let (EndpointDescriptor::GroupNode(id), EndpointDescriptor::GroupNode(target_id)) =
+            (endpoint_desc, target_desc)
+        {
+            if let (Some(from), Some(to)) = (
+                self.group_nodes.get_index_of(&id),
+                self.group_nodes.get_index_of(&target_id),
+            ) {
+                self.group_nodes.move_index(from, to);
+            }
+            return;
+        }
+
+        let list = if self.active_sources.contains(&endpoint_desc) {
+            &mut self.active_sources
+        } else {
+            &mut self.active_sinks
+        };
+        let (Some(from), Some(to)) = (
+            list.iter().position(|desc| *desc == endpoint_desc),
+            list.iter().position(|desc| *desc == target_desc),
+        ) else {
+            return;
+        };
+        let endpoint_desc = list.remove(from);
+        list.insert(to, endpoint_desc);
+
+        let section = self
+            .endpoints
+            .get(&target_desc)
+            .and_then(|target| target.section);
+        if let Some(endpoint) = self.endpoints.get_mut(&endpoint_desc) {
+            endpoint.section = section;
+        }
+    }
+
+    pub(super) fn add_section(&mut self, name: String, list: PortKind) {
+        let id = SectionId::new();
+        self.sections.insert(
+            id,
+            Section {
+                id,
+                name,
+                list,
+                collapsed: false,
+            },
+        );
+    }
+
+    /// Remove a section. Its endpoints are kept, but are no longer in any section.
+    pub(super) fn remove_section(&mut self, id: SectionId) {
+        self.sections.shift_remove(&id);
+        for endpoint in self.endpoints.values_mut() {
+            if endpoint.section == Some(id) {
+                endpoint.section = None;
+            }
+        }
+    }
+
+    /// The endpoints in the sources or sinks list, grouped by section. Endpoints not in a section
+    /// come first, followed by each section in order, including empty ones.
+    pub fn list_layout(&self, list: PortKind) -> Vec<(Option<SectionId>, Vec<EndpointDescriptor>)> {
+        let active_endpoints = match list {
+            PortKind::Source => &self.active_sources,
+            PortKind::Sink => &self.active_sinks,
+        };
+        let sections = std::iter::once(None).chain(
+            self.sections
+                .values()
+                .filter(|section| section.list == list)
+                .map(|section| Some(section.id)),
+        );
+        sections
+            .map(|section| {
+                let endpoints = active_endpoints
+                    .iter()
+                    .filter(|desc| {
+                        self.endpoints
+                            .get(desc)
+                            .is_some_and(|endpoint| endpoint.section == section)
+                    })
+                    .copied()
+                    .collect();
+                (section, endpoints)
+            })
+            .collect()
+    }
+}
@@ -12,7 +12,7 @@ use tokio::time::Instant;
 
 use crate::{state::reducer::SONUSMIX_SETTINGS, APP_VERSION, SONUSMIX_APP_ID};
 
-use super::{settings::SonusmixSettings, SonusmixReducer, SonusmixState};
+use super::{settings::SonusmixSettings, EndpointDescriptor, SonusmixReducer, SonusmixState};
 
 fn data_dir() -> Option<PathBuf> {
     std::env::var("SONUSMIX_DATA_DIR")
@@ -36,6 +36,8 @@ pub(super) struct PersistentState {
 
 impl PersistentState {
     pub fn from_state(mut state: SonusmixState) -> Self {
+        // Solos only last until Sonusmix is closed
+        state.forget_solos();
         // Remove links that aren't locked
         state.links.retain(|link| link.state.is_locked());
         // Remove applications that aren't active
@@ -49,7 +51,19 @@ impl PersistentState {
         }
     }
 
-    pub fn into_state(self) -> SonusmixState {
+    pub fn into_state(mut self) -> SonusmixState {
+        // Nothing has been seen since Sonusmix started, so persistent, application and media role
+        // endpoints count as reappearing the first time they are found
+        for endpoint in self.state.endpoints.values_mut() {
+            if matches!(
+                endpoint.descriptor,
+                EndpointDescriptor::PersistentNode(..)
+                    | EndpointDescriptor::Application(..)
+                    | EndpointDescriptor::MediaRole(..)
+            ) {
+                endpoint.is_placeholder = true;
+            }
+        }
         self.state
     }
 
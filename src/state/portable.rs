@@ -0,0 +1,653 @@
+use std::{collections::HashMap, fs::File, path::Path};
+
+use anyhow::{Context, Result};
+use log::warn;
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    pipewire_api::{Graph, MediaRole, PortKind, ToPipewireMessage},
+    APP_VERSION,
+};
+
+use super::{
+    media_role_name_with_tag, settings::SonusmixSettings, Application, EndpointColor,
+    EndpointDescriptor, GroupNodeKind, LinkState, PushToTalkMode, SonusmixMsg, SonusmixState,
+    StreamMatcher,
+};
+
+/// A routing configuration that can be shared between machines, e.g. by checking it into a
+/// dotfiles repository. Unlike the saved state, endpoints are referred to by name or identifier
+/// instead of by Pipewire ID.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PortableConfig {
+    version: String,
+    pub endpoints: Vec<PortableEndpoint>,
+    pub links: Vec<PortableLink>,
+    pub settings: SonusmixSettings,
+}
+
+/// Refers to an endpoint without using anything specific to one Pipewire session.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum PortableEndpointRef {
+    /// A group node, by name
+    GroupNode(String),
+    /// An application, by the name and binary its nodes have
+    Application {
+        name: String,
+        binary: String,
+        kind: PortKind,
+    },
+    /// A single node, by its identifier (usually `node.name`)
+    Node { identifier: String, kind: PortKind },
+    /// Whichever node is the default sink or source
+    DefaultNode(PortKind),
+    /// All streams with a media role
+    MediaRole(MediaRole, PortKind),
+}
+
+impl PortableEndpointRef {
+    pub fn name(&self) -> String {
+        match self {
+            Self::GroupNode(name) => name.clone(),
+            Self::Application { name, .. } => format!("［App］{name}"),
+            Self::Node { identifier, .. } => identifier.clone(),
+            Self::DefaultNode(PortKind::Sink) => "Default Output".to_owned(),
+            Self::DefaultNode(PortKind::Source) => "Default Input".to_owned(),
+            Self::MediaRole(role, _) => media_role_name_with_tag(*role),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PortableEndpoint {
+    pub endpoint: PortableEndpointRef,
+    /// Only used for group nodes
+    #[serde(default)]
+    pub group_node_kind: Option<GroupNodeKind>,
+    /// Only used for applications, in case the application is not running when it is imported
+    #[serde(default)]
+    pub application_icon_name: Option<String>,
+    #[serde(default)]
+    pub custom_name: Option<String>,
+    pub volume: f32,
+    #[serde(default)]
+    pub muted: bool,
+    #[serde(default)]
+    pub volume_locked: bool,
+    #[serde(default)]
+    pub max_volume: Option<f32>,
+    #[serde(default)]
+    pub start_volume: Option<f32>,
+    #[serde(default)]
+    pub color: Option<EndpointColor>,
+    #[serde(default)]
+    pub custom_icon_name: Option<String>,
+    #[serde(default)]
+    pub push_to_talk: Option<PushToTalkMode>,
+    /// Only used for applications
+    #[serde(default)]
+    pub excluded_streams: Vec<StreamMatcher>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PortableLink {
+    pub source: PortableEndpointRef,
+    pub sink: PortableEndpointRef,
+    pub state: LinkState,
+    /// The gain of the link, if it has a send level
+    #[serde(default)]
+    pub send_volume: Option<f32>,
+    #[serde(default)]
+    pub send_muted: bool,
+}
+
+/// How an imported configuration is combined with the current state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ImportMode {
+    /// Add the configuration's endpoints and links, replacing the settings of any that already
+    /// exist
+    Merge,
+    /// Remove all current endpoints before importing the configuration
+    Replace,
+}
+
+/// What importing a configuration would change, shown to the user before importing.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportPreview {
+    /// Endpoints that will be added
+    pub added: Vec<String>,
+    /// Endpoints that already exist, whose settings will be replaced when merging
+    pub existing: Vec<String>,
+    /// Nodes that are not present right now, which will be skipped
+    pub missing: Vec<String>,
+    /// Existing links whose state will be changed
+    pub changed_links: Vec<String>,
+    /// Endpoints that are not in the configuration, which will be removed when replacing
+    pub not_imported: Vec<String>,
+    /// Whether the configuration's settings differ from the current ones. They are only imported
+    /// when replacing.
+    pub settings_changed: bool,
+}
+
+impl PortableConfig {
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let file = File::create(path).context("Failed to create configuration file")?;
+        ron::ser::to_writer_pretty(file, self, PrettyConfig::new())
+            .context("Failed to serialize configuration")
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = File::open(path).context("Failed to open configuration file")?;
+        ron::de::from_reader(file).context("Failed to deserialize configuration")
+    }
+}
+
+impl ImportPreview {
+    pub fn summary(&self) -> String {
+        let settings = self
+            .settings_changed
+            .then(|| "The settings will be overwritten when replacing, and kept when merging.");
+        [
+            ("Added", &self.added),
+            (
+                "Already present (settings will be overwritten)",
+                &self.existing,
+            ),
+            ("Not found (will be skipped)", &self.missing),
+            ("Links that will change", &self.changed_links),
+            (
+                "Not in the configuration (removed when replacing)",
+                &self.not_imported,
+            ),
+        ]
+        .into_iter()
+        .filter(|(_, names)| !names.is_empty())
+        .map(|(heading, names)| format!("{heading}:\n  {}", names.join("\n  ")))
+        .chain(settings.map(ToOwned::to_owned))
+        .collect::<Vec<_>>()
+        .join("\n\n")
+    }
+}
+
+impl SonusmixState {
+    /// Export the endpoints, links and settings that can be referred to without Pipewire IDs.
+    /// Loopbacks, and nodes that are not present in the graph, are left out.
+    pub fn export_config(&self, graph: &Graph, settings: &SonusmixSettings) -> PortableConfig {
+        let endpoints: Vec<PortableEndpoint> = self
+            .group_nodes
+            .keys()
+            .map(|id| EndpointDescriptor::GroupNode(*id))
+            .chain(self.active_sources.iter().copied())
+            .chain(self.active_sinks.iter().copied())
+            .filter_map(|endpoint_desc| {
+                let endpoint = self.endpoints.get(&endpoint_desc)?;
+                Some(PortableEndpoint {
+                    endpoint: self.portable_ref(endpoint_desc, graph)?,
+                    group_node_kind: match endpoint_desc {
+                        EndpointDescriptor::GroupNode(id) => {
+                            self.group_nodes.get(&id).map(|group_node| group_node.kind)
+                        }
+                        _ => None,
+                    },
+                    application_icon_name: match endpoint_desc {
+                        EndpointDescriptor::Application(id, _) => self
+                            .applications
+                            .get(&id)
+                            .map(|application| application.icon_name.clone()),
+                        _ => None,
+                    },
+                    custom_name: endpoint.custom_name.clone(),
+                    volume: endpoint.volume,
+                    muted: endpoint.volume_locked_muted.is_muted().unwrap_or(false),
+                    volume_locked: endpoint.volume_locked_muted.is_locked(),
+                    max_volume: endpoint.max_volume,
+                    start_volume: endpoint.start_volume,
+                    color: endpoint.color,
+                    custom_icon_name: endpoint.custom_icon_name.clone(),
+                    push_to_talk: endpoint.push_to_talk,
+                    excluded_streams: match endpoint_desc {
+                        EndpointDescriptor::Application(id, _) => self
+                            .applications
+                            .get(&id)
+                            .map(|application| application.excluded_streams.clone())
+                            .unwrap_or_default(),
+                        _ => Vec::new(),
+                    },
+                })
+            })
+            .collect();
+
+        let links = self
+            .links
+            .iter()
+            .filter(|link| link.state != LinkState::PartiallyConnected && !link.temporary)
+            .filter_map(|link| {
+                Some(PortableLink {
+                    source: self.portable_ref(link.start, graph)?,
+                    sink: self.portable_ref(link.end, graph)?,
+                    state: link.state,
+                    send_volume: link.send.map(|send| send.volume),
+                    send_muted: link.send.is_some_and(|send| send.muted),
+                })
+            })
+            .collect();
+
+        PortableConfig {
+            version: APP_VERSION.to_string(),
+            endpoints,
+            links,
+            settings: settings.clone(),
+        }
+    }
+
+    fn portable_ref(
+        &self,
+        endpoint_desc: EndpointDescriptor,
+        graph: &Graph,
+    ) -> Option<PortableEndpointRef> {
+        match endpoint_desc {
+            EndpointDescriptor::GroupNode(_) => Some(PortableEndpointRef::GroupNode(
+                self.endpoints.get(&endpoint_desc)?.display_name.clone(),
+            )),
+            EndpointDescriptor::Application(id, kind) => {
+                let application = self.applications.get(&id)?;
+                Some(PortableEndpointRef::Application {
+                    name: application.name.clone(),
+                    binary: application.binary.clone(),
+                    kind,
+                })
+            }
+            EndpointDescriptor::EphemeralNode(id, kind) => {
+                let identifier = graph.nodes.get(&id)?.identifier.identifier();
+                (!identifier.is_empty()).then(|| PortableEndpointRef::Node {
+                    identifier: identifier.to_owned(),
+                    kind,
+                })
+            }
+            EndpointDescriptor::DefaultNode(kind) => Some(PortableEndpointRef::DefaultNode(kind)),
+            EndpointDescriptor::MediaRole(role, kind) => {
+                Some(PortableEndpointRef::MediaRole(role, kind))
+            }
+            // Loopbacks are tied to this machine's Pipewire modules, and persistent nodes and
+            // devices can't be resolved yet
+            EndpointDescriptor::Loopback(..)
+            | EndpointDescriptor::PersistentNode(..)
+            | EndpointDescriptor::Device(..) => None,
+        }
+    }
+
+    /// Find the existing endpoint a portable reference refers to.
+    fn find_portable_endpoint(
+        &self,
+        endpoint: &PortableEndpointRef,
+        graph: &Graph,
+    ) -> Option<EndpointDescriptor> {
+        match endpoint {
+            PortableEndpointRef::GroupNode(name) => self
+                .group_nodes
+                .keys()
+                .map(|id| EndpointDescriptor::GroupNode(*id))
+                .find(|endpoint_desc| {
+                    self.endpoints
+                        .get(endpoint_desc)
+                        .is_some_and(|endpoint| endpoint.display_name == *name)
+                }),
+            PortableEndpointRef::Application { name, binary, kind } => self
+                .applications
+                .values()
+                .find(|application| {
+                    application.is_active
+                        && application.kind == *kind
+                        && application.name == *name
+                        && application.binary == *binary
+                })
+                .map(|application| EndpointDescriptor::Application(application.id, *kind)),
+            PortableEndpointRef::Node { identifier, kind } => self
+                .active_sources
+                .iter()
+                .chain(self.active_sinks.iter())
+                .copied()
+                .find(|endpoint_desc| match endpoint_desc {
+                    EndpointDescriptor::EphemeralNode(id, node_kind) => {
+                        node_kind == kind
+                            && graph
+                                .nodes
+                                .get(id)
+                                .is_some_and(|node| node.identifier.identifier() == identifier)
+                    }
+                    _ => false,
+                }),
+            PortableEndpointRef::DefaultNode(kind) => {
+                let endpoint_desc = EndpointDescriptor::DefaultNode(*kind);
+                self.endpoints
+                    .contains_key(&endpoint_desc)
+                    .then_some(endpoint_desc)
+            }
+            PortableEndpointRef::MediaRole(role, kind) => {
+                let endpoint_desc = EndpointDescriptor::MediaRole(*role, *kind);
+                self.endpoints
+                    .contains_key(&endpoint_desc)
+                    .then_some(endpoint_desc)
+            }
+        }
+    }
+
+    /// Preview what importing a configuration would change.
+    pub fn preview_import(
+        &self,
+        config: &PortableConfig,
+        graph: &Graph,
+        settings: &SonusmixSettings,
+    ) -> ImportPreview {
+        let mut preview = ImportPreview {
+            settings_changed: config.settings != *settings,
+            ..Default::default()
+        };
+        for endpoint in &config.endpoints {
+            let name = endpoint
+                .custom_name
+                .clone()
+                .unwrap_or_else(|| endpoint.endpoint.name());
+            if self
+                .find_portable_endpoint(&endpoint.endpoint, graph)
+                .is_some()
+            {
+                preview.existing.push(name);
+            } else if matches!(endpoint.endpoint, PortableEndpointRef::Node { .. })
+                && find_portable_node(&endpoint.endpoint, graph).is_none()
+            {
+                preview.missing.push(name);
+            } else {
+                preview.added.push(name);
+            }
+        }
+
+        for link in &config.links {
+            let (Some(source), Some(sink)) = (
+                self.find_portable_endpoint(&link.source, graph),
+                self.find_portable_endpoint(&link.sink, graph),
+            ) else {
+                continue;
+            };
+            if let Some(existing) = self
+                .links
+                .iter()
+                .find(|existing| existing.start == source && existing.end == sink)
+                .filter(|existing| existing.state != link.state)
+            {
+                preview.changed_links.push(format!(
+                    "{} → {} ({:?} → {:?})",
+                    link.source.name(),
+                    link.sink.name(),
+                    existing.state,
+                    link.state
+                ));
+            }
+        }
+
+        let imported: Vec<PortableEndpointRef> = config
+            .endpoints
+            .iter()
+            .map(|endpoint| endpoint.endpoint.clone())
+            .collect();
+        preview.not_imported = self
+            .group_nodes
+            .keys()
+            .map(|id| EndpointDescriptor::GroupNode(*id))
+            .chain(self.active_sources.iter().copied())
+            .chain(self.active_sinks.iter().copied())
+            .filter(|endpoint_desc| {
+                self.portable_ref(*endpoint_desc, graph)
+                    .map_or(true, |endpoint| !imported.contains(&endpoint))
+            })
+            .filter_map(|endpoint_desc| self.endpoints.get(&endpoint_desc))
+            .map(|endpoint| endpoint.custom_or_display_name().to_owned())
+            .collect();
+
+        preview
+    }
+
+    /// Import a configuration, using the same messages as the UI to add endpoints and change
+    /// their settings.
+    pub(super) fn import_config(
+        &mut self,
+        graph: &Graph,
+        config: PortableConfig,
+        mode: ImportMode,
+        settings: &SonusmixSettings,
+    ) -> Vec<ToPipewireMessage> {
+        let mut pipewire_messages = Vec::new();
+        let mut send_node_messages = Vec::new();
+        let mut apply = |state: &mut Self, message: SonusmixMsg| {
+            let (_, messages) = state.update(graph, message, settings);
+            pipewire_messages.extend(messages);
+        };
+
+        if mode == ImportMode::Replace {
+            let endpoints: Vec<EndpointDescriptor> = self
+                .group_nodes
+                .iter()
+                // Group nodes in the configuration are kept, so that their devices and the
+                // streams using them aren't removed
+                .filter(|(id, group_node)| {
+                    !config.endpoints.iter().any(|endpoint| {
+                        endpoint.group_node_kind.unwrap_or_default() == group_node.kind
+                            && self
+                                .portable_ref(EndpointDescriptor::GroupNode(**id), graph)
+                                .is_some_and(|portable_ref| portable_ref == endpoint.endpoint)
+                    })
+                })
+                .map(|(id, _)| EndpointDescriptor::GroupNode(*id))
+                .chain(self.active_sources.iter().copied())
+                .chain(self.active_sinks.iter().copied())
+                // Both halves of a loopback are removed together
+                .filter(|endpoint| {
+                    !matches!(endpoint, EndpointDescriptor::Loopback(_, PortKind::Source))
+                })
+                .collect();
+            for endpoint_desc in endpoints {
+                apply(self, SonusmixMsg::RemoveEndpoint(endpoint_desc));
+            }
+            // Remove the links of the removed endpoints, along with their gain nodes
+            let (links, removed_links): (Vec<_>, Vec<_>) = std::mem::take(&mut self.links)
+                .into_iter()
+                .partition(|link| {
+                    self.endpoints.contains_key(&link.start)
+                        && self.endpoints.contains_key(&link.end)
+                });
+            self.links = links;
+            for mut link in removed_links {
+                if let Some(send) = &mut link.send {
+                    send_node_messages.extend(send.detach(graph));
+                }
+            }
+        }
+
+        let mut imported = HashMap::new();
+        for endpoint in &config.endpoints {
+            let endpoint_desc = match self.find_portable_endpoint(&endpoint.endpoint, graph) {
+                Some(endpoint_desc) => endpoint_desc,
+                None => match &endpoint.endpoint {
+                    PortableEndpointRef::GroupNode(name) => {
+                        apply(
+                            self,
+                            SonusmixMsg::AddGroupNode(
+                                name.clone(),
+                                endpoint.group_node_kind.unwrap_or_default(),
+                            ),
+                        );
+                        let Some(id) = self.group_nodes.keys().last() else {
+                            continue;
+                        };
+                        EndpointDescriptor::GroupNode(*id)
+                    }
+                    PortableEndpointRef::Application { name, binary, kind } => {
+                        // Reuse the application if its nodes have been seen already
+                        let id = match self.applications.values().find(|application| {
+                            application.kind == *kind
+                                && application.name == *name
+                                && application.binary == *binary
+                        }) {
+                            Some(application) => application.id,
+                            None => {
+                                let application = Application::new_inactive(
+                                    name.clone(),
+                                    binary.clone(),
+                                    endpoint.application_icon_name.clone().unwrap_or_else(|| {
+                                        "preferences-desktop-multimedia".to_owned()
+                                    }),
+                                    *kind,
+                                );
+                                let id = application.id;
+                                self.applications.insert(id, application);
+                                id
+                            }
+                        };
+                        apply(self, SonusmixMsg::AddApplication(id, *kind));
+                        EndpointDescriptor::Application(id, *kind)
+                    }
+                    PortableEndpointRef::Node { identifier, kind } => {
+                        let Some(id) = find_portable_node(&endpoint.endpoint, graph) else {
+                            warn!("Skipping imported node '{identifier}' as it does not exist");
+                            continue;
+                        };
+                        apply(self, SonusmixMsg::AddEphemeralNode(id, *kind));
+                        EndpointDescriptor::EphemeralNode(id, *kind)
+                    }
+                    PortableEndpointRef::DefaultNode(kind) => {
+                        apply(self, SonusmixMsg::AddDefaultNode(*kind));
+                        EndpointDescriptor::DefaultNode(*kind)
+                    }
+                    PortableEndpointRef::MediaRole(role, kind) => {
+                        apply(self, SonusmixMsg::AddMediaRole(*role, *kind));
+                        EndpointDescriptor::MediaRole(*role, *kind)
+                    }
+                },
+            };
+
+            if let (EndpointDescriptor::GroupNode(id), Some(kind)) =
+                (endpoint_desc, endpoint.group_node_kind)
+            {
+                apply(self, SonusmixMsg::ChangeGroupNodeKind(id, kind));
+            }
+            if !matches!(endpoint_desc, EndpointDescriptor::GroupNode(_)) {
+                apply(
+                    self,
+                    SonusmixMsg::RenameEndpoint(endpoint_desc, endpoint.custom_name.clone()),
+                );
+            }
+            apply(
+                self,
+                SonusmixMsg::SetMaxVolume(endpoint_desc, endpoint.max_volume),
+            );
+            apply(
+                self,
+                SonusmixMsg::SetStartVolume(endpoint_desc, endpoint.start_volume),
+            );
+            apply(self, SonusmixMsg::SetVolume(endpoint_desc, endpoint.volume));
+            apply(self, SonusmixMsg::SetMute(endpoint_desc, endpoint.muted));
+            apply(
+                self,
+                SonusmixMsg::SetVolumeLocked(endpoint_desc, endpoint.volume_locked),
+            );
+            apply(
+                self,
+                SonusmixMsg::SetEndpointColor(endpoint_desc, endpoint.color),
+            );
+            apply(
+                self,
+                SonusmixMsg::SetEndpointIcon(endpoint_desc, endpoint.custom_icon_name.clone()),
+            );
+            // Setting the push to talk mode changes the mute state, so only do it if it changed
+            if self
+                .endpoints
+                .get(&endpoint_desc)
+                .is_some_and(|existing| existing.push_to_talk != endpoint.push_to_talk)
+            {
+                apply(
+                    self,
+                    SonusmixMsg::SetPushToTalk(endpoint_desc, endpoint.push_to_talk),
+                );
+            }
+            if let EndpointDescriptor::Application(id, _) = endpoint_desc {
+                let current = self
+                    .applications
+                    .get(&id)
+                    .map(|application| application.excluded_streams.clone())
+                    .unwrap_or_default();
+                for matcher in current {
+                    if !endpoint.excluded_streams.contains(&matcher) {
+                        apply(self, SonusmixMsg::SetStreamExcluded(id, matcher, false));
+                    }
+                }
+                for matcher in &endpoint.excluded_streams {
+                    apply(
+                        self,
+                        SonusmixMsg::SetStreamExcluded(id, matcher.clone(), true),
+                    );
+                }
+            }
+            imported.insert(endpoint.endpoint.clone(), endpoint_desc);
+        }
+
+        for link in &config.links {
+            let find = |endpoint: &PortableEndpointRef| {
+                imported
+                    .get(endpoint)
+                    .copied()
+                    .or_else(|| self.find_portable_endpoint(endpoint, graph))
+            };
+            let (Some(source), Some(sink)) = (find(&link.source), find(&link.sink)) else {
+                warn!(
+                    "Skipping imported link from '{}' to '{}' as one of them does not exist",
+                    link.source.name(),
+                    link.sink.name()
+                );
+                continue;
+            };
+
+            let exists = self
+                .links
+                .iter()
+                .any(|existing| existing.start == source && existing.end == sink);
+            match link.state {
+                LinkState::ConnectedUnlocked
+                | LinkState::ConnectedLocked
+                | LinkState::PartiallyConnected => apply(self, SonusmixMsg::Link(source, sink)),
+                LinkState::DisconnectedLocked if exists => {
+                    apply(self, SonusmixMsg::RemoveLink(source, sink))
+                }
+                LinkState::DisconnectedLocked => {}
+            }
+            apply(
+                self,
+                SonusmixMsg::SetLinkLocked(source, sink, link.state.is_locked()),
+            );
+            if let Some(volume) = link.send_volume {
+                apply(self, SonusmixMsg::SetLinkVolume(source, sink, volume));
+                apply(
+                    self,
+                    SonusmixMsg::SetLinkMute(source, sink, link.send_muted),
+                );
+            }
+        }
+
+        pipewire_messages.extend(send_node_messages);
+        pipewire_messages
+    }
+}
+
+/// Find the node a portable node reference would be added from. If there are several, the oldest
+/// one is used.
+fn find_portable_node(endpoint: &PortableEndpointRef, graph: &Graph) -> Option<u32> {
+    let PortableEndpointRef::Node { identifier, kind } = endpoint else {
+        return None;
+    };
+    graph
+        .nodes
+        .values()
+        .filter(|node| node.has_port_kind(*kind) && node.identifier.identifier() == identifier)
+        .map(|node| node.id)
+        .min()
+}
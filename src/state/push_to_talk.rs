@@ -0,0 +1,148 @@
+use std::{collections::HashMap, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use tokio::time::{sleep_until, Instant};
+
+use super::{
+    settings::SonusmixSettings, Endpoint, EndpointDescriptor, SonusmixMsg, SonusmixReducer,
+    SonusmixState, SONUSMIX_SETTINGS,
+};
+
+/// Which way a push to talk key changes an endpoint's mute state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PushToTalkMode {
+    /// Muted except while the key is held
+    PushToTalk,
+    /// Unmuted except while the key is held
+    PushToMute,
+}
+
+impl PushToTalkMode {
+    pub fn muted(self, active: bool) -> bool {
+        (self == Self::PushToTalk) != active
+    }
+}
+
+impl Endpoint {
+    /// The mute state required by the endpoint's push to talk mode, if it has one.
+    pub fn push_to_talk_muted(&self) -> Option<bool> {
+        self.push_to_talk
+            .map(|mode| mode.muted(self.push_to_talk_active))
+    }
+
+    /// Whether the push to talk key was released, but the release delay has not passed yet.
+    fn push_to_talk_releasing(&self) -> bool {
+        self.push_to_talk.is_some() && self.push_to_talk_active && !self.push_to_talk_held
+    }
+}
+
+impl SonusmixState {
+    /// Set or clear an endpoint's push to talk mode. Returns the message to set the endpoint to
+    /// the mute state for when the key is not held.
+    pub(super) fn set_push_to_talk(
+        &mut self,
+        endpoint_desc: EndpointDescriptor,
+        mode: Option<PushToTalkMode>,
+    ) -> Option<SonusmixMsg> {
+        let endpoint = self.endpoints.get_mut(&endpoint_desc)?;
+        endpoint.push_to_talk = mode;
+        endpoint.push_to_talk_held = false;
+        endpoint.push_to_talk_active = false;
+        mode.map(|mode| SonusmixMsg::SetMute(endpoint_desc, mode.muted(false)))
+    }
+
+    /// Handle an endpoint's push to talk key being pressed or released. Returns the message to
+    /// change the endpoint's mute state, if it should change now.
+    pub(super) fn push_to_talk(
+        &mut self,
+        endpoint_desc: EndpointDescriptor,
+        held: bool,
+        settings: &SonusmixSettings,
+    ) -> Option<SonusmixMsg> {
+        let endpoint = self.endpoints.get_mut(&endpoint_desc)?;
+        let mode = endpoint.push_to_talk?;
+        endpoint.push_to_talk_held = held;
+        if held {
+            endpoint.push_to_talk_active = true;
+        } else if settings.push_to_talk_release_delay_ms == 0 {
+            endpoint.push_to_talk_active = false;
+        } else {
+            // The release is applied by `push_to_talk_release_task` once the delay has passed
+            return None;
+        }
+        Some(SonusmixMsg::SetMute(
+            endpoint_desc,
+            mode.muted(endpoint.push_to_talk_active),
+        ))
+    }
+
+    /// Apply the release of a push to talk key after the release delay, unless it was pressed
+    /// again in the meantime.
+    pub(super) fn release_push_to_talk(
+        &mut self,
+        endpoint_desc: EndpointDescriptor,
+    ) -> Option<SonusmixMsg> {
+        let endpoint = self.endpoints.get_mut(&endpoint_desc)?;
+        if !endpoint.push_to_talk_releasing() {
+            return None;
+        }
+        endpoint.push_to_talk_active = false;
+        Some(SonusmixMsg::SetMute(
+            endpoint_desc,
+            endpoint.push_to_talk_muted()?,
+        ))
+    }
+}
+
+/// Release push to talk keys once the release delay has passed since they were let go.
+pub(super) async fn push_to_talk_release_task() {
+    let (state_tx, state_rx) = relm4::channel();
+    let (settings_tx, settings_rx) = relm4::channel();
+
+    let mut state = SonusmixReducer::subscribe(&state_tx, |state| state);
+    let mut settings = { SONUSMIX_SETTINGS.read().clone() };
+    SONUSMIX_SETTINGS.subscribe(&settings_tx, Clone::clone);
+    let mut releases: HashMap<EndpointDescriptor, Instant> = HashMap::new();
+
+    loop {
+        // Forget releases for keys that were pressed again, and schedule releases for keys that
+        // were just let go
+        releases.retain(|desc, _| {
+            state
+                .endpoints
+                .get(desc)
+                .is_some_and(Endpoint::push_to_talk_releasing)
+        });
+        let delay = Duration::from_millis(settings.push_to_talk_release_delay_ms as u64);
+        for endpoint in state
+            .endpoints
+            .values()
+            .filter(|endpoint| endpoint.push_to_talk_releasing())
+        {
+            releases
+                .entry(endpoint.descriptor)
+                .or_insert_with(|| Instant::now() + delay);
+        }
+        let next_release = releases.values().min().copied();
+
+        tokio::select! {
+            Some(new_state) = state_rx.recv() => {
+                state = new_state;
+            }
+            Some(new_settings) = settings_rx.recv() => {
+                settings = new_settings;
+            }
+            _ = sleep_until(next_release.unwrap_or_else(Instant::now)), if next_release.is_some() => {
+                let now = Instant::now();
+                releases.retain(|desc, time| {
+                    if *time > now {
+                        return true;
+                    }
+                    SonusmixReducer::emit(SonusmixMsg::ReleasePushToTalk(*desc));
+                    false
+                });
+            }
+            else => break,
+        }
+    }
+}
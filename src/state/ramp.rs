@@ -0,0 +1,382 @@
+use std::time::{Duration, Instant};
+
+use tokio::time::{interval, MissedTickBehavior};
+
+use crate::pipewire_api::{Graph, Node as PwNode, ToPipewireMessage};
+
+use super::{
+    settings::{FaderCurve, SonusmixSettings},
+    Endpoint, EndpointDescriptor, SonusmixReducer, SonusmixState,
+};
+
+/// How often the nodes of an endpoint that is ramping are set to the next volume
+const RAMP_STEP: Duration = Duration::from_millis(20);
+/// How long to wait for a device's new nodes to appear after switching profiles, before giving up
+/// on fading them in
+const PROFILE_FADE_IN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What happens once an endpoint has faded out to silence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RampEnd {
+    Mute,
+    /// Switch the endpoint's device to the profile with this index, and fade its new nodes in
+    SwitchProfile(i32),
+}
+
+/// A fade of an endpoint's nodes from one volume to another, made of small volume steps so that
+/// live audio doesn't pop. The endpoint's own volume and mute state are changed right away, and
+/// only its nodes follow along slowly.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VolumeRamp {
+    /// The volume the nodes started at
+    from: f32,
+    start: Instant,
+    duration: Duration,
+    /// If set, the ramp fades out to silence and then does this. Otherwise, it fades to the
+    /// endpoint's volume.
+    then: Option<RampEnd>,
+    /// Nodes that have to be replaced before the ramp starts. Until then, `start` is when the
+    /// ramp started waiting.
+    waiting_for: Option<Vec<u32>>,
+}
+
+impl VolumeRamp {
+    /// Whether the ramp is changing the volume, rather than waiting for nodes.
+    pub fn is_running(&self) -> bool {
+        self.waiting_for.is_none()
+    }
+
+    /// Whether the ramp fades out to silence before doing something else, rather than fading to
+    /// the endpoint's volume.
+    pub fn fades_out(&self) -> bool {
+        self.then.is_some()
+    }
+
+    /// How far along the ramp is, from 0 to 1.
+    fn progress(&self, now: Instant) -> f64 {
+        if self.duration.is_zero() {
+            return 1.0;
+        }
+        (now.saturating_duration_since(self.start).as_secs_f64() / self.duration.as_secs_f64())
+            .min(1.0)
+    }
+
+    /// The volume the nodes should be at now. The fade follows the fader curve, so that it sounds
+    /// even rather than dropping off quickly at the end.
+    fn volume(&self, endpoint: &Endpoint, now: Instant, curve: FaderCurve) -> f32 {
+        let target = if self.then.is_some() {
+            0.0
+        } else {
+            endpoint.applied_volume()
+        };
+        let from = curve.volume_to_slider(self.from);
+        let to = curve.volume_to_slider(target);
+        curve.slider_to_volume(from + (to - from) * self.progress(now))
+    }
+}
+
+fn volume_messages(nodes: &[&PwNode], volume: f32) -> impl Iterator<Item = ToPipewireMessage> + '_ {
+    nodes.iter().map(move |node| {
+        ToPipewireMessage::NodeVolume(node.id, vec![volume; node.channel_volumes.len()])
+    })
+}
+
+impl SonusmixState {
+    /// The volume an endpoint's nodes are at right now: partway through a ramp, or the
+    /// endpoint's volume.
+    pub(super) fn current_volume(
+        &self,
+        endpoint_desc: EndpointDescriptor,
+        settings: &SonusmixSettings,
+    ) -> Option<f32> {
+        let endpoint = self.endpoints.get(&endpoint_desc)?;
+        Some(match self.volume_ramps.get(&endpoint_desc) {
+            Some(ramp) if ramp.is_running() => {
+                ramp.volume(endpoint, Instant::now(), settings.fader_curve)
+            }
+            _ => endpoint.applied_volume(),
+        })
+    }
+
+    /// Start fading an endpoint's nodes from a volume. Returns the first step.
+    pub(super) fn start_volume_ramp(
+        &mut self,
+        graph: &Graph,
+        endpoint_desc: EndpointDescriptor,
+        from: f32,
+        duration_ms: u32,
+        then: Option<RampEnd>,
+        settings: &SonusmixSettings,
+    ) -> Vec<ToPipewireMessage> {
+        let now = Instant::now();
+        self.volume_ramps.insert(
+            endpoint_desc,
+            VolumeRamp {
+                from,
+                start: now,
+                duration: Duration::from_millis(duration_ms as u64),
+                then,
+                waiting_for: None,
+            },
+        );
+        self.step_volume_ramp(graph, endpoint_desc, now, settings)
+    }
+
+    /// Stop fading an endpoint whose volume is being set directly. Returns false if the endpoint
+    /// is fading out, in which case its nodes are set to the new volume once that is done.
+    pub(super) fn stop_volume_ramp(&mut self, endpoint_desc: EndpointDescriptor) -> bool {
+        match self.volume_ramps.get(&endpoint_desc) {
+            Some(ramp) if ramp.then.is_some() => false,
+            Some(_) => {
+                self.volume_ramps.remove(&endpoint_desc);
+                true
+            }
+            None => true,
+        }
+    }
+
+    /// Fade an endpoint out before muting its nodes, or fade it in after unmuting them, if the
+    /// settings ask for it. The endpoint's mute state should already be changed. Returns None if
+    /// the nodes should just be muted or unmuted.
+    pub(super) fn fade_mute(
+        &mut self,
+        graph: &Graph,
+        endpoint_desc: EndpointDescriptor,
+        muted: bool,
+        settings: &SonusmixSettings,
+    ) -> Option<Vec<ToPipewireMessage>> {
+        let duration_ms = if muted {
+            settings.mute_fade_ms
+        } else {
+            settings.unmute_fade_ms
+        };
+        let ramp_end = self.volume_ramps.get(&endpoint_desc).map(|ramp| ramp.then);
+        match ramp_end {
+            // Switching profiles takes priority, and its nodes are replaced anyway
+            Some(Some(RampEnd::SwitchProfile(_))) => return None,
+            None if duration_ms == 0 => return None,
+            _ => {}
+        }
+        let from = self.current_volume(endpoint_desc, settings)?;
+        if muted {
+            return Some(self.start_volume_ramp(
+                graph,
+                endpoint_desc,
+                from,
+                duration_ms,
+                Some(RampEnd::Mute),
+                settings,
+            ));
+        }
+
+        let nodes = self
+            .resolve_endpoint(endpoint_desc, graph, settings)
+            .unwrap_or_default();
+        let mut messages = Vec::new();
+        // If the endpoint was still fading out, it fades back in from where it got to. Otherwise
+        // its nodes are silenced before they are unmuted.
+        let from = if ramp_end == Some(Some(RampEnd::Mute)) {
+            from
+        } else {
+            messages.extend(volume_messages(&nodes, 0.0));
+            0.0
+        };
+        messages.extend(
+            nodes
+                .iter()
+                .map(|node| ToPipewireMessage::NodeMute(node.id, false)),
+        );
+        messages.extend(self.start_volume_ramp(
+            graph,
+            endpoint_desc,
+            from,
+            duration_ms,
+            None,
+            settings,
+        ));
+        Some(messages)
+    }
+
+    /// Fade the endpoints of a device out before switching its profile, if the settings ask for
+    /// it. Returns None if the profile should just be switched.
+    pub(super) fn fade_device_profile(
+        &mut self,
+        graph: &Graph,
+        endpoint_desc: EndpointDescriptor,
+        profile_index: i32,
+        settings: &SonusmixSettings,
+    ) -> Option<Vec<ToPipewireMessage>> {
+        if settings.profile_fade_ms == 0 {
+            return None;
+        }
+        let device_id = self.endpoints.get(&endpoint_desc)?.device.as_ref()?.id;
+        let device_endpoints: Vec<EndpointDescriptor> = self
+            .endpoints
+            .values()
+            .filter(|endpoint| {
+                endpoint
+                    .device
+                    .as_ref()
+                    .is_some_and(|device| device.id == device_id)
+            })
+            .map(|endpoint| endpoint.descriptor)
+            .collect();
+        let mut messages = Vec::new();
+        for desc in device_endpoints {
+            let Some(from) = self.current_volume(desc, settings) else {
+                continue;
+            };
+            messages.extend(self.start_volume_ramp(
+                graph,
+                desc,
+                from,
+                settings.profile_fade_ms,
+                Some(RampEnd::SwitchProfile(profile_index)),
+                settings,
+            ));
+        }
+        Some(messages)
+    }
+
+    /// Set the nodes of every running ramp to their next volume, and finish the ramps that are
+    /// done.
+    pub(super) fn step_volume_ramps(
+        &mut self,
+        graph: &Graph,
+        settings: &SonusmixSettings,
+    ) -> Vec<ToPipewireMessage> {
+        let now = Instant::now();
+        let descriptors: Vec<EndpointDescriptor> = self.volume_ramps.keys().copied().collect();
+        descriptors
+            .into_iter()
+            .flat_map(|desc| self.step_volume_ramp(graph, desc, now, settings))
+            .collect()
+    }
+
+    pub(super) fn step_volume_ramp(
+        &mut self,
+        graph: &Graph,
+        endpoint_desc: EndpointDescriptor,
+        now: Instant,
+        settings: &SonusmixSettings,
+    ) -> Vec<ToPipewireMessage> {
+        let Some(ramp) = self
+            .volume_ramps
+            .get(&endpoint_desc)
+            .filter(|ramp| ramp.is_running())
+            .cloned()
+        else {
+            return Vec::new();
+        };
+        let nodes = self
+            .resolve_endpoint(endpoint_desc, graph, settings)
+            .unwrap_or_default();
+        let Some(endpoint) = self.endpoints.get(&endpoint_desc) else {
+            self.volume_ramps.remove(&endpoint_desc);
+            return Vec::new();
+        };
+
+        let mut messages = Vec::new();
+        if ramp.progress(now) < 1.0 {
+            let volume = ramp.volume(endpoint, now, settings.fader_curve);
+            messages.extend(volume_messages(&nodes, volume));
+        } else if let Some(RampEnd::SwitchProfile(profile_index)) = ramp.then {
+            // Every endpoint of the device fades out together, so only the first one to finish
+            // switches the profile
+            let switched = endpoint.device.as_ref().is_some_and(|device| {
+                self.pending_device_profiles
+                    .get(&device.id)
+                    .is_some_and(|pending| pending.index == profile_index)
+            });
+            if !switched {
+                messages.extend(self.set_device_profile(graph, endpoint_desc, profile_index));
+            }
+            // Fade in the nodes the device switches to, once the old ones are gone
+            self.volume_ramps.insert(
+                endpoint_desc,
+                VolumeRamp {
+                    from: 0.0,
+                    start: now,
+                    duration: ramp.duration,
+                    then: None,
+                    waiting_for: Some(nodes.iter().map(|node| node.id).collect()),
+                },
+            );
+            return messages;
+        } else {
+            // Leave the nodes exactly at the endpoint's volume and mute state, so that the diff
+            // sees that the ramp is done
+            self.volume_ramps.remove(&endpoint_desc);
+            messages.extend(volume_messages(&nodes, endpoint.applied_volume()));
+            if let Some(muted) = endpoint.applied_mute_state() {
+                messages.extend(
+                    nodes
+                        .iter()
+                        .map(|node| ToPipewireMessage::NodeMute(node.id, muted)),
+                );
+            }
+        }
+
+        if !messages.is_empty() {
+            if let Some(endpoint) = self.endpoints.get_mut(&endpoint_desc) {
+                endpoint.volume_pending = true;
+            }
+        }
+        messages
+    }
+
+    /// Start the ramps that were waiting for their endpoint's nodes to be replaced, now that the
+    /// new nodes are there.
+    pub(super) fn start_waiting_volume_ramps(
+        &mut self,
+        graph: &Graph,
+        settings: &SonusmixSettings,
+    ) -> Vec<ToPipewireMessage> {
+        let now = Instant::now();
+        let mut messages = Vec::new();
+        let descriptors: Vec<EndpointDescriptor> = self.volume_ramps.keys().copied().collect();
+        for endpoint_desc in descriptors {
+            let nodes = self
+                .resolve_endpoint(endpoint_desc, graph, settings)
+                .unwrap_or_default();
+            let Some(ramp) = self.volume_ramps.get_mut(&endpoint_desc) else {
+                continue;
+            };
+            let Some(waiting_for) = &ramp.waiting_for else {
+                continue;
+            };
+            if nodes.is_empty() || nodes.iter().any(|node| waiting_for.contains(&node.id)) {
+                if now.saturating_duration_since(ramp.start) > PROFILE_FADE_IN_TIMEOUT {
+                    self.volume_ramps.remove(&endpoint_desc);
+                }
+                continue;
+            }
+            ramp.waiting_for = None;
+            ramp.start = now;
+            messages.extend(self.step_volume_ramp(graph, endpoint_desc, now, settings));
+        }
+        messages
+    }
+}
+
+/// Step the volume ramps that are running until they are done. The state is only published when
+/// a ramp starts or ends, not on every step.
+pub(super) async fn volume_ramp_task() {
+    let (state_tx, state_rx) = relm4::channel();
+    let mut state = SonusmixReducer::subscribe(&state_tx, |state| state);
+    let mut steps = interval(RAMP_STEP);
+    steps.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        let running = state.volume_ramps.values().any(VolumeRamp::is_running);
+        tokio::select! {
+            Some(new_state) = state_rx.recv() => {
+                state = new_state;
+            }
+            _ = steps.tick(), if running => {
+                SonusmixReducer::step_volume_ramps();
+            }
+            else => break,
+        }
+    }
+}
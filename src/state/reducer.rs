@@ -12,7 +12,13 @@ use relm4::SharedState;
 
 use crate::{
     pipewire_api::{Graph, ToPipewireMessage},
-    state::persistence::{autosave_task, PersistentSettings, PersistentState},
+    state::{
+        activity::lock_backoff_task,
+        midi::midi_feedback_task,
+        persistence::{autosave_task, PersistentSettings, PersistentState},
+        push_to_talk::push_to_talk_release_task,
+        ramp::volume_ramp_task,
+    },
 };
 
 use super::{settings::SonusmixSettings, SonusmixMsg, SonusmixOutputMsg, SonusmixState};
@@ -21,9 +27,15 @@ static SONUSMIX_REDUCER: RwLock<OnceLock<SonusmixReducer>> = RwLock::new(OnceLoc
 pub static SONUSMIX_SETTINGS: SharedState<SonusmixSettings> = SharedState::new();
 const GRAPH_UPDATE_DEBOUNCE_TIME: f64 = 1.0 / 60.0;
 
+/// Whether a `StepVolumeRamps` message was sent and hasn't been handled yet
+static VOLUME_RAMP_STEP_QUEUED: AtomicBool = AtomicBool::new(false);
+
 #[derive(Debug, Clone)]
 enum ReducerMsg {
     Update(SonusmixMsg),
+    /// Set the nodes of the volume ramps that are running to their next step. Only the nodes
+    /// change until a ramp is done, so the state isn't diffed, and is only published then.
+    StepVolumeRamps,
     GraphUpdate(Box<Graph>),
     SettingsChanged,
     Save {
@@ -39,6 +51,8 @@ pub struct SonusmixReducer {
     reducer_sender: mpsc::Sender<ReducerMsg>,
     thread_handle: JoinHandle<()>,
     state: SharedState<(Arc<SonusmixState>, Option<SonusmixOutputMsg>)>,
+    /// The most recent Pipewire graph, for debugging
+    graph: SharedState<Arc<Graph>>,
 }
 
 impl SonusmixReducer {
@@ -74,7 +88,7 @@ impl SonusmixReducer {
                 let reducer = reducer_guard
                     .get()
                     .expect("reducer was not initialized by SonusmixReducer::init()");
-                let mut graph: Box<Graph> = Default::default();
+                let mut graph: Arc<Graph> = Default::default();
 
                 let save = || {
                     let state = { reducer.state.read().0.as_ref().clone() };
@@ -96,51 +110,62 @@ impl SonusmixReducer {
                             let settings = { SONUSMIX_SETTINGS.read().clone() };
                             let (output_msg, mut messages) =
                                 state.update(&graph, msg.clone(), &settings);
-                            messages.extend(state.diff(&graph, &settings));
+                            let (diff_output_msgs, diff_messages) =
+                                state.diff(&graph, &settings, false);
+                            messages.extend(diff_messages);
                             for message in messages {
                                 reducer
                                     .pw_sender
                                     .send(message)
                                     .expect("Failed to send message to Pipewire thread");
                             }
-                            let state = (Arc::new(state), output_msg);
-                            {
-                                // Write the new version of the state
-                                *reducer.state.write() = state;
+                            reducer.publish(
+                                Arc::new(state),
+                                output_msg.into_iter().chain(diff_output_msgs),
+                            );
+                        }
+                        ReducerMsg::StepVolumeRamps => {
+                            VOLUME_RAMP_STEP_QUEUED.store(false, Ordering::Release);
+                            let old_state = { reducer.state.read().0.clone() };
+                            let mut state = old_state.as_ref().clone();
+                            let settings = { SONUSMIX_SETTINGS.read().clone() };
+                            for message in state.step_volume_ramps(&graph, &settings) {
+                                reducer
+                                    .pw_sender
+                                    .send(message)
+                                    .expect("Failed to send message to Pipewire thread");
+                            }
+                            if state.volume_ramps == old_state.volume_ramps {
+                                reducer.state.write_inner().0 = Arc::new(state);
+                            } else {
+                                reducer.publish(Arc::new(state), []);
                             }
                         }
                         ReducerMsg::GraphUpdate(new_graph) => {
-                            graph = new_graph;
+                            graph = Arc::from(new_graph);
                             let settings = { SONUSMIX_SETTINGS.read().clone() };
                             let mut state = { reducer.state.read().0.as_ref().clone() };
-                            let messages = state.diff(&graph, &settings);
+                            let (output_msgs, messages) = state.diff(&graph, &settings, true);
                             for message in messages {
                                 reducer
                                     .pw_sender
                                     .send(message)
                                     .expect("Failed to send message to Pipewire thread");
                             }
-                            let state = (Arc::new(state), None);
-                            {
-                                // Write the new version of the state
-                                *reducer.state.write() = state;
-                            }
+                            reducer.publish(Arc::new(state), output_msgs);
+                            *reducer.graph.write() = graph.clone();
                         }
                         ReducerMsg::SettingsChanged => {
                             let settings = { SONUSMIX_SETTINGS.read().clone() };
                             let mut state = { reducer.state.read().0.as_ref().clone() };
-                            let messages = state.diff(&graph, &settings);
+                            let (output_msgs, messages) = state.diff(&graph, &settings, false);
                             for message in messages {
                                 reducer
                                     .pw_sender
                                     .send(message)
                                     .expect("Failed to send message to Pipewire thread");
                             }
-                            let state = (Arc::new(state), None);
-                            {
-                                // Write the new version of the state
-                                *reducer.state.write() = state;
-                            }
+                            reducer.publish(Arc::new(state), output_msgs);
                         }
                         ReducerMsg::Save {
                             clear_state,
@@ -184,6 +209,7 @@ impl SonusmixReducer {
             }
         }
 
+        let midi_feedback_pw_sender = pw_sender.clone();
         // TODO: Use the result value from this instead of the AtomicBool to guarantee the reducer
         // is only initialzed once. This may require sending an exit message to the thread, though.
         let _ = reducer.set(Self {
@@ -191,6 +217,7 @@ impl SonusmixReducer {
             reducer_sender: tx,
             thread_handle: reducer_handle,
             state,
+            graph: SharedState::new(),
         });
 
         // Tell the reducer to diff the graph again whenever there is a settings update
@@ -210,6 +237,14 @@ impl SonusmixReducer {
 
         // Start the autosave task
         relm4::spawn(autosave_task());
+        // Start sending state updates to MIDI controllers
+        relm4::spawn(midi_feedback_task(midi_feedback_pw_sender));
+        // Start releasing push to talk keys after their delay
+        relm4::spawn(push_to_talk_release_task());
+        // Start restoring locks that are fought over once their wait is over
+        relm4::spawn(lock_backoff_task());
+        // Start stepping volumes that are fading
+        relm4::spawn(volume_ramp_task());
 
         // Return a function that, after a short delay, sends a `GraphUpdate` message. The message
         // will contain the most recent of the graphs given by calls of this function during the
@@ -248,6 +283,21 @@ impl SonusmixReducer {
         }
     }
 
+    /// Step the volume ramps that are running, unless the last step is still waiting to be
+    /// handled.
+    pub(super) fn step_volume_ramps() {
+        if VOLUME_RAMP_STEP_QUEUED.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        if let Some(reducer) = SONUSMIX_REDUCER
+            .read()
+            .expect("panic if reducer lock is poisoned")
+            .get()
+        {
+            let _ = reducer.reducer_sender.send(ReducerMsg::StepVolumeRamps);
+        }
+    }
+
     /// Clearing the state should almost always be followed by exiting the app!
     pub fn save(clear_state: bool, clear_settings: bool) {
         if let Some(reducer) = SONUSMIX_REDUCER
@@ -290,6 +340,23 @@ impl SonusmixReducer {
         }
     }
 
+    /// Write a new version of the state. Subscribers receive at most one output message with each
+    /// version, so if there are several, the state is written once for each of them.
+    fn publish(
+        &self,
+        state: Arc<SonusmixState>,
+        output_msgs: impl IntoIterator<Item = SonusmixOutputMsg>,
+    ) {
+        let mut output_msgs = output_msgs.into_iter().peekable();
+        if output_msgs.peek().is_none() {
+            *self.state.write() = (state, None);
+            return;
+        }
+        for output_msg in output_msgs {
+            *self.state.write() = (state.clone(), Some(output_msg));
+        }
+    }
+
     /// Subscribe to receive updates to the Sonusmix state.
     /// # Returns
     /// Returns the current state.
@@ -313,10 +380,49 @@ impl SonusmixReducer {
         state
     }
 
+    /// Get the most recent Pipewire graph.
+    /// # Panics
+    /// This function will panic if it is called before the reducer has been initialized.
+    pub fn graph() -> Arc<Graph> {
+        let reducer_guard = SONUSMIX_REDUCER
+            .read()
+            .expect("panic if reducer lock is poisoned");
+        let reducer = reducer_guard
+            .get()
+            .expect("The reducer must be initialized before reading the graph");
+        let graph = reducer.graph.read().clone();
+        graph
+    }
+
+    /// Subscribe to receive updates to the Pipewire graph. This is only meant for debugging, the
+    /// rest of the UI should only depend on the Sonusmix state.
+    /// # Returns
+    /// Returns the current graph.
+    /// # Panics
+    /// This function will panic if it is called before the reducer has been initialized.
+    pub fn subscribe_graph<Msg, F>(sender: &relm4::Sender<Msg>, f: F) -> Arc<Graph>
+    where
+        F: Fn(Arc<Graph>) -> Msg + 'static + Send + Sync,
+        Msg: Send + 'static,
+    {
+        let reducer_guard = SONUSMIX_REDUCER
+            .read()
+            .expect("panic if reducer lock is poisoned");
+        let reducer = reducer_guard
+            .get()
+            .expect("The reducer must be initialized before subscribing to it");
+        reducer
+            .graph
+            .subscribe(sender, move |graph| f(graph.clone()));
+        let graph = reducer.graph.read().clone();
+        graph
+    }
+
     /// Subscribe to receive updates to the Sonusmix state, along with a copy of the message that
-    /// caused the update, if there was one. Note that only changes made by the frontend will
-    /// include a state update message, as the reducer does not attempt to convert Pipewire updates
-    /// into state update messages.
+    /// caused the update, if there was one. Note that Pipewire updates will generally not include
+    /// a state update message, as the reducer does not attempt to convert them into state update
+    /// messages. The exception is when diffing changes the state's structure, such as when an
+    /// endpoint is moved to a new node.
     /// # Returns
     /// Returns the current state.
     /// # Panics
@@ -0,0 +1,266 @@
+use std::time::{Duration, Instant};
+
+use itertools::Itertools;
+use log::error;
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+
+use crate::pipewire_api::{Graph, Node as PwNode, ToPipewireMessage};
+
+use super::{settings::SonusmixSettings, EndpointDescriptor, SonusmixState};
+
+/// How long to wait for a gain node to appear before asking for it again, in case creating it
+/// failed
+const SEND_NODE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SendId(Ulid);
+
+impl SendId {
+    fn new() -> Self {
+        Self(Ulid::new())
+    }
+}
+
+/// The level a link sends its source at, like an aux send on a mixing desk. A link with a send is
+/// routed through a hidden gain node instead of connecting its endpoints directly, and the volume
+/// of that node is the gain of the link.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LinkSend {
+    pub id: SendId,
+    pub volume: f32,
+    pub muted: bool,
+    /// The gain node the link is currently routed through
+    #[serde(skip)]
+    node_id: Option<u32>,
+    /// Whether the gain of the gain node is being changed
+    #[serde(skip)]
+    pending: bool,
+    /// When the gain node was last asked for, while it doesn't exist
+    #[serde(skip)]
+    requested_at: Option<Instant>,
+}
+
+impl LinkSend {
+    fn new() -> Self {
+        Self {
+            id: SendId::new(),
+            volume: 1.0,
+            muted: false,
+            node_id: None,
+            pending: false,
+            requested_at: None,
+        }
+    }
+
+    /// Whether the link is routed through its gain node yet.
+    pub fn is_active(&self) -> bool {
+        self.node_id.is_some()
+    }
+
+    /// The gain node the link is routed through, if it exists.
+    pub(super) fn node<'a>(&self, graph: &'a Graph) -> Option<&'a PwNode> {
+        self.node_id.and_then(|id| graph.nodes.get(&id))
+    }
+
+    /// Remove the gain node while the link is disconnected, keeping the send level for when it
+    /// is connected again.
+    pub(super) fn detach(&mut self, graph: &Graph) -> Option<ToPipewireMessage> {
+        self.node_id = None;
+        self.pending = false;
+        self.requested_at = None;
+        graph
+            .send_nodes
+            .contains_key(&self.id.0)
+            .then_some(ToPipewireMessage::RemoveSendNode(self.id.0))
+    }
+
+    fn gain_messages(&self, node: &PwNode) -> [ToPipewireMessage; 2] {
+        [
+            ToPipewireMessage::NodeVolume(node.id, vec![self.volume; node.channel_volumes.len()]),
+            ToPipewireMessage::NodeMute(node.id, self.muted),
+        ]
+    }
+}
+
+impl SonusmixState {
+    /// Change the gain of a link, routing it through a gain node first if it isn't already. The
+    /// node is created by the next diff.
+    pub(super) fn set_link_send(
+        &mut self,
+        graph: &Graph,
+        source: EndpointDescriptor,
+        sink: EndpointDescriptor,
+        volume: Option<f32>,
+        muted: Option<bool>,
+    ) -> Vec<ToPipewireMessage> {
+        let Some(link) = self
+            .links
+            .iter_mut()
+            .find(|link| link.start == source && link.end == sink)
+        else {
+            error!("Cannot set the send level of a link that does not exist");
+            return Vec::new();
+        };
+        let send = link.send.get_or_insert_with(LinkSend::new);
+        if let Some(volume) = volume {
+            send.volume = volume.max(0.0);
+        }
+        if let Some(muted) = muted {
+            send.muted = muted;
+        }
+
+        match send.node(graph) {
+            Some(node) => {
+                send.pending = true;
+                send.gain_messages(node).into()
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// Connect the endpoints of a link directly again, and remove its gain node.
+    pub(super) fn remove_link_send(
+        &mut self,
+        graph: &Graph,
+        source: EndpointDescriptor,
+        sink: EndpointDescriptor,
+        settings: &SonusmixSettings,
+    ) -> Vec<ToPipewireMessage> {
+        let source_nodes = self
+            .resolve_endpoint(source, graph, settings)
+            .unwrap_or_default();
+        let sink_nodes = self
+            .resolve_endpoint(sink, graph, settings)
+            .unwrap_or_default();
+        let Some(link) = self
+            .links
+            .iter_mut()
+            .find(|link| link.start == source && link.end == sink)
+        else {
+            return Vec::new();
+        };
+        let Some(send) = link.send.take() else {
+            return Vec::new();
+        };
+
+        // Create the direct links before the gain node goes away, so the audio keeps flowing
+        let mut messages = Vec::new();
+        if link.state.is_connected() != Some(false) && send.node_id.is_some() {
+            messages.extend(
+                node_pairs(&link_hops(&source_nodes, &sink_nodes, None))
+                    .into_iter()
+                    .map(|(start_id, end_id)| ToPipewireMessage::CreateNodeLinks {
+                        start_id,
+                        end_id,
+                    }),
+            );
+            link.pending = !messages.is_empty();
+        }
+        if graph.send_nodes.contains_key(&send.id.0) {
+            messages.push(ToPipewireMessage::RemoveSendNode(send.id.0));
+        }
+        messages
+    }
+}
+
+/// The source and sink nodes of each hop a link takes through the graph: directly from the source
+/// to the sink, or through the link's gain node.
+pub(super) fn link_hops<'a>(
+    source: &[&'a PwNode],
+    sink: &[&'a PwNode],
+    send_node: Option<&'a PwNode>,
+) -> Vec<(Vec<&'a PwNode>, Vec<&'a PwNode>)> {
+    match send_node {
+        Some(send_node) => vec![
+            (source.to_vec(), vec![send_node]),
+            (vec![send_node], sink.to_vec()),
+        ],
+        None => vec![(source.to_vec(), sink.to_vec())],
+    }
+}
+
+/// The ids of every pair of nodes that should be connected for the hops of a link.
+pub(super) fn node_pairs(hops: &[(Vec<&PwNode>, Vec<&PwNode>)]) -> Vec<(u32, u32)> {
+    hops.iter()
+        .flat_map(|(source, sink)| {
+            source
+                .iter()
+                .cartesian_product(sink.iter())
+                .map(|(source, sink)| (source.id, sink.id))
+        })
+        .collect()
+}
+
+/// Check that a link with a send level is routed through its gain node, and that the node has the
+/// right gain. Returns the gain node once the link is routed through it; until then, the rest of
+/// the link should not be diffed.
+pub(super) fn diff_send_node<'a>(
+    send: &mut LinkSend,
+    description: &str,
+    source: &[&PwNode],
+    sink: &[&PwNode],
+    graph: &'a Graph,
+    messages: &mut Vec<ToPipewireMessage>,
+) -> Option<&'a PwNode> {
+    let Some(node) = graph
+        .send_nodes
+        .get(&send.id.0)
+        .and_then(|send_node| send_node.id)
+        .and_then(|id| graph.nodes.get(&id))
+        .filter(|node| !node.ports.is_empty())
+    else {
+        // If the node went away, create it again. If it doesn't show up, it is asked for again
+        // after a while.
+        if send.node_id.take().is_some() {
+            send.pending = false;
+            send.requested_at = None;
+        }
+        if send.requested_at.map_or(true, |requested_at| {
+            requested_at.elapsed() >= SEND_NODE_TIMEOUT
+        }) {
+            send.requested_at = Some(Instant::now());
+            messages.push(ToPipewireMessage::CreateSendNode(
+                description.to_owned(),
+                send.id.0,
+            ));
+        }
+        return None;
+    };
+    send.requested_at = None;
+
+    if send.node_id != Some(node.id) {
+        // The node is new, so route the link through it and stop connecting the endpoints
+        // directly
+        send.node_id = Some(node.id);
+        messages.extend(
+            node_pairs(&link_hops(source, sink, Some(node)))
+                .into_iter()
+                .map(|(start_id, end_id)| ToPipewireMessage::CreateNodeLinks { start_id, end_id }),
+        );
+        messages.extend(
+            node_pairs(&link_hops(source, sink, None))
+                .into_iter()
+                .map(|(start_id, end_id)| ToPipewireMessage::RemoveNodeLinks { start_id, end_id }),
+        );
+        messages.extend(send.gain_messages(node));
+        send.pending = true;
+        return None;
+    }
+
+    // The gain node is managed by Sonusmix, so its gain is always restored
+    let applied = node
+        .channel_volumes
+        .iter()
+        .all(|volume| *volume == send.volume)
+        && node.mute == send.muted;
+    if send.pending {
+        if applied {
+            send.pending = false;
+        }
+    } else if !applied {
+        messages.extend(send.gain_messages(node));
+        send.pending = true;
+    }
+    Some(node)
+}
@@ -1,6 +1,13 @@
+use std::{
+    net::{IpAddr, Ipv4Addr},
+    path::PathBuf,
+};
+
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+use super::EndpointDescriptor;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SonusmixSettings {
     pub collapse_to_tray_on_close: bool,
     pub start_collapsed_to_tray: bool,
@@ -8,7 +15,51 @@ pub struct SonusmixSettings {
     pub lock_group_node_connections: bool,
     pub show_group_node_change_warning: bool,
     pub application_sources_include_monitors: bool,
+    /// The highest volume the volume sliders go to, in percent on the cubic curve
     pub volume_limit: f64,
+    #[serde(default)]
+    pub fader_curve: FaderCurve,
+    /// Where recordings are saved. If empty, the user's music directory is used.
+    #[serde(default)]
+    pub recording_directory: String,
+    /// Listen for OSC messages on `osc_port`
+    #[serde(default)]
+    pub osc_enabled: bool,
+    #[serde(default = "default_osc_port")]
+    pub osc_port: u16,
+    /// The address the OSC server listens on. If empty, it only listens on the loopback address,
+    /// so only programs on this computer can send it messages.
+    #[serde(default)]
+    pub osc_bind_address: String,
+    /// The port OSC clients listen for feedback on. If 0, feedback is sent to the port the client
+    /// sent from.
+    #[serde(default)]
+    pub osc_feedback_port: u16,
+    /// How long endpoints stay in their push to talk state after the key is released
+    #[serde(default)]
+    pub push_to_talk_release_delay_ms: u32,
+    /// Show a desktop notification when another client keeps changing something that is locked
+    #[serde(default)]
+    pub notify_lock_fights: bool,
+    /// Dim the other sources connected to the cue endpoint while an endpoint is soloed
+    #[serde(default)]
+    pub dim_cue_on_solo: bool,
+    /// How much the other cue sources are dimmed by, in decibels
+    #[serde(default = "default_cue_dim_db")]
+    pub cue_dim_db: f64,
+    /// How long endpoints take to fade out before they are muted
+    #[serde(default)]
+    pub mute_fade_ms: u32,
+    /// How long endpoints take to fade in after they are unmuted
+    #[serde(default)]
+    pub unmute_fade_ms: u32,
+    /// How long volume changes from OSC and MIDI, and `FadeVolume` requests on the control
+    /// socket, take to fade in
+    #[serde(default)]
+    pub remote_volume_fade_ms: u32,
+    /// How long a device takes to fade out before switching profiles, and to fade in after
+    #[serde(default)]
+    pub profile_fade_ms: u32,
 }
 
 pub const DEFAULT_SETTINGS: SonusmixSettings = SonusmixSettings {
@@ -19,10 +70,200 @@ pub const DEFAULT_SETTINGS: SonusmixSettings = SonusmixSettings {
     show_group_node_change_warning: true,
     application_sources_include_monitors: false,
     volume_limit: 100.0,
+    fader_curve: FaderCurve::Cubic,
+    recording_directory: String::new(),
+    osc_enabled: false,
+    osc_port: 7001,
+    osc_bind_address: String::new(),
+    osc_feedback_port: 0,
+    push_to_talk_release_delay_ms: 0,
+    notify_lock_fights: false,
+    dim_cue_on_solo: false,
+    cue_dim_db: -20.0,
+    mute_fade_ms: 0,
+    unmute_fade_ms: 0,
+    remote_volume_fade_ms: 0,
+    profile_fade_ms: 0,
 };
 
+fn default_osc_port() -> u16 {
+    DEFAULT_SETTINGS.osc_port
+}
+
+fn default_cue_dim_db() -> f64 {
+    DEFAULT_SETTINGS.cue_dim_db
+}
+
 impl Default for SonusmixSettings {
     fn default() -> Self {
         DEFAULT_SETTINGS
     }
 }
+
+impl SonusmixSettings {
+    /// The highest volume that can be set with the volume sliders.
+    pub fn max_volume(&self) -> f32 {
+        FaderCurve::Cubic.slider_to_volume(self.volume_limit)
+    }
+
+    /// The position of the end of the volume sliders.
+    pub fn slider_limit(&self) -> f64 {
+        self.fader_curve.volume_to_slider(self.max_volume())
+    }
+
+    /// Express the volume limit in decibels.
+    pub fn volume_limit_db(&self) -> f64 {
+        volume_to_db(self.max_volume())
+    }
+
+    pub fn set_volume_limit_db(&mut self, db: f64) {
+        self.volume_limit = FaderCurve::Cubic.volume_to_slider(db_to_volume(db));
+    }
+
+    pub fn recording_directory(&self) -> PathBuf {
+        if self.recording_directory.is_empty() {
+            dirs::audio_dir()
+                .or_else(dirs::home_dir)
+                .unwrap_or_default()
+        } else {
+            PathBuf::from(&self.recording_directory)
+        }
+    }
+
+    /// The address the OSC server listens on, or None if the setting isn't a valid address.
+    pub fn osc_bind_address(&self) -> Option<IpAddr> {
+        if self.osc_bind_address.trim().is_empty() {
+            Some(IpAddr::V4(Ipv4Addr::LOCALHOST))
+        } else {
+            self.osc_bind_address.trim().parse().ok()
+        }
+    }
+
+    /// Whether links made between two endpoints from Sonusmix should be locked.
+    pub fn lock_new_link(&self, source: EndpointDescriptor, sink: EndpointDescriptor) -> bool {
+        if matches!(source, EndpointDescriptor::GroupNode(_))
+            || matches!(sink, EndpointDescriptor::GroupNode(_))
+        {
+            self.lock_group_node_connections
+        } else {
+            self.lock_endpoint_connections
+        }
+    }
+}
+
+/// How positions on the volume sliders map to volumes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum FaderCurve {
+    /// The volume is the cube of the slider position, which matches how loud the volume sounds
+    /// more closely than a linear curve.
+    #[default]
+    Cubic,
+    Linear,
+    /// The slider position is proportional to the volume in decibels, with 100% at 0 dB and 0%
+    /// at -60 dB.
+    Decibel,
+}
+
+/// How many decibels each percent of the slider is with the decibel curve
+const DB_PER_PERCENT: f64 = 0.6;
+
+impl FaderCurve {
+    pub const ALL: [Self; 3] = [Self::Cubic, Self::Linear, Self::Decibel];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Cubic => "Cubic",
+            Self::Linear => "Linear",
+            Self::Decibel => "Decibel",
+        }
+    }
+
+    /// Convert a volume to a position on the volume sliders, in percent.
+    pub fn volume_to_slider(self, volume: f32) -> f64 {
+        match self {
+            Self::Cubic => (volume.powf(1.0 / 3.0) * 100.0) as f64,
+            Self::Linear => volume as f64 * 100.0,
+            Self::Decibel if volume <= 0.0 => 0.0,
+            Self::Decibel => (100.0 + volume_to_db(volume) / DB_PER_PERCENT).max(0.0),
+        }
+    }
+
+    pub fn slider_to_volume(self, slider: f64) -> f32 {
+        match self {
+            Self::Cubic => (slider as f32 / 100.0).powf(3.0),
+            Self::Linear => slider as f32 / 100.0,
+            Self::Decibel if slider <= 0.0 => 0.0,
+            Self::Decibel => db_to_volume((slider - 100.0) * DB_PER_PERCENT),
+        }
+    }
+
+    /// Show a volume in decibels, along with its slider position for curves other than the
+    /// decibel curve.
+    pub fn format_volume(self, volume: f32) -> String {
+        let db = if volume <= 0.0 {
+            "-∞ dB".to_owned()
+        } else {
+            format!("{:+.1} dB", volume_to_db(volume))
+        };
+        match self {
+            Self::Decibel => db,
+            _ => format!("{:.0}% ({db})", self.volume_to_slider(volume)),
+        }
+    }
+
+    /// Parse a volume typed in decibels (e.g. "-6 dB"), or as a slider position in percent.
+    pub fn parse_volume(self, text: &str) -> Option<f32> {
+        // Ignore the decibels shown after the percentage, so that shown values can be parsed
+        let text = text.split('(').next().unwrap_or(text).trim();
+        if let Some(db) = text
+            .strip_suffix("dB")
+            .or_else(|| text.strip_suffix("db"))
+            .or_else(|| text.strip_suffix("DB"))
+        {
+            let db = db.trim().replace('∞', "inf");
+            return Some(db_to_volume(db.parse().ok()?));
+        }
+        let percent: f64 = text.strip_suffix('%').unwrap_or(text).trim().parse().ok()?;
+        Some(self.slider_to_volume(percent.max(0.0)))
+    }
+}
+
+pub fn volume_to_db(volume: f32) -> f64 {
+    20.0 * (volume as f64).log10()
+}
+
+pub fn db_to_volume(db: f64) -> f32 {
+    10.0f64.powf(db / 20.0) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fader_curves() {
+        for curve in FaderCurve::ALL {
+            for volume in [0.0, 0.125, 0.5, 1.0, 2.0] {
+                let round_trip = curve.slider_to_volume(curve.volume_to_slider(volume));
+                assert!((round_trip - volume).abs() < 1e-5, "{curve:?} {volume}");
+            }
+            assert!((curve.volume_to_slider(1.0) - 100.0).abs() < 1e-9);
+        }
+        assert!((FaderCurve::Decibel.volume_to_slider(db_to_volume(-30.0)) - 50.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn parse_volumes() {
+        let curve = FaderCurve::Linear;
+        assert_eq!(curve.parse_volume("50%"), Some(0.5));
+        assert_eq!(curve.parse_volume(" 50 "), Some(0.5));
+        assert_eq!(curve.parse_volume("0 dB"), Some(1.0));
+        assert_eq!(curve.parse_volume("-∞ dB"), Some(0.0));
+        assert!((curve.parse_volume("-6dB").unwrap() - 0.501).abs() < 0.001);
+        assert_eq!(curve.parse_volume("loud"), None);
+        // Shown values can be parsed back
+        let text = curve.format_volume(0.25);
+        assert_eq!(text, "25% (-12.0 dB)");
+        assert_eq!(curve.parse_volume(&text), Some(0.25));
+    }
+}
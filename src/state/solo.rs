@@ -0,0 +1,235 @@
+use log::error;
+
+use crate::pipewire_api::{Graph, PortKind, ToPipewireMessage};
+
+use super::{
+    settings::{db_to_volume, SonusmixSettings},
+    EndpointDescriptor, SonusmixMsg, SonusmixState,
+};
+
+/// A link to the cue endpoint that is dimmed while other endpoints are soloed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DimmedCueLink {
+    pub source: EndpointDescriptor,
+    /// The send volume and mute state of the link before it was dimmed, if it had a send level
+    prior_send: Option<(f32, bool)>,
+}
+
+impl SonusmixState {
+    /// Choose the sink that soloed endpoints are sent to. Any solos are released first.
+    pub(super) fn set_cue_endpoint(
+        &mut self,
+        graph: &Graph,
+        cue: Option<EndpointDescriptor>,
+        settings: &SonusmixSettings,
+    ) -> Vec<ToPipewireMessage> {
+        if cue.is_some_and(|cue| !cue.is_kind(PortKind::Sink)) {
+            error!("Cannot use {cue:?} as the cue endpoint, as it is not a sink");
+            return Vec::new();
+        }
+        let messages = self.release_solos(graph, settings);
+        self.cue_endpoint = cue;
+        messages
+    }
+
+    /// Solo an endpoint to the cue endpoint, or release it. Soloing connects the endpoint to the
+    /// cue endpoint with a temporary link if it isn't connected already, and releasing it removes
+    /// that link again.
+    pub(super) fn set_solo(
+        &mut self,
+        graph: &Graph,
+        endpoint_desc: EndpointDescriptor,
+        soloed: bool,
+        settings: &SonusmixSettings,
+    ) -> Vec<ToPipewireMessage> {
+        let Some(cue) = self.cue_endpoint else {
+            error!("Cannot solo {endpoint_desc:?} as there is no cue endpoint");
+            return Vec::new();
+        };
+        if self.soloed.contains(&endpoint_desc) == soloed
+            || soloed
+                && (!endpoint_desc.is_kind(PortKind::Source)
+                    || !self.endpoints.contains_key(&endpoint_desc))
+        {
+            return Vec::new();
+        }
+
+        let mut messages = Vec::new();
+        if soloed {
+            self.soloed.push(endpoint_desc);
+            // Links the user made are left alone, including ones locked to be disconnected
+            if !self
+                .links
+                .iter()
+                .any(|link| link.start == endpoint_desc && link.end == cue)
+            {
+                let (_, link_messages) =
+                    self.update(graph, SonusmixMsg::Link(endpoint_desc, cue), settings);
+                messages.extend(link_messages);
+                if let Some(link) = self
+                    .links
+                    .iter_mut()
+                    .find(|link| link.start == endpoint_desc && link.end == cue)
+                {
+                    link.temporary = true;
+                }
+            }
+        } else {
+            self.soloed.retain(|desc| *desc != endpoint_desc);
+            if self
+                .links
+                .iter()
+                .any(|link| link.start == endpoint_desc && link.end == cue && link.temporary)
+            {
+                let (_, link_messages) =
+                    self.update(graph, SonusmixMsg::RemoveLink(endpoint_desc, cue), settings);
+                messages.extend(link_messages);
+            }
+        }
+        messages.extend(self.dim_cue_links(graph, settings));
+        messages
+    }
+
+    /// Release every solo. Solo links and gain nodes linger in Pipewire, so this is done before
+    /// exiting to leave the graph the way the user set it up.
+    pub(super) fn release_solos(
+        &mut self,
+        graph: &Graph,
+        settings: &SonusmixSettings,
+    ) -> Vec<ToPipewireMessage> {
+        let mut messages = Vec::new();
+        for endpoint_desc in self.soloed.clone() {
+            messages.extend(self.set_solo(graph, endpoint_desc, false, settings));
+        }
+        messages
+    }
+
+    /// Release the solo of an endpoint that was removed, and stop using it as the cue endpoint.
+    pub(super) fn forget_solo_endpoint(
+        &mut self,
+        graph: &Graph,
+        endpoint_desc: EndpointDescriptor,
+        settings: &SonusmixSettings,
+    ) -> Vec<ToPipewireMessage> {
+        if self.cue_endpoint == Some(endpoint_desc) {
+            self.set_cue_endpoint(graph, None, settings)
+        } else {
+            self.dimmed_cue_links
+                .retain(|dimmed| dimmed.source != endpoint_desc);
+            self.set_solo(graph, endpoint_desc, false, settings)
+        }
+    }
+
+    pub(super) fn replace_solo_endpoint(
+        &mut self,
+        old: EndpointDescriptor,
+        new: EndpointDescriptor,
+    ) {
+        if self.cue_endpoint == Some(old) {
+            self.cue_endpoint = Some(new);
+        }
+        for desc in self.soloed.iter_mut().chain(
+            self.dimmed_cue_links
+                .iter_mut()
+                .map(|dimmed| &mut dimmed.source),
+        ) {
+            if *desc == old {
+                *desc = new;
+            }
+        }
+    }
+
+    /// While anything is soloed, dim the links to the cue endpoint from endpoints that aren't,
+    /// if the settings ask for it. Links that no longer need to be dimmed get their send levels
+    /// back.
+    pub(super) fn dim_cue_links(
+        &mut self,
+        graph: &Graph,
+        settings: &SonusmixSettings,
+    ) -> Vec<ToPipewireMessage> {
+        let mut messages = Vec::new();
+        let Some(cue) = self.cue_endpoint else {
+            return messages;
+        };
+        let to_dim: Vec<EndpointDescriptor> = if settings.dim_cue_on_solo && !self.soloed.is_empty()
+        {
+            self.links
+                .iter()
+                .filter(|link| {
+                    link.end == cue && !link.temporary && !self.soloed.contains(&link.start)
+                })
+                .map(|link| link.start)
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        for dimmed in std::mem::take(&mut self.dimmed_cue_links) {
+            if to_dim.contains(&dimmed.source) {
+                self.dimmed_cue_links.push(dimmed);
+                continue;
+            }
+            // The link may have been removed while it was dimmed
+            if !self
+                .links
+                .iter()
+                .any(|link| link.start == dimmed.source && link.end == cue)
+            {
+                continue;
+            }
+            messages.extend(match dimmed.prior_send {
+                Some((volume, muted)) => {
+                    self.set_link_send(graph, dimmed.source, cue, Some(volume), Some(muted))
+                }
+                None => self.remove_link_send(graph, dimmed.source, cue, settings),
+            });
+        }
+
+        let dim_gain = db_to_volume(settings.cue_dim_db);
+        for source in to_dim {
+            if self
+                .dimmed_cue_links
+                .iter()
+                .any(|dimmed| dimmed.source == source)
+            {
+                continue;
+            }
+            let prior_send = self
+                .links
+                .iter()
+                .find(|link| link.start == source && link.end == cue)
+                .and_then(|link| link.send)
+                .map(|send| (send.volume, send.muted));
+            self.dimmed_cue_links
+                .push(DimmedCueLink { source, prior_send });
+            let volume = prior_send.map_or(1.0, |(volume, _)| volume) * dim_gain;
+            messages.extend(self.set_link_send(graph, source, cue, Some(volume), None));
+        }
+        messages
+    }
+
+    /// Drop solo links and undo dims without changing the graph, so that they aren't saved.
+    pub(super) fn forget_solos(&mut self) {
+        if let Some(cue) = self.cue_endpoint {
+            for dimmed in std::mem::take(&mut self.dimmed_cue_links) {
+                let Some(link) = self
+                    .links
+                    .iter_mut()
+                    .find(|link| link.start == dimmed.source && link.end == cue)
+                else {
+                    continue;
+                };
+                match (dimmed.prior_send, &mut link.send) {
+                    (Some((volume, muted)), Some(send)) => {
+                        send.volume = volume;
+                        send.muted = muted;
+                    }
+                    (None, send) => *send = None,
+                    _ => {}
+                }
+            }
+        }
+        self.soloed.clear();
+        self.links.retain(|link| !link.temporary);
+    }
+}
@@ -0,0 +1,199 @@
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+
+use crate::pipewire_api::{Graph, ToPipewireMessage};
+
+use super::{
+    settings::{db_to_volume, SonusmixSettings},
+    Endpoint, EndpointDescriptor, SonusmixState,
+};
+
+/// The range of a VCA fader, in dB.
+pub const VCA_MIN_DB: f32 = -60.0;
+pub const VCA_MAX_DB: f32 = 12.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct VcaId(Ulid);
+
+impl VcaId {
+    fn new() -> Self {
+        Self(Ulid::new())
+    }
+}
+
+/// A virtual fader that scales the volumes of its members together, like a VCA group on a mixing
+/// desk. The members keep their own volumes, and their nodes are set to their own volume scaled
+/// by the gain of every VCA they belong to.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Vca {
+    pub id: VcaId,
+    pub name: String,
+    pub gain_db: f32,
+    pub muted: bool,
+    pub members: Vec<EndpointDescriptor>,
+}
+
+impl Endpoint {
+    /// The volume the endpoint's nodes are set to: its own volume, scaled by its VCAs. A VCA
+    /// can't raise it above the endpoint's max volume.
+    pub fn applied_volume(&self) -> f32 {
+        self.clamp_volume(self.volume * db_to_volume(self.vca_gain_db as f64))
+    }
+
+    /// The endpoint's own volume for a volume its nodes were set to. It is kept within the range
+    /// of the volume slider, since a VCA turned far down would otherwise make an outside change
+    /// into a huge own volume, which would blast out once the VCA is raised again.
+    fn own_volume(&self, applied_volume: f32, settings: &SonusmixSettings) -> f32 {
+        let gain = db_to_volume(self.vca_gain_db as f64);
+        let own_volume = applied_volume / gain;
+        if gain > 0.0 && own_volume.is_finite() {
+            self.clamp_volume(own_volume.clamp(0.0, settings.max_volume()))
+        } else {
+            self.volume
+        }
+    }
+
+    /// The mute state the endpoint's nodes are set to, given the endpoint's own mute state.
+    pub fn applied_mute(&self, muted: bool) -> bool {
+        muted || self.vca_muted
+    }
+
+    /// The mute state all of the endpoint's nodes should have, or None if it's mixed.
+    pub(super) fn applied_mute_state(&self) -> Option<bool> {
+        if self.vca_muted {
+            Some(true)
+        } else {
+            self.volume_locked_muted.is_muted()
+        }
+    }
+
+    /// Take the volume the endpoint's nodes are set to as the endpoint's own volume, unless it's
+    /// just the endpoint's volume scaled by its VCAs. Otherwise, rounding errors would make the
+    /// volume drift a little every time it's read back.
+    pub(super) fn read_applied_volume(&mut self, applied_volume: f32, settings: &SonusmixSettings) {
+        if (applied_volume - self.applied_volume()).abs() > 1e-5 {
+            self.volume = self.own_volume(applied_volume, settings);
+        }
+    }
+}
+
+impl SonusmixState {
+    pub(super) fn add_vca(&mut self, name: String) -> VcaId {
+        let id = VcaId::new();
+        self.vcas.insert(
+            id,
+            Vca {
+                id,
+                name,
+                gain_db: 0.0,
+                muted: false,
+                members: Vec::new(),
+            },
+        );
+        id
+    }
+
+    /// Add an endpoint to a VCA, or remove it.
+    pub(super) fn set_vca_member(
+        &mut self,
+        id: VcaId,
+        endpoint_desc: EndpointDescriptor,
+        member: bool,
+    ) {
+        let Some(vca) = self.vcas.get_mut(&id) else {
+            return;
+        };
+        let position = vca.members.iter().position(|desc| *desc == endpoint_desc);
+        match (member, position) {
+            (true, None) if self.endpoints.contains_key(&endpoint_desc) => {
+                vca.members.push(endpoint_desc)
+            }
+            (false, Some(position)) => {
+                vca.members.remove(position);
+            }
+            _ => {}
+        }
+    }
+
+    /// Forget an endpoint that was removed from every VCA it belongs to.
+    pub(super) fn remove_vca_member(&mut self, endpoint_desc: EndpointDescriptor) {
+        for vca in self.vcas.values_mut() {
+            vca.members.retain(|desc| *desc != endpoint_desc);
+        }
+    }
+
+    pub(super) fn replace_vca_member(&mut self, old: EndpointDescriptor, new: EndpointDescriptor) {
+        for desc in self
+            .vcas
+            .values_mut()
+            .flat_map(|vca| vca.members.iter_mut())
+        {
+            if *desc == old {
+                *desc = new;
+            }
+        }
+    }
+
+    /// The VCAs an endpoint belongs to.
+    pub fn endpoint_vcas(&self, endpoint_desc: EndpointDescriptor) -> impl Iterator<Item = &Vca> {
+        self.vcas
+            .values()
+            .filter(move |vca| vca.members.contains(&endpoint_desc))
+    }
+
+    /// Work out how much each endpoint is scaled by its VCAs, and set the nodes of endpoints
+    /// whose scaling changed to their new volume and mute state.
+    pub(super) fn apply_vcas(
+        &mut self,
+        graph: &Graph,
+        settings: &SonusmixSettings,
+    ) -> Vec<ToPipewireMessage> {
+        let mut messages = Vec::new();
+        let descriptors: Vec<EndpointDescriptor> = self.endpoints.keys().copied().collect();
+        for endpoint_desc in descriptors {
+            let (gain_db, vca_muted) = self
+                .endpoint_vcas(endpoint_desc)
+                .fold((0.0, false), |(gain_db, muted), vca| {
+                    (gain_db + vca.gain_db, muted || vca.muted)
+                });
+            let endpoint = &self.endpoints[&endpoint_desc];
+            if endpoint.vca_gain_db == gain_db && endpoint.vca_muted == vca_muted {
+                continue;
+            }
+            let nodes = self
+                .resolve_endpoint(endpoint_desc, graph, settings)
+                .unwrap_or_default();
+            let endpoint = self
+                .endpoints
+                .get_mut(&endpoint_desc)
+                .expect("endpoint should exist as it was just looked up");
+            let mute_changed = endpoint.vca_muted != vca_muted;
+            endpoint.vca_gain_db = gain_db;
+            endpoint.vca_muted = vca_muted;
+
+            let num_messages_before = messages.len();
+            let volume = endpoint.applied_volume();
+            messages.extend(nodes.iter().map(|node| {
+                ToPipewireMessage::NodeVolume(node.id, vec![volume; node.channel_volumes.len()])
+            }));
+            if mute_changed {
+                // Endpoints with mixed mute states are unmuted along with their VCA, since there's
+                // no single state to go back to
+                if endpoint.applied_mute_state().is_none() {
+                    endpoint.volume_locked_muted = endpoint.volume_locked_muted.with_mute(false);
+                }
+                let muted = endpoint.applied_mute_state().unwrap_or(true);
+                messages.extend(
+                    nodes
+                        .iter()
+                        .map(|node| ToPipewireMessage::NodeMute(node.id, muted)),
+                );
+            }
+            if messages.len() > num_messages_before {
+                endpoint.volume_mixed = false;
+                endpoint.volume_pending = true;
+            }
+        }
+        messages
+    }
+}
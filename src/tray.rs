@@ -1,18 +1,243 @@
-use std::sync::atomic::Ordering;
+use std::sync::{atomic::Ordering, Arc};
 
 use ksni::{menu::*, *};
 
+use crate::state::{
+    settings::SonusmixSettings, Endpoint, EndpointDescriptor, MuteGroupTarget, SonusmixMsg,
+    SonusmixReducer, SonusmixState,
+};
 use crate::{MainMsg, APP_WINDOW_ID, SONUSMIX_APP_ID};
 
+/// How much the volume menu items and scrolling change the volume by, in percent
+const VOLUME_STEP: f64 = 5.0;
+
 #[derive(Debug)]
 pub struct SonusmixTray {
     sender: relm4::Sender<MainMsg>,
+    pub state: Arc<SonusmixState>,
+    pub settings: SonusmixSettings,
 }
 
 impl SonusmixTray {
-    pub fn new(sender: relm4::Sender<MainMsg>) -> Self {
-        Self { sender }
+    pub fn new(
+        sender: relm4::Sender<MainMsg>,
+        state: Arc<SonusmixState>,
+        settings: SonusmixSettings,
+    ) -> Self {
+        Self {
+            sender,
+            state,
+            settings,
+        }
+    }
+
+    /// The message to change an endpoint's volume by a number of steps, staying within the
+    /// volume limit.
+    fn step_volume(&self, endpoint: &Endpoint, steps: f64) -> SonusmixMsg {
+        let curve = self.settings.fader_curve;
+        let slider = (curve.volume_to_slider(endpoint.volume) + steps * VOLUME_STEP)
+            .clamp(0.0, self.settings.slider_limit());
+        SonusmixMsg::SetVolume(endpoint.descriptor, curve.slider_to_volume(slider))
+    }
+
+    /// A check item for muting every source, and one for each mute group.
+    fn mute_group_items(&self) -> Vec<MenuItem<Self>> {
+        let all_sources_muted = self
+            .state
+            .is_mute_group_engaged(MuteGroupTarget::AllSources);
+        let mut items: Vec<MenuItem<Self>> = vec![CheckmarkItem {
+            label: "Mute All Sources".to_owned(),
+            checked: all_sources_muted,
+            activate: Box::new(move |_: &mut Self| {
+                SonusmixReducer::emit(SonusmixMsg::SetMuteGroupEngaged(
+                    MuteGroupTarget::AllSources,
+                    !all_sources_muted,
+                ));
+            }),
+            ..Default::default()
+        }
+        .into()];
+        items.extend(self.state.mute_groups.values().map(|group| {
+            let target = MuteGroupTarget::Group(group.id);
+            let engaged = group.held.is_some();
+            CheckmarkItem {
+                label: format!("Mute {}", escape_label(&group.name)),
+                checked: engaged,
+                activate: Box::new(move |_: &mut Self| {
+                    SonusmixReducer::emit(SonusmixMsg::SetMuteGroupEngaged(target, !engaged));
+                }),
+                ..Default::default()
+            }
+            .into()
+        }));
+        items
     }
+
+    fn endpoint_menu(&self, endpoint: &Endpoint) -> MenuItem<Self> {
+        let descriptor = endpoint.descriptor;
+        let muted = endpoint.volume_locked_muted.is_muted();
+        let locked = endpoint.volume_locked_muted.is_locked();
+        let is_primary = self.state.primary_endpoint == Some(descriptor);
+
+        let mut label = format!(
+            "{}: {}",
+            escape_label(endpoint.custom_or_display_name()),
+            self.settings.fader_curve.format_volume(endpoint.volume)
+        );
+        let mut markers = Vec::new();
+        if endpoint.is_placeholder {
+            markers.push("not available");
+        } else if muted == Some(true) {
+            markers.push("muted");
+        }
+        if locked {
+            markers.push("locked");
+        }
+        if !markers.is_empty() {
+            label.push_str(&format!(" ({})", markers.join(", ")));
+        }
+
+        let mut submenu = vec![
+            CheckmarkItem {
+                label: "Muted".to_owned(),
+                // Muting isn't possible while the endpoint's nodes have mixed mute states
+                enabled: muted.is_some(),
+                checked: muted == Some(true),
+                activate: Box::new(move |_: &mut Self| {
+                    SonusmixReducer::emit(SonusmixMsg::SetMute(descriptor, muted != Some(true)));
+                }),
+                ..Default::default()
+            }
+            .into(),
+            StandardItem {
+                label: "Volume Up".to_owned(),
+                icon_name: "audio-volume-high-symbolic".to_owned(),
+                activate: Box::new(move |tray: &mut Self| {
+                    if let Some(endpoint) = tray.state.endpoints.get(&descriptor) {
+                        SonusmixReducer::emit(tray.step_volume(endpoint, 1.0));
+                    }
+                }),
+                ..Default::default()
+            }
+            .into(),
+            StandardItem {
+                label: "Volume Down".to_owned(),
+                icon_name: "audio-volume-low-symbolic".to_owned(),
+                activate: Box::new(move |tray: &mut Self| {
+                    if let Some(endpoint) = tray.state.endpoints.get(&descriptor) {
+                        SonusmixReducer::emit(tray.step_volume(endpoint, -1.0));
+                    }
+                }),
+                ..Default::default()
+            }
+            .into(),
+            CheckmarkItem {
+                label: "Lock Volume".to_owned(),
+                checked: locked,
+                activate: Box::new(move |_: &mut Self| {
+                    SonusmixReducer::emit(SonusmixMsg::SetVolumeLocked(descriptor, !locked));
+                }),
+                ..Default::default()
+            }
+            .into(),
+            CheckmarkItem {
+                label: "Scroll on Tray Icon to Change Volume".to_owned(),
+                checked: is_primary,
+                activate: Box::new(move |_: &mut Self| {
+                    SonusmixReducer::emit(SonusmixMsg::SetPrimaryEndpoint(
+                        (!is_primary).then_some(descriptor),
+                    ));
+                }),
+                ..Default::default()
+            }
+            .into(),
+        ];
+
+        if let Some(device) = endpoint
+            .device
+            .as_ref()
+            .filter(|device| device.profiles.len() > 1)
+        {
+            let indices: Vec<i32> = device
+                .profiles
+                .iter()
+                .map(|profile| profile.index)
+                .collect();
+            submenu.push(MenuItem::Separator);
+            submenu.push(
+                SubMenu {
+                    label: "Profile".to_owned(),
+                    submenu: vec![RadioGroup {
+                        // If there is no active profile, none of the options are selected
+                        selected: device
+                            .active_profile
+                            .and_then(|active| indices.iter().position(|index| *index == active))
+                            .unwrap_or(usize::MAX),
+                        select: Box::new(move |_: &mut Self, selected: usize| {
+                            SonusmixReducer::emit(SonusmixMsg::SetDeviceProfile(
+                                descriptor,
+                                indices[selected],
+                            ));
+                        }),
+                        options: device
+                            .profiles
+                            .iter()
+                            .map(|profile| RadioItem {
+                                label: escape_label(&profile.description),
+                                enabled: profile.available,
+                                ..Default::default()
+                            })
+                            .collect(),
+                    }
+                    .into()],
+                    ..Default::default()
+                }
+                .into(),
+            );
+        }
+
+        SubMenu {
+            label,
+            icon_name: endpoint.custom_or_default_icon_name().to_owned(),
+            submenu,
+            ..Default::default()
+        }
+        .into()
+    }
+
+    fn endpoint_section(
+        &self,
+        title: &str,
+        descriptors: &[EndpointDescriptor],
+    ) -> Vec<MenuItem<Self>> {
+        let endpoints: Vec<&Endpoint> = descriptors
+            .iter()
+            .filter_map(|descriptor| self.state.endpoints.get(descriptor))
+            .collect();
+        if endpoints.is_empty() {
+            return Vec::new();
+        }
+        let mut items = vec![
+            MenuItem::Separator,
+            StandardItem {
+                label: title.to_owned(),
+                enabled: false,
+                ..Default::default()
+            }
+            .into(),
+        ];
+        items.extend(
+            endpoints
+                .into_iter()
+                .map(|endpoint| self.endpoint_menu(endpoint)),
+        );
+        items
+    }
+}
+
+/// Underscores in menu labels mark access keys, so they need to be doubled to be shown.
+fn escape_label(label: &str) -> String {
+    label.replace('_', "__")
 }
 
 impl Tray for SonusmixTray {
@@ -28,14 +253,69 @@ impl Tray for SonusmixTray {
     fn window_id(&self) -> i32 {
         APP_WINDOW_ID.load(Ordering::Acquire)
     }
+    fn scroll(&mut self, delta: i32, dir: &str) {
+        if !dir.eq_ignore_ascii_case("vertical") || delta == 0 {
+            return;
+        }
+        let Some(endpoint) = self
+            .state
+            .primary_endpoint
+            .and_then(|descriptor| self.state.endpoints.get(&descriptor))
+        else {
+            return;
+        };
+        // Hosts send different amounts per scroll step, so only the direction is used
+        SonusmixReducer::emit(self.step_volume(endpoint, delta.signum() as f64));
+    }
     fn tool_tip(&self) -> ToolTip {
+        let endpoints = || {
+            self.state
+                .active_sources
+                .iter()
+                .chain(self.state.active_sinks.iter())
+                .filter_map(|descriptor| self.state.endpoints.get(descriptor))
+        };
+        let list = |endpoints: Vec<&Endpoint>| {
+            endpoints
+                .iter()
+                .map(|endpoint| endpoint.custom_or_display_name())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        let mut lines = Vec::new();
+        if let Some(primary) = self
+            .state
+            .primary_endpoint
+            .and_then(|descriptor| self.state.endpoints.get(&descriptor))
+        {
+            lines.push(format!(
+                "{}: {}",
+                primary.custom_or_display_name(),
+                self.settings.fader_curve.format_volume(primary.volume)
+            ));
+        }
+        let muted: Vec<&Endpoint> = endpoints()
+            .filter(|endpoint| endpoint.volume_locked_muted.is_muted() == Some(true))
+            .collect();
+        if !muted.is_empty() {
+            lines.push(format!("Muted: {}", list(muted)));
+        }
+        let placeholders: Vec<&Endpoint> = endpoints()
+            .filter(|endpoint| endpoint.is_placeholder)
+            .collect();
+        if !placeholders.is_empty() {
+            lines.push(format!("Not available: {}", list(placeholders)));
+        }
+
         ToolTip {
             title: "Sonusmix is running".to_owned(),
+            description: lines.join("\n"),
             ..Default::default()
         }
     }
     fn menu(&self) -> Vec<MenuItem<Self>> {
-        vec![
+        let mut items: Vec<MenuItem<Self>> = vec![
             StandardItem {
                 label: "Show".to_owned(),
                 activate: Box::new(|tray: &mut Self| {
@@ -52,6 +332,13 @@ impl Tray for SonusmixTray {
                 ..Default::default()
             }
             .into(),
+        ];
+        items.extend(self.endpoint_section("Sources", &self.state.active_sources));
+        items.extend(self.endpoint_section("Sinks", &self.state.active_sinks));
+        items.push(MenuItem::Separator);
+        items.extend(self.mute_group_items());
+        items.push(MenuItem::Separator);
+        items.push(
             StandardItem {
                 label: "Exit".to_owned(),
                 activate: Box::new(|tray: &mut Self| {
@@ -60,6 +347,7 @@ impl Tray for SonusmixTray {
                 ..Default::default()
             }
             .into(),
-        ]
+        );
+        items
     }
 }